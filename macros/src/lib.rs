@@ -0,0 +1,369 @@
+//! Procedural macros for bcrl-rs, use them through the `macros` feature of bcrl-rs.
+
+use proc_macro::{Delimiter, Group, Spacing, Span, TokenStream, TokenTree};
+
+/// Parses an IDA-style signature like `"48 8B ?? ?? E8 ? ? ? ?"` at compile time.
+/// Typos in the pattern are reported as compile errors. The expansion stores the parsed elements in a static
+/// `StaticSignature`, the signature is created from them once, when it is first used.
+#[proc_macro]
+pub fn sig(input: TokenStream) -> TokenStream {
+    match parse_input(input)
+        .and_then(|(pattern, span)| parse_pattern(&pattern).map_err(|error| (error, span)))
+    {
+        Ok(elements) => expand(&elements),
+        Err((error, span)) => compile_error(&error, span),
+    }
+}
+
+fn parse_input(input: TokenStream) -> Result<(String, Span), (String, Span)> {
+    let mut tokens = input.into_iter().collect::<Vec<_>>();
+
+    // Literals passed through macro_rules! are wrapped in an invisible group
+    while let [TokenTree::Group(group)] = tokens.as_slice() {
+        if group.delimiter() != Delimiter::None {
+            break;
+        }
+        tokens = group.stream().into_iter().collect();
+    }
+
+    match tokens.as_slice() {
+        [TokenTree::Literal(literal)] => unquote(&literal.to_string())
+            .map(|pattern| (pattern, literal.span()))
+            .ok_or_else(|| ("expected a string literal".to_string(), literal.span())),
+        [token, ..] => Err(("expected a single string literal".to_string(), token.span())),
+        [] => Err(("expected a string literal".to_string(), Span::call_site())),
+    }
+}
+
+/// Returns the contents of plain and raw string literals. Escapes are not supported, because patterns don't need them.
+fn unquote(literal: &str) -> Option<String> {
+    if let Some(raw) = literal.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        let raw = &raw[hashes..raw.len().checked_sub(hashes)?];
+        return raw.strip_prefix('"')?.strip_suffix('"').map(str::to_string);
+    }
+
+    let contents = literal.strip_prefix('"')?.strip_suffix('"')?;
+    (!contents.contains('\\')).then(|| contents.to_string())
+}
+
+fn parse_pattern(pattern: &str) -> Result<Vec<Option<u8>>, String> {
+    let elements = pattern
+        .split_whitespace()
+        .enumerate()
+        .map(|(index, token)| match token {
+            "?" | "??" => Ok(None),
+            // from_str_radix also accepts a sign, which would let typos like `+F` through
+            _ if token.len() == 2 && token.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                Ok(u8::from_str_radix(token, 16).ok())
+            }
+            _ if token.len() == 2 => Err(format!("invalid byte `{token}` at position {index}")),
+            _ => Err(format!(
+                "invalid element `{token}` at position {index}, expected two hex digits or a wildcard"
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if elements.is_empty() {
+        return Err("the signature is empty".to_string());
+    }
+
+    Ok(elements)
+}
+
+fn expand(elements: &[Option<u8>]) -> TokenStream {
+    expansion(elements).parse().unwrap()
+}
+
+fn expansion(elements: &[Option<u8>]) -> String {
+    let elements = elements
+        .iter()
+        .map(|element| match element {
+            Some(byte) => format!("::core::option::Option::Some({byte}u8)"),
+            None => "::core::option::Option::None".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "{{ \
+            static SIGNATURE: ::bcrl_rs::pattern::StaticSignature = \
+                ::bcrl_rs::pattern::StaticSignature::new(&[{elements}]); \
+            SIGNATURE.get() \
+        }}"
+    )
+}
+
+/// Implements `ScanPattern` for a `#[repr(C)]` struct with named fields. Fields marked with `#[scan(value = EXPR)]`
+/// become concrete bytes, all other fields are wildcards. The values are converted to the type of their field.
+/// `#[repr(C, packed)]` is accepted, a bare `#[repr(packed)]` isn't, since it doesn't fix the order of the fields.
+#[proc_macro_derive(ScanPattern, attributes(scan))]
+pub fn derive_scan_pattern(input: TokenStream) -> TokenStream {
+    match parse_struct(input) {
+        Ok((name, fields)) => expand_scan_pattern(&name, &fields),
+        Err((error, span)) => {
+            // Derives expand to items, which have to end with a semicolon
+            let mut tokens = compile_error(&error, span);
+            tokens.extend(";".parse::<TokenStream>());
+            tokens
+        }
+    }
+}
+
+/// A field of the derived struct, the type and value are kept as source text
+struct Field {
+    name: String,
+    ty: String,
+    value: Option<String>,
+}
+
+type ParseResult<T> = Result<T, (String, Span)>;
+
+/// Splits the attributes off the front of the tokens
+fn take_attributes(tokens: &mut &[TokenTree]) -> Vec<Group> {
+    let mut attributes = Vec::new();
+    while let [TokenTree::Punct(punct), TokenTree::Group(group), rest @ ..] = *tokens {
+        if punct.as_char() != '#' || group.delimiter() != Delimiter::Bracket {
+            break;
+        }
+        attributes.push(group.clone());
+        *tokens = rest;
+    }
+
+    attributes
+}
+
+/// Skips `pub`, `pub(crate)` and the like
+fn skip_visibility(tokens: &mut &[TokenTree]) {
+    if let [TokenTree::Ident(ident), rest @ ..] = *tokens {
+        if ident.to_string() == "pub" {
+            *tokens = match rest {
+                [TokenTree::Group(group), rest @ ..]
+                    if group.delimiter() == Delimiter::Parenthesis =>
+                {
+                    rest
+                }
+                _ => rest,
+            };
+        }
+    }
+}
+
+/// The name of the attribute and its arguments, e.g. `repr` and `C`
+fn attribute_parts(attribute: &Group) -> Option<(String, Option<Group>)> {
+    let tokens = attribute.stream().into_iter().collect::<Vec<_>>();
+    match tokens.as_slice() {
+        [TokenTree::Ident(name)] => Some((name.to_string(), None)),
+        [TokenTree::Ident(name), TokenTree::Group(arguments)] => {
+            Some((name.to_string(), Some(arguments.clone())))
+        }
+        _ => None,
+    }
+}
+
+fn parse_struct(input: TokenStream) -> ParseResult<(String, Vec<Field>)> {
+    let tokens = input.into_iter().collect::<Vec<_>>();
+    let mut tokens = tokens.as_slice();
+
+    // A bare repr(packed) keeps the unspecified field order of the Rust representation, it has to be combined with C
+    let repr_c = take_attributes(&mut tokens).iter().any(|attribute| {
+        attribute_parts(attribute).is_some_and(|(name, arguments)| {
+            name == "repr" && arguments.is_some_and(|arguments| {
+                arguments.stream().into_iter().any(
+                    |token| matches!(&token, TokenTree::Ident(ident) if ident.to_string() == "C"),
+                )
+            })
+        })
+    });
+    skip_visibility(&mut tokens);
+
+    let (name, body) = match tokens {
+        [TokenTree::Ident(keyword), TokenTree::Ident(name), rest @ ..]
+            if keyword.to_string() == "struct" =>
+        {
+            match rest {
+                [TokenTree::Group(body)] if body.delimiter() == Delimiter::Brace => (name, body),
+                [TokenTree::Punct(punct), ..] if punct.as_char() == '<' => {
+                    return Err((
+                        "ScanPattern can't be derived for generic structs".to_string(),
+                        punct.span(),
+                    ))
+                }
+                _ => {
+                    return Err((
+                        "ScanPattern can only be derived for structs with named fields".to_string(),
+                        name.span(),
+                    ))
+                }
+            }
+        }
+        [token, ..] => {
+            return Err((
+                "ScanPattern can only be derived for structs".to_string(),
+                token.span(),
+            ))
+        }
+        [] => return Err(("expected a struct".to_string(), Span::call_site())),
+    };
+    if !repr_c {
+        return Err((
+            "ScanPattern requires #[repr(C)], the layout of other structs is unspecified"
+                .to_string(),
+            name.span(),
+        ));
+    }
+
+    let fields = split_fields(body.stream())
+        .iter()
+        .map(|field| parse_field(field))
+        .collect::<ParseResult<Vec<_>>>()?;
+
+    Ok((name.to_string(), fields))
+}
+
+/// Splits the fields at the commas, which aren't inside of generic arguments
+fn split_fields(body: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut fields = vec![Vec::new()];
+    let mut depth = 0usize;
+    let mut previous_joint = None;
+
+    for token in body {
+        if let TokenTree::Punct(punct) = &token {
+            match punct.as_char() {
+                ',' if depth == 0 => {
+                    fields.push(Vec::new());
+                    continue;
+                }
+                '<' => depth += 1,
+                // The arrow of function pointers isn't a closing bracket
+                '>' if previous_joint != Some('-') => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            previous_joint = (punct.spacing() == Spacing::Joint).then_some(punct.as_char());
+        } else {
+            previous_joint = None;
+        }
+        fields.last_mut().unwrap().push(token);
+    }
+
+    fields.retain(|field| !field.is_empty());
+    fields
+}
+
+fn parse_field(tokens: &[TokenTree]) -> ParseResult<Field> {
+    let mut tokens = tokens;
+    let mut value = None;
+    for attribute in take_attributes(&mut tokens) {
+        match attribute_parts(&attribute) {
+            Some((name, Some(arguments))) if name == "scan" => {
+                value = Some(parse_scan_attribute(&arguments)?)
+            }
+            Some((name, None)) if name == "scan" => {
+                return Err((
+                    "expected #[scan(value = ...)]".to_string(),
+                    attribute.span(),
+                ))
+            }
+            _ => {}
+        }
+    }
+    skip_visibility(&mut tokens);
+
+    match tokens {
+        [TokenTree::Ident(name), TokenTree::Punct(colon), ty @ ..]
+            if colon.as_char() == ':' && !ty.is_empty() =>
+        {
+            Ok(Field {
+                name: name.to_string(),
+                ty: ty.iter().cloned().collect::<TokenStream>().to_string(),
+                value,
+            })
+        }
+        [token, ..] => Err(("expected a named field".to_string(), token.span())),
+        [] => Err(("expected a named field".to_string(), Span::call_site())),
+    }
+}
+
+/// Parses `value = EXPR` and returns the expression
+fn parse_scan_attribute(arguments: &Group) -> ParseResult<String> {
+    let tokens = arguments.stream().into_iter().collect::<Vec<_>>();
+    match tokens.as_slice() {
+        [TokenTree::Ident(key), TokenTree::Punct(equals), value @ ..]
+            if key.to_string() == "value" && equals.as_char() == '=' && !value.is_empty() =>
+        {
+            Ok(value.iter().cloned().collect::<TokenStream>().to_string())
+        }
+        _ => Err((
+            "expected #[scan(value = ...)]".to_string(),
+            arguments.span(),
+        )),
+    }
+}
+
+fn expand_scan_pattern(name: &str, fields: &[Field]) -> TokenStream {
+    let steps = fields
+        .iter()
+        .map(|field| {
+            let offset = format!("::core::mem::offset_of!(Self, {})", field.name);
+            let mut step = format!(".with_field({:?}, {offset})", field.name);
+            if let Some(value) = &field.value {
+                step += &format!(
+                    ".with_constant({offset}, &::bcrl_rs::struct_pattern::PatternBytes::pattern_bytes(&{{ let value: {} = {value}; value }}, endianness))",
+                    field.ty
+                );
+            }
+            step
+        })
+        .collect::<String>();
+
+    format!(
+        "impl ::bcrl_rs::struct_pattern::ScanPattern for {name} {{
+            fn scan_pattern(endianness: ::bcrl_rs::Endianness) -> ::bcrl_rs::struct_pattern::StructPattern {{
+                ::bcrl_rs::struct_pattern::StructPattern::new(::core::mem::size_of::<Self>()){steps}
+            }}
+        }}"
+    )
+    .parse()
+    .unwrap()
+}
+
+fn compile_error(error: &str, span: Span) -> TokenStream {
+    let tokens: TokenStream = format!("::core::compile_error!({error:?})")
+        .parse()
+        .unwrap();
+
+    tokens
+        .into_iter()
+        .map(|mut token| {
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expansion, parse_pattern};
+
+    #[test]
+    fn test_parse_pattern() {
+        assert_eq!(
+            parse_pattern("48 ?? ? e8"),
+            Ok(vec![Some(0x48), None, None, Some(0xE8)])
+        );
+        assert!(parse_pattern("48 8").is_err());
+        assert!(parse_pattern("+F 00").is_err());
+        assert!(parse_pattern("00 -1").is_err());
+        assert!(parse_pattern("  ").is_err());
+    }
+
+    #[test]
+    fn test_expansion() {
+        assert_eq!(
+            expansion(&[Some(0x48), None]),
+            "{ static SIGNATURE: ::bcrl_rs::pattern::StaticSignature = \
+            ::bcrl_rs::pattern::StaticSignature::new(&[::core::option::Option::Some(72u8), \
+            ::core::option::Option::None]); SIGNATURE.get() }"
+        );
+    }
+}
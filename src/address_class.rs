@@ -0,0 +1,119 @@
+//! Tells what an address points into, e.g. to check what a pipeline resolved to.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use procfs_core::process::{MMPermissions, MMapPath};
+
+use crate::{
+    cached_maps::{CachedMaps, FindAddress},
+    module::Module,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AddressKind {
+    /// Executable memory, including the vDSO and JIT code
+    Code,
+    /// Writable memory, which is neither heap nor stack
+    Data,
+    /// Memory, which is only readable
+    Rodata,
+    /// The `[heap]` map
+    Heap,
+    /// The stack of the main thread or of another thread
+    Stack,
+    /// The thread-local storage of a thread
+    Tls,
+    /// Not mapped at all, or mapped without any access, which only reserves address space
+    Unmapped,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AddressClass {
+    address: usize,
+    kind: AddressKind,
+    module: Option<PathBuf>,
+    section: Option<String>,
+}
+
+impl AddressClass {
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+    pub fn get_kind(&self) -> AddressKind {
+        self.kind
+    }
+    /// The file, which the map containing the address is backed by
+    pub fn get_module(&self) -> Option<&PathBuf> {
+        self.module.as_ref()
+    }
+    /// The ELF section containing the address, if the module is an ELF file, whose section headers can be read
+    pub fn get_section(&self) -> Option<&str> {
+        self.section.as_deref()
+    }
+}
+
+impl fmt::Display for AddressClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}: {:?}", self.address, self.kind)?;
+        if let Some(module) = &self.module {
+            write!(f, " in {}", module.display())?;
+        }
+        if let Some(section) = &self.section {
+            write!(f, " ({section})")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Classifies the address using the map containing it. Modules are opened relative to the root, see [`crate::container`].
+pub fn classify(maps: &Rc<CachedMaps>, root: Option<&Path>, address: usize) -> AddressClass {
+    let unmapped = AddressClass {
+        address,
+        kind: AddressKind::Unmapped,
+        module: None,
+        section: None,
+    };
+    let Some(map) = maps.find_map(address) else {
+        return unmapped;
+    };
+
+    let permissions = map.get_permissions();
+    let kind = if map.is_tls() {
+        AddressKind::Tls
+    } else {
+        match map.get_name() {
+            MMapPath::Heap => AddressKind::Heap,
+            MMapPath::Stack | MMapPath::TStack(_) => AddressKind::Stack,
+            _ if permissions.contains(MMPermissions::EXECUTE) => AddressKind::Code,
+            _ if permissions.contains(MMPermissions::WRITE) => AddressKind::Data,
+            _ if permissions.contains(MMPermissions::READ) => AddressKind::Rodata,
+            _ => return unmapped,
+        }
+    };
+
+    let (module, section) = match map.get_name() {
+        MMapPath::Path(path) => {
+            let section = Module::new(maps.clone(), map.get_name().clone())
+                .map(|module| module.with_root(root.map(Path::to_path_buf)))
+                .and_then(|module| {
+                    module
+                        .section_of(address)
+                        .map(|section| section.get_name().to_string())
+                });
+            (Some(path.clone()), section)
+        }
+        _ => (None, None),
+    };
+
+    AddressClass {
+        address,
+        kind,
+        module,
+        section,
+    }
+}
@@ -0,0 +1,129 @@
+/// A half-open range of addresses `[from, to)`.
+/// The end is never in front of the start, ranges that would be are empty instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+// Serialized as a (from, to) pair, so deserialization goes through the constructor
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "(usize, usize)", into = "(usize, usize)")
+)]
+pub struct AddressRange {
+    from: usize,
+    to: usize,
+}
+
+impl AddressRange {
+    /// Creates the range, if to is in front of from the range is empty
+    pub fn new(from: usize, to: usize) -> Self {
+        Self {
+            from,
+            to: to.max(from),
+        }
+    }
+
+    /// Covers the whole address space, except for the very last address
+    pub fn everything() -> Self {
+        Self::new(usize::MIN, usize::MAX)
+    }
+
+    pub fn get_from(&self) -> usize {
+        self.from
+    }
+    pub fn get_to(&self) -> usize {
+        self.to
+    }
+    pub fn len(&self) -> usize {
+        self.to - self.from
+    }
+    pub fn is_empty(&self) -> bool {
+        self.from == self.to
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        self.from <= address && address < self.to
+    }
+
+    /// Returns true if both ranges share at least one address
+    pub fn overlaps(&self, other: &AddressRange) -> bool {
+        !self.intersect(other).is_empty()
+    }
+
+    /// Returns the addresses, which are inside of both ranges. Disjoint ranges result in an empty range.
+    pub fn intersect(&self, other: &AddressRange) -> AddressRange {
+        AddressRange::new(self.from.max(other.from), self.to.min(other.to))
+    }
+
+    /// Returns the offsets of this range relative to base, which can be used to slice the bytes of a mapping
+    pub fn offsets_from(&self, base: usize) -> core::ops::Range<usize> {
+        self.from.saturating_sub(base)..self.to.saturating_sub(base)
+    }
+}
+
+impl From<(usize, usize)> for AddressRange {
+    fn from((from, to): (usize, usize)) -> Self {
+        AddressRange::new(from, to)
+    }
+}
+
+impl From<AddressRange> for (usize, usize) {
+    fn from(range: AddressRange) -> Self {
+        (range.from, range.to)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AddressRange;
+
+    #[test]
+    fn test_inverted_range_is_empty() {
+        let range = AddressRange::new(0x2000, 0x1000);
+
+        assert!(range.is_empty());
+        assert_eq!(range.len(), 0);
+        assert!(!range.contains(0x1000));
+        assert!(!range.contains(0x2000));
+    }
+
+    #[test]
+    fn test_half_open() {
+        let range = AddressRange::new(0x1000, 0x2000);
+
+        assert!(!range.contains(0xFFF));
+        assert!(range.contains(0x1000));
+        assert!(range.contains(0x1FFF));
+        assert!(!range.contains(0x2000));
+        assert_eq!(range.len(), 0x1000);
+    }
+
+    #[test]
+    fn test_intersect() {
+        let range = AddressRange::new(0x1000, 0x3000);
+
+        assert_eq!(
+            range.intersect(&AddressRange::new(0x2000, 0x4000)),
+            AddressRange::new(0x2000, 0x3000)
+        );
+        assert_eq!(
+            range.intersect(&AddressRange::new(0x1800, 0x2000)),
+            AddressRange::new(0x1800, 0x2000)
+        );
+        assert_eq!(range.intersect(&AddressRange::everything()), range);
+
+        // Adjacent and disjoint ranges don't share any address
+        assert!(range
+            .intersect(&AddressRange::new(0x3000, 0x4000))
+            .is_empty());
+        assert!(range.intersect(&AddressRange::new(0x0, 0x800)).is_empty());
+        assert!(!range.overlaps(&AddressRange::new(0x3000, 0x4000)));
+        assert!(range.overlaps(&AddressRange::new(0x2FFF, 0x4000)));
+    }
+
+    #[test]
+    fn test_offsets_from() {
+        let range = AddressRange::new(0x1800, 0x2000);
+
+        assert_eq!(range.offsets_from(0x1000), 0x800..0x1000);
+        assert_eq!(AddressRange::new(0x1000, 0x1000).offsets_from(0x1000), 0..0);
+    }
+}
@@ -0,0 +1,97 @@
+//! Finds arrays of structs by repetitions of a per-element signature at a fixed stride,
+//! like entity lists or registration tables in data sections.
+
+use crate::{pattern::PlannedSignature, search_constraints::ScanDirection};
+
+/// Consecutive elements, which all match the element signature
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ArrayMatch {
+    address: usize,
+    count: usize,
+    stride: usize,
+}
+
+impl ArrayMatch {
+    pub fn new(address: usize, count: usize, stride: usize) -> Self {
+        Self {
+            address,
+            count,
+            stride,
+        }
+    }
+
+    /// The address of the first element
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+    pub fn get_stride(&self) -> usize {
+        self.stride
+    }
+    /// The address behind the last element's stride
+    pub fn get_end(&self) -> usize {
+        self.address + self.count * self.stride
+    }
+
+    /// The addresses of all elements, e.g. for [`crate::BcrlFactory::pointers`]
+    pub fn elements(&self) -> impl Iterator<Item = usize> {
+        let (address, stride) = (self.address, self.stride);
+        (0..self.count).map(move |index| address + index * stride)
+    }
+}
+
+/// Returns the offset and element count of every run of at least min_count matches, which are stride bytes apart.
+/// Runs are maximal, so a run of ten elements is reported once and not as runs starting at its later elements.
+pub fn find_runs(
+    signature: &PlannedSignature,
+    bytes: &[u8],
+    min_count: usize,
+    stride: usize,
+) -> Vec<(usize, usize)> {
+    assert!(stride > 0, "the stride has to be positive");
+
+    let hits = signature
+        .all(bytes, ScanDirection::Forwards)
+        .collect::<Vec<_>>();
+    let is_hit = |offset: usize| hits.binary_search(&offset).is_ok();
+
+    hits.iter()
+        .filter(|offset| {
+            offset
+                .checked_sub(stride)
+                .is_none_or(|previous| !is_hit(previous))
+        })
+        .map(|offset| {
+            let count = (0..)
+                .take_while(|index| is_hit(offset + index * stride))
+                .count();
+            (*offset, count)
+        })
+        .filter(|(_, count)| *count >= min_count.max(1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use signature_scanner::Signature;
+
+    use super::*;
+
+    #[test]
+    fn finds_maximal_runs() {
+        // Elements of 4 bytes starting with 0xAA, the run at 0 has three elements, the one at 14 only one
+        let bytes = [
+            0xAA, 1, 0, 0, 0xAA, 2, 0, 0, 0xAA, 3, 0, 0, 0, 0, 0xAA, 4, 0, 0,
+        ];
+        let signature = PlannedSignature::new(Signature::ida("AA ? 00 00"));
+
+        assert_eq!(find_runs(&signature, &bytes, 2, 4), vec![(0, 3)]);
+        assert_eq!(find_runs(&signature, &bytes, 1, 4), vec![(0, 3), (14, 1)]);
+        assert_eq!(
+            ArrayMatch::new(0x1000, 3, 4).elements().collect::<Vec<_>>(),
+            vec![0x1000, 0x1004, 0x1008]
+        );
+    }
+}
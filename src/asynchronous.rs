@@ -0,0 +1,57 @@
+//! Non-blocking variants of the operations, that read the memory of the target.
+//!
+//! Reading the memory of a large process can take multiple seconds. These functions do the reading on a
+//! separate thread, so the returned futures can be awaited on any executor without blocking it.
+//! Scanning itself operates on the snapshot and is not affected.
+
+use std::thread;
+
+use futures_channel::oneshot;
+use procfs::ProcError;
+
+use crate::{
+    error::BcrlError,
+    factory::{resnapshot_process, snapshot_process, BcrlFactory},
+};
+
+/// Runs the function on a separate thread. Returns None if the thread terminated without a result.
+async fn on_thread<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    let (sender, receiver) = oneshot::channel();
+
+    thread::spawn(move || {
+        // The receiver only disappears if the future was dropped, in which case nobody is interested in the result
+        let _ = sender.send(f());
+    });
+
+    receiver.await.ok()
+}
+
+impl BcrlFactory {
+    /// Creates a new BcrlFactory from the process with the given pid, without blocking the executor
+    pub async fn from_pid_async(pid: i32) -> Result<Self, ProcError> {
+        let (maps, maps_hash) = on_thread(move || snapshot_process(pid, false, false, false))
+            .await
+            .ok_or_else(|| {
+                ProcError::Other("snapshot thread terminated unexpectedly".to_string())
+            })??;
+
+        Ok(Self::from_snapshot(maps, Some(pid), maps_hash))
+    }
+
+    /// Takes a new snapshot of the process, without blocking the executor
+    pub async fn refresh_async(&self) -> Result<(), BcrlError> {
+        let Some(pid) = self.get_pid() else {
+            return Ok(());
+        };
+
+        let (ptrace_fallback, lazy, smaps) =
+            (self.has_ptrace_fallback(), self.is_lazy(), self.has_smaps());
+        let (maps, maps_hash) =
+            on_thread(move || resnapshot_process(pid, ptrace_fallback, lazy, smaps))
+                .await
+                .ok_or(BcrlError::SnapshotFailed { source: None })??;
+        self.replace_snapshot(maps, maps_hash);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+//! Captures the threads, which execute an address, using a breakpoint set with ptrace.
+//!
+//! Static cross references can't see indirect calls through vtables or function pointers. Breaking on a function
+//! and reading the return address from the stack of every thread, that hits it, finds these callers as well.
+//!
+//! All threads of the process are traced while capturing, threads created in the meantime are traced as well.
+//! A software breakpoint is removed while the thread, which hit it, steps over it, so other threads can miss it
+//! during that time. Hardware breakpoints don't have this problem, but only exist on x86 and are limited per thread.
+
+use std::{
+    io,
+    mem::offset_of,
+    thread,
+    time::{Duration, Instant},
+};
+
+use lde::Isa;
+
+use crate::{
+    cached_maps::{CachedMaps, FindAddress},
+    remote::{self, Registers, SeizedThreads},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BreakpointKind {
+    /// An `int3`, which is written over the first byte of the instruction
+    Software,
+    /// The first debug register of every thread, the code stays untouched
+    Hardware,
+}
+
+/// A thread, which executed the address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BreakpointHit {
+    tid: i32,
+    registers: Registers,
+    return_address: Option<usize>,
+    call_site: Option<usize>,
+}
+
+impl BreakpointHit {
+    pub fn get_tid(&self) -> i32 {
+        self.tid
+    }
+    /// The registers before the instruction at the address was executed
+    pub fn get_registers(&self) -> &Registers {
+        &self.registers
+    }
+    /// The word at the stack pointer, which is the return address if the breakpoint is at the start of a function
+    pub fn get_return_address(&self) -> Option<usize> {
+        self.return_address
+    }
+    /// The `call` instruction in front of the return address, if there is one in the snapshot
+    pub fn get_call_site(&self) -> Option<usize> {
+        self.call_site
+    }
+}
+
+const INT3: u8 = 0xCC;
+/// Enables the first debug register for the thread, the zeroed condition bits make it break on execution
+const DR7_LOCAL_ENABLE_0: u64 = 1;
+/// Set in DR6 by the CPU, when the first debug register triggered
+const DR6_HIT_0: u64 = 1;
+
+fn debug_register(index: usize) -> usize {
+    offset_of!(libc::user, u_debugreg) + index * size_of::<u64>()
+}
+
+fn set_debug_register(tid: libc::pid_t, index: usize, value: u64) -> io::Result<()> {
+    remote::request(
+        libc::PTRACE_POKEUSER,
+        tid,
+        debug_register(index),
+        value as usize,
+    )
+    .map(|_| ())
+}
+
+fn get_debug_register(tid: libc::pid_t, index: usize) -> io::Result<u64> {
+    // PEEKUSER returns the word, so errors can only be told apart by errno
+    let value = unsafe {
+        *libc::__errno_location() = 0;
+        libc::ptrace(
+            libc::PTRACE_PEEKUSER,
+            tid,
+            debug_register(index) as *mut libc::c_void,
+            std::ptr::null_mut::<libc::c_void>(),
+        )
+    };
+    if value == -1 && io::Error::last_os_error().raw_os_error() != Some(0) {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(value as u64)
+}
+
+/// Finds the `call` instruction, which ends at the return address.
+/// Direct calls are checked first, then indirect calls of every length, including their REX prefix if they have one.
+pub fn call_site(maps: &CachedMaps, return_address: usize) -> Option<usize> {
+    let map = maps.find_map(return_address)?;
+    let bytes = map.get_bytes();
+    let end = return_address - map.get_from_address();
+
+    let is_call = |length: usize| {
+        let Some(start) = end.checked_sub(length) else {
+            return false;
+        };
+        let Some(instruction) = bytes.get(start..).filter(|bytes| !bytes.is_empty()) else {
+            return false;
+        };
+        let opcode = match instruction[0] {
+            0x40..=0x4F => &instruction[1..],
+            _ => instruction,
+        };
+        let is_call = match opcode {
+            [0xE8, ..] => length == 5,
+            // FF /2 is an indirect call
+            [0xFF, modrm, ..] => (modrm >> 3) & 0b111 == 2,
+            _ => false,
+        };
+        is_call && lde::X64::ld(instruction) as usize == length
+    };
+
+    let has_rex = |length: usize| {
+        end.checked_sub(length + 1)
+            .and_then(|start| bytes.get(start))
+            .is_some_and(|byte| (0x40..=0x4F).contains(byte))
+    };
+
+    [5, 2, 3, 4, 6, 7, 8]
+        .into_iter()
+        .find(|length| is_call(*length))
+        // The call is shorter without its REX prefix, e.g. `call r11` also decodes as `call rbx`
+        .map(|length| match has_rex(length) && is_call(length + 1) {
+            true => length + 1,
+            false => length,
+        })
+        .map(|length| return_address - length)
+}
+
+/// The traced threads of the process and the breakpoint in them
+struct Tracer {
+    address: usize,
+    kind: BreakpointKind,
+    original: u8,
+    seized: SeizedThreads,
+}
+
+impl Tracer {
+    fn arm(&self, tid: libc::pid_t) -> io::Result<()> {
+        match self.kind {
+            BreakpointKind::Software => remote::poke(tid, self.address, &[INT3]),
+            BreakpointKind::Hardware => {
+                set_debug_register(tid, 0, self.address as u64)?;
+                set_debug_register(tid, 7, DR7_LOCAL_ENABLE_0)
+            }
+        }
+    }
+
+    fn disarm(&self, tid: libc::pid_t) -> io::Result<()> {
+        match self.kind {
+            BreakpointKind::Software => remote::poke(tid, self.address, &[self.original]),
+            BreakpointKind::Hardware => set_debug_register(tid, 7, 0),
+        }
+    }
+
+    fn install(&mut self) -> io::Result<()> {
+        let any = *self
+            .seized
+            .threads
+            .iter()
+            .next()
+            .ok_or_else(|| io::Error::other("the process has no threads"))?;
+
+        match self.kind {
+            BreakpointKind::Software => {
+                self.original =
+                    remote::peek(any, self.address, 1).ok_or_else(io::Error::last_os_error)?[0];
+                self.arm(any)
+            }
+            BreakpointKind::Hardware => self
+                .seized
+                .threads
+                .iter()
+                .try_for_each(|tid| self.arm(*tid)),
+        }
+    }
+
+    /// Checks whether the stop was caused by the breakpoint and returns the registers at the address if so
+    fn breakpoint_registers(&self, tid: libc::pid_t) -> io::Result<Option<libc::user_regs_struct>> {
+        let mut registers = remote::get_registers(tid)?;
+
+        match self.kind {
+            BreakpointKind::Software => {
+                // The int3 was executed, so the instruction pointer is behind it
+                if registers.rip != self.address as u64 + 1 {
+                    return Ok(None);
+                }
+                registers.rip = self.address as u64;
+            }
+            BreakpointKind::Hardware => {
+                let status = get_debug_register(tid, 6)?;
+                if status & DR6_HIT_0 == 0 || registers.rip != self.address as u64 {
+                    return Ok(None);
+                }
+                set_debug_register(tid, 6, 0)?;
+            }
+        }
+
+        Ok(Some(registers))
+    }
+
+    /// Executes the instruction at the address without triggering the breakpoint.
+    /// Signals and ptrace events, which stop the thread before the step finished, are remembered and the step is
+    /// repeated, until the trap of the single step arrives or the thread exited.
+    fn step_over(
+        &mut self,
+        tid: libc::pid_t,
+        registers: &libc::user_regs_struct,
+    ) -> io::Result<()> {
+        self.disarm(tid)?;
+        remote::set_registers(tid, registers)?;
+        loop {
+            remote::request(libc::PTRACE_SINGLESTEP, tid, 0, 0)?;
+            match self.seized.wait_for_stop(tid)? {
+                remote::Event::Stopped {
+                    signal: libc::SIGTRAP,
+                    event: 0,
+                } => break,
+                remote::Event::Stopped { .. } => {}
+                remote::Event::Exited => break,
+            }
+        }
+        if self.seized.threads.contains(&tid) {
+            self.arm(tid)?;
+        }
+        Ok(())
+    }
+
+    fn capture(
+        &mut self,
+        maps: &CachedMaps,
+        max_hits: usize,
+        deadline: Instant,
+    ) -> io::Result<Vec<BreakpointHit>> {
+        let mut hits = Vec::new();
+
+        while hits.len() < max_hits && Instant::now() < deadline && !self.seized.threads.is_empty()
+        {
+            let Some((tid, event)) = remote::wait(-1, libc::WNOHANG)? else {
+                thread::sleep(Duration::from_millis(1));
+                continue;
+            };
+
+            let remote::Event::Stopped { signal, event } = event else {
+                self.seized.threads.remove(&tid);
+                continue;
+            };
+
+            // Threads created by a traced thread are seized automatically and report a stop first
+            if self.seized.threads.insert(tid) && self.kind == BreakpointKind::Hardware {
+                self.arm(tid)?;
+            }
+
+            if signal == libc::SIGTRAP && event == 0 {
+                if let Some(registers) = self.breakpoint_registers(tid)? {
+                    let return_address = remote::peek(tid, registers.rsp as usize, 8)
+                        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()) as usize);
+                    hits.push(BreakpointHit {
+                        tid,
+                        registers: registers.into(),
+                        return_address,
+                        call_site: return_address.and_then(|address| call_site(maps, address)),
+                    });
+                    self.step_over(tid, &registers)?;
+                } else {
+                    self.seized.signals.insert(tid, signal);
+                }
+            } else if event == 0 {
+                self.seized.signals.insert(tid, signal);
+            }
+
+            if self.seized.threads.contains(&tid) {
+                self.seized.resume(tid)?;
+            }
+        }
+
+        Ok(hits)
+    }
+
+    /// Stops every thread, removes the breakpoint and detaches
+    fn detach(mut self) -> io::Result<()> {
+        let threads = self.seized.threads.iter().copied().collect::<Vec<_>>();
+        for tid in &threads {
+            let _ = remote::request(libc::PTRACE_INTERRUPT, *tid, 0, 0);
+        }
+        for tid in threads {
+            if let Some((_, remote::Event::Stopped { signal, event })) = remote::wait(tid, 0)? {
+                if signal == libc::SIGTRAP && event == 0 {
+                    // A thread, which hit the breakpoint in the meantime, has to execute the instruction again
+                    if let Some(registers) = self.breakpoint_registers(tid)? {
+                        remote::set_registers(tid, &registers)?;
+                    }
+                } else if event == 0 {
+                    self.seized.signals.insert(tid, signal);
+                }
+            } else {
+                self.seized.threads.remove(&tid);
+            }
+        }
+
+        let mut result = Ok(());
+        match self.kind {
+            BreakpointKind::Software => {
+                if let Some(tid) = self.seized.threads.iter().next() {
+                    result = self.disarm(*tid);
+                }
+            }
+            BreakpointKind::Hardware => {
+                for tid in &self.seized.threads {
+                    result = result.and(self.disarm(*tid));
+                }
+            }
+        }
+
+        self.seized.detach();
+
+        result
+    }
+}
+
+/// Sets a breakpoint at the address and records every hit, until max_hits hits were recorded or the timeout elapsed.
+/// The maps are used to find the call sites of the hits. The breakpoint is removed before returning, even on errors.
+pub fn capture(
+    pid: i32,
+    maps: &CachedMaps,
+    address: usize,
+    kind: BreakpointKind,
+    max_hits: usize,
+    timeout: Duration,
+) -> io::Result<Vec<BreakpointHit>> {
+    let deadline = Instant::now() + timeout;
+    let mut tracer = Tracer {
+        address,
+        kind,
+        original: 0,
+        seized: SeizedThreads::seize(pid, libc::PTRACE_O_TRACECLONE)?,
+    };
+
+    let hits = tracer.install().and_then(|_| {
+        let threads = tracer.seized.threads.iter().copied().collect::<Vec<_>>();
+        for tid in threads {
+            tracer.seized.resume(tid)?;
+        }
+        tracer.capture(maps, max_hits, deadline)
+    });
+    let detached = tracer.detach();
+
+    let hits = hits?;
+    detached?;
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use procfs_core::process::{MMPermissions, MMapPath};
+
+    use crate::cached_map::CachedMap;
+
+    use super::*;
+
+    const BASE: usize = 0x1000;
+
+    fn maps(bytes: &[u8]) -> CachedMaps {
+        BTreeSet::from([CachedMap::new(
+            BASE,
+            BASE + bytes.len(),
+            MMPermissions::READ | MMPermissions::EXECUTE,
+            MMapPath::Anonymous,
+            bytes.into(),
+        )])
+    }
+
+    #[test]
+    fn test_call_site() {
+        let maps = maps(&[
+            0xE8, 0x00, 0x00, 0x00, 0x00, // call rel32
+            0xFF, 0xD0, // call rax
+            0x41, 0xFF, 0xD3, // call r11
+            0xFF, 0x15, 0x00, 0x00, 0x00, 0x00, // call [rip]
+            0x48, 0x89, 0xC0, // mov rax, rax
+        ]);
+
+        assert_eq!(call_site(&maps, BASE + 5), Some(BASE));
+        assert_eq!(call_site(&maps, BASE + 7), Some(BASE + 5));
+        assert_eq!(call_site(&maps, BASE + 10), Some(BASE + 7));
+        assert_eq!(call_site(&maps, BASE + 16), Some(BASE + 10));
+        assert_eq!(call_site(&maps, BASE + 19), None);
+        // Return addresses at the start of the map or outside of the snapshot have no call site
+        assert_eq!(call_site(&maps, BASE), None);
+        assert_eq!(call_site(&maps, BASE + 0x100), None);
+    }
+}
@@ -1,12 +1,44 @@
-use procfs::process::{MMPermissions, MMapPath};
+use std::{
+    cell::{Cell, OnceCell},
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
-#[derive(Eq, Debug)]
+use procfs_core::process::{MMPermissions, MMapPath};
+
+use crate::{address_range::AddressRange, map_usage::MapUsage};
+
+/// Where the bytes of a lazily loaded map are read from, usually the mem file of the process
+pub trait MemorySource: Send + Sync {
+    /// Fills the buffer with the memory at the address, returns false if it couldn't be read completely
+    fn read(&self, address: usize, buffer: &mut [u8]) -> bool;
+}
+
+/// Counts the accesses to the bytes of all maps, it orders them by their last access
+static ACCESS_CLOCK: AtomicU64 = AtomicU64::new(0);
+
+/// A snapshot of a mapping. Like all address ranges in this crate it is half-open, `to_address` is the first
+/// address behind the mapping.
+///
+/// The bytes of a lazy map are read from its source, when they are first accessed. If the read fails, because the map
+/// was unmapped in the meantime, the map becomes unreadable and its bytes are empty, see [`CachedMap::is_readable`].
+#[derive(Clone)]
 pub struct CachedMap {
     from_address: usize,
     to_address: usize,
     permissions: MMPermissions,
     name: MMapPath,
-    bytes: Box<[u8]>,
+    bytes: OnceCell<Arc<[u8]>>,
+    source: Option<Arc<dyn MemorySource>>,
+    last_access: Cell<u64>,
+    tls: bool,
+    offset: u64,
+    device: (i32, i32),
+    inode: u64,
+    usage: Option<MapUsage>,
 }
 
 impl CachedMap {
@@ -22,18 +54,77 @@ impl CachedMap {
             to_address,
             permissions,
             name,
-            bytes,
+            bytes: OnceCell::from(Arc::from(bytes)),
+            source: None,
+            last_access: Cell::new(0),
+            tls: false,
+            offset: 0,
+            device: (0, 0),
+            inode: 0,
+            usage: None,
+        }
+    }
+
+    /// Creates a map, whose bytes are read from the source when they are first accessed
+    pub fn lazy(
+        from_address: usize,
+        to_address: usize,
+        permissions: MMPermissions,
+        name: MMapPath,
+        source: Arc<dyn MemorySource>,
+    ) -> Self {
+        Self {
+            bytes: OnceCell::new(),
+            source: Some(source),
+            ..Self::new(from_address, to_address, permissions, name, Box::new([]))
         }
     }
 
+    /// Creates a map without any access permissions, like a guard page. Its bytes are never read from the process,
+    /// so they are empty and the map isn't readable.
+    pub fn no_access(
+        from_address: usize,
+        to_address: usize,
+        permissions: MMPermissions,
+        name: MMapPath,
+    ) -> Self {
+        Self::new(from_address, to_address, permissions, name, Box::new([]))
+    }
+
+    /// Marks the map as containing thread-local storage
+    pub fn with_tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+
+        self
+    }
+
+    /// Sets where the map comes from, the offset into the backing file and the device and inode of that file
+    pub fn with_file(mut self, offset: u64, device: (i32, i32), inode: u64) -> Self {
+        self.offset = offset;
+        self.device = device;
+        self.inode = inode;
+
+        self
+    }
+
+    /// Attaches how the memory of the map is backed, see [`crate::map_usage`]
+    pub fn with_usage(mut self, usage: MapUsage) -> Self {
+        self.usage = Some(usage);
+
+        self
+    }
+
     pub fn get_from_address(&self) -> usize {
         self.from_address
     }
     pub fn get_to_address(&self) -> usize {
         self.to_address
     }
+    pub fn get_range(&self) -> AddressRange {
+        AddressRange::new(self.from_address, self.to_address)
+    }
     pub fn get_size(&self) -> usize {
-        self.to_address - self.from_address
+        self.get_range().len()
     }
     pub fn get_permissions(&self) -> MMPermissions {
         self.permissions
@@ -41,15 +132,110 @@ impl CachedMap {
     pub fn get_name(&self) -> &MMapPath {
         &self.name
     }
+    /// The bytes of the map, they are empty if the map is unreadable
     pub fn get_bytes(&self) -> &[u8] {
-        &self.bytes
+        self.last_access
+            .set(ACCESS_CLOCK.fetch_add(1, Ordering::Relaxed));
+
+        self.bytes.get_or_init(|| {
+            let Some(source) = &self.source else {
+                return Arc::from([]);
+            };
+            let mut bytes = vec![0; self.get_size()];
+            if source.read(self.from_address, &mut bytes) {
+                Arc::from(bytes)
+            } else {
+                Arc::from([])
+            }
+        })
+    }
+    /// Whether the bytes of the map are known. Lazy maps, which couldn't be read completely, aren't readable.
+    /// The bytes of lazy maps are loaded to tell.
+    pub fn is_readable(&self) -> bool {
+        self.get_bytes().len() == self.get_size()
+    }
+    /// Reads the bytes at the offset into the buffer, without loading the bytes of a lazy map.
+    /// Returns false if they can't be read completely.
+    pub fn read_at(&self, offset: usize, buffer: &mut [u8]) -> bool {
+        let Some(end) = offset
+            .checked_add(buffer.len())
+            .filter(|end| *end <= self.get_size())
+        else {
+            return false;
+        };
+
+        match (self.bytes.get(), &self.source) {
+            (Some(bytes), _) => match bytes.get(offset..end) {
+                Some(bytes) => {
+                    buffer.copy_from_slice(bytes);
+                    true
+                }
+                None => false,
+            },
+            (None, Some(source)) => source.read(self.from_address + offset, buffer),
+            (None, None) => false,
+        }
+    }
+    /// Whether the bytes are in memory. Only lazy maps, which weren't accessed yet or were evicted, aren't.
+    pub fn is_loaded(&self) -> bool {
+        self.bytes.get().is_some()
+    }
+    /// Whether the map can be read, written or executed. Maps mapped with PROT_NONE, like guard pages, can't.
+    pub fn is_accessible(&self) -> bool {
+        self.permissions
+            .intersects(MMPermissions::READ | MMPermissions::WRITE | MMPermissions::EXECUTE)
+    }
+    pub fn is_lazy(&self) -> bool {
+        self.source.is_some()
+    }
+    /// Orders the maps by their last access, larger values were accessed more recently
+    pub fn get_last_access(&self) -> u64 {
+        self.last_access.get()
+    }
+    /// A copy of the lazy map without its bytes, they are read again when they are accessed.
+    /// Maps, which aren't lazy, can't be evicted and are copied with their bytes.
+    pub fn evicted(&self) -> Self {
+        let mut map = self.clone();
+        if map.source.is_some() {
+            map.bytes = OnceCell::new();
+        }
+
+        map
+    }
+    pub fn is_tls(&self) -> bool {
+        self.tls
+    }
+    /// The offset of the map into its backing file
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+    /// The major and minor number of the device, which holds the backing file
+    pub fn get_device(&self) -> (i32, i32) {
+        self.device
+    }
+    /// The inode of the backing file, 0 for anonymous maps. For SysV shared memory this is the shmid.
+    pub fn get_inode(&self) -> u64 {
+        self.inode
+    }
+    /// How the memory is backed, only known if smaps was read
+    pub fn get_usage(&self) -> Option<MapUsage> {
+        self.usage
     }
 
     pub fn contains(&self, address: usize) -> bool {
-        self.from_address <= address && address < self.to_address
+        self.get_range().contains(address)
     }
 }
 
+// Maps are ordered by their start address, this allows looking them up by it
+impl std::borrow::Borrow<usize> for CachedMap {
+    fn borrow(&self) -> &usize {
+        &self.from_address
+    }
+}
+
+impl Eq for CachedMap {}
+
 impl std::cmp::PartialEq for CachedMap {
     fn eq(&self, other: &Self) -> bool {
         self.from_address == other.from_address
@@ -58,7 +244,7 @@ impl std::cmp::PartialEq for CachedMap {
 
 impl PartialOrd for CachedMap {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.from_address.cmp(&other.from_address))
+        Some(self.cmp(other))
     }
 }
 
@@ -67,3 +253,22 @@ impl Ord for CachedMap {
         self.from_address.cmp(&other.from_address)
     }
 }
+
+/// Leaves out the bytes
+impl fmt::Debug for CachedMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedMap")
+            .field("from_address", &format_args!("{:#x}", self.from_address))
+            .field("to_address", &format_args!("{:#x}", self.to_address))
+            .field("permissions", &self.permissions)
+            .field("name", &self.name)
+            .field("loaded", &self.is_loaded())
+            .field("lazy", &self.is_lazy())
+            .field("tls", &self.tls)
+            .field("offset", &self.offset)
+            .field("device", &self.device)
+            .field("inode", &self.inode)
+            .field("usage", &self.usage)
+            .finish()
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::BTreeSet;
+use std::{borrow::Cow, collections::BTreeSet};
 
 use bound_stl::UpperBound;
 
@@ -8,6 +8,32 @@ pub type CachedMaps = BTreeSet<CachedMap>;
 
 pub trait FindAddress {
     fn find_map(&self, address: usize) -> Option<&CachedMap>;
+
+    /// Reads length bytes starting at the address. Reads, which leave a map, continue in the map directly behind it,
+    /// so values straddling contiguous maps can be read. Only those reads copy the bytes.
+    /// Reads stop at module boundaries, the map behind has to have the same name and backing file.
+    /// Maps without access permissions can't be read, see [`CachedMap::is_accessible`].
+    fn read_contiguous(&self, address: usize, length: usize) -> Option<Cow<'_, [u8]>> {
+        let map = self.find_map(address).filter(|map| map.is_accessible())?;
+        let offset = address - map.get_from_address();
+        if let Some(bytes) = map.get_bytes().get(offset..offset.checked_add(length)?) {
+            return Some(Cow::Borrowed(bytes));
+        }
+
+        let mut bytes = map.get_bytes().get(offset..)?.to_vec();
+        let mut next = map.get_to_address();
+        let (name, inode) = (map.get_name(), map.get_inode());
+        while bytes.len() < length {
+            let map = self.find_map(next).filter(|next| {
+                next.is_accessible() && next.get_name() == name && next.get_inode() == inode
+            })?;
+            let missing = (length - bytes.len()).min(map.get_size());
+            bytes.extend_from_slice(map.get_bytes().get(..missing)?);
+            next = map.get_to_address();
+        }
+
+        Some(Cow::Owned(bytes))
+    }
 }
 
 impl FindAddress for CachedMaps {
@@ -29,3 +55,91 @@ impl FindAddress for CachedMaps {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use procfs_core::process::{MMPermissions, MMapPath};
+
+    use std::borrow::Cow;
+
+    use crate::cached_map::CachedMap;
+
+    use super::{CachedMaps, FindAddress};
+
+    fn map(from: usize, to: usize) -> CachedMap {
+        CachedMap::new(
+            from,
+            to,
+            MMPermissions::READ,
+            MMapPath::Anonymous,
+            vec![0; to - from].into(),
+        )
+    }
+
+    #[test]
+    fn test_read_contiguous() {
+        let mut maps = CachedMaps::new();
+        maps.insert(map(0x1000, 0x2000));
+        maps.insert(map(0x2000, 0x3000));
+        maps.insert(map(0x4000, 0x5000));
+
+        assert!(matches!(
+            maps.read_contiguous(0x1FF0, 0x10),
+            Some(Cow::Borrowed(_))
+        ));
+        assert!(matches!(
+            maps.read_contiguous(0x1FFC, 8),
+            Some(Cow::Owned(bytes)) if bytes.len() == 8
+        ));
+        assert_eq!(
+            maps.read_contiguous(0x1FFC, 0x1004)
+                .map(|bytes| bytes.len()),
+            Some(0x1004)
+        );
+        // There is a gap behind the second map
+        assert_eq!(maps.read_contiguous(0x2FFC, 8), None);
+        assert_eq!(maps.read_contiguous(0x3000, 1), None);
+
+        // The map behind belongs to another module
+        maps.insert(CachedMap::new(
+            0x5000,
+            0x6000,
+            MMPermissions::READ,
+            MMapPath::Path("/usr/lib/libfoo.so".into()),
+            vec![0; 0x1000].into(),
+        ));
+        assert_eq!(maps.read_contiguous(0x4FFC, 8), None);
+    }
+
+    #[test]
+    fn test_contains_is_half_open() {
+        let map = map(0x1000, 0x2000);
+
+        assert!(!map.contains(0xFFF));
+        assert!(map.contains(0x1000));
+        assert!(map.contains(0x1FFF));
+        assert!(!map.contains(0x2000));
+    }
+
+    #[test]
+    fn test_find_map() {
+        // Two adjacent maps followed by a gap and a third one
+        let maps = CachedMaps::from([
+            map(0x1000, 0x2000),
+            map(0x2000, 0x3000),
+            map(0x4000, 0x5000),
+        ]);
+        let start_of = |address| maps.find_map(address).map(|map| map.get_from_address());
+
+        assert_eq!(start_of(0xFFF), None);
+        assert_eq!(start_of(0x1000), Some(0x1000));
+        assert_eq!(start_of(0x1FFF), Some(0x1000));
+        assert_eq!(start_of(0x2000), Some(0x2000));
+        assert_eq!(start_of(0x2FFF), Some(0x2000));
+        assert_eq!(start_of(0x3000), None);
+        assert_eq!(start_of(0x3FFF), None);
+        assert_eq!(start_of(0x4000), Some(0x4000));
+        assert_eq!(start_of(0x5000), None);
+        assert_eq!(start_of(usize::MAX), None);
+    }
+}
@@ -0,0 +1,419 @@
+//! Records the progress of long scans on disk, so an interrupted scan can be resumed instead of started over,
+//! see [`crate::BcrlFactory::signature_resumable`].
+//!
+//! The progress is recorded per map, maps larger than the chunk size are recorded in chunks of it, see
+//! [`ScanCheckpoint::with_chunk_size`]. A chunk, which was interrupted, is scanned again on resume. Maps, that were
+//! unmapped or moved since, are scanned again as well. A scan is identified by its pattern, its constraints and the
+//! pid of the process, so a single file can hold the progress of scans of many processes. Closures passed as
+//! predicates can't be told apart, so constraints containing them can't be used for resumable scans.
+//!
+//! Finished scans are kept, resuming them returns their hits without scanning again. Clear the checkpoint or
+//! delete its file once the results are no longer needed.
+//!
+//! Progress is appended to the file and synced to disk, one block per completed chunk or dropped map:
+//! ```text
+//! scan <hash>
+//! map <map from> <map to> <from> <to>
+//! hit <address>
+//! drop <map from> <map to>
+//! end
+//! ```
+//! A block, which was cut off by an interruption, is ignored and removed when the file is opened again.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// The default size of the chunks, in which large maps are recorded
+const CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+/// A scanned part of a map, together with the address range of the map
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct CompletedRange {
+    map: (usize, usize),
+    range: (usize, usize),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct ScanProgress {
+    /// The completed parts of maps
+    ranges: Vec<CompletedRange>,
+    /// The hits of the completed parts in scan order
+    hits: Vec<usize>,
+}
+
+impl ScanProgress {
+    /// Forgets the parts of the map and the hits inside of them
+    fn drop_map(&mut self, map: (usize, usize)) {
+        let (dropped, kept): (Vec<CompletedRange>, Vec<_>) = self
+            .ranges
+            .iter()
+            .partition(|completed| completed.map == map);
+        self.hits.retain(|hit| {
+            !dropped
+                .iter()
+                .any(|completed| (completed.range.0..completed.range.1).contains(hit))
+        });
+        self.ranges = kept;
+    }
+}
+
+/// A block of the file, the dropped maps are forgotten before the progress is added
+struct Block {
+    key: u64,
+    dropped: Vec<(usize, usize)>,
+    progress: ScanProgress,
+}
+
+#[derive(Clone, Debug)]
+pub struct ScanCheckpoint {
+    path: PathBuf,
+    scans: HashMap<u64, ScanProgress>,
+    chunk_size: usize,
+}
+
+/// FNV-1a, unlike the hasher of std the hash stays the same across runs and compiler versions
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub(crate) fn scan_key(pid: Option<i32>, description: &str) -> u64 {
+    let pid = pid.map_or_else(|| "-".to_string(), |pid| pid.to_string());
+
+    fnv1a(&format!("{pid} {description}"))
+}
+
+fn invalid_data(line: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("malformed scan checkpoint at line {line}"),
+    )
+}
+
+/// Parses the blocks of the file, returns the progress and whether a block was cut off at the end
+fn parse(text: &str) -> io::Result<(HashMap<u64, ScanProgress>, bool)> {
+    // Everything behind the last complete block was cut off while it was appended.
+    // No other keyword or hexadecimal number ends in "end".
+    let complete = text.rfind("end\n").map_or(0, |index| index + "end\n".len());
+
+    let mut scans = HashMap::<u64, ScanProgress>::new();
+    let mut current: Option<Block> = None;
+
+    for (index, line) in text[..complete].lines().enumerate() {
+        let line_number = index + 1;
+        let fields = line.split(' ').collect::<Vec<_>>();
+        let address =
+            |text: &str| usize::from_str_radix(text, 16).map_err(|_| invalid_data(line_number));
+
+        match (fields.as_slice(), &mut current) {
+            (["scan", key], None) => {
+                let key = u64::from_str_radix(key, 16).map_err(|_| invalid_data(line_number))?;
+                current = Some(Block {
+                    key,
+                    dropped: Vec::new(),
+                    progress: ScanProgress::default(),
+                });
+            }
+            (["map", from, to], Some(Block { progress, .. })) => {
+                let map = (address(from)?, address(to)?);
+                progress.ranges.push(CompletedRange { map, range: map });
+            }
+            (["map", map_from, map_to, from, to], Some(Block { progress, .. })) => {
+                progress.ranges.push(CompletedRange {
+                    map: (address(map_from)?, address(map_to)?),
+                    range: (address(from)?, address(to)?),
+                });
+            }
+            (["hit", hit], Some(Block { progress, .. })) => progress.hits.push(address(hit)?),
+            (["drop", from, to], Some(Block { dropped, .. })) => {
+                dropped.push((address(from)?, address(to)?));
+            }
+            (["end"], Some(_)) => {
+                let block = current.take().unwrap();
+                let scan = scans.entry(block.key).or_default();
+                for map in block.dropped {
+                    scan.drop_map(map);
+                }
+                scan.ranges.extend(block.progress.ranges);
+                scan.hits.extend(block.progress.hits);
+            }
+            ([""], None) => {}
+            _ => return Err(invalid_data(line_number)),
+        }
+    }
+
+    Ok((scans, complete < text.len()))
+}
+
+fn write_block(text: &mut String, key: u64, progress: &ScanProgress, dropped: &[(usize, usize)]) {
+    text.push_str(&format!("scan {key:x}\n"));
+    for (from, to) in dropped {
+        text.push_str(&format!("drop {from:x} {to:x}\n"));
+    }
+    for completed in &progress.ranges {
+        let (map_from, map_to) = completed.map;
+        let (from, to) = completed.range;
+        text.push_str(&format!("map {map_from:x} {map_to:x} {from:x} {to:x}\n"));
+    }
+    for hit in &progress.hits {
+        text.push_str(&format!("hit {hit:x}\n"));
+    }
+    text.push_str("end\n");
+}
+
+/// Syncs the directory of the file, so that a newly created or renamed file survives a crash
+fn sync_directory(path: &Path) -> io::Result<()> {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+
+    File::open(directory)?.sync_all()
+}
+
+impl ScanCheckpoint {
+    /// Loads the checkpoint from the file. A missing file results in an empty checkpoint.
+    /// If the last block was cut off by an interruption, the file is rewritten without it.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (scans, truncated) = match fs::read_to_string(&path) {
+            Ok(text) => parse(&text)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (HashMap::new(), false),
+            Err(error) => return Err(error),
+        };
+
+        let checkpoint = Self {
+            path,
+            scans,
+            chunk_size: CHUNK_SIZE,
+        };
+        if truncated {
+            checkpoint.save()?;
+        }
+
+        Ok(checkpoint)
+    }
+
+    /// Records the progress of maps larger than the size (in bytes) in chunks of it, so an interrupted scan
+    /// doesn't start such a map over. Defaults to 64 MiB. Changing it drops the progress of partially scanned maps.
+    /// Panics if the size is zero.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0, "the chunk size must not be zero");
+        self.chunk_size = chunk_size;
+
+        self
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+    pub fn get_chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// The amount of recorded scans, finished or not
+    pub fn len(&self) -> usize {
+        self.scans.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.scans.is_empty()
+    }
+
+    /// Forgets the progress of all scans, the file is only changed by the next save
+    pub fn clear(&mut self) {
+        self.scans.clear();
+    }
+
+    /// Writes the whole checkpoint back to its file, which also compacts the appended blocks.
+    /// The file is replaced atomically, so an interruption while saving doesn't lose the previous progress.
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for (key, progress) in &self.scans {
+            write_block(&mut text, *key, progress, &[]);
+        }
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        sync_directory(&self.path)
+    }
+
+    /// Appends a block to the file and syncs it
+    fn append(
+        &self,
+        key: u64,
+        progress: &ScanProgress,
+        dropped: &[(usize, usize)],
+    ) -> io::Result<()> {
+        let mut text = String::new();
+        write_block(&mut text, key, progress, dropped);
+
+        let created = !self.path.exists();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_data()?;
+        if created {
+            sync_directory(&self.path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts or resumes a scan. The progress of maps, for which is_current returns false, is dropped.
+    pub(crate) fn resume(
+        &mut self,
+        key: u64,
+        is_current: impl Fn(usize, usize) -> bool,
+    ) -> io::Result<()> {
+        let progress = self.scans.entry(key).or_default();
+
+        let mut stale = progress
+            .ranges
+            .iter()
+            .map(|completed| completed.map)
+            .filter(|(from, to)| !is_current(*from, *to))
+            .collect::<Vec<_>>();
+        stale.dedup();
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        for map in &stale {
+            progress.drop_map(*map);
+        }
+        self.append(key, &ScanProgress::default(), &stale)
+    }
+
+    /// Splits the map into the chunks, whose progress is recorded separately, in ascending order
+    pub(crate) fn chunks(&self, from: usize, to: usize) -> Vec<(usize, usize)> {
+        (from..to)
+            .step_by(self.chunk_size)
+            .map(|start| (start, to.min(start.saturating_add(self.chunk_size))))
+            .collect()
+    }
+
+    pub(crate) fn is_completed(
+        &self,
+        key: u64,
+        map: (usize, usize),
+        range: (usize, usize),
+    ) -> bool {
+        self.scans
+            .get(&key)
+            .is_some_and(|progress| progress.ranges.contains(&CompletedRange { map, range }))
+    }
+
+    pub(crate) fn hits(&self, key: u64) -> &[usize] {
+        self.scans
+            .get(&key)
+            .map_or(&[], |progress| progress.hits.as_slice())
+    }
+
+    /// Records a completed chunk of a map and appends it to the file
+    pub(crate) fn complete(
+        &mut self,
+        key: u64,
+        map: (usize, usize),
+        range: (usize, usize),
+        hits: Vec<usize>,
+    ) -> io::Result<()> {
+        let completed = ScanProgress {
+            ranges: vec![CompletedRange { map, range }],
+            hits,
+        };
+        self.append(key, &completed, &[])?;
+
+        let progress = self.scans.entry(key).or_default();
+        progress.ranges.extend(completed.ranges);
+        progress.hits.extend(completed.hits);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("bcrl_checkpoint_{name}_{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn appends_and_reloads_progress() {
+        let path = temporary_path("append");
+        let mut checkpoint = ScanCheckpoint::open(&path).unwrap();
+        checkpoint
+            .complete(1, (0x1000, 0x3000), (0x1000, 0x2000), vec![0x1010])
+            .unwrap();
+        checkpoint
+            .complete(1, (0x1000, 0x3000), (0x2000, 0x3000), vec![0x2020])
+            .unwrap();
+        checkpoint
+            .complete(2, (0x4000, 0x5000), (0x4000, 0x5000), vec![])
+            .unwrap();
+
+        let reopened = ScanCheckpoint::open(&path).unwrap();
+        assert_eq!(reopened.hits(1), &[0x1010, 0x2020]);
+        assert!(reopened.is_completed(1, (0x1000, 0x3000), (0x2000, 0x3000)));
+        assert!(reopened.is_completed(2, (0x4000, 0x5000), (0x4000, 0x5000)));
+        assert_eq!(reopened.len(), 2);
+
+        // Dropping a map is appended as well
+        let mut reopened = reopened;
+        reopened.resume(1, |from, _| from != 0x1000).unwrap();
+        let reopened = ScanCheckpoint::open(&path).unwrap();
+        assert!(reopened.hits(1).is_empty());
+        assert!(!reopened.is_completed(1, (0x1000, 0x3000), (0x1000, 0x2000)));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ignores_cut_off_block() {
+        let path = temporary_path("cut_off");
+        fs::write(
+            &path,
+            "scan 1\nmap 1000 2000\nhit 1010\nend\nscan 1\nmap 2000 3000 2000 3000\nhi",
+        )
+        .unwrap();
+
+        let checkpoint = ScanCheckpoint::open(&path).unwrap();
+        assert_eq!(checkpoint.hits(1), &[0x1010]);
+        assert!(checkpoint.is_completed(1, (0x1000, 0x2000), (0x1000, 0x2000)));
+        assert!(!checkpoint.is_completed(1, (0x2000, 0x3000), (0x2000, 0x3000)));
+        // The cut off block was removed, so later blocks can be appended
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "scan 1\nmap 1000 2000 1000 2000\nhit 1010\nend\n"
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn splits_maps_into_chunks() {
+        let checkpoint = ScanCheckpoint::open(temporary_path("chunks"))
+            .unwrap()
+            .with_chunk_size(0x1000);
+        assert_eq!(
+            checkpoint.chunks(0x1000, 0x3800),
+            vec![(0x1000, 0x2000), (0x2000, 0x3000), (0x3000, 0x3800)]
+        );
+        assert_eq!(checkpoint.chunks(0x1000, 0x1800), vec![(0x1000, 0x1800)]);
+    }
+}
@@ -0,0 +1,56 @@
+//! Support for processes inside of containers. Paths in the maps of such processes are relative to their mount namespace,
+//! so their backing files are opened through `/proc/<pid>/root`. Reports keep the container-relative paths.
+
+use std::path::{Path, PathBuf};
+#[cfg(feature = "linux")]
+use std::{fs, os::unix::fs::MetadataExt};
+
+#[cfg(feature = "linux")]
+use procfs::{
+    process::{all_processes, Process},
+    ProcError,
+};
+
+#[cfg(feature = "linux")]
+fn same_namespace(kind: &str, pid: i32, other: &str) -> Option<bool> {
+    let namespace = fs::metadata(format!("/proc/{pid}/ns/{kind}")).ok()?;
+    let other = fs::metadata(format!("/proc/{other}/ns/{kind}")).ok()?;
+
+    Some(namespace.dev() == other.dev() && namespace.ino() == other.ino())
+}
+
+/// Returns the root directory of the process, if it lives in another mount namespace than the current process
+#[cfg(feature = "linux")]
+pub fn root_of(pid: i32) -> Option<PathBuf> {
+    (!same_namespace("mnt", pid, "self")?).then(|| PathBuf::from(format!("/proc/{pid}/root")))
+}
+
+/// Translates a path as seen by a process with the root directory into a path, which can be opened by the current process
+pub fn host_path(root: Option<&Path>, path: &Path) -> PathBuf {
+    match root {
+        Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+        None => path.to_path_buf(),
+    }
+}
+
+/// The pid of the process inside of its innermost pid namespace, e.g. the pid a container reports for it
+#[cfg(feature = "linux")]
+pub fn namespace_pid(process: &Process) -> Result<i32, ProcError> {
+    Ok(process
+        .status()?
+        .nspid
+        .and_then(|pids| pids.last().copied())
+        .unwrap_or(process.pid()))
+}
+
+/// Finds the process, which has the pid inside of the pid namespace of the container process.
+/// The returned process uses the host pid, so it can be attached to.
+#[cfg(feature = "linux")]
+pub fn find_host_process(container: &Process, pid: i32) -> Result<Option<Process>, ProcError> {
+    let container = container.pid().to_string();
+
+    Ok(all_processes()?.filter_map(Result::ok).find(|process| {
+        same_namespace("pid", process.pid(), &container) == Some(true)
+            && namespace_pid(process).is_ok_and(|namespace_pid| namespace_pid == pid)
+    }))
+}
@@ -0,0 +1,245 @@
+//! Records which bytes scans have examined, so signature authors can verify that their constraints cover the code
+//! they think they do.
+//!
+//! A recorder is attached to constraints with [`crate::SearchConstraints::recording_coverage`], every scan using
+//! them records the ranges it was given. Scans, that stop early, e.g. because of the hit limit, still record their
+//! whole range. Clones of a recorder share the recorded ranges, so one recorder can be attached to many constraints.
+
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use procfs_core::process::MMapPath;
+
+use crate::{address_range::AddressRange, cached_maps::CachedMaps};
+
+/// Sets the bits of the offsets, whole bytes are filled at once
+fn set_bits(bitmap: &mut [u8], offsets: Range<usize>) {
+    let mut offset = offsets.start;
+    while offset < offsets.end {
+        if offset.is_multiple_of(8) && offset + 8 <= offsets.end {
+            let end = offsets.end / 8;
+            bitmap[offset / 8..end].fill(0xFF);
+            offset = end * 8;
+        } else {
+            bitmap[offset / 8] |= 1 << (offset % 8);
+            offset += 1;
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CoverageRecorder {
+    /// Sorted and merged
+    ranges: Rc<RefCell<Vec<AddressRange>>>,
+}
+
+impl CoverageRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(&self, range: AddressRange) {
+        if range.is_empty() {
+            return;
+        }
+
+        let mut ranges = self.ranges.borrow_mut();
+        // Ranges, that overlap or touch the new one, are merged into it
+        let start = ranges.partition_point(|other| other.get_to() < range.get_from());
+        let end = ranges.partition_point(|other| other.get_from() <= range.get_to());
+        let merged = ranges[start..end].iter().fold(range, |merged, other| {
+            AddressRange::new(
+                merged.get_from().min(other.get_from()),
+                merged.get_to().max(other.get_to()),
+            )
+        });
+        ranges.splice(start..end, [merged]);
+    }
+
+    /// The examined ranges, sorted by address and merged
+    pub fn get_ranges(&self) -> Vec<AddressRange> {
+        self.ranges.borrow().clone()
+    }
+
+    pub fn covered_bytes(&self) -> usize {
+        self.ranges.borrow().iter().map(AddressRange::len).sum()
+    }
+
+    pub fn is_covered(&self, address: usize) -> bool {
+        let ranges = self.ranges.borrow();
+        let index = ranges.partition_point(|range| range.get_to() <= address);
+        ranges
+            .get(index)
+            .is_some_and(|range| range.contains(address))
+    }
+
+    pub fn clear(&self) {
+        self.ranges.borrow_mut().clear();
+    }
+
+    /// The coverage of the module, which spans from its first to its last map
+    pub fn module(&self, maps: &CachedMaps, module: &MMapPath) -> Option<ModuleCoverage> {
+        let mut module_maps = maps.iter().filter(|map| map.get_name() == module);
+        let first = module_maps.next()?;
+        let range = AddressRange::new(
+            first.get_from_address(),
+            module_maps.last().unwrap_or(first).get_to_address(),
+        );
+
+        let mut bitmap = vec![0u8; range.len().div_ceil(8)];
+        for covered in self.ranges.borrow().iter() {
+            let covered = covered.intersect(&range);
+            if !covered.is_empty() {
+                set_bits(&mut bitmap, covered.offsets_from(range.get_from()));
+            }
+        }
+
+        Some(ModuleCoverage {
+            module: module.clone(),
+            range,
+            bitmap,
+        })
+    }
+
+    /// The coverage of every file-backed module, that was at least partially examined, in address order
+    pub fn modules(&self, maps: &CachedMaps) -> Vec<ModuleCoverage> {
+        let mut modules = Vec::<&MMapPath>::new();
+        for map in maps.iter() {
+            let name = map.get_name();
+            if matches!(name, MMapPath::Path(_)) && !modules.contains(&name) {
+                modules.push(name);
+            }
+        }
+
+        modules
+            .into_iter()
+            .filter_map(|module| self.module(maps, module))
+            .filter(|coverage| coverage.covered_bytes() > 0)
+            .collect()
+    }
+}
+
+/// A bitmap with one bit per byte of a module, which is set if a scan examined the byte
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleCoverage {
+    module: MMapPath,
+    range: AddressRange,
+    bitmap: Vec<u8>,
+}
+
+impl ModuleCoverage {
+    pub fn get_module(&self) -> &MMapPath {
+        &self.module
+    }
+    /// The addresses from the start of the first map of the module to the end of its last one
+    pub fn get_range(&self) -> AddressRange {
+        self.range
+    }
+    /// Bit n, counted from the least significant bit of the first byte, belongs to the nth byte of the module
+    pub fn get_bitmap(&self) -> &[u8] {
+        &self.bitmap
+    }
+
+    pub fn is_covered(&self, address: usize) -> bool {
+        self.range.contains(address) && {
+            let offset = address - self.range.get_from();
+            self.bitmap[offset / 8] & (1 << (offset % 8)) != 0
+        }
+    }
+
+    pub fn covered_bytes(&self) -> usize {
+        self.bitmap
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum()
+    }
+
+    /// The share of the bytes of the module, which were examined, between 0 and 1
+    pub fn ratio(&self) -> f64 {
+        if self.range.is_empty() {
+            return 0.0;
+        }
+        self.covered_bytes() as f64 / self.range.len() as f64
+    }
+
+    /// The ranges of the module, which were not examined, in address order
+    pub fn uncovered_ranges(&self) -> Vec<AddressRange> {
+        let mut ranges = Vec::new();
+        let mut start = None;
+
+        for offset in 0..self.range.len() {
+            let address = self.range.get_from() + offset;
+            match (self.is_covered(address), start) {
+                (false, None) => start = Some(address),
+                (true, Some(from)) => {
+                    ranges.push(AddressRange::new(from, address));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(from) = start {
+            ranges.push(AddressRange::new(from, self.range.get_to()));
+        }
+
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use procfs_core::process::{MMPermissions, MMapPath};
+
+    use crate::{
+        address_range::AddressRange, cached_map::CachedMap, cached_maps::CachedMaps,
+        search_constraints::SearchConstraints,
+    };
+
+    use super::CoverageRecorder;
+
+    fn map(from: usize, to: usize) -> CachedMap {
+        CachedMap::new(
+            from,
+            to,
+            MMPermissions::READ,
+            MMapPath::Path("/usr/lib/libfoo.so".into()),
+            vec![0; to - from].into(),
+        )
+    }
+
+    #[test]
+    fn test_module_coverage() {
+        let mut maps = CachedMaps::new();
+        maps.insert(map(0x1000, 0x2000));
+        maps.insert(map(0x2000, 0x3000));
+
+        let recorder = CoverageRecorder::new();
+        let constraints = SearchConstraints::everything().recording_coverage(&recorder);
+        for map in maps.iter() {
+            constraints.clone().from(0x1804).to(0x2100).regions(map);
+        }
+        constraints
+            .clone()
+            .from(0x2100)
+            .to(0x2203)
+            .regions(&map(0x2000, 0x3000));
+
+        assert_eq!(
+            recorder.get_ranges(),
+            vec![AddressRange::new(0x1804, 0x2203)]
+        );
+        assert!(recorder.is_covered(0x1804) && !recorder.is_covered(0x2203));
+
+        let coverage = recorder.modules(&maps);
+        assert_eq!(coverage.len(), 1);
+        assert_eq!(coverage[0].get_range(), AddressRange::new(0x1000, 0x3000));
+        assert_eq!(coverage[0].covered_bytes(), 0x2203 - 0x1804);
+        assert_eq!(coverage[0].get_bitmap()[0x804 / 8], 0b1111_0000);
+        assert_eq!(
+            coverage[0].uncovered_ranges(),
+            vec![
+                AddressRange::new(0x1000, 0x1804),
+                AddressRange::new(0x2203, 0x3000)
+            ]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! Helpers for decoding instructions of the snapshot with iced-x86.
+
+use iced_x86::{Decoder, DecoderOptions, Formatter, Instruction, NasmFormatter};
+
+/// The bitness of the code, that is expected in the scanned processes
+pub const BITNESS: u32 = if cfg!(target_pointer_width = "64") {
+    64
+} else {
+    32
+};
+
+/// Decodes up to count instructions. Decoding stops early at the end of the bytes or an invalid instruction.
+pub fn decode(bytes: &[u8], address: usize, count: usize) -> Vec<Instruction> {
+    let mut decoder = Decoder::with_ip(BITNESS, bytes, address as u64, DecoderOptions::NONE);
+
+    let mut instructions = Vec::new();
+    while instructions.len() < count && decoder.can_decode() {
+        let instruction = decoder.decode();
+        if instruction.is_invalid() {
+            break;
+        }
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+/// Formats the instructions with one `<address>  <instruction>` line each
+pub fn format_listing(instructions: &[Instruction]) -> String {
+    let mut formatter = NasmFormatter::new();
+    let mut listing = String::new();
+
+    for instruction in instructions {
+        let mut text = String::new();
+        formatter.format(instruction, &mut text);
+        listing.push_str(&format!("{:#x}  {text}\n", instruction.ip()));
+    }
+
+    listing
+}
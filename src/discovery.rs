@@ -0,0 +1,40 @@
+//! Finds processes to attach to, the results can be passed to [`crate::BcrlFactory::from_process`].
+
+use procfs::{
+    process::{all_processes, Process},
+    ProcError,
+};
+pub use regex::Regex;
+
+fn find_processes(predicate: impl Fn(&Process) -> bool) -> Result<Vec<Process>, ProcError> {
+    Ok(all_processes()?
+        .filter_map(Result::ok)
+        .filter(|process| predicate(process))
+        .collect())
+}
+
+/// Returns true if the name (comm) or the executable file name of the process is the given name.
+/// The kernel truncates names to 15 characters, so longer names only match the executable.
+pub fn has_name(process: &Process, name: &str) -> bool {
+    process.stat().is_ok_and(|stat| stat.comm == name)
+        || process
+            .exe()
+            .is_ok_and(|exe| exe.file_name().is_some_and(|file_name| file_name == name))
+}
+
+/// Returns true if the command line of the process, with the arguments separated by spaces, matches the regex
+pub fn has_cmdline_matching(process: &Process, regex: &Regex) -> bool {
+    process
+        .cmdline()
+        .is_ok_and(|cmdline| !cmdline.is_empty() && regex.is_match(&cmdline.join(" ")))
+}
+
+/// Finds all processes with the name, see [`has_name`]. Processes, which vanish or can't be read, are skipped.
+pub fn find_processes_by_name(name: &str) -> Result<Vec<Process>, ProcError> {
+    find_processes(|process| has_name(process, name))
+}
+
+/// Finds all processes, whose command line matches the regex, see [`has_cmdline_matching`]
+pub fn find_processes_by_cmdline_regex(regex: &Regex) -> Result<Vec<Process>, ProcError> {
+    find_processes(|process| has_cmdline_matching(process, regex))
+}
@@ -0,0 +1,506 @@
+//! A minimal ELF parser, which only reads the structures BCRL needs.
+//! Both classes (32/64-bit) and both byte orders are supported.
+
+use alloc::{string::String, vec::Vec};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_NOTE: u32 = 4;
+pub const PT_TLS: u32 = 7;
+pub const PT_GNU_EH_FRAME: u32 = 0x6474_E550;
+
+pub const SHT_RELA: u32 = 4;
+pub const SHT_REL: u32 = 9;
+pub const SHT_DYNSYM: u32 = 11;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElfClass {
+    Elf32,
+    Elf64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElfEndianness {
+    Little,
+    Big,
+}
+
+/// Reads integers of the file's class and byte order
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    class: ElfClass,
+    endianness: ElfEndianness,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8], class: ElfClass, endianness: ElfEndianness) -> Self {
+        Self {
+            bytes,
+            class,
+            endianness,
+        }
+    }
+
+    pub(crate) fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.bytes.get(offset..offset.checked_add(2)?)?;
+        Some(match self.endianness {
+            ElfEndianness::Little => LittleEndian::read_u16(bytes),
+            ElfEndianness::Big => BigEndian::read_u16(bytes),
+        })
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.bytes.get(offset..offset.checked_add(4)?)?;
+        Some(match self.endianness {
+            ElfEndianness::Little => LittleEndian::read_u32(bytes),
+            ElfEndianness::Big => BigEndian::read_u32(bytes),
+        })
+    }
+
+    pub(crate) fn u64(&self, offset: usize) -> Option<u64> {
+        let bytes = self.bytes.get(offset..offset.checked_add(8)?)?;
+        Some(match self.endianness {
+            ElfEndianness::Little => LittleEndian::read_u64(bytes),
+            ElfEndianness::Big => BigEndian::read_u64(bytes),
+        })
+    }
+
+    /// Reads a word of the file's class (Elf32_Addr/Elf64_Addr, Elf32_Off/Elf64_Off, ...)
+    pub(crate) fn word(&self, offset: usize) -> Option<u64> {
+        match self.class {
+            ElfClass::Elf32 => self.u32(offset).map(u64::from),
+            ElfClass::Elf64 => self.u64(offset),
+        }
+    }
+
+    pub(crate) fn word_size(&self) -> usize {
+        match self.class {
+            ElfClass::Elf32 => 4,
+            ElfClass::Elf64 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ElfHeader {
+    class: ElfClass,
+    endianness: ElfEndianness,
+    elf_type: u16,
+    machine: u16,
+    entry: u64,
+    program_header_offset: u64,
+    section_header_offset: u64,
+    program_header_size: u16,
+    program_header_count: u16,
+    section_header_size: u16,
+    section_header_count: u16,
+    section_name_index: u16,
+}
+
+impl ElfHeader {
+    /// Parses the header at the start of the bytes
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if !bytes.starts_with(ELF_MAGIC) {
+            return None;
+        }
+
+        let class = match bytes.get(4)? {
+            1 => ElfClass::Elf32,
+            2 => ElfClass::Elf64,
+            _ => return None,
+        };
+        let endianness = match bytes.get(5)? {
+            1 => ElfEndianness::Little,
+            2 => ElfEndianness::Big,
+            _ => return None,
+        };
+
+        let reader = Reader::new(bytes, class, endianness);
+        let word = reader.word_size();
+
+        // e_entry, e_phoff and e_shoff are words, everything around them has a fixed size
+        let entry_offset = 24;
+        let flags_offset = entry_offset + 3 * word + 4;
+
+        Some(Self {
+            class,
+            endianness,
+            elf_type: reader.u16(16)?,
+            machine: reader.u16(18)?,
+            entry: reader.word(entry_offset)?,
+            program_header_offset: reader.word(entry_offset + word)?,
+            section_header_offset: reader.word(entry_offset + 2 * word)?,
+            program_header_size: reader.u16(flags_offset + 2)?,
+            program_header_count: reader.u16(flags_offset + 4)?,
+            section_header_size: reader.u16(flags_offset + 6)?,
+            section_header_count: reader.u16(flags_offset + 8)?,
+            section_name_index: reader.u16(flags_offset + 10)?,
+        })
+    }
+
+    pub(crate) fn reader<'a>(&self, bytes: &'a [u8]) -> Reader<'a> {
+        Reader::new(bytes, self.class, self.endianness)
+    }
+
+    pub fn get_class(&self) -> ElfClass {
+        self.class
+    }
+    pub fn get_endianness(&self) -> ElfEndianness {
+        self.endianness
+    }
+    /// ET_EXEC (2) for executables, ET_DYN (3) for shared objects and position independent executables
+    pub fn get_type(&self) -> u16 {
+        self.elf_type
+    }
+    pub fn get_machine(&self) -> u16 {
+        self.machine
+    }
+    pub fn get_entry(&self) -> u64 {
+        self.entry
+    }
+    pub fn get_program_header_offset(&self) -> u64 {
+        self.program_header_offset
+    }
+    pub fn get_program_header_size(&self) -> u16 {
+        self.program_header_size
+    }
+    pub fn get_program_header_count(&self) -> u16 {
+        self.program_header_count
+    }
+    pub fn get_section_header_offset(&self) -> u64 {
+        self.section_header_offset
+    }
+    pub fn get_section_header_size(&self) -> u16 {
+        self.section_header_size
+    }
+    pub fn get_section_header_count(&self) -> u16 {
+        self.section_header_count
+    }
+    /// The index of the section containing the section names
+    pub fn get_section_name_index(&self) -> u16 {
+        self.section_name_index
+    }
+
+    /// Parses the program headers. The bytes have to start at the ELF header.
+    pub fn program_headers(&self, bytes: &[u8]) -> Vec<ProgramHeader> {
+        let reader = self.reader(bytes);
+
+        (0..self.program_header_count as usize)
+            .map_while(|index| {
+                let offset =
+                    self.program_header_offset as usize + index * self.program_header_size as usize;
+                ProgramHeader::parse(&reader, offset)
+            })
+            .collect()
+    }
+
+    /// Parses the section headers. The bytes have to start at the section header table.
+    /// Section headers are usually not loaded into memory, so they have to be read from the file.
+    pub fn section_headers(&self, table: &[u8]) -> Vec<SectionHeader> {
+        let reader = self.reader(table);
+
+        (0..self.section_header_count as usize)
+            .map_while(|index| {
+                SectionHeader::parse(&reader, index * self.section_header_size as usize)
+            })
+            .collect()
+    }
+
+    /// Parses the defined symbols of a symbol table (e.g. `.dynsym`), the names are looked up in its string table
+    pub fn symbols(&self, table: &[u8], string_table: &[u8]) -> Vec<Symbol> {
+        let reader = self.reader(table);
+        let size = match self.class {
+            ElfClass::Elf32 => 16,
+            ElfClass::Elf64 => 24,
+        };
+
+        (0..table.len() / size)
+            .filter_map(|index| Symbol::parse(&reader, index * size, string_table))
+            .collect()
+    }
+
+    /// Looks up the name of the symbol at the index of a symbol table, unlike [`Self::symbols`] undefined symbols are
+    /// included, since relocations refer to imports by their index
+    pub fn symbol_name(&self, table: &[u8], string_table: &[u8], index: usize) -> Option<String> {
+        let size = match self.class {
+            ElfClass::Elf32 => 16,
+            ElfClass::Elf64 => 24,
+        };
+        let name = self.reader(table).u32(index * size)?;
+
+        Some(string_at(string_table, name as usize))
+    }
+
+    /// Parses a relocation table, with_addend has to be set for SHT_RELA tables
+    pub fn relocations(&self, table: &[u8], with_addend: bool) -> Vec<Relocation> {
+        let reader = self.reader(table);
+        let size = match (self.class, with_addend) {
+            (ElfClass::Elf32, false) => 8,
+            (ElfClass::Elf32, true) => 12,
+            (ElfClass::Elf64, false) => 16,
+            (ElfClass::Elf64, true) => 24,
+        };
+
+        (0..table.len() / size)
+            .map_while(|index| Relocation::parse(&reader, index * size))
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Relocation {
+    offset: u64,
+    symbol: u32,
+    kind: u32,
+}
+
+impl Relocation {
+    fn parse(reader: &Reader, offset: usize) -> Option<Self> {
+        let (location, symbol, kind) = match reader.class {
+            ElfClass::Elf32 => {
+                let info = reader.u32(offset + 4)?;
+                (reader.u32(offset)? as u64, info >> 8, info & 0xff)
+            }
+            ElfClass::Elf64 => {
+                let info = reader.u64(offset + 8)?;
+                (reader.u64(offset)?, (info >> 32) as u32, info as u32)
+            }
+        };
+
+        Some(Self {
+            offset: location,
+            symbol,
+            kind,
+        })
+    }
+
+    /// The virtual address, which is written by the dynamic linker
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+    /// The index of the symbol in the dynamic symbol table, 0 if the relocation doesn't refer to a symbol
+    pub fn get_symbol(&self) -> u32 {
+        self.symbol
+    }
+    /// The machine specific relocation type, e.g. R_X86_64_JUMP_SLOT
+    pub fn get_kind(&self) -> u32 {
+        self.kind
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    name: String,
+    value: u64,
+    size: u64,
+}
+
+impl Symbol {
+    fn parse(reader: &Reader, offset: usize, string_table: &[u8]) -> Option<Self> {
+        let (value, size, section_index) = match reader.class {
+            ElfClass::Elf32 => (
+                reader.u32(offset + 4)? as u64,
+                reader.u32(offset + 8)? as u64,
+                reader.u16(offset + 14)?,
+            ),
+            ElfClass::Elf64 => (
+                reader.u64(offset + 8)?,
+                reader.u64(offset + 16)?,
+                reader.u16(offset + 6)?,
+            ),
+        };
+        // Undefined symbols are imported from other modules
+        if section_index == 0 {
+            return None;
+        }
+
+        Some(Self {
+            name: string_at(string_table, reader.u32(offset)? as usize),
+            value,
+            size,
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// The virtual address of the symbol
+    pub fn get_value(&self) -> u64 {
+        self.value
+    }
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SectionHeader {
+    name: String,
+    name_offset: u32,
+    section_type: u32,
+    flags: u64,
+    address: u64,
+    offset: u64,
+    size: u64,
+}
+
+impl SectionHeader {
+    fn parse(reader: &Reader, offset: usize) -> Option<Self> {
+        let word = reader.word_size();
+
+        Some(Self {
+            name: String::new(),
+            name_offset: reader.u32(offset)?,
+            section_type: reader.u32(offset + 4)?,
+            flags: reader.word(offset + 8)?,
+            address: reader.word(offset + 8 + word)?,
+            offset: reader.word(offset + 8 + 2 * word)?,
+            size: reader.word(offset + 8 + 3 * word)?,
+        })
+    }
+
+    /// The name is empty until [`resolve_section_names`] was called
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_type(&self) -> u32 {
+        self.section_type
+    }
+    pub fn get_flags(&self) -> u64 {
+        self.flags
+    }
+    /// The virtual address of the section, zero if it isn't loaded
+    pub fn get_address(&self) -> u64 {
+        self.address
+    }
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn contains(&self, virtual_address: u64) -> bool {
+        self.address != 0 && (self.address..self.address + self.size).contains(&virtual_address)
+    }
+}
+
+/// Reads the null-terminated string at the offset of a string table
+fn string_at(string_table: &[u8], offset: usize) -> String {
+    let name = string_table.get(offset..).unwrap_or_default();
+    let length = name
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(name.len());
+
+    String::from_utf8_lossy(&name[..length]).into_owned()
+}
+
+/// Looks up the names of the sections in the section name string table
+pub fn resolve_section_names(section_headers: &mut [SectionHeader], string_table: &[u8]) {
+    for section_header in section_headers {
+        section_header.name = string_at(string_table, section_header.name_offset as usize);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramHeader {
+    segment_type: u32,
+    flags: u32,
+    offset: u64,
+    virtual_address: u64,
+    file_size: u64,
+    memory_size: u64,
+    alignment: u64,
+}
+
+impl ProgramHeader {
+    fn parse(reader: &Reader, offset: usize) -> Option<Self> {
+        // The flags are placed differently to keep the 64-bit structure aligned
+        Some(match reader.class {
+            ElfClass::Elf32 => Self {
+                segment_type: reader.u32(offset)?,
+                offset: reader.word(offset + 4)?,
+                virtual_address: reader.word(offset + 8)?,
+                file_size: reader.word(offset + 16)?,
+                memory_size: reader.word(offset + 20)?,
+                flags: reader.u32(offset + 24)?,
+                alignment: reader.word(offset + 28)?,
+            },
+            ElfClass::Elf64 => Self {
+                segment_type: reader.u32(offset)?,
+                flags: reader.u32(offset + 4)?,
+                offset: reader.word(offset + 8)?,
+                virtual_address: reader.word(offset + 16)?,
+                file_size: reader.word(offset + 32)?,
+                memory_size: reader.word(offset + 40)?,
+                alignment: reader.word(offset + 48)?,
+            },
+        })
+    }
+
+    pub fn get_type(&self) -> u32 {
+        self.segment_type
+    }
+    pub fn get_flags(&self) -> u32 {
+        self.flags
+    }
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+    pub fn get_virtual_address(&self) -> u64 {
+        self.virtual_address
+    }
+    pub fn get_file_size(&self) -> u64 {
+        self.file_size
+    }
+    pub fn get_memory_size(&self) -> u64 {
+        self.memory_size
+    }
+    pub fn get_alignment(&self) -> u64 {
+        self.alignment
+    }
+}
+
+/// The virtual address, at which the image expects to be loaded (the lowest page of a loadable segment)
+pub fn image_base(program_headers: &[ProgramHeader]) -> Option<u64> {
+    program_headers
+        .iter()
+        .filter(|header| header.segment_type == PT_LOAD)
+        .map(|header| {
+            let alignment = header.alignment.max(1);
+            header.virtual_address - header.virtual_address % alignment
+        })
+        .min()
+}
+
+/// Translates a virtual address into an offset inside of the file. Returns None if the address isn't backed by the file.
+pub fn virtual_address_to_file_offset(
+    program_headers: &[ProgramHeader],
+    address: u64,
+) -> Option<u64> {
+    program_headers
+        .iter()
+        .filter(|header| header.segment_type == PT_LOAD)
+        .find(|header| {
+            (header.virtual_address..header.virtual_address + header.file_size).contains(&address)
+        })
+        .map(|header| header.offset + (address - header.virtual_address))
+}
+
+/// Translates an offset inside of the file into a virtual address. Returns None if the offset isn't loaded.
+pub fn file_offset_to_virtual_address(
+    program_headers: &[ProgramHeader],
+    offset: u64,
+) -> Option<u64> {
+    program_headers
+        .iter()
+        .filter(|header| header.segment_type == PT_LOAD)
+        .find(|header| (header.offset..header.offset + header.file_size).contains(&offset))
+        .map(|header| header.virtual_address + (offset - header.offset))
+}
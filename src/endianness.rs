@@ -0,0 +1,25 @@
+//! The byte order of the target, for the non-generic variants of the steps, that read integers.
+//! The generic variants, e.g. [`crate::session::Session::dereference`], stay available for cross-endian analysis.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+impl Endianness {
+    /// The byte order of the machine, that BCRL is running on
+    pub const fn native() -> Self {
+        if cfg!(target_endian = "little") {
+            Endianness::Little
+        } else {
+            Endianness::Big
+        }
+    }
+}
+
+impl Default for Endianness {
+    fn default() -> Self {
+        Self::native()
+    }
+}
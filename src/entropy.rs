@@ -0,0 +1,84 @@
+#[derive(Clone, Debug)]
+pub struct RegionEntropy {
+    from_address: usize,
+    to_address: usize,
+    entropy: f64,
+}
+
+impl RegionEntropy {
+    pub fn new(from_address: usize, to_address: usize, entropy: f64) -> Self {
+        Self {
+            from_address,
+            to_address,
+            entropy,
+        }
+    }
+
+    pub fn get_from_address(&self) -> usize {
+        self.from_address
+    }
+    pub fn get_to_address(&self) -> usize {
+        self.to_address
+    }
+    pub fn get_size(&self) -> usize {
+        self.to_address - self.from_address
+    }
+    /// Shannon entropy in bits per byte, ranging from 0.0 to 8.0
+    pub fn get_entropy(&self) -> f64 {
+        self.entropy
+    }
+}
+
+/// Counts how often each byte value occurs
+pub fn byte_histogram(bytes: &[u8]) -> [usize; 256] {
+    let mut histogram = [0; 256];
+
+    for byte in bytes {
+        histogram[*byte as usize] += 1;
+    }
+
+    histogram
+}
+
+/// Calculates the Shannon entropy of the bytes in bits per byte
+pub fn shannon_entropy(bytes: &[u8]) -> f64 {
+    if bytes.is_empty() {
+        return 0.0;
+    }
+
+    let length = bytes.len() as f64;
+
+    byte_histogram(bytes)
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let probability = *count as f64 / length;
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{byte_histogram, shannon_entropy};
+
+    #[test]
+    fn test_byte_histogram() {
+        let histogram = byte_histogram(&[0, 1, 1, 0xFF]);
+
+        assert_eq!(histogram[0], 1);
+        assert_eq!(histogram[1], 2);
+        assert_eq!(histogram[0xFF], 1);
+        assert_eq!(histogram.iter().sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn test_shannon_entropy() {
+        let ramp = (0..=u8::MAX).collect::<Vec<_>>();
+
+        assert_eq!(shannon_entropy(&[]), 0.0);
+        assert_eq!(shannon_entropy(&[0; 64]), 0.0);
+        assert_eq!(shannon_entropy(&[0, 1]), 1.0);
+        assert!((shannon_entropy(&ramp) - 8.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,92 @@
+use alloc::sync::Arc;
+use core::{error::Error, fmt};
+
+/// Errors of operations on pointers and snapshots, which can fail for more than one reason
+#[derive(Clone, Debug)]
+pub enum BcrlError {
+    /// The pointer was invalidated by a previous step
+    Invalidated { address: usize },
+    /// The address is not inside of any map of the snapshot
+    Unmapped { address: usize },
+    /// The memory of the process couldn't be read, because the address was unmapped or is protected
+    Unreadable { address: usize },
+    /// The address is inside of a map without any access permissions, like a guard page, whose bytes are never read
+    NoAccess { address: usize },
+    /// The process exited, so neither its memory nor its mappings can be read anymore
+    ProcessExited,
+    /// The process still runs, but a new snapshot couldn't be taken, e.g. because of missing permissions.
+    /// The source is the error reading the process failed with (usually a `procfs::ProcError`), if there was one.
+    SnapshotFailed {
+        source: Option<Arc<dyn Error + Send + Sync>>,
+    },
+}
+
+impl BcrlError {
+    /// The address the error occurred at, if it concerns a single pointer
+    pub fn get_address(&self) -> Option<usize> {
+        match self {
+            BcrlError::Invalidated { address }
+            | BcrlError::Unmapped { address }
+            | BcrlError::Unreadable { address }
+            | BcrlError::NoAccess { address } => Some(*address),
+            BcrlError::ProcessExited | BcrlError::SnapshotFailed { .. } => None,
+        }
+    }
+}
+
+/// Errors are compared by their kind and address, the source of a failed snapshot is only informative
+impl PartialEq for BcrlError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BcrlError::SnapshotFailed { .. }, BcrlError::SnapshotFailed { .. }) => true,
+            (BcrlError::ProcessExited, BcrlError::ProcessExited) => true,
+            (BcrlError::Invalidated { address }, BcrlError::Invalidated { address: other })
+            | (BcrlError::Unmapped { address }, BcrlError::Unmapped { address: other })
+            | (BcrlError::Unreadable { address }, BcrlError::Unreadable { address: other })
+            | (BcrlError::NoAccess { address }, BcrlError::NoAccess { address: other }) => {
+                address == other
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Eq for BcrlError {}
+
+impl fmt::Display for BcrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BcrlError::Invalidated { address } => {
+                write!(f, "the pointer at {address:#x} was invalidated")
+            }
+            BcrlError::Unmapped { address } => write!(f, "{address:#x} is not mapped"),
+            BcrlError::Unreadable { address } => {
+                write!(f, "the memory at {address:#x} couldn't be read")
+            }
+            BcrlError::NoAccess { address } => {
+                write!(
+                    f,
+                    "{address:#x} is inside of a map without access permissions"
+                )
+            }
+            BcrlError::ProcessExited => write!(f, "the process exited"),
+            BcrlError::SnapshotFailed { source: None } => {
+                write!(f, "the process couldn't be snapshotted")
+            }
+            BcrlError::SnapshotFailed {
+                source: Some(source),
+            } => write!(f, "the process couldn't be snapshotted: {source}"),
+        }
+    }
+}
+
+impl Error for BcrlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BcrlError::SnapshotFailed {
+                source: Some(source),
+            } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Exports results of live scans as scripts for static analysis tools.
+//!
+//! Addresses are rebased to the image base found in the ELF headers. Since disassemblers may load the image
+//! somewhere else (e.g. Ghidra places position independent executables at 0x100000), the scripts apply the
+//! difference to the base of the opened program when they are executed.
+
+use std::path::PathBuf;
+
+/// A named address inside of a module, relative to the on-disk image base
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    name: String,
+    module: PathBuf,
+    image_base: u64,
+    virtual_address: u64,
+}
+
+impl Label {
+    pub fn new(name: String, module: PathBuf, image_base: u64, virtual_address: u64) -> Self {
+        Self {
+            name,
+            module,
+            image_base,
+            virtual_address,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_module(&self) -> &PathBuf {
+        &self.module
+    }
+    pub fn get_image_base(&self) -> u64 {
+        self.image_base
+    }
+    /// The address as it appears in the file
+    pub fn get_virtual_address(&self) -> u64 {
+        self.virtual_address
+    }
+
+    fn file_name(&self) -> String {
+        self.module
+            .file_name()
+            .unwrap_or(self.module.as_os_str())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Disassemblers only accept identifiers as labels
+    fn symbol(&self) -> String {
+        let mut symbol = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        if !symbol.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            symbol.insert(0, '_');
+        }
+        symbol
+    }
+}
+
+/// Names the hits of a pool. A single hit keeps the name, multiple hits are numbered.
+pub fn name_hits(name: &str, hits: impl IntoIterator<Item = usize>) -> Vec<(String, usize)> {
+    let hits = hits.into_iter().collect::<Vec<_>>();
+    if hits.len() == 1 {
+        return vec![(name.to_string(), hits[0])];
+    }
+
+    hits.into_iter()
+        .enumerate()
+        .map(|(index, hit)| (format!("{name}_{}", index + 1), hit))
+        .collect()
+}
+
+fn quote(string: &str) -> String {
+    format!("\"{}\"", string.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Creates a Ghidra Python script, which labels the addresses in the opened program.
+/// Labels of other modules are skipped, so the same script can be run on every module.
+pub fn ghidra_script(labels: &[Label]) -> String {
+    let mut script = String::new();
+    script.push_str("# Labels exported by bcrl-rs\n");
+    script.push_str("from ghidra.program.model.symbol import SourceType\n\n");
+    script.push_str("labels = [\n");
+    for label in labels {
+        script.push_str(&format!(
+            "    ({}, {:#x}, {:#x}, {}),\n",
+            quote(&label.file_name()),
+            label.image_base,
+            label.virtual_address,
+            quote(&label.symbol()),
+        ));
+    }
+    script.push_str("]\n\n");
+    script.push_str("program = currentProgram.getName()\n");
+    script.push_str("for module, image_base, address, name in labels:\n");
+    script.push_str("    if module != program:\n");
+    script.push_str("        continue\n");
+    script.push_str("    delta = currentProgram.getImageBase().getOffset() - image_base\n");
+    script.push_str(
+        "    createLabel(toAddr(address + delta), name, True, SourceType.USER_DEFINED)\n",
+    );
+
+    script
+}
+
+/// Creates an IDC script, which names the addresses in the opened database.
+/// Labels of other modules are skipped, so the same script can be run on every module.
+pub fn idc_script(labels: &[Label]) -> String {
+    let mut script = String::new();
+    script.push_str("// Labels exported by bcrl-rs\n");
+    script.push_str("#include <idc.idc>\n\n");
+    script.push_str("static main() {\n");
+    script.push_str("    auto module = get_root_filename();\n");
+    for label in labels {
+        script.push_str(&format!(
+            "    if (module == {}) set_name(get_imagebase() - {:#x} + {:#x}, {}, SN_NOWARN);\n",
+            quote(&label.file_name()),
+            label.image_base,
+            label.virtual_address,
+            quote(&label.symbol()),
+        ));
+    }
+    script.push_str("}\n");
+
+    script
+}
@@ -1,97 +1,2401 @@
-use std::{os::unix::fs::FileExt, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+#[cfg(feature = "linux")]
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    os::unix::fs::FileExt,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
+use byteorder::ByteOrder;
+#[cfg(feature = "linux")]
 use procfs::{
-    process::{MemoryMaps, Process},
+    process::{MemoryMaps, ProcState, Process},
     ProcError,
 };
+use procfs_core::process::{MMPermissions, MMapPath, MemoryMap};
 use signature_scanner::Signature;
 
-use std::fs::File;
+use crate::{
+    address_class::{self, AddressClass},
+    array_scan::{self, ArrayMatch},
+    cached_map::CachedMap,
+    cached_maps::CachedMaps,
+    cached_maps::FindAddress,
+    checkpoint::{self, ScanCheckpoint},
+    container,
+    coverage::{CoverageRecorder, ModuleCoverage},
+    elf::{self, ElfHeader, ProgramHeader},
+    endianness::Endianness,
+    entropy::{shannon_entropy, RegionEntropy},
+    export::Label,
+    heap::{self, HeapChunk},
+    memo::MemoStore,
+    memory_image::{self, PhysicalMemory, Translation, X86_64PageTables},
+    module::{self, Module, ModuleId},
+    nearest::{self, Anchor, AnchorKind, HitIndex},
+    page_index::{self, PageIndices},
+    patch::FilePatch,
+    pattern::{self, ExactPattern, PlannedSignature, SignatureMatch},
+    prologue,
+    safe_pointer::SafePointer,
+    scan_plan::ScanPlan,
+    search_constraints::{ScanDirection, SearchConstraints},
+    session::{with_endianness, Session},
+    shared_memory::SharedSegment,
+    signature_test::SignatureTest,
+    statistics::{Benchmark, ScanStatistics, Stopwatch},
+    struct_pattern::ScanPattern,
+    target::{Isa, TargetSpec},
+    tls,
+    validation::{ValidationEntry, ValidationReport, ValidationStatus},
+};
 
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+use crate::{
+    breakpoint::{self, BreakpointHit, BreakpointKind},
+    remote::{self, ThreadRegisters},
+    unwind::Frame,
+};
+#[cfg(feature = "linux")]
+use crate::{
+    cached_map::MemorySource,
+    error::BcrlError,
+    kernel::{self, KernelLayout},
+    map_usage::MapUsage,
+    module_events::{self, ModuleEvent},
+    patch_set::PatchSet,
+};
+#[cfg(feature = "disassembly")]
 use crate::{
-    cached_map::CachedMap, cached_maps::CachedMaps, safe_pointer::SafePointer,
-    search_constraints::SearchConstraints, session::Session,
+    instruction_pattern::InstructionPattern,
+    jump_table::{self, JumpTable},
 };
 
 #[derive(Debug)]
 pub struct BcrlFactory {
-    maps: Rc<CachedMaps>,
+    maps: RefCell<Rc<CachedMaps>>,
+    pid: Option<i32>,
+    // Only processes can change their mappings, offline snapshots never become stale
+    #[cfg_attr(not(feature = "linux"), allow(dead_code))]
+    maps_hash: Cell<u64>,
+    auto_refresh: bool,
+    page_index: bool,
+    page_indices: RefCell<Rc<PageIndices>>,
+    ptrace_fallback: bool,
+    lazy: bool,
+    cache_limit: Option<usize>,
+    #[cfg(feature = "linux")]
+    smaps: bool,
+    root: Option<PathBuf>,
+    target: TargetSpec,
+    #[cfg(feature = "linux")]
+    module_subscribers: RefCell<Vec<Sender<ModuleEvent>>>,
+}
+
+/// The longest x86 instruction has 15 bytes, at most 11 of them can be in front of an imm32 operand
+const MAX_BYTES_BEFORE_IMM32: usize = 11;
+
+/// How often the mappings are checked while waiting for a module
+#[cfg(feature = "linux")]
+const MODULE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Finds the start of the instruction, that ends with the imm32 operand at the address
+fn imm32_instruction_start<Isa: lde::Isa>(map: &CachedMap, operand: usize) -> Option<usize> {
+    let offset = operand - map.get_from_address();
+
+    (1..=MAX_BYTES_BEFORE_IMM32.min(offset)).find_map(|distance| {
+        let bytes = map.get_bytes().get(offset - distance..)?;
+        (Isa::ld(bytes) as usize == distance + 4).then_some(operand - distance)
+    })
+}
+
+#[cfg(feature = "linux")]
+fn hash_mappings(mappings: &MemoryMaps) -> u64 {
+    let mut hasher = DefaultHasher::new();
+
+    for map in mappings {
+        map.address.hash(&mut hasher);
+        map.perms.hash(&mut hasher);
+        map.offset.hash(&mut hasher);
+        map.dev.hash(&mut hasher);
+        map.inode.hash(&mut hasher);
+        map.pathname.hash(&mut hasher);
+    }
+
+    hasher.finish()
+}
+
+#[cfg(feature = "linux")]
+fn read_map(mem_file: &File, map: &MemoryMap) -> Option<Vec<u8>> {
+    let size = (map.address.1 - map.address.0) as usize;
+    let mut memory = vec![0; size];
+    let length = mem_file
+        .read_at(memory.as_mut_slice(), map.address.0)
+        .ok()?;
+
+    (length == size).then_some(memory)
+}
+
+#[cfg(feature = "linux")]
+fn cache_map(map: &MemoryMap, memory: Vec<u8>) -> CachedMap {
+    CachedMap::new(
+        map.address.0 as usize,
+        map.address.1 as usize,
+        map.perms,
+        map.pathname.clone(),
+        memory.into_boxed_slice(),
+    )
+    .with_file(map.offset, map.dev, map.inode)
+}
+
+/// Whether the map was mapped with PROT_NONE, like guard pages
+#[cfg(feature = "linux")]
+fn is_no_access(map: &MemoryMap) -> bool {
+    !map.perms
+        .intersects(MMPermissions::READ | MMPermissions::WRITE | MMPermissions::EXECUTE)
+}
+
+/// Records a map without access permissions, its bytes are neither read nor stored
+#[cfg(feature = "linux")]
+fn no_access_map(map: &MemoryMap) -> CachedMap {
+    CachedMap::no_access(
+        map.address.0 as usize,
+        map.address.1 as usize,
+        map.perms,
+        map.pathname.clone(),
+    )
+    .with_file(map.offset, map.dev, map.inode)
+}
+
+#[cfg(feature = "linux")]
+impl MemorySource for File {
+    fn read(&self, address: usize, buffer: &mut [u8]) -> bool {
+        self.read_at(buffer, address as u64)
+            .is_ok_and(|length| length == buffer.len())
+    }
+}
+
+/// Annotates the maps with their usage from the smaps entries, which the snapshot was taken from.
+/// Since the usage is read together with the mappings and before the memory, it describes the same maps.
+#[cfg(feature = "linux")]
+fn with_usage(maps: CachedMaps, smaps: &MemoryMaps) -> CachedMaps {
+    let usages = smaps
+        .iter()
+        .map(|map| {
+            (
+                (map.address.0 as usize, map.address.1 as usize),
+                MapUsage::from_extension(&map.extension),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    maps.iter()
+        .map(
+            |map| match usages.get(&(map.get_from_address(), map.get_to_address())) {
+                Some(usage) => map.clone().with_usage(*usage),
+                None => map.clone(),
+            },
+        )
+        .collect()
+}
+
+/// Creates lazy maps, which read their bytes from the mem file when they are first accessed.
+/// Maps, whose first byte can't be read, are left out like in eager snapshots.
+/// Since the bytes aren't known yet, no map is marked as containing thread-local storage.
+#[cfg(feature = "linux")]
+fn lazy_snapshot(mappings: &MemoryMaps, mem_file: Arc<File>) -> CachedMaps {
+    mappings
+        .iter()
+        .filter(|map| {
+            map.address.0 < map.address.1
+                && (is_no_access(map)
+                    || MemorySource::read(mem_file.as_ref(), map.address.0 as usize, &mut [0]))
+        })
+        .map(|map| {
+            if is_no_access(map) {
+                return no_access_map(map);
+            }
+
+            CachedMap::lazy(
+                map.address.0 as usize,
+                map.address.1 as usize,
+                map.perms,
+                map.pathname.clone(),
+                mem_file.clone(),
+            )
+            .with_file(map.offset, map.dev, map.inode)
+        })
+        .collect()
+}
+
+/// Reads every map, maps which can't be read through the mem file are read using ptrace if a pid is given
+#[cfg(feature = "linux")]
+#[cfg_attr(
+    not(all(feature = "ptrace", target_arch = "x86_64")),
+    allow(unused_variables)
+)]
+fn snapshot(
+    mappings: &MemoryMaps,
+    mem_file: Option<&File>,
+    ptrace_fallback: Option<i32>,
+) -> CachedMaps {
+    let mut maps = CachedMaps::new();
+    let mut unread = Vec::new();
+
+    for map in mappings {
+        if is_no_access(map) {
+            maps.insert(no_access_map(map));
+            continue;
+        }
+
+        match mem_file.and_then(|mem_file| read_map(mem_file, map)) {
+            Some(memory) => {
+                maps.insert(cache_map(map, memory));
+            }
+            None => unread.push(map),
+        }
+    }
+
+    // Only readable maps are read word by word, reading the others would fail or fault in pages for nothing
+    unread.retain(|map| map.perms.contains(MMPermissions::READ));
+    #[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+    if let Some(pid) = ptrace_fallback.filter(|_| !unread.is_empty()) {
+        let ranges = unread
+            .iter()
+            .map(|map| {
+                (
+                    map.address.0 as usize,
+                    (map.address.1 - map.address.0) as usize,
+                )
+            })
+            .collect::<Vec<_>>();
+        for (map, memory) in unread.into_iter().zip(remote::read_ranges(pid, &ranges)) {
+            if let Some(memory) = memory {
+                maps.insert(cache_map(map, memory));
+            }
+        }
+    }
+
+    maps
+}
+
+/// Reads the mappings and memory of a process, returns the snapshot and the hash of the mappings.
+/// With the ptrace fallback, the process is snapshotted even if its mem file can't be opened.
+/// A lazy snapshot only reads the mappings, see [`BcrlFactory::from_process_lazy`].
+/// With smaps, the mappings are read from smaps, so the maps are annotated with their usage, see [`BcrlFactory::with_smaps`].
+#[cfg(feature = "linux")]
+pub(crate) fn snapshot_process(
+    pid: i32,
+    ptrace_fallback: bool,
+    lazy: bool,
+    smaps: bool,
+) -> Result<(CachedMaps, u64), ProcError> {
+    let process = Process::new(pid)?;
+    let smaps = smaps.then(|| process.smaps().ok()).flatten();
+    let mappings = match &smaps {
+        Some(smaps) => smaps.clone(),
+        None => process.maps()?,
+    };
+    let mem_file = match process.mem() {
+        Ok(mem_file) => Some(mem_file),
+        Err(_) if ptrace_fallback => None,
+        Err(error) => return Err(error),
+    };
+
+    let maps = match mem_file {
+        Some(mem_file) if lazy => lazy_snapshot(&mappings, Arc::new(mem_file)),
+        mem_file => snapshot(&mappings, mem_file.as_ref(), ptrace_fallback.then_some(pid)),
+    };
+    let maps = match &smaps {
+        Some(smaps) => with_usage(maps, smaps),
+        None => maps,
+    };
+
+    Ok((maps, hash_mappings(&mappings)))
+}
+
+/// Whether the process still runs. Zombies count as exited, since their address space is already gone.
+#[cfg(feature = "linux")]
+pub fn is_running(pid: i32) -> bool {
+    Process::new(pid)
+        .and_then(|process| process.stat())
+        .is_ok_and(|stat| !matches!(stat.state(), Ok(ProcState::Zombie | ProcState::Dead)))
+}
+
+/// Tells apart a process, that exited, from one which couldn't be read
+#[cfg(feature = "linux")]
+fn read_failure(pid: i32, error: ProcError) -> BcrlError {
+    if is_running(pid) {
+        BcrlError::SnapshotFailed {
+            source: Some(Arc::new(error)),
+        }
+    } else {
+        BcrlError::ProcessExited
+    }
+}
+
+/// Takes a new snapshot like [`snapshot_process`], but reports why it failed.
+/// A process, which exits while it is read, may leave an incomplete snapshot behind, so it is checked once more afterwards.
+#[cfg(feature = "linux")]
+pub(crate) fn resnapshot_process(
+    pid: i32,
+    ptrace_fallback: bool,
+    lazy: bool,
+    smaps: bool,
+) -> Result<(CachedMaps, u64), BcrlError> {
+    let snapshot = snapshot_process(pid, ptrace_fallback, lazy, smaps)
+        .map_err(|error| read_failure(pid, error))?;
+    if !is_running(pid) {
+        return Err(BcrlError::ProcessExited);
+    }
+
+    Ok(snapshot)
+}
+
+/// Detects the architecture from the ELF header of the main module, which is the lowest mapped ELF file.
+/// Falls back to the architecture of this machine, if there is none.
+pub fn detect_target(maps: &CachedMaps) -> TargetSpec {
+    maps.iter()
+        .filter(|map| matches!(map.get_name(), MMapPath::Path(_)))
+        .find_map(|map| ElfHeader::parse(map.get_bytes()))
+        .map(|header| TargetSpec::from_elf_header(&header))
+        .unwrap_or_default()
+}
+
+/// The hits of the signature in a map, the page index of the map narrows the regions down if there is one
+fn signature_hits(
+    pattern: &PlannedSignature,
+    map: &CachedMap,
+    constraints: &SearchConstraints,
+    page_indices: Option<&PageIndices>,
+) -> Vec<usize> {
+    let address = map.get_from_address();
+    let regions = constraints.regions(map);
+    let regions = match page_indices.and_then(|indices| indices.get(&address)) {
+        Some(index) => page_index::narrow_regions(
+            index,
+            address,
+            regions,
+            pattern.get_signature().get_elements(),
+            constraints.get_direction(),
+        ),
+        None => regions,
+    };
+
+    regions
+        .into_iter()
+        .flat_map(|(from, bytes)| {
+            pattern
+                .all(bytes, constraints.get_direction())
+                .map(|offset| from + offset)
+                .filter(|address| constraints.allows_hit(map, *address))
+                .take(constraints.hit_limit())
+                .collect::<Vec<_>>()
+        })
+        .collect()
 }
 
 impl BcrlFactory {
+    /// Creates a new BcrlFactory from maps, which were captured elsewhere, e.g. for offline analysis
+    pub fn from_maps(maps: CachedMaps) -> Self {
+        Self::from_snapshot(maps, None, 0)
+    }
+
+    /// Creates a new BcrlFactory from the memory ranges of a minidump (Breakpad/Crashpad), see [`crate::minidump`]
+    #[cfg(feature = "minidump")]
+    pub fn from_minidump(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::from_minidump_bytes(&std::fs::read(path)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a minidump"))
+    }
+
+    /// Creates a new BcrlFactory from a minidump, which was already read into memory
+    #[cfg(feature = "minidump")]
+    pub fn from_minidump_bytes(bytes: &[u8]) -> Option<Self> {
+        crate::minidump::parse(bytes).map(Self::from_maps)
+    }
+
+    /// Creates a new BcrlFactory from the address space of a process in a physical memory image, see [`crate::memory_image`]
+    pub fn from_memory_image<'a>(
+        memory: &PhysicalMemory,
+        translation: &impl Translation,
+        mappings: impl IntoIterator<Item = &'a MemoryMap>,
+    ) -> Self {
+        Self::from_maps(memory_image::snapshot(memory, translation, mappings))
+    }
+
+    /// Creates a new BcrlFactory from the address space of an x86-64 process in a physical memory image.
+    /// The mappings are derived from the page tables, so they are anonymous.
+    pub fn from_page_tables(memory: &PhysicalMemory, directory_table_base: u64) -> Self {
+        let tables = X86_64PageTables::new(directory_table_base);
+
+        Self::from_memory_image(memory, &tables, &tables.mappings(memory))
+    }
+
+    /// Creates a new BcrlFactory from the sections of the running kernel and its modules, see [`crate::kernel`]
+    #[cfg(feature = "linux")]
+    pub fn from_kernel(layout: &KernelLayout) -> io::Result<Self> {
+        let kcore = File::open("/proc/kcore")?;
+
+        Ok(Self::from_maps(kernel::snapshot(layout, &kcore)?))
+    }
+
+    pub(crate) fn from_snapshot(maps: CachedMaps, pid: Option<i32>, maps_hash: u64) -> Self {
+        let target = detect_target(&maps);
+        let maps = tls::mark_thread_local_storage(maps, target.get_endianness());
+
+        BcrlFactory {
+            maps: RefCell::new(Rc::new(maps)),
+            pid,
+            maps_hash: Cell::new(maps_hash),
+            auto_refresh: false,
+            page_index: false,
+            page_indices: RefCell::new(Rc::new(PageIndices::new())),
+            ptrace_fallback: false,
+            lazy: false,
+            cache_limit: None,
+            #[cfg(feature = "linux")]
+            smaps: false,
+            #[cfg(feature = "linux")]
+            root: pid.and_then(container::root_of),
+            #[cfg(not(feature = "linux"))]
+            root: None,
+            target,
+            #[cfg(feature = "linux")]
+            module_subscribers: RefCell::new(Vec::new()),
+        }
+    }
+
+    #[cfg(feature = "linux")]
+    pub(crate) fn replace_snapshot(&self, maps: CachedMaps, maps_hash: u64) {
+        let maps = tls::mark_thread_local_storage(maps, self.get_endianness());
+        if self.page_index {
+            *self.page_indices.borrow_mut() = Rc::new(page_index::index_maps(&maps));
+        }
+        let mut subscribers = self.module_subscribers.borrow_mut();
+        if !subscribers.is_empty() {
+            let events = module_events::diff(
+                &module_events::modules_of(&self.get_cache()),
+                &module_events::modules_of(&maps),
+            );
+            module_events::publish(&mut subscribers, &events);
+        }
+        *self.maps.borrow_mut() = Rc::new(maps);
+        self.maps_hash.set(maps_hash);
+    }
+
     /// Creates a new BcrlFactory from a process
+    #[cfg(feature = "linux")]
     pub fn from_process(process: &Process) -> Result<Self, ProcError> {
         let maps = process.maps()?;
         let mem_file = process.mem()?;
 
-        Self::from_files(&maps, &mem_file)
+        let mut factory = Self::from_files(&maps, &mem_file)?;
+        factory.pid = Some(process.pid());
+        factory.root = container::root_of(process.pid());
+
+        Ok(factory)
+    }
+
+    /// Creates a new BcrlFactory from a process, maps which can't be read through /proc/$/mem are read using ptrace instead.
+    /// This is also used for refreshes. Some hardened processes deny reading their mem file, but still allow being traced.
+    /// Attaching stops the process while the remaining maps are read.
+    #[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+    pub fn from_process_with_ptrace_fallback(process: &Process) -> Result<Self, ProcError> {
+        let (maps, maps_hash) = snapshot_process(process.pid(), true, false, false)?;
+
+        let mut factory = Self::from_snapshot(maps, Some(process.pid()), maps_hash);
+        factory.ptrace_fallback = true;
+
+        Ok(factory)
+    }
+
+    /// Creates a new BcrlFactory from a process, which reads the bytes of a map when they are first needed.
+    /// Refreshes stay lazy. This saves memory if only a few maps are scanned, e.g. because the constraints
+    /// select a module. The bytes are read at different points in time, so unlike eager snapshots,
+    /// lazy ones aren't consistent if the process modifies its memory meanwhile. See [`Self::with_cache_limit`].
+    #[cfg(feature = "linux")]
+    pub fn from_process_lazy(process: &Process) -> Result<Self, ProcError> {
+        let (maps, maps_hash) = snapshot_process(process.pid(), false, true, false)?;
+
+        let mut factory = Self::from_snapshot(maps, Some(process.pid()), maps_hash);
+        factory.lazy = true;
+
+        Ok(factory)
+    }
+
+    pub fn is_lazy(&self) -> bool {
+        self.lazy
+    }
+
+    /// Limits the bytes of lazy maps kept in memory. Whenever the snapshot is accessed, e.g. before each scan,
+    /// the maps accessed least recently are evicted until the limit is met, they are read again when they are needed.
+    /// The limit can be exceeded during a scan.
+    /// Existing sessions and pointers keep the bytes they use alive. Maps, which aren't lazy, are never evicted.
+    pub fn with_cache_limit(mut self, bytes: usize) -> Self {
+        self.cache_limit = Some(bytes);
+
+        self
+    }
+
+    pub fn get_cache_limit(&self) -> Option<usize> {
+        self.cache_limit
+    }
+
+    /// The number of bytes of the snapshot, which are in memory
+    pub fn get_loaded_bytes(&self) -> usize {
+        self.maps
+            .borrow()
+            .iter()
+            .filter(|map| map.is_loaded())
+            .map(CachedMap::get_size)
+            .sum()
+    }
+
+    /// Evicts the lazy maps accessed least recently, until the loaded bytes don't exceed the cache limit
+    fn enforce_cache_limit(&self) {
+        let Some(limit) = self.cache_limit else {
+            return;
+        };
+        let mut loaded = self.get_loaded_bytes();
+        if loaded <= limit {
+            return;
+        }
+
+        let maps = self.maps.borrow().clone();
+        let mut candidates = maps
+            .iter()
+            .filter(|map| map.is_lazy() && map.is_loaded())
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|map| map.get_last_access());
+
+        let mut evicted = HashSet::new();
+        for map in candidates {
+            if loaded <= limit {
+                break;
+            }
+            loaded -= map.get_size();
+            evicted.insert(map.get_from_address());
+        }
+
+        let maps = maps
+            .iter()
+            .map(|map| {
+                if evicted.contains(&map.get_from_address()) {
+                    map.evicted()
+                } else {
+                    map.clone()
+                }
+            })
+            .collect();
+        *self.maps.borrow_mut() = Rc::new(maps);
+    }
+
+    /// When enabled, the mappings of every snapshot are read from /proc/$/smaps and each map is annotated with its
+    /// [`crate::map_usage::MapUsage`]. The usage is read before the memory, so it describes the maps of the snapshot.
+    /// Enabling it takes a new snapshot, if that fails the old one is kept without usages.
+    /// This has no effect on factories, which were not created from a process.
+    #[cfg(feature = "linux")]
+    pub fn with_smaps(mut self, smaps: bool) -> Self {
+        self.smaps = smaps;
+        if smaps {
+            let _ = self.refresh();
+        }
+
+        self
+    }
+
+    /// Whether the maps are annotated with their usage from smaps
+    #[cfg(feature = "linux")]
+    pub fn has_smaps(&self) -> bool {
+        self.smaps
+    }
+
+    /// Whether maps, which can't be read through /proc/$/mem, are read using ptrace
+    pub fn has_ptrace_fallback(&self) -> bool {
+        self.ptrace_fallback
     }
 
     /// Creates a new BcrlFactory from mappings and a /proc/$/mem file
+    #[cfg(feature = "linux")]
     pub fn from_files(mappings: &MemoryMaps, mem_file: &File) -> Result<Self, ProcError> {
-        let mut maps = CachedMaps::new();
+        Ok(Self::from_snapshot(
+            snapshot(mappings, Some(mem_file), None),
+            None,
+            hash_mappings(mappings),
+        ))
+    }
+
+    /// When enabled, the factory checks if the mappings of the process changed before each scan and refreshes the snapshot if they did.
+    /// This has no effect on factories, which were not created from a process.
+    pub fn with_auto_refresh(mut self, auto_refresh: bool) -> Self {
+        self.auto_refresh = auto_refresh;
+
+        self
+    }
+
+    /// When enabled, a byte-presence bitmap is built for every page of the snapshot and signature scans skip pages,
+    /// which can't contain the rarest literal byte of the signature. This pays off when scanning the same snapshot repeatedly.
+    pub fn with_page_index(mut self, page_index: bool) -> Self {
+        self.page_index = page_index;
+        *self.page_indices.get_mut() = Rc::new(if page_index {
+            page_index::index_maps(&self.get_cache())
+        } else {
+            PageIndices::new()
+        });
+
+        self
+    }
+
+    /// The byte order of the process, which sessions of the factory use for their non-generic steps.
+    /// Overrides the endianness of the detected target.
+    pub fn with_endianness(self, endianness: Endianness) -> Self {
+        let target = self.target.with_endianness(endianness);
+
+        self.with_target(target)
+    }
+
+    pub fn get_endianness(&self) -> Endianness {
+        self.target.get_endianness()
+    }
+
+    /// The architecture of the process, which sessions of the factory use for their non-generic steps.
+    /// By default it is detected from the ELF header of the main module, see [`detect_target`].
+    pub fn with_target(mut self, target: TargetSpec) -> Self {
+        if target.get_endianness() != self.get_endianness() {
+            let maps = tls::mark_thread_local_storage(
+                (*self.get_cache()).clone(),
+                target.get_endianness(),
+            );
+            *self.maps.get_mut() = Rc::new(maps);
+        }
+        self.target = target;
+
+        self
+    }
+
+    pub fn get_target(&self) -> TargetSpec {
+        self.target
+    }
+
+    /// Creates a session with the target of the factory
+    fn session<'a>(&self, pool: impl Iterator<Item = SafePointer> + 'a) -> Session<'a> {
+        Session::new(pool)
+            .with_target(self.target)
+            .with_root(self.root.clone())
+    }
+
+    /// The pid of the process, which the snapshot was taken from
+    pub fn get_pid(&self) -> Option<i32> {
+        self.pid
+    }
+
+    /// The root directory of the process, if it lives in a container, see [`crate::container`]
+    pub fn get_root(&self) -> Option<&Path> {
+        self.root.as_deref()
+    }
+
+    /// Translates a path from the maps of the process into one, which can be opened by the current process
+    pub fn host_path(&self, path: &Path) -> PathBuf {
+        container::host_path(self.get_root(), path)
+    }
+
+    /// Checks if the process, which the snapshot was taken from, still runs.
+    /// Factories, which were not created from a process, are always alive.
+    #[cfg(feature = "linux")]
+    pub fn is_alive(&self) -> bool {
+        self.pid.is_none_or(is_running)
+    }
+
+    /// Checks if the mappings of the process changed since the snapshot was taken.
+    /// Factories, which were not created from a process, are never stale.
+    #[cfg(feature = "linux")]
+    pub fn is_stale(&self) -> Result<bool, BcrlError> {
+        let Some(pid) = self.pid else {
+            return Ok(false);
+        };
+
+        let mappings = Process::new(pid)
+            .and_then(|process| process.maps())
+            .map_err(|error| read_failure(pid, error))?;
+
+        Ok(hash_mappings(&mappings) != self.maps_hash.get())
+    }
+
+    /// Takes a new snapshot of the process. Existing sessions and pointers keep using the old snapshot.
+    /// If the process exited, the old snapshot is kept and [`BcrlError::ProcessExited`] is returned.
+    #[cfg(feature = "linux")]
+    pub fn refresh(&self) -> Result<(), BcrlError> {
+        let Some(pid) = self.pid else {
+            return Ok(());
+        };
+
+        let (maps, maps_hash) =
+            resnapshot_process(pid, self.ptrace_fallback, self.lazy, self.smaps)?;
+        self.replace_snapshot(maps, maps_hash);
+
+        Ok(())
+    }
+
+    /// Subscribes to the modules, which were mapped or unmapped, whenever the snapshot is refreshed.
+    /// Refreshes, which happen automatically before scans, are reported as well. See [`crate::module_events`].
+    #[cfg(feature = "linux")]
+    pub fn module_events(&self) -> Receiver<ModuleEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.module_subscribers.borrow_mut().push(sender);
+
+        receiver
+    }
+
+    /// Takes a new snapshot if the mappings of the process changed. Returns whether a refresh happened.
+    #[cfg(feature = "linux")]
+    pub fn refresh_if_stale(&self) -> Result<bool, BcrlError> {
+        if !self.is_stale()? {
+            return Ok(false);
+        }
+
+        self.refresh()?;
+
+        Ok(true)
+    }
 
-        for map in mappings {
-            let size = (map.address.1 - map.address.0) as usize;
-            let mut memory = vec![0; size];
-            if let Ok(length) = mem_file.read_at(memory.as_mut_slice(), map.address.0) {
-                if length != size {
+    /// The pid for operations, which need a live process
+    #[cfg_attr(not(feature = "linux"), allow(dead_code))]
+    fn require_pid(&self) -> io::Result<i32> {
+        self.pid.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "the factory was not created from a process",
+            )
+        })
+    }
+
+    /// Returns the snapshot that scans should operate on, refreshing it beforehand if requested
+    fn current_maps(&self) -> Rc<CachedMaps> {
+        #[cfg(feature = "linux")]
+        if self.auto_refresh {
+            // Scans can't report errors, if the process can't be read anymore, the old snapshot is still the best we have
+            let _ = self.refresh_if_stale();
+        }
+
+        self.get_cache()
+    }
+
+    /// Creates a Session with a signature.
+    /// The longest run of literal bytes is searched first and the hits are verified against the whole signature, see [`PlannedSignature`].
+    pub fn signature(&self, pattern: Signature, constraints: SearchConstraints) -> Session<'_> {
+        let pattern = PlannedSignature::new(pattern);
+        let maps = self.current_maps();
+        let page_indices = self.page_index.then(|| self.page_indices.borrow().clone());
+        let addresses = maps
+            .iter()
+            .map(|map| map.get_from_address())
+            .collect::<Vec<_>>();
+        let addresses = constraints.in_scan_order(addresses);
+
+        let limit = constraints.hit_limit();
+        let ordered = constraints.is_ordered();
+
+        self.session(
+            addresses
+                .into_iter()
+                .flat_map(move |address| {
+                    let map = maps.get(&address).unwrap();
+                    if !constraints.allows_map(map) {
+                        return Vec::new();
+                    }
+                    signature_hits(&pattern, map, &constraints, page_indices.as_deref())
+                        .into_iter()
+                        .map(|address| SafePointer::new(maps.clone(), address))
+                        .collect::<Vec<_>>()
+                })
+                .take(limit),
+        )
+        .in_address_order_if(ordered)
+    }
+
+    /// Scans for a signature like [`Self::signature`], but records the progress in the checkpoint after every map
+    /// or chunk of a large map, so the scan can be resumed after an interruption, see [`crate::checkpoint`].
+    /// Fails if the checkpoint can't be saved or closures were passed as predicates.
+    pub fn signature_resumable(
+        &self,
+        pattern: Signature,
+        constraints: SearchConstraints,
+        checkpoint: &mut ScanCheckpoint,
+    ) -> io::Result<Session<'_>> {
+        let description = format!("signature {}", pattern::to_ida(pattern.get_elements()));
+        let length = pattern.get_elements().len();
+        let pattern = PlannedSignature::new(pattern);
+        let page_indices = self.page_index.then(|| self.page_indices.borrow().clone());
+
+        self.scan_resumable(
+            &description,
+            length,
+            constraints,
+            checkpoint,
+            |map, constraints| signature_hits(&pattern, map, constraints, page_indices.as_deref()),
+        )
+    }
+
+    /// Scans for the bytes like [`Self::bytes`], but records the progress in the checkpoint after every map,
+    /// see [`Self::signature_resumable`]
+    pub fn bytes_resumable(
+        &self,
+        needle: &[u8],
+        constraints: SearchConstraints,
+        checkpoint: &mut ScanCheckpoint,
+    ) -> io::Result<Session<'_>> {
+        let description = format!(
+            "bytes {}",
+            pattern::to_ida(&needle.iter().copied().map(Some).collect::<Vec<_>>())
+        );
+        let pattern = ExactPattern::new(needle);
+
+        self.scan_resumable(
+            &description,
+            needle.len(),
+            constraints,
+            checkpoint,
+            |map, constraints| {
+                constraints
+                    .regions(map)
+                    .into_iter()
+                    .flat_map(|(from, bytes)| {
+                        pattern
+                            .all(bytes, constraints.get_direction())
+                            .map(|offset| from + offset)
+                            .filter(|address| constraints.allows_hit(map, *address))
+                            .take(constraints.hit_limit())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    /// Scans the maps chunk by chunk, chunks are extended by the length of the pattern, so hits starting
+    /// in a chunk are found even if they end in the next one.
+    /// Fails if closures were passed as predicates, since scans that only differ in them would share their progress.
+    fn scan_resumable(
+        &self,
+        description: &str,
+        length: usize,
+        constraints: SearchConstraints,
+        checkpoint: &mut ScanCheckpoint,
+        mut scan_map: impl FnMut(&CachedMap, &SearchConstraints) -> Vec<usize>,
+    ) -> io::Result<Session<'_>> {
+        if constraints.has_opaque_predicates() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "constraints with closure predicates can't be resumed",
+            ));
+        }
+
+        let maps = self.current_maps();
+        let description = format!("{description} {}", constraints.describe());
+        let key = checkpoint::scan_key(self.pid, &description);
+        checkpoint.resume(key, |from, to| {
+            maps.get(&from)
+                .is_some_and(|map| map.get_to_address() == to)
+        })?;
+
+        let addresses = constraints.in_scan_order(
+            maps.iter()
+                .map(|map| map.get_from_address())
+                .collect::<Vec<_>>(),
+        );
+        let limit = constraints.hit_limit();
+
+        for address in addresses {
+            if checkpoint.hits(key).len() >= limit {
+                break;
+            }
+            let map = maps.get(&address).unwrap();
+            let range = (address, map.get_to_address());
+            let chunks = constraints.in_scan_order(checkpoint.chunks(range.0, range.1));
+            for (from, to) in chunks {
+                if checkpoint.is_completed(key, range, (from, to)) {
                     continue;
                 }
-                maps.insert(CachedMap::new(
-                    map.address.0 as usize,
-                    map.address.1 as usize,
-                    map.perms,
-                    map.pathname.clone(),
-                    memory.into_boxed_slice(),
-                ));
+                let hits = if constraints.allows_map(map) {
+                    let extended = constraints
+                        .clamp_address_range((from, to.saturating_add(length.saturating_sub(1))));
+                    let chunk_constraints = constraints
+                        .clone()
+                        .from(extended.get_from())
+                        .to(extended.get_to())
+                        .also_hit(move |_, address| address < to);
+                    scan_map(map, &chunk_constraints)
+                } else {
+                    Vec::new()
+                };
+                checkpoint.complete(key, range, (from, to), hits)?;
             }
         }
 
-        Ok(BcrlFactory {
-            maps: Rc::new(maps),
-        })
+        let hits = checkpoint.hits(key).to_vec();
+
+        Ok(self
+            .session(
+                hits.into_iter()
+                    .take(limit)
+                    .map(move |address| SafePointer::new(maps.clone(), address)),
+            )
+            .in_address_order_if(constraints.is_ordered()))
     }
 
-    /// Creates a Session with a signature
-    pub fn signature(&self, pattern: Signature, constraints: SearchConstraints) -> Session<'_> {
-        Session {
-            pool: Box::new(self.maps.iter().flat_map(move |map| {
+    /// Scans for a signature like [`Self::signature`], but reports the address of the first non-wildcard byte
+    /// next to the start of each match
+    pub fn signature_matches(
+        &self,
+        pattern: Signature,
+        constraints: SearchConstraints,
+    ) -> Vec<SignatureMatch> {
+        self.signature(pattern.clone(), constraints)
+            .get_pool()
+            .map(|start| SignatureMatch::new(start, &pattern))
+            .collect()
+    }
+
+    /// Creates a Session with the signature of a struct, using the endianness of the factory, see [`crate::struct_pattern`]
+    pub fn struct_pattern<T: ScanPattern>(&self, constraints: SearchConstraints) -> Session<'_> {
+        self.signature(
+            T::scan_pattern(self.get_endianness()).signature(),
+            constraints,
+        )
+    }
+
+    /// Finds arrays of at least min_count elements, which all match the element signature and are stride bytes apart.
+    /// The constraints select the maps and the start of the arrays, arrays are reported in scan order of the maps.
+    /// Panics if the stride is zero.
+    pub fn find_arrays(
+        &self,
+        element_signature: Signature,
+        min_count: usize,
+        stride: usize,
+        constraints: SearchConstraints,
+    ) -> Vec<ArrayMatch> {
+        let signature = PlannedSignature::new(element_signature);
+        let maps = self.current_maps();
+        let addresses = constraints.in_scan_order(
+            maps.iter()
+                .map(|map| map.get_from_address())
+                .collect::<Vec<_>>(),
+        );
+
+        addresses
+            .into_iter()
+            .flat_map(|address| {
+                let map = maps.get(&address).unwrap();
                 if !constraints.allows_map(map) {
                     return Vec::new();
                 }
-                let (from, to) =
-                    constraints.clamp_address_range((map.get_from_address(), map.get_to_address()));
+                constraints
+                    .regions(map)
+                    .into_iter()
+                    .flat_map(|(from, bytes)| {
+                        array_scan::find_runs(&signature, bytes, min_count, stride)
+                            .into_iter()
+                            .map(move |(offset, count)| {
+                                ArrayMatch::new(from + offset, count, stride)
+                            })
+                    })
+                    .filter(|array| constraints.allows_hit(map, array.get_address()))
+                    .collect()
+            })
+            .take(constraints.hit_limit())
+            .collect()
+    }
+
+    /// Creates a Session with a signature made of bytes and a code-style mask, see [`pattern::from_masked`].
+    /// None if the mask doesn't have one character per byte.
+    pub fn signature_masked(
+        &self,
+        bytes: &[u8],
+        mask: &[u8],
+        constraints: SearchConstraints,
+    ) -> Option<Session<'_>> {
+        pattern::from_masked(bytes, mask).map(|signature| self.signature(signature, constraints))
+    }
+
+    /// Creates a Session with all occurrences of the bytes. This is faster than a signature without wildcards.
+    pub fn bytes(&self, needle: &[u8], constraints: SearchConstraints) -> Session<'_> {
+        let pattern = ExactPattern::new(needle);
+        let maps = self.current_maps();
+        let addresses = maps
+            .iter()
+            .map(|map| map.get_from_address())
+            .collect::<Vec<_>>();
+        let addresses = constraints.in_scan_order(addresses);
+
+        let limit = constraints.hit_limit();
+        let ordered = constraints.is_ordered();
+
+        self.session(
+            addresses
+                .into_iter()
+                .flat_map(move |address| {
+                    let map = maps.get(&address).unwrap();
+                    if !constraints.allows_map(map) {
+                        return Vec::new();
+                    }
+                    constraints
+                        .regions(map)
+                        .into_iter()
+                        .flat_map(|(from, bytes)| {
+                            pattern
+                                .all(bytes, constraints.get_direction())
+                                .map(|offset| from + offset)
+                                .filter(|address| constraints.allows_hit(map, *address))
+                                .take(constraints.hit_limit())
+                                .map(|address| SafePointer::new(maps.clone(), address))
+                                .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .take(limit),
+        )
+        .in_address_order_if(ordered)
+    }
+
+    /// Creates a Session with all occurrences of the value encoded in the given byte order.
+    /// Use [`SearchConstraints::aligned_to`] to only find naturally aligned values.
+    pub fn immediate_u32<Endian: ByteOrder>(
+        &self,
+        value: u32,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        let mut needle = [0u8; 4];
+        Endian::write_u32(&mut needle, value);
+        self.bytes(&needle, constraints)
+    }
+
+    /// Creates a Session with all occurrences of the value encoded in the given byte order, see [`Self::immediate_u32`]
+    pub fn immediate_u64<Endian: ByteOrder>(
+        &self,
+        value: u64,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        let mut needle = [0u8; 8];
+        Endian::write_u64(&mut needle, value);
+        self.bytes(&needle, constraints)
+    }
+
+    /// Creates a Session with all occurrences of the value encoded in the byte order of the factory's target
+    pub fn immediate_u32_configured(
+        &self,
+        value: u32,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        with_endianness!(self.immediate_u32(value, constraints))
+    }
+
+    /// Creates a Session with all occurrences of the value encoded in the byte order of the factory's target
+    pub fn immediate_u64_configured(
+        &self,
+        value: u64,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        with_endianness!(self.immediate_u64(value, constraints))
+    }
+
+    /// Creates a Session with all x86 instructions, which end in the value as an imm32 operand.
+    /// The pointers point to the start of the instructions instead of the operand.
+    /// Instruction starts are guessed by decoding backwards from the operand, the closest start that decodes to an instruction ending after the operand wins.
+    pub fn immediate_operand<Isa: lde::Isa>(
+        &self,
+        value: u32,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        let constraints = constraints
+            .also_hit(|map, address| imm32_instruction_start::<Isa>(map, address).is_some());
+
+        self.bytes(&value.to_le_bytes(), constraints)
+            .mutate(|pointer| {
+                let start = pointer
+                    .get_maps()
+                    .find_map(pointer.get_address())
+                    .and_then(|map| imm32_instruction_start::<Isa>(map, pointer.get_address()));
+                match start {
+                    Some(start) => pointer.sub(pointer.get_address() - start),
+                    None => pointer.invalidate(),
+                };
+            })
+    }
+
+    /// Finds instructions with the value as an imm32 operand using the ISA of the factory's target.
+    /// The Session is empty, if the instructions of the ISA can't be decoded.
+    pub fn immediate_operand_configured(
+        &self,
+        value: u32,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        match self.target.get_isa() {
+            Isa::X86 => self.immediate_operand::<lde::X86>(value, constraints),
+            Isa::X86_64 => self.immediate_operand::<lde::X64>(value, constraints),
+            Isa::Other(_) => self.pointers(std::iter::empty()),
+        }
+    }
+
+    /// Creates a Session with all f32 values, which differ from the value by at most epsilon. NaN never matches.
+    /// Every byte offset is decoded, so use [`SearchConstraints::aligned_to`] and narrow constraints to keep the number of hits reasonable.
+    pub fn float_f32<Endian: ByteOrder>(
+        &self,
+        value: f32,
+        epsilon: f32,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        self.floats(
+            size_of::<f32>(),
+            move |bytes| (Endian::read_f32(bytes) - value).abs() <= epsilon,
+            constraints,
+        )
+    }
+
+    /// Creates a Session with all f64 values, which differ from the value by at most epsilon, see [`Self::float_f32`]
+    pub fn float_f64<Endian: ByteOrder>(
+        &self,
+        value: f64,
+        epsilon: f64,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        self.floats(
+            size_of::<f64>(),
+            move |bytes| (Endian::read_f64(bytes) - value).abs() <= epsilon,
+            constraints,
+        )
+    }
+
+    /// Finds f32 values like [`Self::float_f32`] in the byte order of the factory's target
+    pub fn float_f32_configured(
+        &self,
+        value: f32,
+        epsilon: f32,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        with_endianness!(self.float_f32(value, epsilon, constraints))
+    }
+
+    /// Finds f64 values like [`Self::float_f64`] in the byte order of the factory's target
+    pub fn float_f64_configured(
+        &self,
+        value: f64,
+        epsilon: f64,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        with_endianness!(self.float_f64(value, epsilon, constraints))
+    }
+
+    /// Scans every offset for values of the width, which are accepted by the predicate
+    fn floats(
+        &self,
+        width: usize,
+        matches: impl Fn(&[u8]) -> bool + 'static,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        let maps = self.current_maps();
+        let addresses = maps
+            .iter()
+            .map(|map| map.get_from_address())
+            .collect::<Vec<_>>();
+        let addresses = constraints.in_scan_order(addresses);
+
+        let limit = constraints.hit_limit();
+        let ordered = constraints.is_ordered();
+
+        self.session(
+            addresses
+                .into_iter()
+                .flat_map(move |address| {
+                    let map = maps.get(&address).unwrap();
+                    if !constraints.allows_map(map) {
+                        return Vec::new();
+                    }
+                    constraints
+                        .regions(map)
+                        .into_iter()
+                        .flat_map(|(from, bytes)| {
+                            pattern::offsets_in_direction(
+                                bytes.len(),
+                                width,
+                                constraints.get_direction(),
+                            )
+                            .filter(|offset| matches(&bytes[*offset..*offset + width]))
+                            .map(|offset| from + offset)
+                            .filter(|address| constraints.allows_hit(map, *address))
+                            .take(constraints.hit_limit())
+                            .map(|address| SafePointer::new(maps.clone(), address))
+                            .collect::<Vec<_>>()
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .take(limit),
+        )
+        .in_address_order_if(ordered)
+    }
+
+    /// Creates a Session with a pattern over decoded instructions, see [`crate::instruction_pattern`].
+    /// Every byte is considered a possible instruction start, so the scan is restricted to executable maps.
+    #[cfg(feature = "disassembly")]
+    pub fn instruction_pattern(
+        &self,
+        pattern: InstructionPattern,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        let maps = self.current_maps();
+        let addresses = maps
+            .iter()
+            .filter(|map| map.get_permissions().contains(MMPermissions::EXECUTE))
+            .map(|map| map.get_from_address())
+            .collect::<Vec<_>>();
+        let addresses = constraints.in_scan_order(addresses);
+
+        let limit = constraints.hit_limit();
+        let ordered = constraints.is_ordered();
+
+        self.session(
+            addresses
+                .into_iter()
+                .flat_map(move |address| {
+                    let map = maps.get(&address).unwrap();
+                    if !constraints.allows_map(map) {
+                        return Vec::new();
+                    }
+                    constraints
+                        .regions(map)
+                        .into_iter()
+                        .flat_map(|(from, bytes)| {
+                            constraints.collect_hits(
+                                pattern
+                                    .all(bytes, from)
+                                    .into_iter()
+                                    .map(|offset| from + offset)
+                                    .filter(|address| constraints.allows_hit(map, *address))
+                                    .map(|address| SafePointer::new(maps.clone(), address)),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .take(limit),
+        )
+        .in_address_order_if(ordered)
+    }
+
+    /// Creates a Session with probable function starts in executable maps, see [`crate::prologue`]
+    pub fn function_prologues(&self, constraints: SearchConstraints) -> Session<'_> {
+        let maps = self.current_maps();
+        let addresses = maps
+            .iter()
+            .filter(|map| map.get_permissions().contains(MMPermissions::EXECUTE))
+            .map(|map| map.get_from_address())
+            .collect::<Vec<_>>();
+        let addresses = constraints.in_scan_order(addresses);
+
+        let limit = constraints.hit_limit();
+        let ordered = constraints.is_ordered();
 
-                let bytes =
-                    &map.get_bytes()[from - map.get_from_address()..to - map.get_from_address()];
+        self.session(
+            addresses
+                .into_iter()
+                .flat_map(move |address| {
+                    let map = maps.get(&address).unwrap();
+                    if !constraints.allows_map(map) {
+                        return Vec::new();
+                    }
+                    constraints
+                        .regions(map)
+                        .into_iter()
+                        .flat_map(|(from, bytes)| {
+                            constraints.collect_hits(
+                                prologue::find_prologues(bytes)
+                                    .into_iter()
+                                    .map(|offset| from + offset)
+                                    .filter(|address| constraints.allows_hit(map, *address))
+                                    .map(|address| SafePointer::new(maps.clone(), address)),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .take(limit),
+        )
+        .in_address_order_if(ordered)
+    }
+
+    /// Finds all jump tables used by executable maps, see [`crate::jump_table`]
+    #[cfg(feature = "disassembly")]
+    pub fn jump_tables<Endian: ByteOrder>(&self, constraints: SearchConstraints) -> Vec<JumpTable> {
+        let maps = self.current_maps();
 
-                pattern
-                    .all(bytes)
-                    .map(move |offset| {
-                        SafePointer::new(self.maps.clone(), map.get_from_address() + offset)
+        let executable = maps
+            .iter()
+            .filter(|map| map.get_permissions().contains(MMPermissions::EXECUTE))
+            .filter(|map| constraints.allows_map(map))
+            .collect::<Vec<_>>();
+
+        constraints
+            .in_scan_order(executable)
+            .into_iter()
+            .flat_map(|map| {
+                constraints
+                    .in_scan_order(constraints.scanned_ranges(map.get_range()))
+                    .into_iter()
+                    .flat_map(|range| {
+                        constraints.in_scan_order(jump_table::find_jump_tables::<Endian>(
+                            &maps,
+                            map,
+                            range.get_from(),
+                            range.get_to(),
+                        ))
                     })
-                    .collect::<Vec<_>>()
-            })),
+                    .filter(|table| constraints.allows_hit(map, table.get_jump()))
+            })
+            .take(constraints.hit_limit())
+            .collect()
+    }
+
+    /// Finds all jump tables in the byte order of the factory's target, none are found for targets other than x86-64
+    #[cfg(feature = "disassembly")]
+    pub fn jump_tables_configured(&self, constraints: SearchConstraints) -> Vec<JumpTable> {
+        if self.target.get_isa() != Isa::X86_64 {
+            return Vec::new();
         }
+
+        with_endianness!(self.jump_tables(constraints))
+    }
+
+    /// Creates a Session with the targets of a jump table
+    #[cfg(feature = "disassembly")]
+    pub fn jump_table_targets(&self, table: &JumpTable) -> Session<'_> {
+        self.pointers(table.get_targets().to_vec().into_iter())
+    }
+
+    /// Scans for the signature like [`Self::signature`] and measures the hits and scan time of every module.
+    /// On wasm32 targets without an operating system no time is measured, the durations are zero.
+    pub fn signature_statistics(
+        &self,
+        pattern: Signature,
+        constraints: SearchConstraints,
+    ) -> ScanStatistics {
+        let pattern = PlannedSignature::new(pattern);
+        let mut statistics = ScanStatistics::default();
+
+        for map in self
+            .current_maps()
+            .iter()
+            .filter(|map| constraints.allows_map(map))
+        {
+            let start = Stopwatch::start();
+            let mut bytes = 0;
+            let mut hits = 0;
+
+            for (from, region) in constraints.regions(map) {
+                bytes += region.len();
+                hits += pattern
+                    .all(region, ScanDirection::Forwards)
+                    .filter(|offset| constraints.allows_hit(map, from + offset))
+                    .count();
+            }
+
+            statistics.record(map.get_name(), bytes, hits, start.elapsed());
+        }
+
+        statistics
+    }
+
+    /// Scans for the signature the given number of times, at least once, and measures the throughput.
+    /// This scans the current snapshot, so the maps are not re-read between iterations.
+    /// Like [`Self::signature_statistics`], no time is measured on wasm32 targets without an operating system.
+    pub fn bench_signature(
+        &self,
+        pattern: Signature,
+        constraints: SearchConstraints,
+        iterations: usize,
+    ) -> Benchmark {
+        let pattern = PlannedSignature::new(pattern);
+        let maps = self.current_maps();
+        let mut bytes = 0;
+        let mut hits = 0;
+        let mut durations = Vec::with_capacity(iterations.max(1));
+
+        for _ in 0..iterations.max(1) {
+            let start = Stopwatch::start();
+            bytes = 0;
+            hits = 0;
+
+            for map in maps.iter().filter(|map| constraints.allows_map(map)) {
+                for (from, region) in constraints.regions(map) {
+                    bytes += region.len();
+                    hits += pattern
+                        .all(region, ScanDirection::Forwards)
+                        .filter(|offset| constraints.allows_hit(map, from + offset))
+                        .count();
+                }
+            }
+
+            durations.push(start.elapsed());
+        }
+
+        Benchmark::new(bytes, hits, durations)
+    }
+
+    /// Scans for the signature and reports all hits
+    pub fn test_signature(
+        &self,
+        signature: Signature,
+        constraints: SearchConstraints,
+    ) -> SignatureTest {
+        SignatureTest::new(self.signature(signature, constraints).get_pool().collect())
+    }
+
+    /// Turns the candidate into the shortest signature, which only matches the address.
+    /// If the candidate is ambiguous, it is extended with the bytes following it, up to max_length elements.
+    /// Afterwards trailing elements are removed as long as the signature stays unique.
+    pub fn minimize(
+        &self,
+        address: usize,
+        candidate: Signature,
+        max_length: usize,
+        constraints: SearchConstraints,
+    ) -> Option<Signature> {
+        let is_unique_at_address = |elements: &[Option<u8>]| {
+            // A second hit is enough to tell, that the signature is ambiguous
+            let test = self.test_signature(
+                pattern::from_elements(elements),
+                constraints.clone().max_hits(2),
+            );
+            test.is_unique() && test.get_hits()[0] == address
+        };
+
+        let maps = self.get_cache();
+        let map = maps.find_map(address)?;
+        let following = map.get_bytes().get(address - map.get_from_address()..)?;
+
+        let mut elements = candidate.get_elements().to_vec();
+        if elements.is_empty() || elements.len() > following.len() {
+            return None;
+        }
+
+        while !is_unique_at_address(&elements) {
+            if elements.len() >= max_length || elements.len() >= following.len() {
+                return None;
+            }
+            elements.push(Some(following[elements.len()]));
+        }
+
+        while elements.len() > 1 && is_unique_at_address(&elements[..elements.len() - 1]) {
+            elements.pop();
+        }
+
+        Some(pattern::from_elements(&elements))
+    }
+
+    /// Spawns the command stopped at the entry of its program and snapshots it, so its initial state can be scanned.
+    /// Call [`remote::Suspended::resume`] to let it run.
+    #[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+    pub fn spawn_suspended(
+        command: &mut std::process::Command,
+    ) -> std::io::Result<(Self, remote::Suspended)> {
+        let suspended = remote::Suspended::spawn(command)?;
+        let process = Process::new(suspended.get_pid()).map_err(std::io::Error::other)?;
+        let factory = Self::from_process(&process).map_err(std::io::Error::other)?;
+
+        Ok((factory, suspended))
+    }
+
+    /// Reads the registers of every thread of the process, see [`remote::thread_registers`].
+    /// Only works for factories created from a process.
+    #[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+    pub fn thread_registers(&self) -> io::Result<Vec<ThreadRegisters>> {
+        remote::thread_registers(self.require_pid()?)
+    }
+
+    /// Creates a Session with the instruction pointer of every thread, e.g. to scan around where the threads execute
+    #[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+    pub fn thread_instruction_pointers(&self) -> io::Result<Session<'_>> {
+        let threads = self.thread_registers()?;
+        Ok(self.pointers(
+            threads
+                .into_iter()
+                .map(|thread| thread.get_instruction_pointer()),
+        ))
+    }
+
+    /// Creates a Session with the stack pointer of every thread
+    #[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+    pub fn thread_stack_pointers(&self) -> io::Result<Session<'_>> {
+        let threads = self.thread_registers()?;
+        Ok(self.pointers(threads.into_iter().map(|thread| thread.get_stack_pointer())))
+    }
+
+    /// Stops the process and unwinds the stack of the thread, see [`crate::unwind`]. Returns at most max_frames
+    /// frames, innermost first, with the module offset and the nearest exported symbol of each. Callers are looked up
+    /// one byte in front of their return address, so calls at the end of a function are attributed to it.
+    /// Only works for factories created from a process.
+    #[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+    pub fn backtrace(&self, tid: i32, max_frames: usize) -> io::Result<Vec<Frame>> {
+        let pcs = remote::backtrace(self.require_pid()?, tid, &self.get_cache(), max_frames)?;
+
+        Ok(pcs
+            .into_iter()
+            .enumerate()
+            .map(|(index, pc)| {
+                let lookup = if index == 0 { pc } else { pc - 1 };
+                let module = self
+                    .module_offset(lookup)
+                    .map(|(module, offset)| (module, offset + pc - lookup));
+                let symbol = self.nearest_symbol(lookup).map(|symbol| {
+                    Anchor::new(
+                        symbol.get_address(),
+                        pc - symbol.get_address(),
+                        symbol.get_kind(),
+                        symbol.get_name().map(str::to_string),
+                    )
+                });
+                Frame::new(pc, module, symbol)
+            })
+            .collect())
+    }
+
+    /// Sets a breakpoint at the address and records the registers and callers of the threads, that hit it, until
+    /// max_hits hits were recorded or the timeout elapsed, see [`crate::breakpoint`].
+    /// Only works for factories created from a process.
+    #[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+    pub fn capture_breakpoint(
+        &self,
+        address: usize,
+        kind: BreakpointKind,
+        max_hits: usize,
+        timeout: std::time::Duration,
+    ) -> std::io::Result<Vec<BreakpointHit>> {
+        let pid = self.require_pid()?;
+
+        breakpoint::capture(pid, &self.get_cache(), address, kind, max_hits, timeout)
+    }
+
+    /// Maps size bytes of fresh anonymous memory into the process using ptrace and returns a pointer to it.
+    /// The snapshot is refreshed afterwards, so the pointer is valid. Only works for factories created from a process.
+    #[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+    pub fn allocate_remote(
+        &self,
+        size: usize,
+        permissions: MMPermissions,
+    ) -> std::io::Result<SafePointer> {
+        let pid = self.require_pid()?;
+
+        let instruction = self
+            .bytes(
+                &remote::SYSCALL,
+                SearchConstraints::everything()
+                    .thats_executable()
+                    .max_hits(1),
+            )
+            .get_pool()
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "no syscall instruction was found in the process",
+                )
+            })?;
+
+        let address = remote::mmap(pid, instruction, size, permissions)?;
+        self.refresh().map_err(std::io::Error::other)?;
+
+        Ok(SafePointer::new(self.get_cache(), address))
+    }
+
+    /// Returns the shared memory segments, which are mapped into the process, without duplicates
+    pub fn shared_segments(&self) -> Vec<SharedSegment> {
+        let mut segments = self
+            .current_maps()
+            .iter()
+            .filter_map(SharedSegment::of)
+            .collect::<Vec<_>>();
+        segments.sort_unstable();
+        segments.dedup();
+        segments
+    }
+
+    /// Translates an address inside of a shared memory segment into the address of the same byte in the other process,
+    /// so state found in one process can be followed into the others sharing it.
+    /// Returns None if the address is not shared or the other process doesn't map that part of the segment.
+    pub fn translate_shared(&self, address: usize, other: &BcrlFactory) -> Option<usize> {
+        let maps = self.current_maps();
+        let map = maps.find_map(address)?;
+        let segment = SharedSegment::of(map)?;
+        let offset = map.get_offset() + (address - map.get_from_address()) as u64;
+
+        other.current_maps().iter().find_map(|other| {
+            let start = other.get_offset();
+            (SharedSegment::of(other) == Some(segment)
+                && (start..start + other.get_size() as u64).contains(&offset))
+            .then(|| other.get_from_address() + (offset - start) as usize)
+        })
     }
 
     /// Creates a Session with a list of pointers
     pub fn pointers<'a>(&'a self, pointers: impl Iterator<Item = usize> + 'a) -> Session<'a> {
-        Session {
-            pool: Box::new(pointers.map(|address| SafePointer::new(self.maps.clone(), address))),
-        }
+        let maps = self.get_cache();
+
+        self.session(pointers.map(move |address| SafePointer::new(maps.clone(), address)))
     }
 
     /// Creates a Session with a single pointer
     pub fn pointer(&self, pointer: usize) -> Session<'_> {
-        Session {
-            pool: Box::new([SafePointer::new(self.maps.clone(), pointer)].into_iter()),
+        self.session([SafePointer::new(self.get_cache(), pointer)].into_iter())
+    }
+
+    /// Calculates the Shannon entropy of every map allowed by the constraints
+    pub fn region_entropy(&self, constraints: SearchConstraints) -> Vec<RegionEntropy> {
+        self.current_maps()
+            .iter()
+            .filter(|map| constraints.allows_map(map))
+            .flat_map(|map| constraints.regions(map))
+            .map(|(from, bytes)| {
+                RegionEntropy::new(from, from + bytes.len(), shannon_entropy(bytes))
+            })
+            .collect()
+    }
+
+    /// Calculates the Shannon entropy of consecutive windows inside every map allowed by the constraints.
+    /// The last window of a map may be smaller than the window size.
+    pub fn window_entropy(
+        &self,
+        window_size: usize,
+        constraints: SearchConstraints,
+    ) -> Vec<RegionEntropy> {
+        assert!(window_size > 0, "window size must not be zero");
+
+        self.current_maps()
+            .iter()
+            .filter(|map| constraints.allows_map(map))
+            .flat_map(|map| constraints.regions(map))
+            .flat_map(|(from, bytes)| {
+                bytes
+                    .chunks(window_size)
+                    .enumerate()
+                    .map(|(index, window)| {
+                        let start = from + index * window_size;
+                        RegionEntropy::new(start, start + window.len(), shannon_entropy(window))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Creates a Session with the start addresses of all windows, which have at least the given entropy.
+    /// Packed or encrypted data usually comes close to 8 bits per byte.
+    pub fn high_entropy(
+        &self,
+        window_size: usize,
+        threshold: f64,
+        constraints: SearchConstraints,
+    ) -> Session<'_> {
+        self.pointers(
+            self.window_entropy(window_size, constraints)
+                .into_iter()
+                .filter(move |window| window.get_entropy() >= threshold)
+                .map(|window| window.get_from_address()),
+        )
+    }
+
+    /// Walks all glibc malloc chunks in the snapshot
+    pub fn heap_chunks<Endian: ByteOrder>(&self) -> Vec<HeapChunk> {
+        heap::heap_chunks::<Endian>(&self.current_maps())
+    }
+
+    /// Walks all glibc malloc chunks in the byte order of the factory's target,
+    /// none are found if the target's pointers are wider or narrower than the ones of this machine
+    pub fn heap_chunks_configured(&self) -> Vec<HeapChunk> {
+        if !self.target.has_native_pointer_width() {
+            return Vec::new();
         }
+
+        with_endianness!(self.heap_chunks())
+    }
+
+    /// Creates a Session with the user addresses of all allocated glibc malloc chunks
+    pub fn heap_allocations<Endian: ByteOrder>(&self) -> Session<'_> {
+        self.pointers(
+            self.heap_chunks::<Endian>()
+                .into_iter()
+                .filter(|chunk| chunk.is_in_use())
+                .map(|chunk| chunk.get_user_address()),
+        )
+    }
+
+    /// Creates a Session with the user addresses of all allocated glibc malloc chunks, see [`Self::heap_chunks_configured`]
+    pub fn heap_allocations_configured(&self) -> Session<'_> {
+        self.pointers(
+            self.heap_chunks_configured()
+                .into_iter()
+                .filter(|chunk| chunk.is_in_use())
+                .map(|chunk| chunk.get_user_address()),
+        )
+    }
+
+    /// Returns the addresses of all thread control blocks, the static TLS blocks are located right below them
+    pub fn thread_control_blocks<Endian: ByteOrder>(&self) -> Vec<usize> {
+        tls::thread_control_blocks::<Endian>(&self.current_maps())
+    }
+
+    /// Returns the addresses of all thread control blocks in the byte order of the factory's target,
+    /// none are found if the target's pointers are wider or narrower than the ones of this machine
+    pub fn thread_control_blocks_configured(&self) -> Vec<usize> {
+        if !self.target.has_native_pointer_width() {
+            return Vec::new();
+        }
+
+        with_endianness!(self.thread_control_blocks())
+    }
+
+    /// Returns the lowest address, at which the module is mapped
+    pub fn module_base(&self, module: &MMapPath) -> Option<usize> {
+        self.get_cache()
+            .iter()
+            .find(|map| map.get_name() == module)
+            .map(|map| map.get_from_address())
+    }
+
+    /// Tells what the address points into, with the module and section if it is inside of one
+    pub fn classify(&self, address: usize) -> AddressClass {
+        address_class::classify(&self.get_cache(), self.get_root(), address)
+    }
+
+    /// Returns the file-backed module containing the address and the offset from its base
+    pub fn module_offset(&self, address: usize) -> Option<(MMapPath, usize)> {
+        let maps = self.get_cache();
+        let module = maps.find_map(address)?.get_name().clone();
+        if !matches!(module, MMapPath::Path(_)) {
+            return None;
+        }
+
+        let base = self.module_base(&module)?;
+
+        Some((module, address - base))
+    }
+
+    /// The coverage of the modules of the snapshot, which were examined by scans recorded by the recorder
+    pub fn coverage(&self, recorder: &CoverageRecorder) -> Vec<ModuleCoverage> {
+        recorder.modules(&self.get_cache())
+    }
+
+    /// The exported symbol of the module containing the address, which is at or closest in front of it
+    pub fn nearest_symbol(&self, address: usize) -> Option<Anchor> {
+        let module = self.module(self.get_cache().find_map(address)?.get_name())?;
+
+        module
+            .exported_symbols()
+            .iter()
+            .filter_map(|symbol| {
+                let start = module.to_runtime_address(symbol.get_value())?;
+                (start <= address).then_some((start, symbol))
+            })
+            .max_by_key(|(start, _)| *start)
+            .map(|(start, symbol)| {
+                Anchor::new(
+                    start,
+                    address - start,
+                    AnchorKind::Symbol,
+                    Some(symbol.get_name().to_string()),
+                )
+            })
+    }
+
+    /// The probable function start at or closest in front of the address, inside of its executable map, see [`crate::prologue`]
+    pub fn nearest_function_start(&self, address: usize) -> Option<Anchor> {
+        let maps = self.get_cache();
+        let map = maps
+            .find_map(address)
+            .filter(|map| map.get_permissions().contains(MMPermissions::EXECUTE))?;
+        let offset = address - map.get_from_address();
+
+        prologue::find_prologues(map.get_bytes().get(..=offset)?)
+            .into_iter()
+            .max()
+            .map(|start| {
+                Anchor::new(
+                    map.get_from_address() + start,
+                    offset - start,
+                    AnchorKind::FunctionStart,
+                    None,
+                )
+            })
+    }
+
+    /// The closest known location at or in front of the address: an exported symbol, a function start or one of the hits
+    pub fn nearest(&self, address: usize, hits: Option<&HitIndex>) -> Option<Anchor> {
+        nearest::closest([
+            self.nearest_symbol(address),
+            self.nearest_function_start(address),
+            hits.and_then(|hits| hits.nearest_preceding(address)),
+        ])
+    }
+
+    /// Parses the ELF header and program headers of the module
+    pub fn elf_headers(&self, module: &MMapPath) -> Option<(ElfHeader, Vec<ProgramHeader>)> {
+        let module = self.module(module)?;
+
+        Some((
+            module.elf_header()?.clone(),
+            module.program_headers().to_vec(),
+        ))
+    }
+
+    /// Returns the module, if it is mapped
+    pub fn module(&self, name: &MMapPath) -> Option<Module> {
+        Module::new(self.get_cache(), name.clone())
+            .map(|module| module.with_root(self.root.clone()))
+    }
+
+    /// Points the GOT slots of an imported symbol of the module to the new target, so calls to the import go there
+    /// instead. Unlike an inline hook, no code is modified. Returns the original slots, so they can be restored.
+    /// Code, which already loaded the address of the import, keeps calling the original.
+    #[cfg(feature = "linux")]
+    pub fn redirect_import(
+        &self,
+        module: &MMapPath,
+        symbol: &str,
+        new_target: usize,
+    ) -> io::Result<PatchSet> {
+        let process = Process::new(self.require_pid()?).map_err(io::Error::other)?;
+        let slots = self
+            .module(module)
+            .map(|module| module.import_slots(symbol))
+            .unwrap_or_default();
+        if slots.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("the module doesn't import {symbol}"),
+            ));
+        }
+
+        let width = self.target.get_pointer_width();
+        let mut value = (new_target as u64).to_le_bytes()[..width.min(8)].to_vec();
+        if self.get_endianness() == Endianness::Big {
+            value.reverse();
+        }
+
+        let mut patches = PatchSet::new(&process)?;
+        for slot in slots {
+            if let Err(error) = patches.patch(slot, &value) {
+                patches.restore()?;
+                return Err(error);
+            }
+        }
+
+        Ok(patches)
+    }
+
+    /// Waits until a module with the file name is mapped, e.g. `libclient.so`, and returns it.
+    /// The mappings of the process are polled, once the module appears, the snapshot is refreshed.
+    /// Returns None if it didn't appear before the timeout. Factories, which were not created from a process, don't wait.
+    #[cfg(feature = "linux")]
+    pub fn wait_for_module(
+        &self,
+        name: &str,
+        timeout: Duration,
+    ) -> Result<Option<Module>, BcrlError> {
+        let is_module = |path: &MMapPath| matches!(path, MMapPath::Path(path) if path.file_name().is_some_and(|file_name| file_name == name));
+
+        if let Some(map) = self
+            .get_cache()
+            .iter()
+            .find(|map| is_module(map.get_name()))
+        {
+            return Ok(self.module(map.get_name()));
+        }
+        let Some(pid) = self.pid else {
+            return Ok(None);
+        };
+
+        let start = Instant::now();
+        loop {
+            let mappings = Process::new(pid)
+                .and_then(|process| process.maps())
+                .map_err(|error| read_failure(pid, error))?;
+            if let Some(map) = mappings.iter().find(|map| is_module(&map.pathname)) {
+                self.refresh()?;
+                return Ok(self.module(&map.pathname));
+            }
+
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                return Ok(None);
+            }
+            thread::sleep(MODULE_POLL_INTERVAL.min(timeout - elapsed));
+        }
+    }
+
+    /// Every instance of a file-backed module in address order, see [`ModuleId`]
+    pub fn module_ids(&self) -> Vec<ModuleId> {
+        module::module_ids(&self.get_cache())
+    }
+
+    /// The instances of modules with the file name, more than one means the name is ambiguous
+    pub fn module_ids_named(&self, name: &str) -> Vec<ModuleId> {
+        self.module_ids()
+            .into_iter()
+            .filter(|id| id.get_file_name() == Some(name))
+            .collect()
+    }
+
+    /// The module instance containing the address
+    pub fn module_id_of(&self, address: usize) -> Option<ModuleId> {
+        let maps = self.get_cache();
+        let map = maps.find_map(address)?;
+        self.module_ids()
+            .into_iter()
+            .find(|id| id.contains_map(map))
+    }
+
+    pub fn module_by_id(&self, id: &ModuleId) -> Option<Module> {
+        Module::from_id(self.get_cache(), id).map(|module| module.with_root(self.root.clone()))
+    }
+
+    /// Returns all file-backed modules ordered by their address
+    pub fn modules(&self) -> Vec<Module> {
+        let maps = self.get_cache();
+        let mut names = Vec::new();
+        for map in maps.iter() {
+            if matches!(map.get_name(), MMapPath::Path(_)) && !names.contains(map.get_name()) {
+                names.push(map.get_name().clone());
+            }
+        }
+
+        names
+            .into_iter()
+            .filter_map(|name| Module::new(maps.clone(), name))
+            .map(|module| module.with_root(self.root.clone()))
+            .collect()
+    }
+
+    /// Resolves the ELF file containing the address, its program headers and the address as it appears in the file
+    fn static_address(&self, address: usize) -> Option<(PathBuf, Vec<ProgramHeader>, u64)> {
+        let (module, offset) = self.module_offset(address)?;
+        let MMapPath::Path(path) = &module else {
+            return None;
+        };
+
+        let (_, program_headers) = self.elf_headers(&module)?;
+        let image_base = elf::image_base(&program_headers)?;
+
+        Some((path.clone(), program_headers, image_base + offset as u64))
+    }
+
+    /// Converts the address into a label for static analysis tools, see [`crate::export`].
+    /// Returns None if the address is not inside of an ELF file.
+    pub fn label(&self, name: impl Into<String>, address: usize) -> Option<Label> {
+        let (path, program_headers, virtual_address) = self.static_address(address)?;
+
+        Some(Label::new(
+            name.into(),
+            path,
+            elf::image_base(&program_headers)?,
+            virtual_address,
+        ))
+    }
+
+    /// Translates the address into an offset inside of the backing ELF file.
+    /// Returns None if the address isn't stored in a file, e.g. zero-initialized memory.
+    pub fn vaddr_to_file_offset(&self, address: usize) -> Option<(PathBuf, u64)> {
+        let (path, program_headers, virtual_address) = self.static_address(address)?;
+
+        Some((
+            path,
+            elf::virtual_address_to_file_offset(&program_headers, virtual_address)?,
+        ))
+    }
+
+    /// Translates an offset inside of an ELF file into the address, at which it is mapped in the process.
+    /// Returns None if the file isn't mapped or the offset isn't part of a loadable segment.
+    pub fn file_offset_to_vaddr(&self, file: &Path, offset: u64) -> Option<usize> {
+        let module = MMapPath::Path(file.to_path_buf());
+        let base = self.module_base(&module)?;
+
+        let (_, program_headers) = self.elf_headers(&module)?;
+        let image_base = elf::image_base(&program_headers)?;
+        let virtual_address = elf::file_offset_to_virtual_address(&program_headers, offset)?;
+
+        Some(base + (virtual_address - image_base) as usize)
+    }
+
+    /// Describes a patch, which writes the replacement into the backing file at the location of the address.
+    /// Returns None if the bytes are not (completely) stored in a file or the file can't be read.
+    pub fn file_patch(&self, address: usize, replacement: &[u8]) -> Option<FilePatch> {
+        let (file, offset) = self.vaddr_to_file_offset(address)?;
+        if !replacement.is_empty() {
+            // The bytes may not leave the segment, e.g. into zero-initialized memory
+            let (last_file, last_offset) =
+                self.vaddr_to_file_offset(address + replacement.len() - 1)?;
+            if last_file != file || last_offset != offset + replacement.len() as u64 - 1 {
+                return None;
+            }
+        }
+
+        let mut original = vec![0; replacement.len()];
+        File::open(self.host_path(&file))
+            .and_then(|mut handle| {
+                handle.seek(SeekFrom::Start(offset))?;
+                handle.read_exact(&mut original)
+            })
+            .ok()?;
+
+        Some(FilePatch::new(file, offset, original, replacement.to_vec()))
+    }
+
+    /// Converts all named addresses into labels, addresses outside of ELF files are skipped
+    pub fn labels<Name: Into<String>>(
+        &self,
+        addresses: impl IntoIterator<Item = (Name, usize)>,
+    ) -> Vec<Label> {
+        addresses
+            .into_iter()
+            .filter_map(|(name, address)| self.label(name, address))
+            .collect()
+    }
+
+    /// Returns the cached results of the plan or executes it and caches the results, see [`crate::memo`]
+    pub fn execute_memoized(&self, plan: &ScanPlan, store: &mut MemoStore) -> Vec<usize> {
+        if let Some(hits) = store.get(self, plan) {
+            return hits;
+        }
+
+        let hits = plan.execute(self).get_pool().collect::<Vec<_>>();
+        store.insert(self, plan, &hits);
+
+        hits
+    }
+
+    /// Executes every plan and reports whether it resolved to exactly one address
+    pub fn validate<Name: Into<String>>(
+        &self,
+        plans: impl IntoIterator<Item = (Name, ScanPlan)>,
+    ) -> ValidationReport {
+        ValidationReport::new(
+            plans
+                .into_iter()
+                .map(|(name, plan)| {
+                    let hits = plan.execute(self).get_pool().collect::<Vec<_>>();
+
+                    let status = match hits.as_slice() {
+                        [] => ValidationStatus::Missing,
+                        [address] => {
+                            let (module, offset) = self.module_offset(*address).unzip();
+                            ValidationStatus::Unique {
+                                address: *address,
+                                module,
+                                offset,
+                            }
+                        }
+                        _ => ValidationStatus::Ambiguous(hits.len()),
+                    };
+
+                    ValidationEntry::new(name.into(), status)
+                })
+                .collect(),
+        )
     }
 
     /// Get the internal caches that BCRL stores. You will likely never need this.
+    /// Lazy maps exceeding the cache limit are evicted first, see [`Self::with_cache_limit`].
     pub fn get_cache(&self) -> Rc<CachedMaps> {
-        self.maps.clone()
+        self.enforce_cache_limit();
+
+        self.maps.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        rc::Rc,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use procfs_core::process::{MMPermissions, MMapPath};
+    use signature_scanner::Signature;
+
+    use crate::{
+        cached_map::{CachedMap, MemorySource},
+        cached_maps::{CachedMaps, FindAddress},
+        checkpoint::ScanCheckpoint,
+        error::BcrlError,
+        safe_pointer::SafePointer,
+        search_constraints::SearchConstraints,
+    };
+
+    use super::BcrlFactory;
+
+    /// Counts the reads of lazy maps, which fail unless the memory is readable
+    struct Memory {
+        reads: AtomicUsize,
+        readable: bool,
+    }
+
+    impl MemorySource for Memory {
+        fn read(&self, _address: usize, buffer: &mut [u8]) -> bool {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            buffer.fill(0xAB);
+            self.readable
+        }
+    }
+
+    fn lazy_maps(memory: &Arc<Memory>, starts: &[usize]) -> CachedMaps {
+        starts
+            .iter()
+            .map(|from| {
+                CachedMap::lazy(
+                    *from,
+                    from + 0x1000,
+                    MMPermissions::READ,
+                    MMapPath::Anonymous,
+                    memory.clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cache_limit_evicts_least_recently_used() {
+        let memory = Arc::new(Memory {
+            reads: AtomicUsize::new(0),
+            readable: true,
+        });
+        let factory = BcrlFactory::from_maps(lazy_maps(&memory, &[0x1000, 0x2000, 0x3000]))
+            .with_cache_limit(0x2000);
+        let load = |address| factory.get_cache().find_map(address).unwrap().get_bytes()[0];
+
+        assert_eq!(factory.get_loaded_bytes(), 0);
+        assert_eq!(load(0x1000), 0xAB);
+        load(0x2000);
+        load(0x1000);
+        load(0x3000);
+        // The limit is exceeded until the snapshot is accessed again
+        assert_eq!(factory.get_loaded_bytes(), 0x3000);
+
+        let maps = factory.get_cache();
+        assert_eq!(factory.get_loaded_bytes(), 0x2000);
+        let loaded = maps.iter().map(CachedMap::is_loaded).collect::<Vec<_>>();
+        assert_eq!(loaded, vec![true, false, true]);
+
+        // Evicted maps are read again
+        assert_eq!(memory.reads.load(Ordering::Relaxed), 3);
+        load(0x2000);
+        assert_eq!(memory.reads.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_failed_lazy_read() {
+        let memory = Arc::new(Memory {
+            reads: AtomicUsize::new(0),
+            readable: false,
+        });
+        let maps = Rc::new(lazy_maps(&memory, &[0x1000]));
+        let map = maps.first().unwrap();
+
+        assert!(map.get_bytes().is_empty());
+        assert!(!map.is_readable());
+        assert_eq!(
+            SafePointer::new(maps.clone(), 0x1000).read(4).err(),
+            Some(BcrlError::Unreadable { address: 0x1000 })
+        );
+        // The read isn't retried
+        map.get_bytes();
+        assert_eq!(memory.reads.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_live_pointer_into_unreadable_map() {
+        let memory = Arc::new(Memory {
+            reads: AtomicUsize::new(0),
+            readable: false,
+        });
+        let bytes = std::hint::black_box(vec![0x90u8; 0x40]);
+        let from = bytes.as_ptr() as usize;
+        let maps = Rc::new(CachedMaps::from([CachedMap::lazy(
+            from,
+            from + bytes.len(),
+            MMPermissions::READ,
+            MMapPath::Anonymous,
+            memory,
+        )]));
+        let mut ptr = SafePointer::new(maps, from + 0x10);
+        assert!(ptr.context(4, 4).is_none());
+        assert!(ptr.read_remaining().is_none());
+
+        // The snapshot has no bytes, live pointers read the process instead
+        ptr.live(Rc::new(std::fs::File::open("/proc/self/mem").unwrap()));
+        let context = ptr.context(4, 4).unwrap();
+        assert_eq!(context.get_start(), from + 0xC);
+        assert_eq!(context.get_bytes(), &[0x90; 8]);
+        assert_eq!(ptr.read_remaining().unwrap().len(), 0x30);
+    }
+
+    #[test]
+    fn test_no_access_map() {
+        let maps = [
+            CachedMap::no_access(
+                0x1000,
+                0x100000,
+                MMPermissions::PRIVATE,
+                MMapPath::Anonymous,
+            ),
+            CachedMap::new(
+                0x100000,
+                0x100004,
+                MMPermissions::READ,
+                MMapPath::Anonymous,
+                Box::new([0; 4]),
+            ),
+        ]
+        .into_iter()
+        .collect::<CachedMaps>();
+        let maps = Rc::new(maps);
+        let map = maps.first().unwrap();
+
+        // The bytes of guard pages aren't allocated
+        assert!(map.get_bytes().is_empty());
+        assert!(!map.is_readable());
+        assert_eq!(
+            SafePointer::new(maps.clone(), 0x1000).read(4).err(),
+            Some(BcrlError::NoAccess { address: 0x1000 })
+        );
+
+        let factory = BcrlFactory::from_maps(Rc::unwrap_or_clone(maps));
+        let hits = factory
+            .signature(
+                Signature::ida("00 00 00 00"),
+                SearchConstraints::everything().including_inaccessible(),
+            )
+            .get_pool()
+            .collect::<Vec<_>>();
+        assert_eq!(hits, vec![0x100000]);
+    }
+
+    #[test]
+    fn test_window_entropy() {
+        let mut bytes = (0..=u8::MAX).collect::<Vec<_>>();
+        bytes.extend([0; 0x40]);
+        let factory = BcrlFactory::from_maps(CachedMaps::from([CachedMap::new(
+            0x1000,
+            0x1000 + bytes.len(),
+            MMPermissions::READ,
+            MMapPath::Anonymous,
+            bytes.into(),
+        )]));
+
+        let windows = factory.window_entropy(0x100, SearchConstraints::everything());
+        let ranges = windows
+            .iter()
+            .map(|window| (window.get_from_address(), window.get_size()))
+            .collect::<Vec<_>>();
+        // The last window only covers the rest of the map
+        assert_eq!(ranges, vec![(0x1000, 0x100), (0x1100, 0x40)]);
+        assert!((windows[0].get_entropy() - 8.0).abs() < 1e-9);
+        assert_eq!(windows[1].get_entropy(), 0.0);
+
+        let hits = factory
+            .high_entropy(0x100, 7.5, SearchConstraints::everything())
+            .get_pool()
+            .collect::<Vec<_>>();
+        assert_eq!(hits, vec![0x1000]);
+    }
+
+    #[test]
+    fn test_resumable_scan_in_chunks() {
+        let mut bytes = vec![0; 0x3000];
+        // The second hit crosses the boundary between the first two chunks
+        bytes[0x10..0x12].copy_from_slice(&[0xAB, 0xCD]);
+        bytes[0xFFF..0x1001].copy_from_slice(&[0xAB, 0xCD]);
+        bytes[0x2FFE..].copy_from_slice(&[0xAB, 0xCD]);
+        let factory = BcrlFactory::from_maps(CachedMaps::from([CachedMap::new(
+            0x1000,
+            0x4000,
+            MMPermissions::READ,
+            MMapPath::Anonymous,
+            bytes.into(),
+        )]));
+        let path = std::env::temp_dir().join(format!("bcrl_chunks_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut checkpoint = ScanCheckpoint::open(&path).unwrap().with_chunk_size(0x1000);
+
+        let hits = factory
+            .bytes_resumable(
+                &[0xAB, 0xCD],
+                SearchConstraints::everything(),
+                &mut checkpoint,
+            )
+            .unwrap()
+            .get_pool()
+            .collect::<Vec<_>>();
+        assert_eq!(hits, vec![0x1010, 0x1FFF, 0x3FFE]);
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.matches("end\n").count(), 3);
+
+        // Scans with other constraints are recorded separately
+        let hits = factory
+            .bytes_resumable(
+                &[0xAB, 0xCD],
+                SearchConstraints::everything().from(0x2000),
+                &mut checkpoint,
+            )
+            .unwrap()
+            .get_pool()
+            .collect::<Vec<_>>();
+        assert_eq!(hits, vec![0x3FFE]);
+        assert_eq!(checkpoint.len(), 2);
+
+        // Closures can't be told apart, scans with them would share their progress
+        let error = factory
+            .bytes_resumable(
+                &[0xAB, 0xCD],
+                SearchConstraints::everything().also_hit(|_, address| address > 0x2000),
+                &mut checkpoint,
+            )
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(checkpoint.len(), 2);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_best_skips_invalidated_pointers() {
+        let factory = BcrlFactory::from_maps(lazy_maps(
+            &Arc::new(Memory {
+                reads: AtomicUsize::new(0),
+                readable: true,
+            }),
+            &[0x1000],
+        ));
+        let mut invalidated = SafePointer::new(factory.get_cache(), 0x1000);
+        invalidated.add_score(2).invalidate();
+        let mut valid = SafePointer::new(factory.get_cache(), 0x1004);
+        valid.add_score(1);
+
+        let best = factory
+            .session([invalidated, valid].into_iter())
+            .best()
+            .get_pool()
+            .collect::<Vec<_>>();
+        assert_eq!(best, vec![0x1004]);
     }
 }
@@ -0,0 +1,142 @@
+//! Keeps values in the memory of a process at fixed contents, by writing them again and again on a background thread.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io,
+    os::unix::fs::FileExt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use procfs::process::Process;
+
+use crate::patch_set;
+
+#[derive(Default)]
+struct Entries {
+    values: HashMap<usize, Vec<u8>>,
+    /// The addresses, which couldn't be written the last time they were frozen
+    failed: HashSet<usize>,
+}
+
+/// Writes the frozen values every interval until it is dropped or stopped
+pub struct Freezer {
+    entries: Arc<Mutex<Entries>>,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Freezer {
+    /// Opens the memory of the process for writing and starts the background thread
+    pub fn new(process: &Process, interval: Duration) -> io::Result<Self> {
+        let memory = patch_set::open_memory(process)?;
+
+        let entries = Arc::new(Mutex::new(Entries::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let entries = entries.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                while !stopped.load(Ordering::Acquire) {
+                    freeze(&memory, &mut entries.lock().unwrap());
+                    // Stopping unparks the thread, so dropping doesn't wait for the whole interval
+                    thread::park_timeout(interval);
+                }
+            })
+        };
+
+        Ok(Self {
+            entries,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+
+    /// Freezes the bytes at the address, replacing the value previously frozen there
+    pub fn add(&self, address: usize, bytes: impl Into<Vec<u8>>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.values.insert(address, bytes.into());
+        entries.failed.remove(&address);
+    }
+
+    /// Stops freezing the address, returns the value which was frozen there
+    pub fn remove(&self, address: usize) -> Option<Vec<u8>> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.failed.remove(&address);
+        entries.values.remove(&address)
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.values.clear();
+        entries.failed.clear();
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        self.entries.lock().unwrap().values.contains_key(&address)
+    }
+
+    pub fn get_addresses(&self) -> Vec<usize> {
+        let mut addresses = self
+            .entries
+            .lock()
+            .unwrap()
+            .values
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        addresses.sort_unstable();
+        addresses
+    }
+
+    /// The addresses, which couldn't be written the last time, for example because they were unmapped
+    pub fn get_failed(&self) -> Vec<usize> {
+        let mut addresses = self
+            .entries
+            .lock()
+            .unwrap()
+            .failed
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        addresses.sort_unstable();
+        addresses
+    }
+
+    /// Stops the background thread and waits for it to finish, the values are not written anymore afterwards
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Freezer {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+fn freeze(memory: &File, entries: &mut Entries) {
+    let Entries { values, failed } = entries;
+
+    for (address, bytes) in values.iter() {
+        if memory.write_all_at(bytes, *address as u64).is_ok() {
+            failed.remove(address);
+        } else {
+            failed.insert(*address);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Walks the chunks of glibc's malloc implementation inside the snapshot.
+//!
+//! The main arena is found through the `[heap]` mapping, since `main_arena` is not an exported symbol of libc.
+//! Thread arenas are found through their `heap_info` headers, which glibc places at the start of
+//! mappings aligned to `HEAP_MAX_SIZE`.
+//! Chunks inside the tcache or fastbins are still marked as in use by glibc and are reported as allocated.
+
+use byteorder::ByteOrder;
+use procfs_core::process::{MMPermissions, MMapPath};
+
+use crate::{
+    cached_map::CachedMap,
+    cached_maps::{CachedMaps, FindAddress},
+};
+
+const SIZE_SZ: usize = std::mem::size_of::<usize>();
+const MALLOC_ALIGNMENT: usize = 16;
+const MIN_CHUNK_SIZE: usize = 4 * SIZE_SZ;
+
+const PREV_INUSE: usize = 0x1;
+const IS_MMAPPED: usize = 0x2;
+const NON_MAIN_ARENA: usize = 0x4;
+const SIZE_BITS: usize = PREV_INUSE | IS_MMAPPED | NON_MAIN_ARENA;
+
+#[cfg(target_pointer_width = "64")]
+const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024;
+#[cfg(not(target_pointer_width = "64"))]
+const HEAP_MAX_SIZE: usize = 1024 * 1024;
+
+/// How far after the start of a heap the first chunk is searched for.
+/// This has to cover `heap_info` and `malloc_state`, whose sizes differ between glibc versions.
+const FIRST_CHUNK_SEARCH_LIMIT: usize = 0x1000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeapChunk {
+    address: usize,
+    size: usize,
+    in_use: bool,
+}
+
+impl HeapChunk {
+    pub fn new(address: usize, size: usize, in_use: bool) -> Self {
+        Self {
+            address,
+            size,
+            in_use,
+        }
+    }
+
+    /// The address of the chunk header
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+    /// The address returned by malloc
+    pub fn get_user_address(&self) -> usize {
+        self.address + 2 * SIZE_SZ
+    }
+    /// The size of the chunk including its header
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+    /// The amount of bytes usable by the allocation
+    pub fn get_user_size(&self) -> usize {
+        self.size - 2 * SIZE_SZ
+    }
+    pub fn is_in_use(&self) -> bool {
+        self.in_use
+    }
+}
+
+fn read_usize<Endian: ByteOrder>(bytes: &[u8], offset: usize) -> Option<usize> {
+    let bytes = bytes.get(offset..offset + SIZE_SZ)?;
+
+    if cfg!(target_pointer_width = "64") {
+        Some(Endian::read_u64(bytes) as usize)
+    } else {
+        Some(Endian::read_u32(bytes) as usize)
+    }
+}
+
+/// Walks the chunks from start to end (offsets into bytes). The walk only succeeds if it ends exactly at end.
+fn walk<Endian: ByteOrder>(
+    bytes: &[u8],
+    base: usize,
+    start: usize,
+    end: usize,
+) -> Option<Vec<HeapChunk>> {
+    let mut offsets = Vec::new();
+    let mut offset = start;
+
+    while offset < end {
+        let size = read_usize::<Endian>(bytes, offset + SIZE_SZ)? & !SIZE_BITS;
+
+        if size < MIN_CHUNK_SIZE || !size.is_multiple_of(MALLOC_ALIGNMENT) || end - offset < size {
+            return None;
+        }
+
+        offsets.push((offset, size));
+        offset += size;
+    }
+
+    if offset != end || offsets.is_empty() {
+        return None;
+    }
+
+    let mut chunks = Vec::with_capacity(offsets.len());
+    for (index, (offset, size)) in offsets.iter().enumerate() {
+        // The top chunk is never in use, every other chunk has its state stored in the following chunk
+        let in_use = match offsets.get(index + 1) {
+            Some((next, _)) => read_usize::<Endian>(bytes, next + SIZE_SZ)? & PREV_INUSE != 0,
+            None => false,
+        };
+
+        chunks.push(HeapChunk::new(base + offset, *size, in_use));
+    }
+
+    Some(chunks)
+}
+
+fn find_first_chunk<Endian: ByteOrder>(
+    bytes: &[u8],
+    base: usize,
+    header_size: usize,
+    end: usize,
+) -> Vec<HeapChunk> {
+    (header_size..FIRST_CHUNK_SEARCH_LIMIT.min(end))
+        .step_by(SIZE_SZ)
+        .find_map(|start| walk::<Endian>(bytes, base, start, end))
+        .unwrap_or_default()
+}
+
+fn is_anonymous_data(map: &CachedMap) -> bool {
+    map.get_name() == &MMapPath::Anonymous
+        && map
+            .get_permissions()
+            .contains(MMPermissions::READ | MMPermissions::WRITE)
+}
+
+/// Walks the heap of the main arena
+pub fn main_heap_chunks<Endian: ByteOrder>(maps: &CachedMaps) -> Vec<HeapChunk> {
+    maps.iter()
+        .filter(|map| map.get_name() == &MMapPath::Heap)
+        .flat_map(|map| {
+            find_first_chunk::<Endian>(map.get_bytes(), map.get_from_address(), 0, map.get_size())
+        })
+        .collect()
+}
+
+/// Walks the heaps of all thread arenas
+pub fn thread_heap_chunks<Endian: ByteOrder>(maps: &CachedMaps) -> Vec<HeapChunk> {
+    maps.iter()
+        .filter(|map| {
+            is_anonymous_data(map) && map.get_from_address().is_multiple_of(HEAP_MAX_SIZE)
+        })
+        .flat_map(|map| {
+            let bytes = map.get_bytes();
+
+            // heap_info starts with the arena pointer, the previous heap and the size of the heap
+            let arena = read_usize::<Endian>(bytes, 0);
+            let size = read_usize::<Endian>(bytes, 2 * SIZE_SZ);
+
+            match (arena, size) {
+                (Some(arena), Some(size))
+                    if size > 0 && size <= map.get_size() && maps.find_map(arena).is_some() =>
+                {
+                    find_first_chunk::<Endian>(bytes, map.get_from_address(), 4 * SIZE_SZ, size)
+                }
+                _ => Vec::new(),
+            }
+        })
+        .collect()
+}
+
+/// Walks chunks that were allocated using mmap, because they exceeded the mmap threshold
+pub fn mmapped_chunks<Endian: ByteOrder>(maps: &CachedMaps) -> Vec<HeapChunk> {
+    maps.iter()
+        .filter(|map| is_anonymous_data(map))
+        .flat_map(|map| {
+            let bytes = map.get_bytes();
+            let mut chunks = Vec::new();
+            let mut offset = 0;
+
+            while offset < map.get_size() {
+                let Some(size) = read_usize::<Endian>(bytes, offset + SIZE_SZ) else {
+                    return Vec::new();
+                };
+                if size & IS_MMAPPED == 0 {
+                    return Vec::new();
+                }
+                let size = size & !SIZE_BITS;
+                if size < MIN_CHUNK_SIZE || map.get_size() - offset < size {
+                    return Vec::new();
+                }
+
+                chunks.push(HeapChunk::new(map.get_from_address() + offset, size, true));
+                offset += size;
+            }
+
+            chunks
+        })
+        .collect()
+}
+
+/// Walks all chunks that can be found in the snapshot
+pub fn heap_chunks<Endian: ByteOrder>(maps: &CachedMaps) -> Vec<HeapChunk> {
+    let mut chunks = main_heap_chunks::<Endian>(maps);
+    chunks.extend(thread_heap_chunks::<Endian>(maps));
+    chunks.extend(mmapped_chunks::<Endian>(maps));
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::LittleEndian;
+    use procfs_core::process::{MMPermissions, MMapPath};
+
+    use crate::{cached_map::CachedMap, cached_maps::CachedMaps};
+
+    use super::{
+        heap_chunks, main_heap_chunks, mmapped_chunks, thread_heap_chunks, walk, HeapChunk,
+        HEAP_MAX_SIZE, IS_MMAPPED, PREV_INUSE, SIZE_SZ,
+    };
+
+    const MAIN_HEAP: usize = 0x10000;
+    const MMAPPED: usize = 0x20000;
+    const THREAD_HEAP: usize = HEAP_MAX_SIZE;
+
+    fn write(bytes: &mut [u8], offset: usize, value: usize) {
+        bytes[offset..offset + SIZE_SZ].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes the size field of the chunk at offset
+    fn chunk(bytes: &mut [u8], offset: usize, size: usize) {
+        write(bytes, offset + SIZE_SZ, size);
+    }
+
+    fn map(from: usize, name: MMapPath, bytes: Vec<u8>) -> CachedMap {
+        CachedMap::new(
+            from,
+            from + bytes.len(),
+            MMPermissions::READ | MMPermissions::WRITE | MMPermissions::PRIVATE,
+            name,
+            bytes.into(),
+        )
+    }
+
+    fn maps() -> CachedMaps {
+        // An allocated, a free and another allocated chunk followed by the top chunk
+        let mut heap = vec![0; 0x1000];
+        chunk(&mut heap, 0, 0x20 | PREV_INUSE);
+        chunk(&mut heap, 0x20, 0x40 | PREV_INUSE);
+        chunk(&mut heap, 0x60, 0x20);
+        chunk(&mut heap, 0x80, 0xF80 | PREV_INUSE);
+
+        // heap_info points to the arena behind it, the chunks start after the arena
+        let mut thread_heap = vec![0; 0x1000];
+        write(&mut thread_heap, 0, THREAD_HEAP + 4 * SIZE_SZ);
+        write(&mut thread_heap, 2 * SIZE_SZ, 0x1000);
+        chunk(&mut thread_heap, 0x100, 0x30 | PREV_INUSE);
+        chunk(&mut thread_heap, 0x130, 0xED0 | PREV_INUSE);
+
+        let mut mmapped = vec![0; 0x2000];
+        chunk(&mut mmapped, 0, 0x2000 | IS_MMAPPED);
+
+        CachedMaps::from([
+            map(MAIN_HEAP, MMapPath::Heap, heap),
+            map(MMAPPED, MMapPath::Anonymous, mmapped),
+            map(THREAD_HEAP, MMapPath::Anonymous, thread_heap),
+        ])
+    }
+
+    #[test]
+    fn test_main_heap_chunks() {
+        assert_eq!(
+            main_heap_chunks::<LittleEndian>(&maps()),
+            vec![
+                HeapChunk::new(MAIN_HEAP, 0x20, true),
+                HeapChunk::new(MAIN_HEAP + 0x20, 0x40, false),
+                HeapChunk::new(MAIN_HEAP + 0x60, 0x20, true),
+                HeapChunk::new(MAIN_HEAP + 0x80, 0xF80, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_thread_heap_chunks() {
+        assert_eq!(
+            thread_heap_chunks::<LittleEndian>(&maps()),
+            vec![
+                HeapChunk::new(THREAD_HEAP + 0x100, 0x30, true),
+                HeapChunk::new(THREAD_HEAP + 0x130, 0xED0, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mmapped_chunks() {
+        let chunks = mmapped_chunks::<LittleEndian>(&maps());
+
+        assert_eq!(chunks, vec![HeapChunk::new(MMAPPED, 0x2000, true)]);
+        assert_eq!(chunks[0].get_user_address(), MMAPPED + 2 * SIZE_SZ);
+        assert_eq!(heap_chunks::<LittleEndian>(&maps()).len(), 7);
+    }
+
+    #[test]
+    fn test_walk_must_end_at_the_end() {
+        let mut bytes = vec![0; 0x40];
+        chunk(&mut bytes, 0, 0x20 | PREV_INUSE);
+        chunk(&mut bytes, 0x20, 0x20 | PREV_INUSE);
+
+        assert_eq!(
+            walk::<LittleEndian>(&bytes, 0, 0, 0x40).map(|chunks| chunks.len()),
+            Some(2)
+        );
+        assert_eq!(walk::<LittleEndian>(&bytes, 0, 0, 0x30), None);
+        // A size that isn't aligned can't be a chunk
+        chunk(&mut bytes, 0x20, 0x18 | PREV_INUSE);
+        assert_eq!(walk::<LittleEndian>(&bytes, 0, 0, 0x40), None);
+    }
+}
@@ -0,0 +1,44 @@
+//! Formats memory as a classic hexdump with addresses, hex bytes and printable characters.
+//!
+//! ```text
+//! 0x7f0000001000  48 89 e5 48 83 ec 10 c7  45 fc 2a 00 00 00 8b 45  |H..H....E.*....E|
+//! ```
+
+use alloc::string::String;
+use core::fmt::Write;
+
+/// The number of bytes on each line
+pub const BYTES_PER_LINE: usize = 16;
+
+/// Formats the bytes, which start at the address. Every line ends with a newline.
+pub fn format(address: usize, bytes: &[u8]) -> String {
+    let mut output = String::new();
+
+    for (index, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        let _ = write!(output, "{:#x} ", address + index * BYTES_PER_LINE);
+
+        for column in 0..BYTES_PER_LINE {
+            if column == BYTES_PER_LINE / 2 {
+                output.push(' ');
+            }
+            match line.get(column) {
+                Some(byte) => {
+                    let _ = write!(output, " {byte:02x}");
+                }
+                None => output.push_str("   "),
+            }
+        }
+
+        output.push_str("  |");
+        output.extend(line.iter().map(|byte| {
+            if byte.is_ascii_graphic() || *byte == b' ' {
+                *byte as char
+            } else {
+                '.'
+            }
+        }));
+        output.push_str("|\n");
+    }
+
+    output
+}
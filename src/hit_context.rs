@@ -0,0 +1,54 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use crate::hexdump;
+
+/// A hit together with the bytes surrounding it.
+/// The bytes are cut off at the borders of the mapping containing the hit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HitContext {
+    address: usize,
+    start: usize,
+    bytes: Vec<u8>,
+}
+
+impl HitContext {
+    pub fn new(address: usize, start: usize, bytes: Vec<u8>) -> Self {
+        Self {
+            address,
+            start,
+            bytes,
+        }
+    }
+
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+    /// The address of the first byte
+    pub fn get_start(&self) -> usize {
+        self.start
+    }
+    pub fn get_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+    /// The bytes in front of the hit
+    pub fn get_before(&self) -> &[u8] {
+        &self.bytes[..self.address - self.start]
+    }
+    /// The bytes starting at the hit
+    pub fn get_after(&self) -> &[u8] {
+        &self.bytes[self.address - self.start..]
+    }
+
+    /// Formats the bytes as a hexdump, see [`crate::hexdump`]
+    pub fn hexdump(&self) -> String {
+        hexdump::format(self.start, &self.bytes)
+    }
+}
+
+impl fmt::Display for HitContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:#x}:", self.address)?;
+        write!(f, "{}", self.hexdump())
+    }
+}
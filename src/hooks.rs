@@ -0,0 +1,423 @@
+//! Installs inline hooks into x86-64 processes.
+//!
+//! A hook overwrites the first instructions at its target with a relative `jmp` to a code cave, an unused run of
+//! int3 padding in executable memory or memory given with [`HookSet::add_cave`], e.g. from
+//! [`crate::BcrlFactory::allocate_remote`]. The cave has to be within ±2 GiB of the target and holds:
+//!
+//! - the relay, an absolute `jmp` to the detour
+//! - the trampoline, the displaced instructions relocated using iced-x86 followed by an absolute `jmp` back behind them
+//!
+//! Calling the trampoline runs the original function. The process should be stopped while hooks are installed or
+//! removed, see [`crate::freezer`], since a thread executing the displaced instructions would crash.
+
+use std::{fs::File, io, os::unix::fs::FileExt, rc::Rc};
+
+use iced_x86::{
+    BlockEncoder, BlockEncoderOptions, Decoder, DecoderOptions, Instruction, InstructionBlock,
+};
+use procfs::process::Process;
+use procfs_core::process::MMPermissions;
+
+use crate::{
+    cached_maps::CachedMaps,
+    patch_set::{self, PatchSet},
+    safe_pointer::SafePointer,
+};
+
+/// The size of the relative jump at the target
+pub const JMP_REL32_SIZE: usize = 5;
+/// The size of `jmp [rip]` followed by the absolute destination
+pub const JMP_ABS_SIZE: usize = 14;
+
+/// The longest x86 instruction, bounds how many bytes are read to decode the displaced instructions
+const MAX_INSTRUCTION_SIZE: usize = 15;
+
+/// The byte compilers pad functions with, int3. Runs of zeros aren't used, they may be data or live code.
+const PADDING: u8 = 0xCC;
+
+fn jmp_abs(destination: usize) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0x25, 0x00, 0x00, 0x00, 0x00];
+    bytes.extend_from_slice(&(destination as u64).to_le_bytes());
+    bytes
+}
+
+fn jmp_rel32(from: usize, to: usize) -> Option<Vec<u8>> {
+    let displacement = (to as i64).wrapping_sub((from + JMP_REL32_SIZE) as i64);
+    let displacement = i32::try_from(displacement).ok()?;
+
+    let mut bytes = vec![0xE9];
+    bytes.extend_from_slice(&displacement.to_le_bytes());
+    Some(bytes)
+}
+
+fn is_within_rel32(from: usize, to: usize) -> bool {
+    from.abs_diff(to) < i32::MAX as usize - MAX_INSTRUCTION_SIZE
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+/// How much a short branch can grow when it is relocated, `jrcxz` and `loop` are replaced by three instructions
+const MAX_BRANCH_GROWTH: usize = 8;
+
+/// Decodes the instructions, that are overwritten by the jump at the target.
+/// Returns them together with the amount of displaced bytes and an upper bound for their size once relocated.
+fn displaced_instructions(
+    bytes: &[u8],
+    target: usize,
+) -> io::Result<(Vec<Instruction>, usize, usize)> {
+    let mut decoder = Decoder::with_ip(64, bytes, target as u64, DecoderOptions::NONE);
+    let mut instructions = Vec::new();
+    let mut displaced = 0;
+    let mut relocated = 0;
+
+    while displaced < JMP_REL32_SIZE {
+        let instruction = decoder.decode();
+        if instruction.is_invalid() {
+            return Err(invalid_input(
+                "the target doesn't start with valid instructions",
+            ));
+        }
+        displaced += instruction.len();
+        relocated += instruction.len();
+        if instruction.len() == 2 && instruction.near_branch_target() != 0 {
+            relocated += MAX_BRANCH_GROWTH;
+        }
+        instructions.push(instruction);
+    }
+
+    Ok((instructions, displaced, relocated))
+}
+
+/// Relocates the instructions to the trampoline using iced-x86, which fixes up relative branches and memory operands
+fn relocate(instructions: &[Instruction], trampoline: usize) -> io::Result<Vec<u8>> {
+    let block = InstructionBlock::new(instructions, trampoline as u64);
+    let encoded = BlockEncoder::encode(64, block, BlockEncoderOptions::NONE).map_err(|error| {
+        invalid_input(&format!(
+            "the displaced instructions can't be relocated: {error}"
+        ))
+    })?;
+
+    Ok(encoded.code_buffer)
+}
+
+/// An installed hook
+#[derive(Debug)]
+pub struct Hook {
+    target: usize,
+    detour: usize,
+    cave: (usize, usize),
+    displaced: usize,
+    patches: PatchSet,
+}
+
+impl Hook {
+    pub fn get_target(&self) -> usize {
+        self.target
+    }
+    pub fn get_detour(&self) -> usize {
+        self.detour
+    }
+    /// Calling the trampoline runs the original code at the target
+    pub fn get_trampoline(&self) -> usize {
+        self.cave.0 + JMP_ABS_SIZE
+    }
+    /// The address range in the cave, that is used by the relay and the trampoline
+    pub fn get_cave(&self) -> (usize, usize) {
+        self.cave
+    }
+    /// How many bytes of instructions were moved from the target to the trampoline
+    pub fn get_displaced_length(&self) -> usize {
+        self.displaced
+    }
+}
+
+/// The hooks, which were installed into a process
+#[derive(Debug)]
+pub struct HookSet {
+    process: Process,
+    memory: File,
+    hooks: Vec<Hook>,
+    /// Memory given for caves, which is used before padding
+    caves: Vec<(usize, usize)>,
+}
+
+impl HookSet {
+    pub fn new(process: &Process) -> io::Result<Self> {
+        let process = Process::new(process.pid()).map_err(io::Error::other)?;
+
+        Ok(Self {
+            memory: patch_set::open_memory(&process)?,
+            process,
+            hooks: Vec::new(),
+            caves: Vec::new(),
+        })
+    }
+
+    /// Adds executable memory, that can hold relays and trampolines
+    pub fn add_cave(&mut self, address: usize, size: usize) -> &mut Self {
+        self.caves.push((address, address + size));
+
+        self
+    }
+
+    pub fn get_hooks(&self) -> &[Hook] {
+        &self.hooks
+    }
+    pub fn get_hook(&self, target: usize) -> Option<&Hook> {
+        self.hooks.iter().find(|hook| hook.target == target)
+    }
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// The first address in the range, where size bytes are neither used by another hook nor out of reach of the target
+    fn first_free(&self, (from, to): (usize, usize), size: usize, target: usize) -> Option<usize> {
+        // Caves are packed behind each other, so free space starts at the range or behind the cave of a hook
+        std::iter::once(from)
+            .chain(self.hooks.iter().map(|hook| hook.cave.1))
+            .filter(|start| (from..to).contains(start) && start + size <= to)
+            .filter(|start| {
+                self.hooks
+                    .iter()
+                    .all(|hook| start + size <= hook.cave.0 || hook.cave.1 <= *start)
+            })
+            .find(|start| is_within_rel32(target, *start) && is_within_rel32(target, start + size))
+    }
+
+    /// Finds size free bytes within reach of the target, given caves are preferred over padding in the snapshot
+    fn find_cave(&self, maps: &CachedMaps, target: usize, size: usize) -> Option<usize> {
+        if let Some(cave) = self
+            .caves
+            .iter()
+            .find_map(|cave| self.first_free(*cave, size, target))
+        {
+            return Some(cave);
+        }
+
+        maps.iter()
+            .filter(|map| map.get_permissions().contains(MMPermissions::EXECUTE))
+            .filter(|map| {
+                is_within_rel32(target, map.get_from_address())
+                    || is_within_rel32(target, map.get_to_address())
+            })
+            .find_map(|map| {
+                let bytes = map.get_bytes();
+                let mut offset = 0;
+                while offset < bytes.len() {
+                    if bytes[offset] != PADDING {
+                        offset += 1;
+                        continue;
+                    }
+                    let run = bytes[offset..]
+                        .iter()
+                        .take_while(|byte| **byte == PADDING)
+                        .count();
+                    // Keep a byte of padding in front, so the code before the cave doesn't run into it
+                    let from = map.get_from_address() + offset + 1;
+                    let to = map.get_from_address() + offset + run;
+                    if let Some(cave) = self.first_free((from, to), size, target) {
+                        return Some(cave);
+                    }
+                    offset += run;
+                }
+                None
+            })
+    }
+
+    /// Installs a hook, which redirects the code at the target to the detour.
+    /// The maps are used to find a cave, the bytes are read from the process, since they may have changed.
+    pub fn install(
+        &mut self,
+        maps: &Rc<CachedMaps>,
+        target: usize,
+        detour: usize,
+    ) -> io::Result<&Hook> {
+        if self.get_hook(target).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the target is already hooked",
+            ));
+        }
+
+        let mut bytes = vec![0; JMP_REL32_SIZE + MAX_INSTRUCTION_SIZE];
+        self.memory.read_exact_at(&mut bytes, target as u64)?;
+
+        let (instructions, displaced, relocated) = displaced_instructions(&bytes, target)?;
+        let size = JMP_ABS_SIZE + relocated + JMP_ABS_SIZE;
+        let cave = self.find_cave(maps, target, size).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "no code cave was found within reach of the target",
+            )
+        })?;
+
+        let mut trampoline = relocate(&instructions, cave + JMP_ABS_SIZE)?;
+        if trampoline.len() > relocated {
+            return Err(invalid_input(
+                "the relocated instructions don't fit into the cave",
+            ));
+        }
+        trampoline.extend(jmp_abs(target + displaced));
+
+        let mut code = jmp_abs(detour);
+        code.extend(trampoline);
+        let jump =
+            jmp_rel32(target, cave).ok_or_else(|| invalid_input("the cave is out of reach"))?;
+
+        let mut patches = PatchSet::new(&self.process)?;
+        patches.patch(cave, &code)?;
+        // Pad the displaced bytes with int3, so the remains of split instructions are never executed
+        let mut hook = jump;
+        hook.resize(displaced, 0xCC);
+        if let Err(error) = patches.patch(target, &hook) {
+            patches.restore()?;
+            return Err(error);
+        }
+
+        self.hooks.push(Hook {
+            target,
+            detour,
+            cave: (cave, cave + code.len()),
+            displaced,
+            patches,
+        });
+
+        Ok(self.hooks.last().unwrap())
+    }
+
+    /// Installs a hook at the pointer, see [`Self::install`]
+    pub fn install_at(&mut self, pointer: &SafePointer, detour: usize) -> io::Result<&Hook> {
+        self.install(pointer.get_maps(), pointer.get_address(), detour)
+    }
+
+    /// Removes the hook at the target by restoring the original bytes at the target and in the cave
+    pub fn remove(&mut self, target: usize) -> io::Result<()> {
+        let index = self
+            .hooks
+            .iter()
+            .position(|hook| hook.target == target)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "the target is not hooked"))?;
+
+        self.hooks[index].patches.restore()?;
+        self.hooks.remove(index);
+
+        Ok(())
+    }
+
+    /// Removes all hooks, newest first. If a removal fails, the remaining hooks are kept.
+    pub fn remove_all(&mut self) -> io::Result<()> {
+        while let Some(hook) = self.hooks.last_mut() {
+            hook.patches.restore()?;
+            self.hooks.pop();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use procfs_core::process::MMapPath;
+
+    use crate::{cached_map::CachedMap, BcrlFactory};
+
+    use super::*;
+
+    // A run of padding in the text of the test binary, the hooks of the tests are placed into it
+    std::arch::global_asm!(
+        ".pushsection .text",
+        ".balign 16",
+        ".globl bcrl_hooks_test_padding",
+        "bcrl_hooks_test_padding:",
+        ".fill 256, 1, 0xcc",
+        ".popsection",
+    );
+
+    extern "C" {
+        static bcrl_hooks_test_padding: u8;
+    }
+
+    #[inline(never)]
+    extern "C" fn original(x: u64) -> u64 {
+        std::hint::black_box(x).wrapping_mul(3) + std::hint::black_box(1)
+    }
+
+    extern "C" fn detour(_x: u64) -> u64 {
+        42
+    }
+
+    fn executable_map(from: usize, bytes: &[u8]) -> Rc<CachedMaps> {
+        Rc::new(BTreeSet::from([CachedMap::new(
+            from,
+            from + bytes.len(),
+            MMPermissions::READ | MMPermissions::EXECUTE,
+            MMapPath::Anonymous,
+            bytes.into(),
+        )]))
+    }
+
+    #[test]
+    fn finds_caves_in_int3_padding_only() {
+        let hooks = HookSet::new(&Process::myself().unwrap()).unwrap();
+        let mut bytes = vec![0x90; 16];
+        bytes.extend([0; 128]);
+        bytes.push(0xC3);
+        bytes.extend([0xCC; 64]);
+
+        // The zeros are skipped, a byte of the int3 padding is kept in front of the cave
+        let maps = executable_map(0x10000, &bytes);
+        assert_eq!(
+            hooks.find_cave(&maps, 0x10000, 48),
+            Some(0x10000 + 16 + 128 + 2)
+        );
+        assert_eq!(hooks.find_cave(&maps, 0x10000, 64), None);
+
+        let maps = executable_map(0x10000, &[0; 256]);
+        assert_eq!(hooks.find_cave(&maps, 0x10000, 48), None);
+    }
+
+    #[test]
+    fn prefers_given_caves() {
+        let mut hooks = HookSet::new(&Process::myself().unwrap()).unwrap();
+        let maps = executable_map(0x10000, &[0xCC; 256]);
+
+        // Given caves, which are too small, are skipped
+        hooks.add_cave(0x20000, 0x20);
+        assert_eq!(hooks.find_cave(&maps, 0x10000, 48), Some(0x10001));
+        hooks.add_cave(0x30000, 0x100);
+        assert_eq!(hooks.find_cave(&maps, 0x10000, 48), Some(0x30000));
+    }
+
+    #[test]
+    fn hooks_duplicate_pointers_once() {
+        let process = Process::myself().unwrap();
+        let factory = BcrlFactory::from_process(&process).unwrap();
+        let mut hooks = HookSet::new(&process).unwrap();
+        // Referenced, so the linker keeps the padding
+        std::hint::black_box(std::ptr::addr_of!(bcrl_hooks_test_padding));
+        let function: extern "C" fn(u64) -> u64 = std::hint::black_box(original);
+        assert_eq!(function(2), 7);
+
+        let target = original as *const () as usize;
+        let trampolines = factory
+            .pointers([target, target].into_iter())
+            .hook(&mut hooks, detour as *const () as usize)
+            .unwrap();
+        assert_eq!(trampolines.len(), 1);
+        assert_eq!(hooks.len(), 1);
+        assert_eq!(function(2), 42);
+
+        let trampoline: extern "C" fn(u64) -> u64 = unsafe { std::mem::transmute(trampolines[0]) };
+        assert_eq!(trampoline(2), 7);
+
+        hooks.remove_all().unwrap();
+        assert_eq!(function(2), 7);
+    }
+}
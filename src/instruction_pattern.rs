@@ -0,0 +1,351 @@
+//! Patterns over decoded instructions instead of raw bytes.
+//!
+//! A pattern is a `;`-separated list of instructions, e.g. `lea reg, [rip+?]; call ?; test rax, rax`.
+//! Every instruction consists of a mnemonic (or `?` for any instruction) and optionally its operands.
+//! If no operands are given, they are not checked. Operands can be:
+//! - `?` matches any operand
+//! - `reg` matches any register, a register name (e.g. `rax`) only matches that register
+//! - `imm` matches any immediate or branch target, a number (e.g. `0x10`) only matches that value
+//! - `[...]` matches memory operands, whose parts are separated by `+`:
+//!   registers (or `reg`) for the base and index, `reg*N`/`rax*N` for scaled indices, a number for the displacement
+//!   and `?` to accept any value for the parts, that are not listed. `[rip+?]` matches all RIP-relative operands.
+//!
+//! Since registers can be wildcarded, patterns keep working across different register allocations.
+
+use std::{fmt, str::FromStr};
+
+use iced_x86::{Decoder, DecoderOptions, Instruction, Mnemonic, OpKind, Register};
+
+use crate::disassembly::BITNESS;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstructionPatternError {
+    instruction: usize,
+    message: String,
+}
+
+impl InstructionPatternError {
+    fn new(instruction: usize, message: impl Into<String>) -> Self {
+        Self {
+            instruction,
+            message: message.into(),
+        }
+    }
+
+    /// The instruction (starting at 1) that couldn't be parsed
+    pub fn get_instruction(&self) -> usize {
+        self.instruction
+    }
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for InstructionPatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "instruction {}: {}", self.instruction, self.message)
+    }
+}
+
+impl std::error::Error for InstructionPatternError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterPattern {
+    Any,
+    Exact(Register),
+}
+
+impl RegisterPattern {
+    fn matches(&self, register: Register) -> bool {
+        match self {
+            RegisterPattern::Any => register != Register::None,
+            RegisterPattern::Exact(expected) => *expected == register,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryPattern {
+    base: Option<RegisterPattern>,
+    index: Option<RegisterPattern>,
+    scale: Option<u32>,
+    displacement: Option<u64>,
+    /// Parts, which are not listed, are not checked
+    partial: bool,
+}
+
+impl MemoryPattern {
+    fn matches(&self, instruction: &Instruction) -> bool {
+        let matches_register = |pattern: &Option<RegisterPattern>, register: Register| match pattern
+        {
+            Some(pattern) => pattern.matches(register),
+            None => self.partial || register == Register::None,
+        };
+
+        matches_register(&self.base, instruction.memory_base())
+            && matches_register(&self.index, instruction.memory_index())
+            && match self.scale {
+                Some(scale) => instruction.memory_index_scale() == scale,
+                None => self.partial || instruction.memory_index_scale() == 1,
+            }
+            && match self.displacement {
+                Some(displacement) => instruction.memory_displacement64() == displacement,
+                None => self.partial || instruction.memory_displacement64() == 0,
+            }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OperandPattern {
+    Any,
+    Register(RegisterPattern),
+    AnyImmediate,
+    Immediate(u64),
+    Memory(MemoryPattern),
+}
+
+fn immediate(instruction: &Instruction, operand: u32) -> Option<u64> {
+    match instruction.op_kind(operand) {
+        OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64 => {
+            Some(instruction.near_branch_target())
+        }
+        OpKind::FarBranch16 | OpKind::FarBranch32 => Some(instruction.far_branch32() as u64),
+        OpKind::Immediate8
+        | OpKind::Immediate8_2nd
+        | OpKind::Immediate16
+        | OpKind::Immediate32
+        | OpKind::Immediate64
+        | OpKind::Immediate8to16
+        | OpKind::Immediate8to32
+        | OpKind::Immediate8to64
+        | OpKind::Immediate32to64 => Some(instruction.immediate(operand)),
+        _ => None,
+    }
+}
+
+impl OperandPattern {
+    fn matches(&self, instruction: &Instruction, operand: u32) -> bool {
+        match self {
+            OperandPattern::Any => true,
+            OperandPattern::Register(pattern) => {
+                instruction.op_kind(operand) == OpKind::Register
+                    && pattern.matches(instruction.op_register(operand))
+            }
+            OperandPattern::AnyImmediate => immediate(instruction, operand).is_some(),
+            OperandPattern::Immediate(value) => immediate(instruction, operand) == Some(*value),
+            OperandPattern::Memory(pattern) => {
+                instruction.op_kind(operand) == OpKind::Memory && pattern.matches(instruction)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstructionPatternElement {
+    /// None matches any instruction
+    mnemonic: Option<Mnemonic>,
+    /// None doesn't check the operands
+    operands: Option<Vec<OperandPattern>>,
+}
+
+impl InstructionPatternElement {
+    pub fn matches(&self, instruction: &Instruction) -> bool {
+        if self
+            .mnemonic
+            .is_some_and(|mnemonic| mnemonic != instruction.mnemonic())
+        {
+            return false;
+        }
+
+        match &self.operands {
+            Some(operands) => {
+                operands.len() == instruction.op_count() as usize
+                    && operands
+                        .iter()
+                        .enumerate()
+                        .all(|(index, operand)| operand.matches(instruction, index as u32))
+            }
+            None => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InstructionPattern {
+    elements: Vec<InstructionPatternElement>,
+}
+
+impl InstructionPattern {
+    pub fn get_elements(&self) -> &[InstructionPatternElement] {
+        &self.elements
+    }
+
+    /// Checks whether the first instructions match the pattern
+    pub fn matches(&self, instructions: &[Instruction]) -> bool {
+        instructions.len() >= self.elements.len()
+            && self
+                .elements
+                .iter()
+                .zip(instructions)
+                .all(|(element, instruction)| element.matches(instruction))
+    }
+
+    /// Checks whether the instructions at the start of the bytes match the pattern
+    pub fn matches_bytes(&self, bytes: &[u8], address: usize) -> bool {
+        let mut decoder = Decoder::with_ip(BITNESS, bytes, address as u64, DecoderOptions::NONE);
+
+        self.elements.iter().all(|element| {
+            if !decoder.can_decode() {
+                return false;
+            }
+            let instruction = decoder.decode();
+            !instruction.is_invalid() && element.matches(&instruction)
+        })
+    }
+
+    /// Returns the offsets of all matches inside of the bytes, every byte is considered a possible instruction start
+    pub fn all(&self, bytes: &[u8], address: usize) -> Vec<usize> {
+        (0..bytes.len())
+            .filter(|offset| self.matches_bytes(&bytes[*offset..], address + offset))
+            .collect()
+    }
+}
+
+fn parse_register(name: &str) -> Option<Register> {
+    Register::values()
+        .filter(|register| *register != Register::None)
+        .find(|register| format!("{register:?}").eq_ignore_ascii_case(name))
+}
+
+fn parse_mnemonic(name: &str) -> Option<Mnemonic> {
+    Mnemonic::values()
+        .filter(|mnemonic| *mnemonic != Mnemonic::INVALID)
+        .find(|mnemonic| format!("{mnemonic:?}").eq_ignore_ascii_case(name))
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else if let Some(hex) = text.strip_suffix('h') {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+fn parse_register_pattern(text: &str) -> Option<RegisterPattern> {
+    if text == "reg" {
+        Some(RegisterPattern::Any)
+    } else {
+        parse_register(text).map(RegisterPattern::Exact)
+    }
+}
+
+fn parse_memory(index: usize, text: &str) -> Result<MemoryPattern, InstructionPatternError> {
+    let mut pattern = MemoryPattern::default();
+
+    for term in text.split('+').map(str::trim) {
+        if term == "?" {
+            pattern.partial = true;
+        } else if let Some((register, scale)) = term.split_once('*') {
+            pattern.index = Some(parse_register_pattern(register.trim()).ok_or_else(|| {
+                InstructionPatternError::new(index, format!("unknown register '{register}'"))
+            })?);
+            pattern.scale = Some(scale.trim().parse().map_err(|_| {
+                InstructionPatternError::new(index, format!("invalid scale '{scale}'"))
+            })?);
+        } else if let Some(register) = parse_register_pattern(term) {
+            if pattern.base.is_none() {
+                pattern.base = Some(register);
+            } else if pattern.index.is_none() {
+                pattern.index = Some(register);
+            } else {
+                return Err(InstructionPatternError::new(
+                    index,
+                    "too many registers in memory operand",
+                ));
+            }
+        } else if let Some(displacement) = parse_number(term) {
+            pattern.displacement = Some(displacement);
+        } else {
+            return Err(InstructionPatternError::new(
+                index,
+                format!("invalid memory operand part '{term}'"),
+            ));
+        }
+    }
+
+    Ok(pattern)
+}
+
+fn parse_operand(index: usize, text: &str) -> Result<OperandPattern, InstructionPatternError> {
+    if text == "?" {
+        return Ok(OperandPattern::Any);
+    }
+    if text == "imm" {
+        return Ok(OperandPattern::AnyImmediate);
+    }
+    if let Some(memory) = text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+    {
+        return parse_memory(index, memory).map(OperandPattern::Memory);
+    }
+    if let Some(register) = parse_register_pattern(text) {
+        return Ok(OperandPattern::Register(register));
+    }
+    if let Some(value) = parse_number(text) {
+        return Ok(OperandPattern::Immediate(value));
+    }
+
+    Err(InstructionPatternError::new(
+        index,
+        format!("invalid operand '{text}'"),
+    ))
+}
+
+impl FromStr for InstructionPattern {
+    type Err = InstructionPatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let elements = s
+            .split(';')
+            .map(str::trim)
+            .filter(|instruction| !instruction.is_empty())
+            .enumerate()
+            .map(|(index, instruction)| {
+                let index = index + 1;
+                let (mnemonic, operands) = instruction
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((instruction, ""));
+
+                let mnemonic = match mnemonic {
+                    "?" => None,
+                    name => Some(parse_mnemonic(name).ok_or_else(|| {
+                        InstructionPatternError::new(index, format!("unknown mnemonic '{name}'"))
+                    })?),
+                };
+
+                let operands = operands.trim();
+                let operands = if operands.is_empty() {
+                    None
+                } else {
+                    Some(
+                        operands
+                            .split(',')
+                            .map(|operand| parse_operand(index, operand.trim()))
+                            .collect::<Result<Vec<_>, _>>()?,
+                    )
+                };
+
+                Ok(InstructionPatternElement { mnemonic, operands })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if elements.is_empty() {
+            return Err(InstructionPatternError::new(1, "pattern is empty"));
+        }
+
+        Ok(Self { elements })
+    }
+}
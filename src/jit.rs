@@ -0,0 +1,44 @@
+//! Recognizes the code caches of JIT compilers. HotSpot (and most other JITs, like V8 or LuaJIT) put their code into
+//! anonymous executable maps, ART into a memfd named `jit-cache` or, on older versions, into anonymous maps named
+//! `dalvik-jit-code-cache`.
+//!
+//! These regions are created, resized and freed while the process runs, so a pointer into them may no longer be
+//! valid by the time it is used, see [`crate::session::Session::still_mapped`].
+
+use procfs_core::process::{MMPermissions, MMapPath};
+
+use crate::cached_map::CachedMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum JitKind {
+    /// The code cache of the Android Runtime
+    Art,
+    /// An anonymous executable map, like the code cache of HotSpot
+    Anonymous,
+}
+
+impl JitKind {
+    pub fn of(map: &CachedMap) -> Option<Self> {
+        if !map.get_permissions().contains(MMPermissions::EXECUTE) {
+            return None;
+        }
+
+        match map.get_name() {
+            MMapPath::Anonymous => Some(JitKind::Anonymous),
+            MMapPath::Other(name) if name.contains("jit-code-cache") => Some(JitKind::Art),
+            MMapPath::Path(path)
+                if path
+                    .to_str()
+                    .is_some_and(|path| path.starts_with("/memfd:jit-")) =>
+            {
+                Some(JitKind::Art)
+            }
+            _ => None,
+        }
+    }
+}
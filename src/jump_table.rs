@@ -0,0 +1,266 @@
+//! Detects jump tables, which compilers emit for switch statements.
+//!
+//! Two forms are recognized:
+//! - Relative tables of 32-bit offsets from the table start, which are used in position independent code:
+//!   `lea rdx, [rip+table]; movsxd rax, [rdx+rax*4]; add rax, rdx; jmp rax`
+//! - Absolute tables of code pointers: `jmp [table+rax*8]`
+//!
+//! The number of entries is taken from the bounds check (`cmp eax, imm; ja default`) in front of the jump.
+//! If there is none, entries are read as long as they point into executable memory of the same mapping name.
+
+use byteorder::ByteOrder;
+use iced_x86::{Code, Instruction, Mnemonic, OpKind, Register};
+use procfs_core::process::MMPermissions;
+
+use crate::{
+    cached_map::CachedMap,
+    cached_maps::{CachedMaps, FindAddress},
+    disassembly,
+};
+
+/// How many bytes in front of the jump are analyzed
+const WINDOW: usize = 48;
+/// Tables without a bounds check are cut off after this many entries
+const MAX_ENTRIES: usize = 1024;
+
+const POINTER_SIZE: usize = std::mem::size_of::<usize>();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JumpTableKind {
+    /// 32-bit offsets relative to the start of the table
+    Relative,
+    /// Absolute code pointers
+    Absolute,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JumpTable {
+    jump: usize,
+    table: usize,
+    kind: JumpTableKind,
+    targets: Vec<usize>,
+}
+
+impl JumpTable {
+    /// The address of the indirect jump
+    pub fn get_jump(&self) -> usize {
+        self.jump
+    }
+    /// The address of the first entry
+    pub fn get_table(&self) -> usize {
+        self.table
+    }
+    pub fn get_kind(&self) -> JumpTableKind {
+        self.kind
+    }
+    pub fn get_targets(&self) -> &[usize] {
+        &self.targets
+    }
+}
+
+fn is_indirect_register_jump(instruction: &Instruction) -> bool {
+    instruction.mnemonic() == Mnemonic::Jmp && instruction.op0_kind() == OpKind::Register
+}
+
+fn is_indirect_memory_jump(instruction: &Instruction) -> bool {
+    instruction.mnemonic() == Mnemonic::Jmp
+        && instruction.op0_kind() == OpKind::Memory
+        && instruction.memory_base() == Register::None
+        && instruction.memory_index() != Register::None
+        && instruction.memory_index_scale() as usize == POINTER_SIZE
+}
+
+/// Decodes the instructions from start up to and including the jump, if they end exactly at the jump
+fn decode_up_to(bytes: &[u8], base: usize, start: usize, jump: usize) -> Option<Vec<Instruction>> {
+    let instructions = disassembly::decode(&bytes[start..], base + start, WINDOW);
+
+    let mut end = base + start;
+    let mut sequence = Vec::new();
+    for instruction in instructions {
+        sequence.push(instruction);
+        end += instruction.len();
+        if end > base + jump {
+            return (instruction.ip() as usize == base + jump).then_some(sequence);
+        }
+    }
+
+    None
+}
+
+/// Returns the number of entries, that the bounds check in front of the jump allows
+fn bound(instructions: &[Instruction]) -> Option<usize> {
+    instructions.windows(2).rev().find_map(|pair| {
+        let [compare, branch] = pair else {
+            return None;
+        };
+        if compare.mnemonic() != Mnemonic::Cmp || compare.op0_kind() != OpKind::Register {
+            return None;
+        }
+        let limit = match compare.op1_kind() {
+            OpKind::Immediate8
+            | OpKind::Immediate16
+            | OpKind::Immediate32
+            | OpKind::Immediate8to16
+            | OpKind::Immediate8to32
+            | OpKind::Immediate8to64
+            | OpKind::Immediate32to64 => compare.immediate(1) as usize,
+            _ => return None,
+        };
+
+        match branch.mnemonic() {
+            Mnemonic::Ja => Some(limit + 1),
+            Mnemonic::Jae => Some(limit),
+            _ => None,
+        }
+    })
+}
+
+/// Finds `lea base, [rip+table]; movsxd _, [base+index*4]` and returns the table
+fn relative_table(instructions: &[Instruction]) -> Option<usize> {
+    let load = instructions.iter().rev().find(|instruction| {
+        instruction.code() == Code::Movsxd_r64_rm32
+            && instruction.op1_kind() == OpKind::Memory
+            && instruction.memory_index_scale() == 4
+    })?;
+
+    instructions
+        .iter()
+        .filter(|instruction| instruction.ip() < load.ip())
+        .rev()
+        .find(|instruction| {
+            instruction.mnemonic() == Mnemonic::Lea
+                && instruction.is_ip_rel_memory_operand()
+                && instruction.op0_register() == load.memory_base()
+        })
+        .map(|instruction| instruction.ip_rel_memory_address() as usize)
+}
+
+fn read_entry<Endian: ByteOrder>(
+    maps: &CachedMaps,
+    kind: JumpTableKind,
+    table: usize,
+    index: usize,
+) -> Option<usize> {
+    let (size, address) = match kind {
+        JumpTableKind::Relative => (4, table + index * 4),
+        JumpTableKind::Absolute => (POINTER_SIZE, table + index * POINTER_SIZE),
+    };
+    let map = maps.find_map(address)?;
+    let offset = address - map.get_from_address();
+    let bytes = map.get_bytes().get(offset..offset + size)?;
+
+    Some(match kind {
+        JumpTableKind::Relative => table.wrapping_add_signed(Endian::read_i32(bytes) as isize),
+        JumpTableKind::Absolute => {
+            if cfg!(target_pointer_width = "64") {
+                Endian::read_u64(bytes) as usize
+            } else {
+                Endian::read_u32(bytes) as usize
+            }
+        }
+    })
+}
+
+fn read_targets<Endian: ByteOrder>(
+    maps: &CachedMaps,
+    code: &CachedMap,
+    kind: JumpTableKind,
+    table: usize,
+    count: Option<usize>,
+) -> Vec<usize> {
+    let is_code = |target: usize| {
+        maps.find_map(target).is_some_and(|map| {
+            map.get_permissions().contains(MMPermissions::EXECUTE)
+                && map.get_name() == code.get_name()
+        })
+    };
+
+    let mut targets = Vec::new();
+    for index in 0..count.unwrap_or(MAX_ENTRIES) {
+        match read_entry::<Endian>(maps, kind, table, index) {
+            Some(target) if is_code(target) => targets.push(target),
+            _ => break,
+        }
+    }
+
+    targets
+}
+
+/// Analyzes the indirect jump at the offset inside of the map
+fn analyze<Endian: ByteOrder>(
+    maps: &CachedMaps,
+    map: &CachedMap,
+    jump: usize,
+) -> Option<JumpTable> {
+    let bytes = map.get_bytes();
+    let base = map.get_from_address();
+
+    // Earlier starts give longer sequences, which are more likely to contain the bounds check.
+    // Starts in the middle of instructions may decode to garbage, so the first sequence that makes sense is used.
+    let (instructions, kind, table) = (jump.saturating_sub(WINDOW)..=jump).find_map(|start| {
+        let instructions = decode_up_to(bytes, base, start, jump)?;
+        let jump_instruction = instructions.last()?;
+
+        let (kind, table) = if is_indirect_memory_jump(jump_instruction) {
+            (
+                JumpTableKind::Absolute,
+                jump_instruction.memory_displacement64() as usize,
+            )
+        } else if is_indirect_register_jump(jump_instruction) {
+            (JumpTableKind::Relative, relative_table(&instructions)?)
+        } else {
+            return None;
+        };
+
+        Some((instructions, kind, table))
+    })?;
+    let count = bound(&instructions);
+
+    let targets = read_targets::<Endian>(maps, map, kind, table, count);
+    if targets.is_empty() {
+        return None;
+    }
+
+    Some(JumpTable {
+        jump: base + jump,
+        table,
+        kind,
+        targets,
+    })
+}
+
+/// Finds all jump tables used by code inside of the map, unreadable maps contain none
+pub fn find_jump_tables<Endian: ByteOrder>(
+    maps: &CachedMaps,
+    map: &CachedMap,
+    from: usize,
+    to: usize,
+) -> Vec<JumpTable> {
+    if !map.is_readable() {
+        return Vec::new();
+    }
+    let bytes = map.get_bytes();
+    let base = map.get_from_address();
+
+    (from - base..to - base)
+        .filter(|offset| {
+            // jmp r/m is FF /4, optionally with REX and notrack prefixes in front
+            bytes[*offset] == 0xFF
+                && bytes
+                    .get(offset + 1)
+                    .is_some_and(|modrm| (modrm >> 3) & 0b111 == 4)
+        })
+        .filter_map(|offset| {
+            // Step back over prefixes so the whole instruction is decoded
+            (offset.saturating_sub(2)..=offset).find_map(|start| {
+                let instruction = disassembly::decode(&bytes[start..], base + start, 1);
+                let instruction = instruction.first()?;
+                (instruction.len() + start > offset + 1
+                    && (is_indirect_register_jump(instruction)
+                        || is_indirect_memory_jump(instruction)))
+                .then_some(start)
+            })
+        })
+        .filter_map(|jump| analyze::<Endian>(maps, map, jump))
+        .collect()
+}
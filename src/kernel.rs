@@ -0,0 +1,299 @@
+//! Scans the running kernel and its modules through `/proc/kcore`, which requires root.
+//!
+//! The layout is read from `/proc/kallsyms` for the kernel image and from `/proc/modules` and
+//! `/sys/module/*/sections` for the modules. Every section becomes a map, which is named after its module
+//! (`vmlinux` for the kernel image), so [`SearchConstraints::with_name`] selects a module.
+//! The sizes of module sections are not exported, a section is assumed to end where the next one starts.
+
+use std::{
+    fs::{self, File},
+    io,
+    os::unix::fs::FileExt,
+};
+
+use procfs_core::process::{MMPermissions, MMapPath};
+
+use crate::{
+    address_range::AddressRange,
+    cached_map::CachedMap,
+    cached_maps::CachedMaps,
+    elf::{ElfHeader, ProgramHeader, PT_LOAD},
+    search_constraints::SearchConstraints,
+};
+
+/// The name of the maps, that belong to the kernel image
+pub const KERNEL_IMAGE: &str = "vmlinux";
+
+/// The sections of the kernel image with the symbols marking their start and end
+const IMAGE_SECTIONS: [(&str, &str, &str); 4] = [
+    (".text", "_stext", "_etext"),
+    (".rodata", "__start_rodata", "__end_rodata"),
+    (".data", "_sdata", "_edata"),
+    (".bss", "__bss_start", "__bss_stop"),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KernelSection {
+    module: String,
+    name: String,
+    range: AddressRange,
+}
+
+impl KernelSection {
+    pub fn new(module: impl Into<String>, name: impl Into<String>, range: AddressRange) -> Self {
+        Self {
+            module: module.into(),
+            name: name.into(),
+            range,
+        }
+    }
+
+    pub fn get_module(&self) -> &str {
+        &self.module
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_range(&self) -> AddressRange {
+        self.range
+    }
+
+    /// Guesses the permissions from the name, the kernel doesn't export them
+    pub fn get_permissions(&self) -> MMPermissions {
+        let name = self.name.as_str();
+        if name.starts_with(".text") || name.starts_with(".init.text") || name == ".noinstr.text" {
+            MMPermissions::READ | MMPermissions::EXECUTE
+        } else if name.starts_with(".rodata")
+            || name.starts_with("__ksymtab")
+            || name.starts_with(".note")
+            || name.starts_with("__ex_table")
+        {
+            MMPermissions::READ
+        } else {
+            MMPermissions::READ | MMPermissions::WRITE
+        }
+    }
+}
+
+/// Parses the lines of /proc/modules into the name, address and size of each module
+pub fn parse_modules(modules: &str) -> Vec<(String, usize, usize)> {
+    modules
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next()?;
+            let size = fields.next()?.parse().ok()?;
+            let address = fields.nth(3)?.trim_start_matches("0x");
+
+            Some((
+                name.to_string(),
+                usize::from_str_radix(address, 16).ok()?,
+                size,
+            ))
+        })
+        .collect()
+}
+
+/// Finds the sections of the kernel image in the lines of /proc/kallsyms
+pub fn parse_kallsyms(kallsyms: &str) -> Vec<KernelSection> {
+    let symbol = |name: &str| {
+        kallsyms.lines().find_map(|line| {
+            let mut fields = line.split_whitespace();
+            let address = fields.next()?;
+            (fields.nth(1)? == name).then(|| usize::from_str_radix(address, 16).ok())?
+        })
+    };
+
+    IMAGE_SECTIONS
+        .iter()
+        .filter_map(|(section, start, end)| {
+            let range = AddressRange::new(symbol(start)?, symbol(end)?);
+            // Without root, all addresses are zero
+            (!range.is_empty()).then(|| KernelSection::new(KERNEL_IMAGE, *section, range))
+        })
+        .collect()
+}
+
+/// Divides the module into its sections, which are given by their name and start address.
+/// Sections outside of the range of the module are left out, since their end is unknown.
+pub fn module_sections(
+    module: &str,
+    address: usize,
+    size: usize,
+    mut sections: Vec<(String, usize)>,
+) -> Vec<KernelSection> {
+    let range = AddressRange::new(address, address + size);
+    sections.retain(|(_, start)| range.contains(*start));
+    sections.sort_by_key(|(_, start)| *start);
+
+    let ends = sections
+        .iter()
+        .skip(1)
+        .map(|(_, start)| *start)
+        .chain([range.get_to()])
+        .collect::<Vec<_>>();
+
+    sections
+        .into_iter()
+        .zip(ends)
+        .map(|((name, start), end)| KernelSection::new(module, name, AddressRange::new(start, end)))
+        .filter(|section| !section.range.is_empty())
+        .collect()
+}
+
+/// Where the kernel image and its modules are located
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KernelLayout {
+    sections: Vec<KernelSection>,
+}
+
+impl KernelLayout {
+    pub fn new(sections: Vec<KernelSection>) -> Self {
+        Self { sections }
+    }
+
+    /// Reads the layout of the running kernel
+    pub fn current() -> io::Result<Self> {
+        let mut sections = parse_kallsyms(&fs::read_to_string("/proc/kallsyms")?);
+
+        for (module, address, size) in parse_modules(&fs::read_to_string("/proc/modules")?) {
+            let starts = fs::read_dir(format!("/sys/module/{module}/sections"))?
+                .filter_map(Result::ok)
+                .filter_map(|entry| {
+                    let address = fs::read_to_string(entry.path()).ok()?;
+                    Some((
+                        entry.file_name().into_string().ok()?,
+                        usize::from_str_radix(address.trim().trim_start_matches("0x"), 16).ok()?,
+                    ))
+                })
+                .collect();
+            sections.extend(module_sections(&module, address, size, starts));
+        }
+
+        Ok(Self { sections })
+    }
+
+    pub fn get_sections(&self) -> &[KernelSection] {
+        &self.sections
+    }
+
+    fn ranges(&self, predicate: impl Fn(&KernelSection) -> bool) -> Vec<(usize, usize)> {
+        self.sections
+            .iter()
+            .filter(|section| predicate(section))
+            .map(|section| (section.range.get_from(), section.range.get_to()))
+            .collect()
+    }
+
+    /// Only scans the sections of the module, use [`KERNEL_IMAGE`] for the kernel image
+    pub fn module(&self, module: &str) -> SearchConstraints {
+        SearchConstraints::everything().in_ranges(&self.ranges(|section| section.module == module))
+    }
+
+    /// Only scans the section of the module
+    pub fn section(&self, module: &str, section: &str) -> SearchConstraints {
+        SearchConstraints::everything().in_ranges(
+            &self.ranges(|candidate| candidate.module == module && candidate.name == section),
+        )
+    }
+}
+
+/// Reads the program headers of /proc/kcore
+fn program_headers(kcore: &File) -> io::Result<Vec<ProgramHeader>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "kcore is not an ELF file");
+
+    // The 64-bit header is the larger one
+    let mut bytes = vec![0; 64];
+    kcore.read_exact_at(&mut bytes, 0)?;
+    let header = ElfHeader::parse(&bytes).ok_or_else(invalid)?;
+    let end = header.get_program_header_offset() as usize
+        + header.get_program_header_count() as usize * header.get_program_header_size() as usize;
+    bytes.resize(end, 0);
+    kcore.read_exact_at(&mut bytes, 0)?;
+
+    Ok(header.program_headers(&bytes))
+}
+
+/// Reads every section of the layout from /proc/kcore. Sections, which can't be read, are skipped.
+pub fn snapshot(layout: &KernelLayout, kcore: &File) -> io::Result<CachedMaps> {
+    let segments = program_headers(kcore)?
+        .into_iter()
+        .filter(|header| header.get_type() == PT_LOAD)
+        .collect::<Vec<_>>();
+
+    let mut maps = CachedMaps::new();
+    for section in &layout.sections {
+        let from = section.range.get_from() as u64;
+        let Some(segment) = segments.iter().find(|segment| {
+            let start = segment.get_virtual_address();
+            from >= start && from + section.range.len() as u64 <= start + segment.get_file_size()
+        }) else {
+            continue;
+        };
+
+        let mut bytes = vec![0; section.range.len()];
+        let offset = segment.get_offset() + (from - segment.get_virtual_address());
+        if kcore.read_exact_at(&mut bytes, offset).is_err() {
+            continue;
+        }
+
+        maps.insert(CachedMap::new(
+            section.range.get_from(),
+            section.range.get_to(),
+            section.get_permissions(),
+            MMapPath::Other(section.module.clone()),
+            bytes.into_boxed_slice(),
+        ));
+    }
+
+    Ok(maps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_layout() {
+        let modules = parse_modules(
+            "ext4 1011712 1 - Live 0xffffffffc0a00000\nsnd 135168 0 - Live 0xffffffffc0200000 (O)\n",
+        );
+        assert_eq!(
+            modules,
+            vec![
+                ("ext4".to_string(), 0xffffffffc0a00000, 1011712),
+                ("snd".to_string(), 0xffffffffc0200000, 135168),
+            ]
+        );
+
+        let image = parse_kallsyms(
+            "ffffffff81000000 T _stext\nffffffff82000000 T _etext\n0000000000000000 D __start_rodata\n",
+        );
+        assert_eq!(
+            image,
+            vec![KernelSection::new(
+                KERNEL_IMAGE,
+                ".text",
+                AddressRange::new(0xffffffff81000000, 0xffffffff82000000)
+            )]
+        );
+
+        let sections = module_sections(
+            "snd",
+            0x1000,
+            0x3000,
+            vec![
+                (".data".to_string(), 0x3000),
+                (".text".to_string(), 0x1000),
+                (".init.text".to_string(), 0x9000),
+            ],
+        );
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].get_range(), AddressRange::new(0x1000, 0x3000));
+        assert_eq!(
+            sections[0].get_permissions(),
+            MMPermissions::READ | MMPermissions::EXECUTE
+        );
+        assert_eq!(sections[1].get_range(), AddressRange::new(0x3000, 0x4000));
+    }
+}
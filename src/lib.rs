@@ -7,6 +7,11 @@
 //! - String signatures
 //! - Cross references
 //! - Builder pattern
+//! - Compile-time checked signatures and struct patterns with the `macros` feature
+//! - Inline hooks with the `hooks` feature
+//! - Serializable search constraints with the `serde` feature
+//! - Snapshots of minidumps with the `minidump` feature
+//! - Offline analysis of snapshots without the default `linux` feature, e.g. on `wasm32-unknown-unknown`
 //! - Easy to use
 //!
 //! ## Usage:
@@ -44,6 +49,10 @@
 //! # let session = factory.pointers(Vec::<usize>::new().into_iter());
 //! session.dereference::<NativeEndian>();
 //!
+//! // Dereference using the endianness of the factory, which defaults to the native one
+//! # let session = factory.pointers(Vec::<usize>::new().into_iter());
+//! session.dereference_configured();
+//!
 //! // Dereference relative addresses
 //! # let session = factory.pointers(Vec::<usize>::new().into_iter());
 //! session.relative_to_absolute::<NativeEndian>();
@@ -83,17 +92,106 @@
 //! // And more...
 //! ```
 
+// The parsers and formatters only depend on core and alloc, the rest of the crate requires std.
+// The feature is reserved for a no_std + alloc core, until then it can't be disabled.
+#[cfg(not(feature = "std"))]
+compile_error!("bcrl-rs requires the `std` feature");
+
+extern crate alloc;
+// The derives refer to the crate by name, also in its own tests
+#[cfg(all(test, feature = "macros"))]
+extern crate self as bcrl_rs;
+
+pub mod address_class;
+pub mod address_range;
+pub mod array_scan;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+pub mod breakpoint;
 pub mod cached_map;
 pub mod cached_maps;
+pub mod checkpoint;
+pub mod container;
+pub mod coverage;
+#[cfg(feature = "disassembly")]
+pub mod disassembly;
+#[cfg(feature = "linux")]
+pub mod discovery;
+pub mod elf;
+pub mod endianness;
+pub mod entropy;
+pub mod error;
+pub mod export;
 pub mod factory;
+#[cfg(feature = "linux")]
+pub mod freezer;
+pub mod heap;
+pub mod hexdump;
+pub mod hit_context;
+#[cfg(all(feature = "hooks", target_arch = "x86_64"))]
+pub mod hooks;
+#[cfg(feature = "disassembly")]
+pub mod instruction_pattern;
+pub mod jit;
+#[cfg(feature = "disassembly")]
+pub mod jump_table;
+#[cfg(feature = "linux")]
+pub mod kernel;
+pub mod managed;
+pub mod map_usage;
+pub mod memo;
+pub mod memory_image;
+#[cfg(feature = "minidump")]
+pub mod minidump;
+pub mod module;
+#[cfg(feature = "linux")]
+pub mod module_events;
+#[cfg(feature = "linux")]
+pub mod multi_factory;
+pub mod nearest;
+pub mod page_index;
+pub mod pat;
+pub mod patch;
+#[cfg(feature = "linux")]
+pub mod patch_set;
+pub mod pattern;
+pub mod predicate_registry;
+pub mod prologue;
+pub mod reference_kind;
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+pub mod remote;
 pub mod safe_pointer;
+pub mod scan_plan;
 pub mod search_constraints;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod session;
+pub mod shared_memory;
+#[cfg(feature = "disassembly")]
+pub mod signature_maker;
+pub mod signature_test;
+pub mod statistics;
+pub mod struct_pattern;
+pub mod target;
+pub mod tls;
+pub mod trace;
+pub mod unwind;
+pub mod validation;
+#[cfg(feature = "linux")]
+pub mod watcher;
 
+#[cfg(feature = "macros")]
+pub use bcrl_rs_macros::{sig, ScanPattern};
+#[cfg(feature = "linux")]
+pub use discovery::{find_processes_by_cmdline_regex, find_processes_by_name};
+pub use endianness::Endianness;
 pub use factory::BcrlFactory;
 pub use search_constraints::SearchConstraints;
+pub use struct_pattern::ScanPattern;
+pub use target::TargetSpec;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "linux"))]
 mod tests {
     use byteorder::NativeEndian;
     use procfs::process::Process;
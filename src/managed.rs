@@ -0,0 +1,351 @@
+//! Locates the Mono or Il2Cpp runtime, which Unity games embed, through the functions it exports.
+//!
+//! With the `ptrace` feature, the images, classes and methods of the runtime can be enumerated by calling
+//! its API inside of the process, see [`crate::remote::with_caller`]. The main thread is attached to the runtime
+//! during the calls, since their API may only be used by attached threads, and detached again afterwards.
+
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+use std::io;
+
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+use crate::remote::RemoteCaller;
+use crate::{factory::BcrlFactory, module::Module};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RuntimeKind {
+    Mono,
+    Il2Cpp,
+}
+
+impl RuntimeKind {
+    /// An export, which only the runtime has
+    fn marker(&self) -> &'static str {
+        match self {
+            RuntimeKind::Mono => "mono_get_root_domain",
+            RuntimeKind::Il2Cpp => "il2cpp_domain_get",
+        }
+    }
+}
+
+/// The longest name of an image, class or method, which is read
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+const MAX_NAME_LENGTH: usize = 1024;
+
+/// The metadata table of type definitions in Mono images
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+const MONO_TABLE_TYPEDEF: u64 = 2;
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+const MONO_TOKEN_TYPE_DEF: u64 = 0x0200_0000;
+
+#[derive(Debug)]
+pub struct ManagedRuntime {
+    kind: RuntimeKind,
+    module: Module,
+}
+
+impl ManagedRuntime {
+    /// Finds the module, which exports the API of Il2Cpp or Mono. Il2Cpp is preferred, if both are loaded.
+    pub fn find(factory: &BcrlFactory) -> Option<Self> {
+        let modules = factory.modules();
+
+        [RuntimeKind::Il2Cpp, RuntimeKind::Mono]
+            .into_iter()
+            .find_map(|kind| {
+                modules
+                    .iter()
+                    .position(|module| module.symbol(kind.marker()).is_some())
+                    .map(|index| (kind, index))
+            })
+            .map(|(kind, index)| Self {
+                kind,
+                module: modules.into_iter().nth(index).unwrap(),
+            })
+    }
+
+    pub fn get_kind(&self) -> RuntimeKind {
+        self.kind
+    }
+    pub fn get_module(&self) -> &Module {
+        &self.module
+    }
+
+    /// The address of an API function, e.g. `il2cpp_class_from_name`
+    pub fn function(&self, name: &str) -> Option<usize> {
+        self.module.symbol(name)
+    }
+}
+
+/// An image (assembly) loaded by the runtime
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManagedImage {
+    address: usize,
+    name: String,
+}
+
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+impl ManagedImage {
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManagedClass {
+    address: usize,
+    namespace: String,
+    name: String,
+}
+
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+impl ManagedClass {
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+    pub fn get_namespace(&self) -> &str {
+        &self.namespace
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManagedMethod {
+    address: usize,
+    name: String,
+    pointer: usize,
+}
+
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+impl ManagedMethod {
+    /// The address of the MethodInfo/MonoMethod
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    /// The address of the native code of the method, zero if it has none
+    pub fn get_pointer(&self) -> usize {
+        self.pointer
+    }
+}
+
+#[cfg(all(feature = "ptrace", target_arch = "x86_64"))]
+impl ManagedRuntime {
+    fn prefixed(&self, name: &str) -> String {
+        match self.kind {
+            RuntimeKind::Mono => format!("mono_{name}"),
+            RuntimeKind::Il2Cpp => format!("il2cpp_{name}"),
+        }
+    }
+
+    /// Calls an API function without the `mono_`/`il2cpp_` prefix
+    fn call(
+        &self,
+        caller: &mut RemoteCaller<'_>,
+        name: &str,
+        arguments: &[u64],
+    ) -> io::Result<u64> {
+        let name = self.prefixed(name);
+        let function = self.function(&name).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("{name} is not exported"))
+        })?;
+
+        caller.call(function, arguments)
+    }
+
+    fn string(
+        &self,
+        caller: &mut RemoteCaller<'_>,
+        name: &str,
+        argument: u64,
+    ) -> io::Result<String> {
+        let address = self.call(caller, name, &[argument])?;
+        if address == 0 {
+            return Ok(String::new());
+        }
+
+        caller.read_string(address as usize, MAX_NAME_LENGTH)
+    }
+
+    /// Attaches the calling thread to the root domain, runs the closure with the domain and detaches the thread again.
+    /// Threads, which were attached before, stay attached.
+    fn attached<T>(
+        &self,
+        caller: &mut RemoteCaller<'_>,
+        f: impl FnOnce(&mut RemoteCaller<'_>, u64) -> io::Result<T>,
+    ) -> io::Result<T> {
+        // The current domain of Mono is only set on attached threads
+        let (domain, current) = match self.kind {
+            RuntimeKind::Mono => (
+                self.call(caller, "get_root_domain", &[])?,
+                self.call(caller, "domain_get", &[])?,
+            ),
+            RuntimeKind::Il2Cpp => (
+                self.call(caller, "domain_get", &[])?,
+                self.call(caller, "thread_current", &[])?,
+            ),
+        };
+        let thread = match current {
+            0 => Some(self.call(caller, "thread_attach", &[domain])?),
+            _ => None,
+        };
+
+        let result = f(caller, domain);
+        match thread {
+            Some(thread) => {
+                let detached = self.call(caller, "thread_detach", &[thread]);
+                result.and_then(|result| detached.map(|_| result))
+            }
+            None => result,
+        }
+    }
+
+    /// Enumerates the loaded images. Mono doesn't export a way to list them without a callback,
+    /// there images have to be looked up by name using [`Self::image`].
+    pub fn images(&self, caller: &mut RemoteCaller<'_>) -> io::Result<Vec<ManagedImage>> {
+        if self.kind == RuntimeKind::Mono {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Mono images have to be looked up by name",
+            ));
+        }
+
+        self.attached(caller, |caller, domain| {
+            let size = caller.get_scratch();
+            let assemblies = self.call(caller, "domain_get_assemblies", &[domain, size as u64])?;
+            let count = caller.read_u64(size)?;
+
+            (0..count)
+                .map(|index| {
+                    let assembly = caller.read_u64(assemblies as usize + index as usize * 8)?;
+                    let image = self.call(caller, "assembly_get_image", &[assembly])?;
+
+                    Ok(ManagedImage {
+                        address: image as usize,
+                        name: self.string(caller, "image_get_name", image)?,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// Looks up a loaded image by its name, e.g. `Assembly-CSharp`
+    pub fn image(
+        &self,
+        caller: &mut RemoteCaller<'_>,
+        name: &str,
+    ) -> io::Result<Option<ManagedImage>> {
+        if self.kind == RuntimeKind::Il2Cpp {
+            return Ok(self
+                .images(caller)?
+                .into_iter()
+                .find(|image| image.name.trim_end_matches(".dll") == name));
+        }
+
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.push(0);
+        if bytes.len() > caller.get_scratch_size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the name doesn't fit into the scratch memory",
+            ));
+        }
+        let image = self.attached(caller, |caller, _| {
+            caller.write(caller.get_scratch(), &bytes)?;
+            self.call(caller, "image_loaded", &[caller.get_scratch() as u64])
+        })?;
+
+        Ok((image != 0).then(|| ManagedImage {
+            address: image as usize,
+            name: name.to_string(),
+        }))
+    }
+
+    /// Enumerates the classes defined by the image
+    pub fn classes(
+        &self,
+        caller: &mut RemoteCaller<'_>,
+        image: &ManagedImage,
+    ) -> io::Result<Vec<ManagedClass>> {
+        self.attached(caller, |caller, _| {
+            let image = image.address as u64;
+
+            let classes = match self.kind {
+                RuntimeKind::Il2Cpp => {
+                    let count = self.call(caller, "image_get_class_count", &[image])?;
+                    (0..count)
+                        .map(|index| self.call(caller, "image_get_class", &[image, index]))
+                        .collect::<io::Result<Vec<_>>>()?
+                }
+                RuntimeKind::Mono => {
+                    let rows =
+                        self.call(caller, "image_get_table_rows", &[image, MONO_TABLE_TYPEDEF])?;
+                    // Tokens are 1-based
+                    (1..=rows)
+                        .map(|row| {
+                            self.call(caller, "class_get", &[image, MONO_TOKEN_TYPE_DEF | row])
+                        })
+                        .collect::<io::Result<Vec<_>>>()?
+                }
+            };
+
+            classes
+                .into_iter()
+                .filter(|class| *class != 0)
+                .map(|class| {
+                    Ok(ManagedClass {
+                        address: class as usize,
+                        namespace: self.string(caller, "class_get_namespace", class)?,
+                        name: self.string(caller, "class_get_name", class)?,
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// Enumerates the methods of the class. Mono methods are compiled, if they weren't yet, to get their native code.
+    pub fn methods(
+        &self,
+        caller: &mut RemoteCaller<'_>,
+        class: &ManagedClass,
+    ) -> io::Result<Vec<ManagedMethod>> {
+        self.attached(caller, |caller, _| {
+            // The iterator is an opaque pointer, which starts out as null
+            let iterator = caller.get_scratch();
+            caller.write(iterator, &0u64.to_ne_bytes())?;
+
+            let mut methods = Vec::new();
+            loop {
+                let method = self.call(
+                    caller,
+                    "class_get_methods",
+                    &[class.address as u64, iterator as u64],
+                )?;
+                if method == 0 {
+                    break;
+                }
+
+                let pointer = match self.kind {
+                    // The method pointer is the first field of MethodInfo
+                    RuntimeKind::Il2Cpp => caller.read_u64(method as usize)?,
+                    RuntimeKind::Mono => self.call(caller, "compile_method", &[method])?,
+                };
+                methods.push(ManagedMethod {
+                    address: method as usize,
+                    name: self.string(caller, "method_get_name", method)?,
+                    pointer: pointer as usize,
+                });
+            }
+
+            Ok(methods)
+        })
+    }
+}
@@ -0,0 +1,122 @@
+//! How the memory of a map is backed, read from /proc/$/smaps. Reading smaps makes the kernel walk the page tables
+//! of every map, so it is only done on request, see [`crate::BcrlFactory::with_smaps`].
+//!
+//! Maps, which are swapped out, are slow to read, since every access to one of their pages faults it back in.
+
+use std::fmt;
+
+use procfs_core::process::{MMapExtension, VmFlags};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MapUsage {
+    resident: usize,
+    swapped: usize,
+    huge_pages: usize,
+    locked: bool,
+    huge_page_eligible: bool,
+}
+
+impl MapUsage {
+    pub fn new(
+        resident: usize,
+        swapped: usize,
+        huge_pages: usize,
+        locked: bool,
+        huge_page_eligible: bool,
+    ) -> Self {
+        Self {
+            resident,
+            swapped,
+            huge_pages,
+            locked,
+            huge_page_eligible,
+        }
+    }
+
+    /// Reads the usage from the fields of a smaps entry
+    pub fn from_extension(extension: &MMapExtension) -> Self {
+        let field = |key: &str| extension.map.get(key).copied().unwrap_or(0) as usize;
+
+        Self {
+            resident: field("Rss"),
+            swapped: field("Swap"),
+            huge_pages: field("AnonHugePages") + field("ShmemPmdMapped") + field("FilePmdMapped"),
+            locked: extension.vm_flags.contains(VmFlags::LO) || field("Locked") > 0,
+            // THPeligible is only reported since Linux 4.20, older kernels only tell about madvise(MADV_HUGEPAGE)
+            huge_page_eligible: field("THPeligible") > 0
+                || extension.vm_flags.contains(VmFlags::HG),
+        }
+    }
+
+    /// The bytes of the map, which are in physical memory
+    pub fn get_resident(&self) -> usize {
+        self.resident
+    }
+    /// The bytes of the map, which are swapped out
+    pub fn get_swapped(&self) -> usize {
+        self.swapped
+    }
+    /// The bytes of the map, which are backed by transparent huge pages
+    pub fn get_huge_pages(&self) -> usize {
+        self.huge_pages
+    }
+    /// Whether the map is locked into memory using mlock
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+    /// Whether the map can be backed by transparent huge pages
+    pub fn is_huge_page_eligible(&self) -> bool {
+        self.huge_page_eligible
+    }
+    /// Whether any page of the map is in physical memory
+    pub fn is_resident(&self) -> bool {
+        self.resident > 0
+    }
+    pub fn is_swapped(&self) -> bool {
+        self.swapped > 0
+    }
+}
+
+impl fmt::Display for MapUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} KiB resident, {} KiB swapped",
+            self.resident / 1024,
+            self.swapped / 1024
+        )?;
+        if self.huge_pages > 0 {
+            write!(f, ", {} KiB in huge pages", self.huge_pages / 1024)?;
+        }
+        if self.locked {
+            write!(f, ", locked")?;
+        }
+        if self.huge_page_eligible {
+            write!(f, ", huge page eligible")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_smaps_fields() {
+        let mut extension = MMapExtension::default();
+        extension.map.insert("Rss".to_string(), 8192);
+        extension.map.insert("Swap".to_string(), 4096);
+        extension.map.insert("THPeligible".to_string(), 1);
+        extension.vm_flags = VmFlags::LO;
+
+        let usage = MapUsage::from_extension(&extension);
+        assert_eq!(usage, MapUsage::new(8192, 4096, 0, true, true));
+        assert!(usage.is_resident() && usage.is_swapped());
+        assert_eq!(
+            usage.to_string(),
+            "8 KiB resident, 4 KiB swapped, locked, huge page eligible"
+        );
+    }
+}
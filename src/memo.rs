@@ -0,0 +1,231 @@
+//! Caches the results of scan plans on disk, so later runs against the same binaries don't have to scan again.
+//!
+//! Results are stored relative to the modules containing them. An entry is keyed by a hash of the plan and is only
+//! used if the build-ids of all modules, which the constraints of the plan cover, are unchanged and no module was
+//! added to or removed from them, otherwise the plan is executed again.
+//! Only non-empty results can be cached and only if every covered module has a build-id.
+//!
+//! The store is a text file with one block per plan:
+//! ```text
+//! plan <hash>
+//! module <build-id> <path>
+//! hit <offset> <path>
+//! end
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use procfs_core::process::MMapPath;
+
+use crate::{
+    checkpoint,
+    factory::BcrlFactory,
+    scan_plan::{PlanConstraints, ScanPlan, Step},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct MemoEntry {
+    /// The build-ids of all modules, that are covered by the constraints of the plan
+    modules: Vec<(PathBuf, String)>,
+    /// Offsets from the module bases
+    hits: Vec<(PathBuf, usize)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoStore {
+    path: PathBuf,
+    entries: HashMap<u64, MemoEntry>,
+}
+
+fn hash_plan(plan: &ScanPlan) -> u64 {
+    checkpoint::fnv1a(&plan.to_string())
+}
+
+/// The paths of the modules, which are scanned by the initial scan or a step of the plan, in address order
+fn covered_modules(factory: &BcrlFactory, plan: &ScanPlan) -> Vec<PathBuf> {
+    let constraints = std::iter::once(plan.get_constraints())
+        .chain(plan.get_steps().iter().filter_map(|step| match step {
+            Step::PrevOccurrence(_, constraints) | Step::NextOccurrence(_, constraints) => {
+                Some(constraints)
+            }
+            #[cfg(target_pointer_width = "64")]
+            Step::FindAllReferences(_, constraints)
+            | Step::FindRelativeReferences(_, constraints) => Some(constraints),
+            Step::FindAbsoluteReferences(constraints) => Some(constraints),
+            _ => None,
+        }))
+        .map(PlanConstraints::to_search_constraints)
+        .collect::<Vec<_>>();
+
+    let mut modules = Vec::new();
+    for map in factory.get_cache().iter() {
+        let MMapPath::Path(path) = map.get_name() else {
+            continue;
+        };
+        let covered = constraints.iter().any(|constraints| {
+            constraints.allows_map(map) && !constraints.scanned_ranges(map.get_range()).is_empty()
+        });
+        if covered && !modules.contains(path) {
+            modules.push(path.clone());
+        }
+    }
+
+    modules
+}
+
+fn invalid_data(line: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("malformed memo store at line {line}"),
+    )
+}
+
+fn parse(text: &str) -> io::Result<HashMap<u64, MemoEntry>> {
+    let mut entries = HashMap::new();
+    let mut current: Option<(u64, MemoEntry)> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        match (keyword, &mut current) {
+            ("plan", None) => {
+                let key = u64::from_str_radix(rest, 16).map_err(|_| invalid_data(line_number))?;
+                current = Some((key, MemoEntry::default()));
+            }
+            ("module", Some((_, entry))) => {
+                let (build_id, path) = rest
+                    .split_once(' ')
+                    .ok_or_else(|| invalid_data(line_number))?;
+                entry
+                    .modules
+                    .push((PathBuf::from(path), build_id.to_string()));
+            }
+            ("hit", Some((_, entry))) => {
+                let (offset, path) = rest
+                    .split_once(' ')
+                    .ok_or_else(|| invalid_data(line_number))?;
+                let offset =
+                    usize::from_str_radix(offset, 16).map_err(|_| invalid_data(line_number))?;
+                entry.hits.push((PathBuf::from(path), offset));
+            }
+            ("end", Some(_)) => {
+                let (key, entry) = current.take().unwrap();
+                entries.insert(key, entry);
+            }
+            ("", None) => {}
+            _ => return Err(invalid_data(line_number)),
+        }
+    }
+
+    Ok(entries)
+}
+
+impl MemoStore {
+    /// Loads the store from the file. A missing file results in an empty store.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = match fs::read_to_string(&path) {
+            Ok(text) => parse(&text)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => return Err(error),
+        };
+
+        Ok(Self { path, entries })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the store back to its file
+    pub fn save(&self) -> io::Result<()> {
+        let mut text = String::new();
+        for (key, entry) in &self.entries {
+            text.push_str(&format!("plan {key:x}\n"));
+            for (path, build_id) in &entry.modules {
+                text.push_str(&format!("module {build_id} {}\n", path.display()));
+            }
+            for (path, offset) in &entry.hits {
+                text.push_str(&format!("hit {offset:x} {}\n", path.display()));
+            }
+            text.push_str("end\n");
+        }
+
+        fs::write(&self.path, text)
+    }
+
+    /// Returns the cached results of the plan, if none of the covered modules changed
+    pub fn get(&self, factory: &BcrlFactory, plan: &ScanPlan) -> Option<Vec<usize>> {
+        let entry = self.entries.get(&hash_plan(plan))?;
+
+        // Modules, which were loaded into the covered range since, may contain new hits
+        let covered = covered_modules(factory, plan);
+        if !covered
+            .iter()
+            .all(|path| entry.modules.iter().any(|(module, _)| module == path))
+        {
+            return None;
+        }
+        for (path, build_id) in &entry.modules {
+            let module = factory.module(&MMapPath::Path(path.clone()))?;
+            if module.build_id_hex().as_ref() != Some(build_id) {
+                return None;
+            }
+        }
+
+        entry
+            .hits
+            .iter()
+            .map(|(path, offset)| {
+                factory
+                    .module_base(&MMapPath::Path(path.clone()))
+                    .map(|base| base + offset)
+            })
+            .collect()
+    }
+
+    /// Caches the results of the plan. Returns false if a result is not inside of a module or a module covered by
+    /// the constraints of the plan has no build-id. Empty results are never cached.
+    pub fn insert(&mut self, factory: &BcrlFactory, plan: &ScanPlan, hits: &[usize]) -> bool {
+        if hits.is_empty() {
+            return false;
+        }
+
+        let mut entry = MemoEntry::default();
+
+        let mut modules = covered_modules(factory, plan);
+        for hit in hits {
+            let Some((MMapPath::Path(path), offset)) = factory.module_offset(*hit) else {
+                return false;
+            };
+            if !modules.contains(&path) {
+                modules.push(path.clone());
+            }
+
+            entry.hits.push((path, offset));
+        }
+
+        for path in modules {
+            let Some(build_id) = factory
+                .module(&MMapPath::Path(path.clone()))
+                .and_then(|module| module.build_id_hex())
+            else {
+                return false;
+            };
+            entry.modules.push((path, build_id));
+        }
+
+        self.entries.insert(hash_plan(plan), entry);
+        true
+    }
+
+    /// Forgets the results of the plan
+    pub fn remove(&mut self, plan: &ScanPlan) {
+        self.entries.remove(&hash_plan(plan));
+    }
+}
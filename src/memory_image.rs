@@ -0,0 +1,381 @@
+//! Loads physical memory images for memory forensics, either in the LiME format or as raw images,
+//! in which the file offset is the physical address.
+//!
+//! The address space of a process is reconstructed by translating its virtual addresses into physical ones.
+//! The translation is either supplied by the user, or derived from the page tables of the process,
+//! given its directory table base (CR3), which is usually found through the kernel symbols.
+
+use byteorder::{ByteOrder, LittleEndian};
+use procfs_core::process::{MMPermissions, MMapPath, MemoryMap};
+
+use crate::{cached_map::CachedMap, cached_maps::CachedMaps};
+
+const LIME_MAGIC: u32 = 0x4c69_4d45;
+const LIME_HEADER_SIZE: usize = 32;
+
+const PAGE_SIZE: u64 = 4096;
+
+const PRESENT: u64 = 1 << 0;
+const WRITABLE: u64 = 1 << 1;
+const USER: u64 = 1 << 2;
+const HUGE: u64 = 1 << 7;
+const NO_EXECUTE: u64 = 1 << 63;
+const ADDRESS_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+/// The ranges of physical memory contained in an image
+#[derive(Debug, Default)]
+pub struct PhysicalMemory {
+    ranges: Vec<(u64, Vec<u8>)>,
+}
+
+impl PhysicalMemory {
+    /// Parses a LiME image, which consists of ranges prefixed with a header. Returns None if the image is malformed.
+    pub fn from_lime(bytes: &[u8]) -> Option<Self> {
+        let mut ranges = Vec::new();
+        let mut offset = 0;
+
+        while offset < bytes.len() {
+            let header = bytes.get(offset..offset.checked_add(LIME_HEADER_SIZE)?)?;
+            if LittleEndian::read_u32(header) != LIME_MAGIC {
+                return None;
+            }
+            let start = LittleEndian::read_u64(&header[8..]);
+            // The end address is inclusive
+            let end = LittleEndian::read_u64(&header[16..]).checked_add(1)?;
+            let length = usize::try_from(end.checked_sub(start)?).ok()?;
+
+            offset = offset.checked_add(LIME_HEADER_SIZE)?;
+            let range_end = offset.checked_add(length)?;
+            ranges.push((start, bytes.get(offset..range_end)?.to_vec()));
+            offset = range_end;
+        }
+
+        ranges.sort_by_key(|(start, _)| *start);
+        // Reads look up the last range starting in front of the address, so ranges may not overlap
+        if ranges
+            .windows(2)
+            .any(|pair| pair[0].0 + pair[0].1.len() as u64 > pair[1].0)
+        {
+            return None;
+        }
+
+        Some(Self { ranges })
+    }
+
+    /// A raw image contains the physical memory starting at address 0
+    pub fn from_raw(bytes: Vec<u8>) -> Self {
+        Self {
+            ranges: vec![(0, bytes)],
+        }
+    }
+
+    pub fn get_ranges(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.ranges
+            .iter()
+            .map(|(start, bytes)| (*start, bytes.as_slice()))
+    }
+
+    /// Reads the bytes at the physical address, they may not cross the border of a range
+    pub fn read(&self, address: u64, length: usize) -> Option<&[u8]> {
+        let index = self
+            .ranges
+            .partition_point(|(start, _)| *start <= address)
+            .checked_sub(1)?;
+        let (start, bytes) = &self.ranges[index];
+        let offset = (address - start) as usize;
+
+        bytes.get(offset..offset.checked_add(length)?)
+    }
+
+    fn read_u64(&self, address: u64) -> Option<u64> {
+        self.read(address, 8).map(LittleEndian::read_u64)
+    }
+}
+
+/// Translates virtual addresses of a process into physical addresses
+pub trait Translation {
+    fn translate(&self, memory: &PhysicalMemory, address: u64) -> Option<u64>;
+}
+
+impl<F: Fn(u64) -> Option<u64>> Translation for F {
+    fn translate(&self, _memory: &PhysicalMemory, address: u64) -> Option<u64> {
+        self(address)
+    }
+}
+
+/// A page, that is mapped in the page tables
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MappedPage {
+    address: u64,
+    physical_address: u64,
+    size: u64,
+    permissions: MMPermissions,
+}
+
+impl MappedPage {
+    pub fn get_address(&self) -> u64 {
+        self.address
+    }
+    pub fn get_physical_address(&self) -> u64 {
+        self.physical_address
+    }
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+    pub fn get_permissions(&self) -> MMPermissions {
+        self.permissions
+    }
+}
+
+/// The 4-level page tables of an x86-64 process
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct X86_64PageTables {
+    directory_table_base: u64,
+}
+
+impl X86_64PageTables {
+    /// The directory table base is the value of CR3 while the process runs
+    pub fn new(directory_table_base: u64) -> Self {
+        Self {
+            directory_table_base,
+        }
+    }
+
+    /// Walks the tables for the user half of the address space, tables, which are not in the image, are skipped
+    pub fn user_pages(&self, memory: &PhysicalMemory) -> Vec<MappedPage> {
+        let mut pages = Vec::new();
+        // Only the lower half of the top level table maps the user space
+        self.walk(
+            memory,
+            self.directory_table_base & ADDRESS_MASK,
+            3,
+            0,
+            0..256,
+            WRITABLE | USER,
+            &mut pages,
+        );
+
+        pages
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn walk(
+        &self,
+        memory: &PhysicalMemory,
+        table: u64,
+        level: u32,
+        base: u64,
+        indices: std::ops::Range<u64>,
+        inherited: u64,
+        pages: &mut Vec<MappedPage>,
+    ) {
+        let size = PAGE_SIZE << (9 * level);
+
+        for index in indices {
+            let Some(entry) = memory.read_u64(table + index * 8) else {
+                continue;
+            };
+            if entry & PRESENT == 0 {
+                continue;
+            }
+            let flags =
+                (inherited & entry & (WRITABLE | USER)) | ((inherited | entry) & NO_EXECUTE);
+            if flags & USER == 0 {
+                continue;
+            }
+            let address = base + index * size;
+
+            if level == 0 || (level < 3 && entry & HUGE != 0) {
+                let mut permissions = MMPermissions::READ | MMPermissions::PRIVATE;
+                if flags & WRITABLE != 0 {
+                    permissions |= MMPermissions::WRITE;
+                }
+                if flags & NO_EXECUTE == 0 {
+                    permissions |= MMPermissions::EXECUTE;
+                }
+                pages.push(MappedPage {
+                    address,
+                    physical_address: entry & ADDRESS_MASK & !(size - 1),
+                    size,
+                    permissions,
+                });
+            } else {
+                self.walk(
+                    memory,
+                    entry & ADDRESS_MASK,
+                    level - 1,
+                    address,
+                    0..512,
+                    flags,
+                    pages,
+                );
+            }
+        }
+    }
+
+    /// The mappings of the process, made of adjacent pages with the same permissions
+    pub fn mappings(&self, memory: &PhysicalMemory) -> Vec<MemoryMap> {
+        let mut mappings: Vec<MemoryMap> = Vec::new();
+
+        for page in self.user_pages(memory) {
+            match mappings.last_mut() {
+                Some(last) if last.address.1 == page.address && last.perms == page.permissions => {
+                    last.address.1 += page.size;
+                }
+                _ => mappings.push(MemoryMap {
+                    address: (page.address, page.address + page.size),
+                    perms: page.permissions,
+                    offset: 0,
+                    dev: (0, 0),
+                    inode: 0,
+                    pathname: MMapPath::Anonymous,
+                    extension: Default::default(),
+                }),
+            }
+        }
+
+        mappings
+    }
+}
+
+impl Translation for X86_64PageTables {
+    fn translate(&self, memory: &PhysicalMemory, address: u64) -> Option<u64> {
+        let mut table = self.directory_table_base & ADDRESS_MASK;
+
+        for level in (0..4).rev() {
+            let shift = 12 + 9 * level;
+            let entry = memory.read_u64(table + ((address >> shift) & 0x1ff) * 8)?;
+            if entry & PRESENT == 0 {
+                return None;
+            }
+            if level == 0 || (level < 3 && entry & HUGE != 0) {
+                let size = 1 << shift;
+                return Some((entry & ADDRESS_MASK & !(size - 1)) + (address & (size - 1)));
+            }
+            table = entry & ADDRESS_MASK;
+        }
+
+        None
+    }
+}
+
+/// Reconstructs the address space of a process. The bytes of each mapping are gathered page by page,
+/// mappings are split where pages can't be translated or are not contained in the image.
+pub fn snapshot<'a>(
+    memory: &PhysicalMemory,
+    translation: &impl Translation,
+    mappings: impl IntoIterator<Item = &'a MemoryMap>,
+) -> CachedMaps {
+    let mut maps = CachedMaps::new();
+
+    for map in mappings {
+        let mut start = map.address.0;
+        let mut bytes = Vec::new();
+
+        let mut address = map.address.0;
+        while address < map.address.1 {
+            let length = (PAGE_SIZE - address % PAGE_SIZE).min(map.address.1 - address);
+            let page = translation
+                .translate(memory, address)
+                .and_then(|physical_address| memory.read(physical_address, length as usize));
+
+            match page {
+                Some(page) => bytes.extend_from_slice(page),
+                None => {
+                    if !bytes.is_empty() {
+                        maps.insert(cache_map(map, start, std::mem::take(&mut bytes)));
+                    }
+                    start = address + length;
+                }
+            }
+            address += length;
+        }
+
+        if !bytes.is_empty() {
+            maps.insert(cache_map(map, start, bytes));
+        }
+    }
+
+    maps
+}
+
+fn cache_map(map: &MemoryMap, start: u64, bytes: Vec<u8>) -> CachedMap {
+    CachedMap::new(
+        start as usize,
+        start as usize + bytes.len(),
+        map.perms,
+        map.pathname.clone(),
+        bytes.into_boxed_slice(),
+    )
+    .with_file(map.offset + (start - map.address.0), map.dev, map.inode)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cached_maps::FindAddress;
+
+    use super::*;
+
+    fn lime_range(start: u64, bytes: &[u8]) -> Vec<u8> {
+        let mut range = Vec::new();
+        range.extend_from_slice(&LIME_MAGIC.to_le_bytes());
+        range.extend_from_slice(&1u32.to_le_bytes());
+        range.extend_from_slice(&start.to_le_bytes());
+        range.extend_from_slice(&(start + bytes.len() as u64 - 1).to_le_bytes());
+        range.extend_from_slice(&[0; 8]);
+        range.extend_from_slice(bytes);
+        range
+    }
+
+    #[test]
+    fn rejects_malformed_lime_ranges() {
+        let mut truncated = lime_range(0x1000, &[1, 2, 3, 4]);
+        truncated.pop();
+        assert!(PhysicalMemory::from_lime(&truncated).is_none());
+
+        let mut huge = lime_range(0, &[0]);
+        huge[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(PhysicalMemory::from_lime(&huge).is_none());
+
+        let mut overlapping = lime_range(0x1000, &[0; 16]);
+        overlapping.extend(lime_range(0x1008, &[0; 16]));
+        assert!(PhysicalMemory::from_lime(&overlapping).is_none());
+    }
+
+    #[test]
+    fn walks_page_tables() {
+        // PML4 at 0x0000, PDPT at 0x1000, PD at 0x2000, PT at 0x3000, the page at 0x4000
+        let mut image = vec![0u8; 0x5000];
+        let mut entry = |table: usize, index: usize, value: u64| {
+            image[table + index * 8..table + index * 8 + 8].copy_from_slice(&value.to_le_bytes())
+        };
+        entry(0x0000, 1, 0x1000 | PRESENT | WRITABLE | USER);
+        entry(0x1000, 0, 0x2000 | PRESENT | WRITABLE | USER);
+        entry(0x2000, 0, 0x3000 | PRESENT | USER);
+        entry(0x3000, 2, 0x4000 | PRESENT | WRITABLE | USER | NO_EXECUTE);
+        image[0x4010] = 0x42;
+
+        let memory = PhysicalMemory::from_lime(&lime_range(0, &image)).unwrap();
+        let tables = X86_64PageTables::new(0);
+        let address = (1 << 39) + 2 * PAGE_SIZE;
+
+        assert_eq!(tables.translate(&memory, address + 0x10), Some(0x4010));
+
+        let mappings = tables.mappings(&memory);
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].address, (address, address + PAGE_SIZE));
+        assert_eq!(
+            mappings[0].perms,
+            MMPermissions::READ | MMPermissions::PRIVATE
+        );
+
+        let maps = snapshot(&memory, &tables, &mappings);
+        let map = maps.find_map(address as usize).unwrap();
+        assert_eq!(map.get_bytes()[0x10], 0x42);
+    }
+
+    #[test]
+    fn rejects_malformed_lime_images() {
+        assert!(PhysicalMemory::from_lime(&[0; LIME_HEADER_SIZE]).is_none());
+    }
+}
@@ -0,0 +1,332 @@
+//! Creates snapshots from minidumps (Breakpad/Crashpad), which are read with the `minidump` crate.
+//! The captured memory ranges become the maps of the snapshot, they are named after the module containing them.
+//! Permissions are taken from the memory info list. Dumps without one, like most of Breakpad's, don't tell the
+//! permissions, so their ranges are assumed to be readable, writable and executable.
+//! Ranges are split where a module or memory info region begins or ends, so every part gets its own name and
+//! permissions. Streams, which can't be read, are treated as missing instead of failing the whole dump.
+
+use std::path::PathBuf;
+
+use ::minidump::{
+    Minidump, MinidumpMemory64List, MinidumpMemoryInfoList, MinidumpMemoryList, MinidumpModuleList,
+    Module,
+};
+use procfs_core::process::{MMPermissions, MMapPath};
+
+use crate::{cached_map::CachedMap, cached_maps::CachedMaps};
+
+const MEM_COMMIT: u32 = 0x1000;
+const PAGE_GUARD: u32 = 0x100;
+
+/// A module of the module list stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MinidumpModule {
+    base: usize,
+    size: usize,
+    name: String,
+}
+
+impl MinidumpModule {
+    pub fn get_base(&self) -> usize {
+        self.base
+    }
+    pub fn get_size(&self) -> usize {
+        self.size
+    }
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        (self.base..self.base.saturating_add(self.size)).contains(&address)
+    }
+}
+
+/// A committed region of the memory info list stream
+struct MemoryInfo {
+    from_address: usize,
+    to_address: usize,
+    permissions: MMPermissions,
+}
+
+fn permissions(protect: u32) -> MMPermissions {
+    let mut permissions = MMPermissions::PRIVATE;
+    match protect & 0xff {
+        0x02 => permissions |= MMPermissions::READ,
+        0x04 | 0x08 => permissions |= MMPermissions::READ | MMPermissions::WRITE,
+        0x10 => permissions |= MMPermissions::EXECUTE,
+        0x20 => permissions |= MMPermissions::READ | MMPermissions::EXECUTE,
+        0x40 | 0x80 => {
+            permissions |= MMPermissions::READ | MMPermissions::WRITE | MMPermissions::EXECUTE
+        }
+        _ => {}
+    }
+    if protect & PAGE_GUARD != 0 {
+        permissions.remove(MMPermissions::READ | MMPermissions::WRITE | MMPermissions::EXECUTE);
+    }
+
+    permissions
+}
+
+/// Regions, which don't fit into the address space, are skipped
+fn memory_infos(dump: &Minidump<'_, &[u8]>) -> Vec<MemoryInfo> {
+    let Ok(infos) = dump.get_stream::<MinidumpMemoryInfoList>() else {
+        return Vec::new();
+    };
+
+    infos
+        .iter()
+        .filter(|info| info.raw.state & MEM_COMMIT != 0)
+        .filter_map(|info| {
+            let from_address = usize::try_from(info.raw.base_address).ok()?;
+            let to_address =
+                from_address.checked_add(usize::try_from(info.raw.region_size).ok()?)?;
+
+            Some(MemoryInfo {
+                from_address,
+                to_address,
+                permissions: permissions(info.raw.protection),
+            })
+        })
+        .collect()
+}
+
+fn modules_of(dump: &Minidump<'_, &[u8]>) -> Vec<MinidumpModule> {
+    let Ok(modules) = dump.get_stream::<MinidumpModuleList>() else {
+        return Vec::new();
+    };
+
+    modules
+        .iter()
+        .filter_map(|module| {
+            Some(MinidumpModule {
+                base: usize::try_from(module.base_address()).ok()?,
+                size: usize::try_from(module.size()).ok()?,
+                name: module.code_file().into_owned(),
+            })
+        })
+        .collect()
+}
+
+/// Parses the module list stream. Returns None if the bytes are not a minidump.
+pub fn modules(bytes: &[u8]) -> Option<Vec<MinidumpModule>> {
+    Minidump::read(bytes).ok().map(|dump| modules_of(&dump))
+}
+
+/// Returns the address and the bytes of each captured memory range
+fn memory_ranges<'a>(dump: &'a Minidump<'a, &[u8]>) -> Vec<(u64, &'a [u8])> {
+    let mut ranges = Vec::new();
+
+    if let Ok(memory) = dump.get_stream::<MinidumpMemoryList>() {
+        ranges.extend(memory.iter().map(|range| (range.base_address, range.bytes)));
+    }
+    if let Ok(memory) = dump.get_stream::<MinidumpMemory64List>() {
+        ranges.extend(memory.iter().map(|range| (range.base_address, range.bytes)));
+    }
+
+    ranges
+}
+
+/// Creates a snapshot from the memory ranges of the minidump. Returns None if the bytes are not a minidump.
+pub fn parse(bytes: &[u8]) -> Option<CachedMaps> {
+    let dump = Minidump::read(bytes).ok()?;
+    let modules = modules_of(&dump);
+    let infos = memory_infos(&dump);
+
+    let mut maps = CachedMaps::new();
+    for (address, memory) in memory_ranges(&dump) {
+        let Some((from, to)) = usize::try_from(address)
+            .ok()
+            .and_then(|from| Some((from, from.checked_add(memory.len())?)))
+        else {
+            continue;
+        };
+
+        let mut bounds = modules
+            .iter()
+            .flat_map(|module| [module.base, module.base.saturating_add(module.size)])
+            .chain(
+                infos
+                    .iter()
+                    .flat_map(|info| [info.from_address, info.to_address]),
+            )
+            .filter(|bound| from < *bound && *bound < to)
+            .chain([from, to])
+            .collect::<Vec<_>>();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        for part in bounds.windows(2) {
+            let (start, end) = (part[0], part[1]);
+
+            let name = modules
+                .iter()
+                .find(|module| module.contains(start))
+                .map(|module| MMapPath::Path(PathBuf::from(&module.name)))
+                .unwrap_or(MMapPath::Anonymous);
+            let permissions = if infos.is_empty() {
+                MMPermissions::READ
+                    | MMPermissions::WRITE
+                    | MMPermissions::EXECUTE
+                    | MMPermissions::PRIVATE
+            } else {
+                infos
+                    .iter()
+                    .find(|info| (info.from_address..info.to_address).contains(&start))
+                    .map_or(MMPermissions::PRIVATE, |info| info.permissions)
+            };
+
+            maps.insert(CachedMap::new(
+                start,
+                end,
+                permissions,
+                name,
+                memory[start - from..end - from].into(),
+            ));
+        }
+    }
+
+    Some(maps)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cached_maps::FindAddress;
+
+    use super::*;
+
+    const SIGNATURE: u32 = 0x504d_444d; // "MDMP"
+    const VERSION: u32 = 0xa793;
+
+    const MODULE_LIST_STREAM: u32 = 4;
+    const MEMORY64_LIST_STREAM: u32 = 9;
+    const MEMORY_INFO_LIST_STREAM: u32 = 16;
+
+    const HEADER_SIZE: usize = 32;
+    const DIRECTORY_ENTRY_SIZE: usize = 12;
+    const MODULE_SIZE: usize = 108;
+    const MEMORY_INFO_SIZE: usize = 48;
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_u64(bytes: &mut Vec<u8>, value: u64) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn push_memory_info(bytes: &mut Vec<u8>, base: u64, size: u64, protect: u32) {
+        push_u64(bytes, base);
+        push_u64(bytes, base);
+        push_u32(bytes, protect);
+        push_u32(bytes, 0);
+        push_u64(bytes, size);
+        push_u32(bytes, MEM_COMMIT);
+        push_u32(bytes, protect);
+        push_u32(bytes, 0x20000);
+        push_u32(bytes, 0);
+    }
+
+    /// A minidump with one module and one memory64 range inside of it, which spans two memory info regions,
+    /// the first one executable and the second one writable
+    fn minidump(name_rva: Option<u32>) -> Vec<u8> {
+        let directory = HEADER_SIZE;
+        let module_list = directory + 3 * DIRECTORY_ENTRY_SIZE;
+        let name = module_list + 4 + MODULE_SIZE;
+        let name_units = "/usr/bin/app".encode_utf16().collect::<Vec<_>>();
+        let memory_list = name + 4 + name_units.len() * 2;
+        let info_list = memory_list + 16 + 16;
+        let memory = info_list + 16 + 2 * MEMORY_INFO_SIZE;
+
+        let mut bytes = Vec::new();
+        push_u32(&mut bytes, SIGNATURE);
+        push_u32(&mut bytes, VERSION);
+        push_u32(&mut bytes, 3);
+        push_u32(&mut bytes, directory as u32);
+        bytes.resize(HEADER_SIZE, 0);
+
+        for (stream_type, size, rva) in [
+            (MODULE_LIST_STREAM, 4 + MODULE_SIZE, module_list),
+            (MEMORY64_LIST_STREAM, 16 + 16, memory_list),
+            (
+                MEMORY_INFO_LIST_STREAM,
+                16 + 2 * MEMORY_INFO_SIZE,
+                info_list,
+            ),
+        ] {
+            push_u32(&mut bytes, stream_type);
+            push_u32(&mut bytes, size as u32);
+            push_u32(&mut bytes, rva as u32);
+        }
+
+        push_u32(&mut bytes, 1);
+        push_u64(&mut bytes, 0x1000);
+        push_u32(&mut bytes, 0x2000);
+        push_u32(&mut bytes, 0);
+        push_u32(&mut bytes, 0);
+        push_u32(&mut bytes, name_rva.unwrap_or(name as u32));
+        bytes.resize(module_list + 4 + MODULE_SIZE, 0);
+
+        push_u32(&mut bytes, (name_units.len() * 2) as u32);
+        for unit in name_units {
+            bytes.extend_from_slice(&unit.to_le_bytes());
+        }
+
+        push_u64(&mut bytes, 1);
+        push_u64(&mut bytes, memory as u64);
+        push_u64(&mut bytes, 0x1FFE);
+        push_u64(&mut bytes, 4);
+
+        push_u32(&mut bytes, 16);
+        push_u32(&mut bytes, MEMORY_INFO_SIZE as u32);
+        push_u64(&mut bytes, 2);
+        push_memory_info(&mut bytes, 0x1000, 0x1000, 0x20);
+        push_memory_info(&mut bytes, 0x2000, 0x1000, 0x04);
+
+        bytes.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        bytes
+    }
+
+    #[test]
+    fn parses_memory_modules_and_permissions() {
+        let maps = parse(&minidump(None)).unwrap();
+        let map = maps.find_map(0x1FFE).unwrap();
+
+        assert_eq!(map.get_from_address(), 0x1FFE);
+        assert_eq!(map.get_to_address(), 0x2000);
+        assert_eq!(map.get_bytes(), &[0xde, 0xad]);
+        assert_eq!(map.get_name(), &MMapPath::Path("/usr/bin/app".into()));
+        assert!(map
+            .get_permissions()
+            .contains(MMPermissions::READ | MMPermissions::EXECUTE));
+        assert!(!map.get_permissions().contains(MMPermissions::WRITE));
+    }
+
+    #[test]
+    fn splits_ranges_spanning_memory_infos() {
+        let maps = parse(&minidump(None)).unwrap();
+        let map = maps.find_map(0x2000).unwrap();
+
+        assert_eq!(maps.len(), 2);
+        assert_eq!(map.get_from_address(), 0x2000);
+        assert_eq!(map.get_to_address(), 0x2002);
+        assert_eq!(map.get_bytes(), &[0xbe, 0xef]);
+        assert_eq!(map.get_name(), &MMapPath::Path("/usr/bin/app".into()));
+        assert!(map
+            .get_permissions()
+            .contains(MMPermissions::READ | MMPermissions::WRITE));
+        assert!(!map.get_permissions().contains(MMPermissions::EXECUTE));
+    }
+
+    #[test]
+    fn keeps_memory_of_malformed_module_lists() {
+        let maps = parse(&minidump(Some(u32::MAX))).unwrap();
+
+        assert_eq!(maps.find_map(0x1FFE).unwrap().get_bytes(), &[0xde, 0xad]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(parse(b"\x7fELF").is_none());
+    }
+}
@@ -0,0 +1,589 @@
+use std::{
+    cell::OnceCell,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use procfs_core::process::MMapPath;
+
+use crate::{
+    address_range::AddressRange,
+    cached_map::CachedMap,
+    cached_maps::{CachedMaps, FindAddress},
+    container,
+    elf::{
+        self, ElfHeader, ProgramHeader, SectionHeader, Symbol, PT_DYNAMIC, PT_NOTE, SHT_DYNSYM,
+        SHT_REL, SHT_RELA,
+    },
+};
+
+const NT_GNU_BUILD_ID: u32 = 3;
+const DT_NULL: u64 = 0;
+const DT_STRTAB: u64 = 5;
+const DT_SONAME: u64 = 14;
+
+/// Strings in the dynamic string table are not read beyond this length
+const MAX_STRING_LENGTH: usize = 4096;
+
+type ElfHeaders = (ElfHeader, Vec<ProgramHeader>);
+
+/// Identifies a loaded instance of a module by its full path and the addresses it spans. Unlike the file name, it
+/// tells apart different files with the same name, like `libfoo.so` from two directories, and the same file being
+/// loaded twice. It is only valid for the snapshot it was taken from, see [`module_ids`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModuleId {
+    path: PathBuf,
+    range: AddressRange,
+}
+
+impl ModuleId {
+    pub fn new(path: impl Into<PathBuf>, range: impl Into<AddressRange>) -> Self {
+        Self {
+            path: path.into(),
+            range: range.into(),
+        }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+    /// From the start of the first map of the instance to the end of its last one
+    pub fn get_range(&self) -> AddressRange {
+        self.range
+    }
+    pub fn get_file_name(&self) -> Option<&str> {
+        self.path.file_name()?.to_str()
+    }
+
+    /// Whether the map is part of this instance
+    pub fn contains_map(&self, map: &CachedMap) -> bool {
+        matches!(map.get_name(), MMapPath::Path(path) if *path == self.path)
+            && self.range.contains(map.get_from_address())
+    }
+}
+
+/// Every instance of a file-backed module in address order.
+/// A map of a file starts another instance, if its offset into the file doesn't grow, since the file was mapped
+/// again, or if it maps another file with the same path. Snapshots without file offsets have one instance per path.
+pub fn module_ids(maps: &CachedMaps) -> Vec<ModuleId> {
+    // The instances with the file offset and inode of their last map
+    let mut instances = Vec::<(ModuleId, u64, u64)>::new();
+
+    for map in maps.iter() {
+        let MMapPath::Path(path) = map.get_name() else {
+            continue;
+        };
+
+        let current = instances
+            .iter_mut()
+            .rev()
+            .find(|(id, _, _)| id.path == *path);
+        match current {
+            Some((id, offset, inode))
+                if map.get_inode() == *inode
+                    && (map.get_offset() > *offset || map.get_inode() == 0) =>
+            {
+                id.range = AddressRange::new(id.range.get_from(), map.get_to_address());
+                *offset = map.get_offset();
+            }
+            _ => instances.push((
+                ModuleId::new(path.clone(), map.get_range()),
+                map.get_offset(),
+                map.get_inode(),
+            )),
+        }
+    }
+
+    instances.into_iter().map(|(id, _, _)| id).collect()
+}
+
+/// A file-backed module of the process. The metadata is parsed on first use, either from memory or the backing file.
+#[derive(Debug)]
+pub struct Module {
+    maps: Rc<CachedMaps>,
+    name: MMapPath,
+    from_address: usize,
+    to_address: usize,
+    root: Option<PathBuf>,
+    elf: OnceCell<Option<ElfHeaders>>,
+    build_id: OnceCell<Option<Vec<u8>>>,
+    soname: OnceCell<Option<String>>,
+    sections: OnceCell<Vec<SectionHeader>>,
+    symbols: OnceCell<Vec<Symbol>>,
+}
+
+impl Module {
+    /// Returns None if the module isn't mapped
+    pub fn new(maps: Rc<CachedMaps>, name: MMapPath) -> Option<Self> {
+        let mut module_maps = maps.iter().filter(|map| *map.get_name() == name);
+        let from_address = module_maps.next()?.get_from_address();
+        let to_address = module_maps
+            .last()
+            .or_else(|| maps.find_map(from_address))?
+            .get_to_address();
+
+        Some(Self {
+            maps,
+            name,
+            from_address,
+            to_address,
+            root: None,
+            elf: OnceCell::new(),
+            build_id: OnceCell::new(),
+            soname: OnceCell::new(),
+            sections: OnceCell::new(),
+            symbols: OnceCell::new(),
+        })
+    }
+
+    /// The instance of the module, which is identified by the id. Returns None if it isn't mapped.
+    pub fn from_id(maps: Rc<CachedMaps>, id: &ModuleId) -> Option<Self> {
+        let name = MMapPath::Path(id.path.clone());
+        let module = Self::new(maps, name)?;
+
+        Some(Self {
+            from_address: id.range.get_from(),
+            to_address: id.range.get_to(),
+            ..module
+        })
+        .filter(|module| {
+            module
+                .maps
+                .find_map(module.from_address)
+                .is_some_and(|map| id.contains_map(map))
+        })
+    }
+
+    /// The id of the instance, which starts at the lowest address of the module
+    pub fn id(&self) -> Option<ModuleId> {
+        let path = self.get_path()?;
+        module_ids(&self.maps)
+            .into_iter()
+            .find(|id| id.path == path && id.range.contains(self.from_address))
+    }
+
+    /// Opens the backing file relative to the root directory of the process, see [`crate::container`]
+    pub fn with_root(mut self, root: Option<PathBuf>) -> Self {
+        self.root = root;
+
+        self
+    }
+
+    pub fn get_name(&self) -> &MMapPath {
+        &self.name
+    }
+    pub fn get_path(&self) -> Option<&Path> {
+        match &self.name {
+            MMapPath::Path(path) => Some(path),
+            _ => None,
+        }
+    }
+    /// The lowest address, at which the module is mapped
+    pub fn get_from_address(&self) -> usize {
+        self.from_address
+    }
+    /// The end of the highest mapping of the module
+    pub fn get_to_address(&self) -> usize {
+        self.to_address
+    }
+
+    fn read_file(&self, offset: u64, length: usize) -> Option<Vec<u8>> {
+        let mut bytes = vec![0; length];
+        File::open(container::host_path(self.root.as_deref(), self.get_path()?))
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(&mut bytes)
+            })
+            .ok()?;
+
+        Some(bytes)
+    }
+
+    fn parse_elf(&self) -> Option<ElfHeaders> {
+        // The headers are usually part of the first mapping
+        if let Some(map) = self.maps.find_map(self.from_address) {
+            if let Some(header) = ElfHeader::parse(map.get_bytes()) {
+                let program_headers = header.program_headers(map.get_bytes());
+                if program_headers.len() == header.get_program_header_count() as usize {
+                    return Some((header, program_headers));
+                }
+            }
+        }
+
+        // The 64-bit header is the larger one
+        let bytes = self.read_file(0, 64)?;
+        let header = ElfHeader::parse(&bytes)?;
+        let end = header.get_program_header_offset() as usize
+            + header.get_program_header_count() as usize
+                * header.get_program_header_size() as usize;
+        let bytes = self.read_file(0, end)?;
+        let program_headers = header.program_headers(&bytes);
+
+        Some((header, program_headers))
+    }
+
+    pub fn elf_header(&self) -> Option<&ElfHeader> {
+        self.elf
+            .get_or_init(|| self.parse_elf())
+            .as_ref()
+            .map(|(header, _)| header)
+    }
+
+    pub fn program_headers(&self) -> &[ProgramHeader] {
+        self.elf
+            .get_or_init(|| self.parse_elf())
+            .as_ref()
+            .map(|(_, program_headers)| program_headers.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The virtual address, at which the image expects to be loaded
+    pub fn image_base(&self) -> Option<u64> {
+        elf::image_base(self.program_headers())
+    }
+
+    /// Translates a virtual address of the file into an address of the process
+    pub fn to_runtime_address(&self, virtual_address: u64) -> Option<usize> {
+        let image_base = self.image_base()?;
+        Some(self.from_address + virtual_address.checked_sub(image_base)? as usize)
+    }
+
+    /// Translates an address of the process into a virtual address of the file
+    pub fn to_virtual_address(&self, address: usize) -> Option<u64> {
+        let image_base = self.image_base()?;
+        Some(image_base + address.checked_sub(self.from_address)? as u64)
+    }
+
+    /// Reads up to `length` bytes at a virtual address of the file.
+    /// Memory is preferred, the backing file is used if the bytes are not mapped.
+    fn read(&self, virtual_address: u64, length: usize) -> Option<Vec<u8>> {
+        if let Some(address) = self.to_runtime_address(virtual_address) {
+            if let Some(map) = self.maps.find_map(address) {
+                if *map.get_name() == self.name {
+                    let start = address - map.get_from_address();
+                    let end = (start + length).min(map.get_bytes().len());
+                    if let Some(bytes) = map.get_bytes().get(start..end) {
+                        return Some(bytes.to_vec());
+                    }
+                }
+            }
+        }
+
+        let offset = elf::virtual_address_to_file_offset(self.program_headers(), virtual_address)?;
+        self.read_file(offset, length)
+    }
+
+    fn parse_build_id(&self) -> Option<Vec<u8>> {
+        let header = self.elf_header()?;
+
+        for note in self
+            .program_headers()
+            .iter()
+            .filter(|program_header| program_header.get_type() == PT_NOTE)
+        {
+            let bytes = self.read(note.get_virtual_address(), note.get_file_size() as usize)?;
+            let reader = header.reader(&bytes);
+            let alignment = if note.get_alignment() == 8 { 8 } else { 4 };
+            let align = |offset: usize| offset.next_multiple_of(alignment);
+
+            let mut offset = 0;
+            while offset + 12 <= bytes.len() {
+                let name_size = reader.u32(offset)? as usize;
+                let description_size = reader.u32(offset + 4)? as usize;
+                let note_type = reader.u32(offset + 8)?;
+
+                let name = offset + 12;
+                let description = align(name + name_size);
+                let end = align(description + description_size);
+
+                if note_type == NT_GNU_BUILD_ID
+                    && bytes.get(name..name + name_size) == Some(b"GNU\0")
+                {
+                    return bytes
+                        .get(description..description + description_size)
+                        .map(<[u8]>::to_vec);
+                }
+
+                offset = end;
+            }
+        }
+
+        None
+    }
+
+    /// The GNU build-id, which uniquely identifies the binary
+    pub fn build_id(&self) -> Option<&[u8]> {
+        self.build_id
+            .get_or_init(|| self.parse_build_id())
+            .as_deref()
+    }
+
+    /// The build-id as lowercase hexadecimal string, the same format that `file` and debuginfod use
+    pub fn build_id_hex(&self) -> Option<String> {
+        Some(
+            self.build_id()?
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        )
+    }
+
+    /// Pointers in the dynamic section are relocated by the dynamic linker, but only when they are read from memory
+    fn dynamic_pointer(&self, pointer: u64) -> u64 {
+        if (self.from_address as u64..self.to_address as u64).contains(&pointer) {
+            self.to_virtual_address(pointer as usize).unwrap_or(pointer)
+        } else {
+            pointer
+        }
+    }
+
+    fn parse_soname(&self) -> Option<String> {
+        let header = self.elf_header()?;
+        let dynamic = self
+            .program_headers()
+            .iter()
+            .find(|program_header| program_header.get_type() == PT_DYNAMIC)?;
+
+        let bytes = self.read(
+            dynamic.get_virtual_address(),
+            dynamic.get_file_size() as usize,
+        )?;
+        let reader = header.reader(&bytes);
+        let word = reader.word_size();
+
+        let mut string_table = None;
+        let mut soname = None;
+        for entry in (0..bytes.len() / (2 * word)).map(|index| index * 2 * word) {
+            let tag = reader.word(entry)?;
+            let value = reader.word(entry + word)?;
+            match tag {
+                DT_NULL => break,
+                DT_STRTAB => string_table = Some(self.dynamic_pointer(value)),
+                DT_SONAME => soname = Some(value),
+                _ => {}
+            }
+        }
+
+        let bytes = self.read(string_table? + soname?, MAX_STRING_LENGTH)?;
+        let length = bytes.iter().position(|&byte| byte == 0)?;
+
+        String::from_utf8(bytes[..length].to_vec()).ok()
+    }
+
+    /// The name, under which the shared object is loaded (DT_SONAME). Executables usually don't have one.
+    pub fn soname(&self) -> Option<&str> {
+        self.soname.get_or_init(|| self.parse_soname()).as_deref()
+    }
+
+    fn parse_sections(&self) -> Option<Vec<SectionHeader>> {
+        let header = self.elf_header()?;
+        let table = self.read_file(
+            header.get_section_header_offset(),
+            header.get_section_header_count() as usize * header.get_section_header_size() as usize,
+        )?;
+        let mut sections = header.section_headers(&table);
+
+        let names = sections.get(header.get_section_name_index() as usize)?;
+        let names = self.read_file(names.get_offset(), names.get_size() as usize)?;
+        elf::resolve_section_names(&mut sections, &names);
+
+        Some(sections)
+    }
+
+    /// The section headers, which are read from the backing file. Empty if the file can't be read.
+    pub fn sections(&self) -> &[SectionHeader] {
+        self.sections
+            .get_or_init(|| self.parse_sections().unwrap_or_default())
+    }
+
+    /// Returns the section containing an address of the process
+    pub fn section_of(&self, address: usize) -> Option<&SectionHeader> {
+        let virtual_address = self.to_virtual_address(address)?;
+        self.sections()
+            .iter()
+            .find(|section| section.contains(virtual_address))
+    }
+
+    fn parse_symbols(&self) -> Option<Vec<Symbol>> {
+        let header = self.elf_header()?;
+        let sections = self.sections();
+        let table = sections
+            .iter()
+            .find(|section| section.get_type() == SHT_DYNSYM)?;
+        let names = sections
+            .iter()
+            .find(|section| section.get_name() == ".dynstr")?;
+
+        Some(header.symbols(
+            &self.read_file(table.get_offset(), table.get_size() as usize)?,
+            &self.read_file(names.get_offset(), names.get_size() as usize)?,
+        ))
+    }
+
+    /// The exported symbols (`.dynsym`), which are read from the backing file. Empty if the file can't be read.
+    pub fn exported_symbols(&self) -> &[Symbol] {
+        self.symbols
+            .get_or_init(|| self.parse_symbols().unwrap_or_default())
+    }
+
+    /// Returns the address of an exported symbol in the process
+    pub fn symbol(&self, name: &str) -> Option<usize> {
+        let symbol = self
+            .exported_symbols()
+            .iter()
+            .find(|symbol| symbol.get_name() == name)?;
+
+        self.to_runtime_address(symbol.get_value())
+    }
+
+    /// Returns the addresses of the GOT slots, which the dynamic linker fills with the address of an imported symbol.
+    /// Calls through the PLT and loads of the symbol's address both read one of them.
+    /// The relocations are read from the backing file. Empty if the symbol isn't imported or the file can't be read.
+    pub fn import_slots(&self, name: &str) -> Vec<usize> {
+        self.parse_import_slots(name).unwrap_or_default()
+    }
+
+    /// Returns the addresses, which the dynamic linker patches when the module is loaded, sorted.
+    /// The relocations are read from the backing file. Empty if the file can't be read.
+    pub fn relocated_addresses(&self) -> Vec<usize> {
+        let Some(header) = self.elf_header() else {
+            return Vec::new();
+        };
+
+        let mut addresses = Vec::new();
+        for section in self.sections() {
+            let with_addend = match section.get_type() {
+                SHT_RELA => true,
+                SHT_REL => false,
+                _ => continue,
+            };
+            let Some(table) = self.read_file(section.get_offset(), section.get_size() as usize)
+            else {
+                continue;
+            };
+
+            addresses.extend(
+                header
+                    .relocations(&table, with_addend)
+                    .iter()
+                    .filter_map(|relocation| self.to_runtime_address(relocation.get_offset())),
+            );
+        }
+        addresses.sort_unstable();
+
+        addresses
+    }
+
+    fn parse_import_slots(&self, name: &str) -> Option<Vec<usize>> {
+        let header = self.elf_header()?;
+        let sections = self.sections();
+        let section_bytes = |section: &SectionHeader| {
+            self.read_file(section.get_offset(), section.get_size() as usize)
+        };
+        let symbols = section_bytes(
+            sections
+                .iter()
+                .find(|section| section.get_type() == SHT_DYNSYM)?,
+        )?;
+        let names = section_bytes(
+            sections
+                .iter()
+                .find(|section| section.get_name() == ".dynstr")?,
+        )?;
+
+        let mut slots = Vec::new();
+        for section in sections {
+            let with_addend = match section.get_type() {
+                SHT_RELA => true,
+                SHT_REL => false,
+                _ => continue,
+            };
+            let Some(table) = section_bytes(section) else {
+                continue;
+            };
+
+            for relocation in header.relocations(&table, with_addend) {
+                let is_import = relocation.get_symbol() != 0
+                    && header
+                        .symbol_name(&symbols, &names, relocation.get_symbol() as usize)
+                        .is_some_and(|symbol| symbol == name);
+                // Relocations of the symbol outside of the GOT are e.g. copies or references in data
+                let in_got = sections.iter().any(|section| {
+                    section.get_name().starts_with(".got")
+                        && section.contains(relocation.get_offset())
+                });
+
+                if is_import && in_got {
+                    slots.extend(self.to_runtime_address(relocation.get_offset()));
+                }
+            }
+        }
+
+        Some(slots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use procfs_core::process::{MMPermissions, MMapPath};
+
+    use crate::{
+        address_range::AddressRange, cached_map::CachedMap, cached_maps::CachedMaps,
+        search_constraints::SearchConstraints,
+    };
+
+    use super::{module_ids, ModuleId};
+
+    fn map(from: usize, to: usize, path: &str, offset: u64, inode: u64) -> CachedMap {
+        CachedMap::new(
+            from,
+            to,
+            MMPermissions::READ,
+            MMapPath::Path(path.into()),
+            vec![0; to - from].into(),
+        )
+        .with_file(offset, (8, 1), inode)
+    }
+
+    #[test]
+    fn test_module_ids() {
+        let mut maps = CachedMaps::new();
+        maps.insert(map(0x1000, 0x2000, "/a/libfoo.so", 0, 1));
+        maps.insert(map(0x2000, 0x3000, "/a/libfoo.so", 0x1000, 1));
+        maps.insert(map(0x3000, 0x4000, "/b/libfoo.so", 0, 2));
+        // The first file loaded again, e.g. with dlmopen
+        maps.insert(map(0x5000, 0x6000, "/a/libfoo.so", 0, 1));
+        maps.insert(map(0x6000, 0x7000, "/a/libfoo.so", 0x1000, 1));
+
+        let ids = module_ids(&maps);
+        assert_eq!(
+            ids,
+            vec![
+                ModuleId::new("/a/libfoo.so", (0x1000, 0x3000)),
+                ModuleId::new("/b/libfoo.so", (0x3000, 0x4000)),
+                ModuleId::new("/a/libfoo.so", (0x5000, 0x7000)),
+            ]
+        );
+
+        let allowed = |constraints: SearchConstraints| {
+            maps.iter()
+                .filter(|map| constraints.test(map))
+                .map(|map| map.get_range())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            allowed(SearchConstraints::everything().with_path("/b/libfoo.so")),
+            vec![AddressRange::new(0x3000, 0x4000)]
+        );
+        assert_eq!(
+            allowed(SearchConstraints::everything().in_module(&ids[2])),
+            vec![
+                AddressRange::new(0x5000, 0x6000),
+                AddressRange::new(0x6000, 0x7000)
+            ]
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Reports modules, which were mapped or unmapped, so caches and resolved pointers depending on them can be invalidated.
+//!
+//! Events are either produced by the refreshes of a factory, see [`crate::BcrlFactory::module_events`],
+//! or by a background thread polling the mappings of a process, see [`poll_module_events`].
+//! A module is identified by its path and base address, a module, which was reloaded at another address,
+//! is reported as unloaded and loaded again.
+
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use procfs::process::Process;
+use procfs_core::process::MMapPath;
+
+use crate::cached_maps::CachedMaps;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ModuleEvent {
+    Loaded { path: PathBuf, base: usize },
+    Unloaded { path: PathBuf, base: usize },
+}
+
+impl ModuleEvent {
+    pub fn get_path(&self) -> &PathBuf {
+        match self {
+            ModuleEvent::Loaded { path, .. } | ModuleEvent::Unloaded { path, .. } => path,
+        }
+    }
+    pub fn get_base(&self) -> usize {
+        match self {
+            ModuleEvent::Loaded { base, .. } | ModuleEvent::Unloaded { base, .. } => *base,
+        }
+    }
+}
+
+/// The file-backed modules with their lowest mapped address
+pub(crate) type LoadedModules = BTreeMap<PathBuf, usize>;
+
+fn loaded_modules<'a>(maps: impl Iterator<Item = (&'a MMapPath, usize)>) -> LoadedModules {
+    let mut modules = LoadedModules::new();
+    for (name, address) in maps {
+        if let MMapPath::Path(path) = name {
+            modules
+                .entry(path.clone())
+                .and_modify(|base| *base = (*base).min(address))
+                .or_insert(address);
+        }
+    }
+
+    modules
+}
+
+pub(crate) fn modules_of(maps: &CachedMaps) -> LoadedModules {
+    loaded_modules(
+        maps.iter()
+            .map(|map| (map.get_name(), map.get_from_address())),
+    )
+}
+
+/// The events, that turn the old modules into the new ones. Unloads are reported first.
+pub(crate) fn diff(old: &LoadedModules, new: &LoadedModules) -> Vec<ModuleEvent> {
+    let unloaded = old
+        .iter()
+        .filter(|(path, base)| new.get(*path) != Some(base))
+        .map(|(path, base)| ModuleEvent::Unloaded {
+            path: path.clone(),
+            base: *base,
+        });
+    let loaded = new
+        .iter()
+        .filter(|(path, base)| old.get(*path) != Some(base))
+        .map(|(path, base)| ModuleEvent::Loaded {
+            path: path.clone(),
+            base: *base,
+        });
+
+    unloaded.chain(loaded).collect()
+}
+
+/// Sends the events to every subscriber and forgets the ones, which dropped their receiver
+pub(crate) fn publish(subscribers: &mut Vec<Sender<ModuleEvent>>, events: &[ModuleEvent]) {
+    subscribers.retain(|subscriber| {
+        events
+            .iter()
+            .all(|event| subscriber.send(event.clone()).is_ok())
+    });
+}
+
+/// Polls the mappings of the process in the interval on a background thread. The modules mapped at the start are not
+/// reported. The thread stops once the receiver is dropped or the process can't be read anymore, e.g. because it exited.
+pub fn poll_module_events(pid: i32, interval: Duration) -> Receiver<ModuleEvent> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let read = || -> Option<LoadedModules> {
+            let mappings = Process::new(pid).ok()?.maps().ok()?;
+            Some(loaded_modules(
+                mappings
+                    .iter()
+                    .map(|map| (&map.pathname, map.address.0 as usize)),
+            ))
+        };
+
+        let Some(mut modules) = read() else {
+            return;
+        };
+        loop {
+            thread::sleep(interval);
+            let Some(current) = read() else {
+                return;
+            };
+
+            for event in diff(&modules, &current) {
+                if sender.send(event).is_err() {
+                    return;
+                }
+            }
+            modules = current;
+        }
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_modules() {
+        let old = LoadedModules::from([("/a.so".into(), 0x1000), ("/b.so".into(), 0x2000)]);
+        let new = LoadedModules::from([("/b.so".into(), 0x3000), ("/c.so".into(), 0x4000)]);
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                ModuleEvent::Unloaded {
+                    path: "/a.so".into(),
+                    base: 0x1000
+                },
+                ModuleEvent::Unloaded {
+                    path: "/b.so".into(),
+                    base: 0x2000
+                },
+                ModuleEvent::Loaded {
+                    path: "/b.so".into(),
+                    base: 0x3000
+                },
+                ModuleEvent::Loaded {
+                    path: "/c.so".into(),
+                    base: 0x4000
+                },
+            ]
+        );
+    }
+}
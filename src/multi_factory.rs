@@ -0,0 +1,129 @@
+//! Runs the same scan against several processes, for example a game and its helper processes.
+
+use std::{collections::BTreeMap, io};
+
+use procfs::{process::Process, ProcError};
+use regex::Regex;
+use signature_scanner::Signature;
+
+use crate::{
+    checkpoint::ScanCheckpoint, discovery, factory::BcrlFactory,
+    search_constraints::SearchConstraints,
+};
+
+/// A factory per process. Processes, which couldn't be snapshotted, are remembered with their error.
+pub struct MultiFactory {
+    factories: BTreeMap<i32, BcrlFactory>,
+    errors: BTreeMap<i32, ProcError>,
+}
+
+impl MultiFactory {
+    pub fn from_processes(processes: impl IntoIterator<Item = Process>) -> Self {
+        let mut factories = BTreeMap::new();
+        let mut errors = BTreeMap::new();
+
+        for process in processes {
+            match BcrlFactory::from_process(&process) {
+                Ok(factory) => {
+                    factories.insert(process.pid(), factory);
+                }
+                Err(error) => {
+                    errors.insert(process.pid(), error);
+                }
+            }
+        }
+
+        Self { factories, errors }
+    }
+
+    pub fn from_pids(pids: impl IntoIterator<Item = i32>) -> Self {
+        let mut errors = BTreeMap::new();
+        let processes = pids
+            .into_iter()
+            .filter_map(|pid| {
+                Process::new(pid)
+                    .map_err(|error| errors.insert(pid, error))
+                    .ok()
+            })
+            .collect::<Vec<_>>();
+
+        let mut factory = Self::from_processes(processes);
+        factory.errors.extend(errors);
+        factory
+    }
+
+    /// Snapshots every process with the name, see [`discovery::find_processes_by_name`]
+    pub fn from_name(name: &str) -> Result<Self, ProcError> {
+        Ok(Self::from_processes(discovery::find_processes_by_name(
+            name,
+        )?))
+    }
+
+    /// Snapshots every process, whose command line matches the regex, see [`discovery::find_processes_by_cmdline_regex`]
+    pub fn from_cmdline_regex(regex: &Regex) -> Result<Self, ProcError> {
+        Ok(Self::from_processes(
+            discovery::find_processes_by_cmdline_regex(regex)?,
+        ))
+    }
+
+    pub fn get_factories(&self) -> &BTreeMap<i32, BcrlFactory> {
+        &self.factories
+    }
+    pub fn get_factory(&self, pid: i32) -> Option<&BcrlFactory> {
+        self.factories.get(&pid)
+    }
+    /// The processes, which couldn't be snapshotted, and why
+    pub fn get_errors(&self) -> &BTreeMap<i32, ProcError> {
+        &self.errors
+    }
+    pub fn get_pids(&self) -> impl Iterator<Item = i32> + '_ {
+        self.factories.keys().copied()
+    }
+
+    /// Runs the pipeline against every factory and returns the results by pid
+    pub fn run<T>(&self, mut pipeline: impl FnMut(&BcrlFactory) -> T) -> BTreeMap<i32, T> {
+        self.factories
+            .iter()
+            .map(|(pid, factory)| (*pid, pipeline(factory)))
+            .collect()
+    }
+
+    /// Scans every process for the signature, processes without hits are left out
+    pub fn signature(
+        &self,
+        pattern: &Signature,
+        constraints: &SearchConstraints,
+    ) -> BTreeMap<i32, Vec<usize>> {
+        let mut hits = self.run(|factory| {
+            factory
+                .signature(pattern.clone(), constraints.clone())
+                .get_pool()
+                .collect::<Vec<_>>()
+        });
+        hits.retain(|_, hits| !hits.is_empty());
+        hits
+    }
+
+    /// Scans every process for the signature like [`Self::signature`], but records the progress of all processes in
+    /// the checkpoint, see [`BcrlFactory::signature_resumable`]. Processes, which were finished before an
+    /// interruption, are not scanned again.
+    pub fn signature_resumable(
+        &self,
+        pattern: &Signature,
+        constraints: &SearchConstraints,
+        checkpoint: &mut ScanCheckpoint,
+    ) -> io::Result<BTreeMap<i32, Vec<usize>>> {
+        let mut hits = BTreeMap::new();
+        for (pid, factory) in &self.factories {
+            let pool = factory
+                .signature_resumable(pattern.clone(), constraints.clone(), checkpoint)?
+                .get_pool()
+                .collect::<Vec<_>>();
+            if !pool.is_empty() {
+                hits.insert(*pid, pool);
+            }
+        }
+
+        Ok(hits)
+    }
+}
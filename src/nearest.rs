@@ -0,0 +1,130 @@
+//! Attributes arbitrary addresses, like pointers found on stacks or in heaps, to the closest known location in front of them.
+//! Known locations are exported symbols, probable function starts and named hits of earlier scans.
+
+use std::{collections::BTreeMap, fmt};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnchorKind {
+    Symbol,
+    FunctionStart,
+    Hit,
+}
+
+/// A known location at or in front of an address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Anchor {
+    address: usize,
+    distance: usize,
+    kind: AnchorKind,
+    name: Option<String>,
+}
+
+impl Anchor {
+    pub fn new(address: usize, distance: usize, kind: AnchorKind, name: Option<String>) -> Self {
+        Self {
+            address,
+            distance,
+            kind,
+            name,
+        }
+    }
+
+    /// The address of the known location
+    pub fn get_address(&self) -> usize {
+        self.address
+    }
+    /// How far the queried address is behind the known location
+    pub fn get_distance(&self) -> usize {
+        self.distance
+    }
+    pub fn get_kind(&self) -> AnchorKind {
+        self.kind
+    }
+    /// Function starts found by their prologue don't have a name
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+impl fmt::Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{name}+{:#x}", self.distance),
+            None => write!(f, "{:#x}+{:#x}", self.address, self.distance),
+        }
+    }
+}
+
+/// Named hits of earlier scans, ordered by their address
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HitIndex {
+    hits: BTreeMap<usize, String>,
+}
+
+impl HitIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a hit, a later hit at the same address replaces its name
+    pub fn insert(&mut self, name: impl Into<String>, address: usize) {
+        self.hits.insert(address, name.into());
+    }
+
+    /// Records all hits of a scan under the same name, e.g. the pool of a session
+    pub fn extend(&mut self, name: &str, addresses: impl IntoIterator<Item = usize>) {
+        for address in addresses {
+            self.insert(name, address);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.hits.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.hits.is_empty()
+    }
+
+    /// The hit at or closest in front of the address
+    pub fn nearest_preceding(&self, address: usize) -> Option<Anchor> {
+        let (hit, name) = self.hits.range(..=address).next_back()?;
+
+        Some(Anchor::new(
+            *hit,
+            address - hit,
+            AnchorKind::Hit,
+            Some(name.clone()),
+        ))
+    }
+}
+
+/// The anchor, which is closest to the address
+pub fn closest(anchors: impl IntoIterator<Item = Option<Anchor>>) -> Option<Anchor> {
+    anchors
+        .into_iter()
+        .flatten()
+        .min_by_key(|anchor| anchor.distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_preceding_hits() {
+        let mut hits = HitIndex::new();
+        hits.extend("entity", [0x1000, 0x2000]);
+        hits.insert("player", 0x3000);
+
+        let anchor = hits.nearest_preceding(0x2010).unwrap();
+        assert_eq!(anchor.get_address(), 0x2000);
+        assert_eq!(anchor.to_string(), "entity+0x10");
+        assert!(hits.nearest_preceding(0xfff).is_none());
+
+        let function = Anchor::new(0x2008, 8, AnchorKind::FunctionStart, None);
+        assert_eq!(
+            closest([Some(anchor), Some(function.clone()), None]),
+            Some(function)
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! A coarse index over the bytes of a map, which records which byte values occur on each page.
+//! Scans use it to skip pages, that can't contain the rarest literal byte of a signature.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::{cached_maps::CachedMaps, search_constraints::ScanDirection};
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// One 256-bit byte-presence bitmap per page of a map
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageIndex {
+    pages: Vec<[u64; 4]>,
+    length: usize,
+}
+
+impl PageIndex {
+    pub fn new(bytes: &[u8]) -> Self {
+        let pages = bytes
+            .chunks(PAGE_SIZE)
+            .map(|page| {
+                let mut bitmap = [0u64; 4];
+                for byte in page {
+                    bitmap[(*byte >> 6) as usize] |= 1 << (*byte & 63);
+                }
+                bitmap
+            })
+            .collect();
+
+        Self {
+            pages,
+            length: bytes.len(),
+        }
+    }
+
+    pub fn get_page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Returns true if the byte occurs on the page
+    pub fn contains(&self, page: usize, byte: u8) -> bool {
+        self.pages
+            .get(page)
+            .is_some_and(|bitmap| bitmap[(byte >> 6) as usize] & (1 << (byte & 63)) != 0)
+    }
+
+    /// The number of pages on which the byte occurs
+    pub fn count_pages(&self, byte: u8) -> usize {
+        (0..self.pages.len())
+            .filter(|page| self.contains(*page, byte))
+            .count()
+    }
+
+    /// Returns the literal byte of the elements, which occurs on the fewest pages, and its position inside of the elements
+    pub fn rarest_literal(&self, elements: &[Option<u8>]) -> Option<(usize, u8)> {
+        let mut seen = [false; 256];
+
+        elements
+            .iter()
+            .enumerate()
+            .filter_map(|(position, element)| Some((position, (*element)?)))
+            .filter(|(_, byte)| !std::mem::replace(&mut seen[*byte as usize], true))
+            .min_by_key(|(_, byte)| self.count_pages(*byte))
+    }
+
+    /// Returns the sorted and disjoint offsets, which have to be scanned to find every match of the elements.
+    /// Matches are only possible around pages, which contain the rarest literal byte. Returns None if there are no literals.
+    pub fn candidate_ranges(&self, elements: &[Option<u8>]) -> Option<Vec<Range<usize>>> {
+        let (position, byte) = self.rarest_literal(elements)?;
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+
+        for page in (0..self.pages.len()).filter(|page| self.contains(*page, byte)) {
+            // The literal lies on the page, so the match has to start `position` bytes in front of it
+            let start = (page * PAGE_SIZE).saturating_sub(position);
+            let end = ((page + 1) * PAGE_SIZE + elements.len() - 1)
+                .saturating_sub(position)
+                .min(self.length);
+
+            match ranges.last_mut() {
+                Some(last) if last.end >= start => last.end = last.end.max(end),
+                _ => ranges.push(start..end),
+            }
+        }
+
+        Some(ranges)
+    }
+}
+
+/// The page indices of all maps of a snapshot, by the start address of the map
+pub type PageIndices = HashMap<usize, PageIndex>;
+
+/// Maps without access permissions are left out, their bytes aren't part of the snapshot
+pub fn index_maps(maps: &CachedMaps) -> PageIndices {
+    maps.iter()
+        .filter(|map| map.is_accessible())
+        .map(|map| (map.get_from_address(), PageIndex::new(map.get_bytes())))
+        .collect()
+}
+
+/// Narrows the regions of a map starting at base, which are in scan order, down to the parts which can contain a match of the elements
+pub fn narrow_regions<'a>(
+    index: &PageIndex,
+    base: usize,
+    regions: Vec<(usize, &'a [u8])>,
+    elements: &[Option<u8>],
+    direction: ScanDirection,
+) -> Vec<(usize, &'a [u8])> {
+    let Some(candidates) = index.candidate_ranges(elements) else {
+        return regions;
+    };
+
+    regions
+        .into_iter()
+        .flat_map(|(from, bytes)| {
+            let offset = from - base;
+            let mut narrowed = candidates
+                .iter()
+                .map(|range| range.start.max(offset)..range.end.min(offset + bytes.len()))
+                .filter(|range| !range.is_empty())
+                .map(|range| {
+                    (
+                        base + range.start,
+                        &bytes[range.start - offset..range.end - offset],
+                    )
+                })
+                .collect::<Vec<_>>();
+            if direction == ScanDirection::Backwards {
+                narrowed.reverse();
+            }
+            narrowed
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PageIndex, PAGE_SIZE};
+
+    #[test]
+    fn test_candidate_ranges() {
+        let mut bytes = vec![0u8; PAGE_SIZE * 4];
+        // A match spanning the border between the first and second page
+        bytes[PAGE_SIZE - 1] = 0xAA;
+        bytes[PAGE_SIZE] = 0xBB;
+        bytes[PAGE_SIZE * 3 + 5] = 0xBB;
+        let index = PageIndex::new(&bytes);
+
+        assert!(index.contains(0, 0xAA));
+        assert!(!index.contains(1, 0xAA));
+        assert_eq!(
+            index.rarest_literal(&[Some(0), Some(0xAA), Some(0xBB)]),
+            Some((1, 0xAA))
+        );
+        let first_page = 0..PAGE_SIZE + 1;
+        assert_eq!(
+            index.candidate_ranges(&[None, Some(0xAA), Some(0xBB)]),
+            Some(vec![first_page])
+        );
+        assert_eq!(
+            index.candidate_ranges(&[Some(0xBB)]),
+            Some(vec![PAGE_SIZE..PAGE_SIZE * 2, PAGE_SIZE * 3..PAGE_SIZE * 4])
+        );
+        assert_eq!(index.candidate_ranges(&[Some(0xCC)]), Some(Vec::new()));
+        assert_eq!(index.candidate_ranges(&[None]), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! Loads pattern collections of other tools.
+//!
+//! Two line formats are understood and can be mixed in one file:
+//! - FLIRT `.pat` lines as produced by IDA's `pelf`/`plb` tools, e.g.
+//!   `5589E5..8B45 00 0000 0010 :0000 function_name (000C) C3`.
+//!   The CRC of the bytes following the first 32 bytes is not checked, only the leading bytes and tail bytes are.
+//! - Byte/mask lines, e.g. `\x48\x8B\x05\x00\x00\x00\x00 xxx???? name` or `488B0500000000 xxx???? name`.
+//!
+//! Empty lines, lines starting with `#` or `;` and the `---` terminator are ignored.
+
+use signature_scanner::Signature;
+
+use crate::{
+    factory::BcrlFactory, pattern, scan_plan::ParseError, search_constraints::SearchConstraints,
+    session::Session,
+};
+
+/// Tail bytes of FLIRT patterns may be at most this far from the start, the module length of FLIRT is 16 bits wide
+pub const MAX_PATTERN_LENGTH: usize = 0x10000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamedPattern {
+    name: String,
+    elements: Vec<Option<u8>>,
+    offset: usize,
+}
+
+impl NamedPattern {
+    pub fn new(name: String, elements: Vec<Option<u8>>, offset: usize) -> Self {
+        Self {
+            name,
+            elements,
+            offset,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_elements(&self) -> &[Option<u8>] {
+        &self.elements
+    }
+    /// The offset of the named symbol from the start of the pattern
+    pub fn get_offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn to_signature(&self) -> Signature {
+        pattern::from_elements(&self.elements)
+    }
+
+    /// Scans for the pattern and steps to the named symbol
+    pub fn scan<'a>(
+        &self,
+        factory: &'a BcrlFactory,
+        constraints: SearchConstraints,
+    ) -> Session<'a> {
+        factory
+            .signature(self.to_signature(), constraints)
+            .step_forwards(self.offset)
+    }
+}
+
+fn parse_hex_byte(line: usize, text: &str) -> Result<u8, ParseError> {
+    u8::from_str_radix(text, 16)
+        .map_err(|_| ParseError::new(line, format!("invalid hex byte '{text}'")))
+}
+
+fn parse_hex(line: usize, text: &str) -> Result<usize, ParseError> {
+    usize::from_str_radix(text, 16)
+        .map_err(|_| ParseError::new(line, format!("invalid hex number '{text}'")))
+}
+
+/// Splits text into pairs of characters, `..` becomes a wildcard
+fn parse_flirt_bytes(line: usize, text: &str) -> Result<Vec<Option<u8>>, ParseError> {
+    if !text.is_ascii() || !text.len().is_multiple_of(2) {
+        return Err(ParseError::new(
+            line,
+            format!("invalid hex string '{text}'"),
+        ));
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|index| match &text[index..index + 2] {
+            ".." => Ok(None),
+            byte => parse_hex_byte(line, byte).map(Some),
+        })
+        .collect()
+}
+
+fn parse_flirt(line: usize, tokens: &[&str]) -> Result<NamedPattern, ParseError> {
+    let mut elements = parse_flirt_bytes(line, tokens[0])?;
+    let [_crc_length, _crc, module_length, rest @ ..] = &tokens[1..] else {
+        return Err(ParseError::new(
+            line,
+            "expected CRC length, CRC and module length",
+        ));
+    };
+    let module_length = parse_hex(line, module_length)?.min(MAX_PATTERN_LENGTH);
+
+    let mut name = None;
+    let mut rest = rest.iter();
+    while let Some(token) = rest.next() {
+        if let Some(offset) = token.strip_prefix('(') {
+            // Tail bytes (XXXX) YY, which are located after the CRC'd bytes
+            let offset = parse_hex(line, offset.trim_end_matches(')'))?;
+            let byte = rest
+                .next()
+                .ok_or_else(|| ParseError::new(line, "missing tail byte"))?;
+            let byte = parse_hex_byte(line, byte)?;
+            if offset >= module_length {
+                return Err(ParseError::new(
+                    line,
+                    format!("tail byte at {offset:#x} is outside of the module"),
+                ));
+            }
+
+            if elements.len() <= offset {
+                elements.resize(offset + 1, None);
+            }
+            elements[offset] = Some(byte);
+        } else if let Some(offset) = token.strip_prefix(':') {
+            let symbol = rest
+                .next()
+                .ok_or_else(|| ParseError::new(line, "missing name"))?;
+            // Local names (:XXXX@) are only used if there is no public name
+            let local = offset.ends_with('@');
+            let offset = parse_hex(line, offset.trim_end_matches('@'))?;
+
+            if name.is_none() || (!local && matches!(name, Some((_, _, true)))) {
+                name = Some((symbol.to_string(), offset, local));
+            }
+        } else if token.starts_with('^') {
+            // References to other symbols
+            rest.next();
+        }
+    }
+
+    let (name, offset, _) = name.ok_or_else(|| ParseError::new(line, "pattern has no name"))?;
+
+    // Trailing wildcards don't contribute to matching
+    while elements.last() == Some(&None) {
+        elements.pop();
+    }
+
+    Ok(NamedPattern::new(name, elements, offset))
+}
+
+fn parse_bytes(line: usize, text: &str) -> Result<Vec<u8>, ParseError> {
+    let hex = if text.starts_with("\\x") {
+        text.split("\\x").skip(1).collect::<String>()
+    } else {
+        text.to_string()
+    };
+
+    parse_flirt_bytes(line, &hex)?
+        .into_iter()
+        .map(|byte| byte.ok_or_else(|| ParseError::new(line, "wildcards are defined by the mask")))
+        .collect()
+}
+
+fn parse_masked(line: usize, tokens: &[&str]) -> Result<NamedPattern, ParseError> {
+    let bytes = parse_bytes(line, tokens[0])?;
+    let mask = tokens[1];
+    if bytes.len() != mask.len() {
+        return Err(ParseError::new(
+            line,
+            format!(
+                "{} bytes, but the mask has {} characters",
+                bytes.len(),
+                mask.len()
+            ),
+        ));
+    }
+
+    let elements = bytes
+        .into_iter()
+        .zip(mask.chars())
+        .map(|(byte, mask)| (mask == 'x').then_some(byte))
+        .collect();
+    let name = tokens
+        .get(2..)
+        .filter(|name| !name.is_empty())
+        .map(|name| name.join(" "))
+        .unwrap_or_else(|| format!("pattern_{line}"));
+
+    Ok(NamedPattern::new(name, elements, 0))
+}
+
+fn is_mask(token: &str) -> bool {
+    !token.is_empty() && token.chars().all(|c| c == 'x' || c == '?')
+}
+
+/// Parses a pattern collection, see the module documentation for the supported formats
+pub fn parse(text: &str) -> Result<Vec<NamedPattern>, ParseError> {
+    let mut patterns = Vec::new();
+
+    for (index, content) in text.lines().enumerate() {
+        let line = index + 1;
+        let content = content.trim();
+        if content.is_empty() || content.starts_with(['#', ';']) || content == "---" {
+            continue;
+        }
+
+        let tokens = content.split_whitespace().collect::<Vec<_>>();
+        let pattern = match tokens.get(1) {
+            Some(token) if is_mask(token) => parse_masked(line, &tokens)?,
+            Some(_) => parse_flirt(line, &tokens)?,
+            None => {
+                return Err(ParseError::new(
+                    line,
+                    "expected a pattern and its mask or CRC",
+                ))
+            }
+        };
+        patterns.push(pattern);
+    }
+
+    Ok(patterns)
+}
@@ -0,0 +1,58 @@
+//! Describes patches of the files backing a process, so changes found with live scans can be applied on disk.
+
+use std::{fmt, path::PathBuf};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FilePatch {
+    file: PathBuf,
+    offset: u64,
+    original: Vec<u8>,
+    replacement: Vec<u8>,
+}
+
+impl FilePatch {
+    pub fn new(file: PathBuf, offset: u64, original: Vec<u8>, replacement: Vec<u8>) -> Self {
+        Self {
+            file,
+            offset,
+            original,
+            replacement,
+        }
+    }
+
+    pub fn get_file(&self) -> &PathBuf {
+        &self.file
+    }
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+    /// The bytes, which are currently stored in the file
+    pub fn get_original(&self) -> &[u8] {
+        &self.original
+    }
+    pub fn get_replacement(&self) -> &[u8] {
+        &self.replacement
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Formats the patch as `<file> <offset>: <original> -> <replacement>`
+impl fmt::Display for FilePatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:#x}: {} -> {}",
+            self.file.display(),
+            self.offset,
+            hex(&self.original),
+            hex(&self.replacement)
+        )
+    }
+}
@@ -0,0 +1,69 @@
+//! Patches the memory of a process while remembering the original bytes, so every patch can be reverted.
+
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::unix::fs::FileExt,
+};
+
+use procfs::process::Process;
+
+/// The x86 single-byte `nop`
+pub const NOP: u8 = 0x90;
+
+/// Opens the memory of the process for reading and writing
+pub(crate) fn open_memory(process: &Process) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(format!("/proc/{}/mem", process.pid()))
+}
+
+/// The patches, which were written to a process, in the order they were written
+#[derive(Debug)]
+pub struct PatchSet {
+    memory: File,
+    /// The addresses and the bytes, which were there before they were patched
+    originals: Vec<(usize, Vec<u8>)>,
+}
+
+impl PatchSet {
+    pub fn new(process: &Process) -> io::Result<Self> {
+        Ok(Self {
+            memory: open_memory(process)?,
+            originals: Vec::new(),
+        })
+    }
+
+    /// Writes the bytes to the address and remembers the bytes, which were there before
+    pub fn patch(&mut self, address: usize, bytes: &[u8]) -> io::Result<()> {
+        let mut original = vec![0; bytes.len()];
+        self.memory.read_exact_at(&mut original, address as u64)?;
+        self.memory.write_all_at(bytes, address as u64)?;
+        self.originals.push((address, original));
+
+        Ok(())
+    }
+
+    /// The patched addresses and their original bytes
+    pub fn get_originals(&self) -> &[(usize, Vec<u8>)] {
+        &self.originals
+    }
+    pub fn len(&self) -> usize {
+        self.originals.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.originals.is_empty()
+    }
+
+    /// Writes the original bytes back, newest patch first, so overlapping patches are reverted correctly.
+    /// Patches, which were restored, are forgotten. If a write fails, the remaining patches are kept.
+    pub fn restore(&mut self) -> io::Result<()> {
+        while let Some((address, original)) = self.originals.last() {
+            self.memory.write_all_at(original, *address as u64)?;
+            self.originals.pop();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+//! Helpers for building signatures out of raw elements, where `None` is a wildcard.
+
+use std::sync::OnceLock;
+
+use memchr::memmem;
+use signature_scanner::Signature;
+
+use crate::{address_range::AddressRange, search_constraints::ScanDirection};
+
+/// Formats the elements as an IDA-style pattern
+pub fn to_ida(elements: &[Option<u8>]) -> String {
+    elements
+        .iter()
+        .map(|element| match element {
+            Some(byte) => format!("{byte:02X}"),
+            None => "?".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Creates a signature out of the elements
+pub fn from_elements(elements: &[Option<u8>]) -> Signature {
+    Signature::ida(&to_ida(elements))
+}
+
+/// A signature, whose elements are known at compile time, as created by the `sig!` macro.
+/// The signature is built once, when it is first needed, clones of it are handed out afterwards.
+///
+/// ```
+/// # #[cfg(feature = "macros")] {
+/// let signature = bcrl_rs::sig!("48 8B ?? E8");
+/// assert_eq!(signature.get_elements(), &[Some(0x48), Some(0x8B), None, Some(0xE8)]);
+/// # }
+/// ```
+pub struct StaticSignature {
+    elements: &'static [Option<u8>],
+    signature: OnceLock<Signature>,
+}
+
+impl StaticSignature {
+    pub const fn new(elements: &'static [Option<u8>]) -> Self {
+        Self {
+            elements,
+            signature: OnceLock::new(),
+        }
+    }
+
+    pub fn get_elements(&self) -> &'static [Option<u8>] {
+        self.elements
+    }
+    pub fn get(&self) -> Signature {
+        self.signature
+            .get_or_init(|| from_elements(self.elements))
+            .clone()
+    }
+}
+
+/// Creates a signature out of bytes and a code-style mask like `xx?x?`, where `?` is a wildcard and every other character requires an exact match.
+/// None if the mask doesn't have one character per byte.
+pub fn from_masked(bytes: &[u8], mask: &[u8]) -> Option<Signature> {
+    if bytes.len() != mask.len() {
+        return None;
+    }
+
+    let elements = bytes
+        .iter()
+        .zip(mask)
+        .map(|(byte, mask)| (*mask != b'?').then_some(*byte))
+        .collect::<Vec<_>>();
+
+    Some(from_elements(&elements))
+}
+
+/// The offset of the first element, which isn't a wildcard. None if all elements are wildcards.
+pub fn first_concrete_offset(elements: &[Option<u8>]) -> Option<usize> {
+    elements.iter().position(Option::is_some)
+}
+
+/// A match of a signature, which also tells where its first non-wildcard byte is.
+/// Signatures starting with wildcards match in front of the bytes they actually describe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SignatureMatch {
+    start: usize,
+    first_concrete: usize,
+}
+
+impl SignatureMatch {
+    /// Signatures without any concrete byte have it at their start
+    pub fn new(start: usize, signature: &Signature) -> Self {
+        Self {
+            start,
+            first_concrete: start + first_concrete_offset(signature.get_elements()).unwrap_or(0),
+        }
+    }
+
+    pub fn get_start(&self) -> usize {
+        self.start
+    }
+    /// The address of the first byte, which isn't matched by a wildcard
+    pub fn get_first_concrete(&self) -> usize {
+        self.first_concrete
+    }
+    /// The number of leading wildcards
+    pub fn get_first_concrete_offset(&self) -> usize {
+        self.first_concrete - self.start
+    }
+}
+
+/// Finds all matches of the signature inside the bytes, starting with the last one
+pub fn all_reversed<'a>(
+    signature: &'a Signature,
+    bytes: &'a [u8],
+) -> impl Iterator<Item = usize> + 'a {
+    let length = signature.get_elements().len();
+    let mut end = Some(bytes.len());
+
+    std::iter::from_fn(move || {
+        let slice = &bytes[..end?];
+        let offset = slice.len() - signature.prev(slice)?;
+        // Following matches have to start in front of this one
+        end = (length > 0).then(|| offset + length - 1);
+
+        Some(offset)
+    })
+}
+
+/// Finds all matches of the signature inside the bytes in the given direction
+pub fn all_in_direction<'a>(
+    signature: &'a Signature,
+    bytes: &'a [u8],
+    direction: ScanDirection,
+) -> Box<dyn Iterator<Item = usize> + 'a> {
+    match direction {
+        ScanDirection::Forwards => Box::new(signature.all(bytes)),
+        ScanDirection::Backwards => Box::new(all_reversed(signature, bytes)),
+    }
+}
+
+/// Returns every offset at which a value of the width fits into the bytes, in the given direction
+pub fn offsets_in_direction(
+    length: usize,
+    width: usize,
+    direction: ScanDirection,
+) -> Box<dyn Iterator<Item = usize>> {
+    let offsets = 0..(length + 1).saturating_sub(width.max(1));
+    match direction {
+        ScanDirection::Forwards => Box::new(offsets),
+        ScanDirection::Backwards => Box::new(offsets.rev()),
+    }
+}
+
+/// Finds the address of the first match, which lies completely inside one of the ranges and is allowed by the predicate.
+/// The bytes start at base. The ranges have to be sorted and are clamped to the bytes.
+pub fn next_in_ranges(
+    signature: &Signature,
+    base: usize,
+    bytes: &[u8],
+    ranges: &[AddressRange],
+    allows: impl Fn(usize) -> bool,
+) -> Option<usize> {
+    let bounds = AddressRange::new(base, base.saturating_add(bytes.len()));
+
+    ranges
+        .iter()
+        .map(|range| range.intersect(&bounds))
+        .find_map(|range| {
+            signature
+                .all(bytes.get(range.offsets_from(base))?)
+                .map(|offset| range.get_from() + offset)
+                .find(|address| allows(*address))
+        })
+}
+
+/// Finds the address of the last match, which lies completely inside one of the ranges and is allowed by the predicate,
+/// see [`next_in_ranges`]
+pub fn prev_in_ranges(
+    signature: &Signature,
+    base: usize,
+    bytes: &[u8],
+    ranges: &[AddressRange],
+    allows: impl Fn(usize) -> bool,
+) -> Option<usize> {
+    let bounds = AddressRange::new(base, base.saturating_add(bytes.len()));
+
+    ranges
+        .iter()
+        .rev()
+        .map(|range| range.intersect(&bounds))
+        .find_map(|range| {
+            all_reversed(signature, bytes.get(range.offsets_from(base))?)
+                .map(|offset| range.get_from() + offset)
+                .find(|address| allows(*address))
+        })
+}
+
+/// A pattern without wildcards, which is searched using memmem instead of comparing element by element
+#[derive(Clone, Debug)]
+pub struct ExactPattern {
+    forwards: memmem::Finder<'static>,
+    backwards: memmem::FinderRev<'static>,
+}
+
+impl ExactPattern {
+    pub fn new(needle: &[u8]) -> Self {
+        Self {
+            forwards: memmem::Finder::new(needle).into_owned(),
+            backwards: memmem::FinderRev::new(needle).into_owned(),
+        }
+    }
+
+    pub fn get_needle(&self) -> &[u8] {
+        self.forwards.needle()
+    }
+
+    /// Finds all, possibly overlapping, matches inside the bytes in the given direction.
+    /// An empty needle doesn't match anything.
+    pub fn all<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        direction: ScanDirection,
+    ) -> Box<dyn Iterator<Item = usize> + 'a> {
+        let length = self.get_needle().len();
+        if length == 0 {
+            return Box::new(std::iter::empty());
+        }
+
+        match direction {
+            ScanDirection::Forwards => {
+                let mut start = 0;
+                Box::new(std::iter::from_fn(move || {
+                    let offset = start + self.forwards.find(bytes.get(start..)?)?;
+                    start = offset + 1;
+                    Some(offset)
+                }))
+            }
+            ScanDirection::Backwards => {
+                let mut end = bytes.len();
+                Box::new(std::iter::from_fn(move || {
+                    let offset = self.backwards.rfind(&bytes[..end])?;
+                    // Following matches have to start in front of this one
+                    end = offset + length - 1;
+                    Some(offset)
+                }))
+            }
+        }
+    }
+}
+
+/// A signature, which is searched by its longest run of literal bytes, the anchor, using memmem.
+/// Every anchor hit is then verified against the whole signature. This keeps signatures, that start with wildcards, fast.
+#[derive(Clone, Debug)]
+pub struct PlannedSignature {
+    signature: Signature,
+    /// The offset of the anchor inside the signature and the anchor itself, None if the signature only has wildcards
+    anchor: Option<(usize, ExactPattern)>,
+}
+
+impl PlannedSignature {
+    pub fn new(signature: Signature) -> Self {
+        let anchor = longest_literal_run(signature.get_elements()).map(|(offset, length)| {
+            let literal = signature.get_elements()[offset..offset + length]
+                .iter()
+                .map(|element| element.unwrap())
+                .collect::<Vec<_>>();
+            (offset, ExactPattern::new(&literal))
+        });
+
+        Self { signature, anchor }
+    }
+
+    pub fn get_signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    /// Returns the offset and length of the anchor inside the signature
+    pub fn get_anchor(&self) -> Option<(usize, usize)> {
+        self.anchor
+            .as_ref()
+            .map(|(offset, anchor)| (*offset, anchor.get_needle().len()))
+    }
+
+    /// Finds all matches of the signature inside the bytes in the given direction, like [`all_in_direction`]
+    pub fn all<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        direction: ScanDirection,
+    ) -> Box<dyn Iterator<Item = usize> + 'a> {
+        let Some((anchor_offset, anchor)) = &self.anchor else {
+            return all_in_direction(&self.signature, bytes, direction);
+        };
+        let length = self.signature.get_elements().len();
+
+        Box::new(anchor.all(bytes, direction).filter_map(move |hit| {
+            let start = hit.checked_sub(*anchor_offset)?;
+            let candidate = bytes.get(start..start.checked_add(length)?)?;
+            self.signature.matches(candidate).then_some(start)
+        }))
+    }
+}
+
+/// Returns the offset and length of the longest run of non-wildcard elements, the first one wins ties
+fn longest_literal_run(elements: &[Option<u8>]) -> Option<(usize, usize)> {
+    let mut longest: Option<(usize, usize)> = None;
+    let mut start = 0;
+
+    for (index, element) in elements.iter().enumerate() {
+        if element.is_none() {
+            start = index + 1;
+            continue;
+        }
+        let length = index + 1 - start;
+        if longest.is_none_or(|(_, longest)| length > longest) {
+            longest = Some((start, length));
+        }
+    }
+
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use signature_scanner::Signature;
+
+    use super::{
+        all_reversed, first_concrete_offset, longest_literal_run, PlannedSignature, SignatureMatch,
+    };
+    use crate::search_constraints::ScanDirection;
+
+    #[test]
+    fn test_longest_literal_run() {
+        assert_eq!(
+            longest_literal_run(&[None, Some(1), Some(2), None, Some(3)]),
+            Some((1, 2))
+        );
+        assert_eq!(longest_literal_run(&[Some(1), None, Some(2)]), Some((0, 1)));
+        assert_eq!(longest_literal_run(&[None, None]), None);
+        assert_eq!(longest_literal_run(&[]), None);
+    }
+
+    #[test]
+    fn test_all_reversed() {
+        let bytes = [0xAA, 0xAA, 0xAA, 0x01, 0xAA, 0x02, 0xAA];
+        for pattern in ["AA AA", "AA ?", "? AA", "AA", "01 ? 02", "03"] {
+            let signature = Signature::ida(pattern);
+            let mut expected = signature.all(&bytes).collect::<Vec<_>>();
+            expected.reverse();
+
+            assert_eq!(
+                all_reversed(&signature, &bytes).collect::<Vec<_>>(),
+                expected,
+                "{pattern}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_first_concrete_offset() {
+        assert_eq!(first_concrete_offset(&[None, None, Some(1), None]), Some(2));
+        assert_eq!(first_concrete_offset(&[None]), None);
+
+        let hit = SignatureMatch::new(0x1000, &Signature::ida("? ? E8 ? ? ? ?"));
+        assert_eq!(hit.get_first_concrete(), 0x1002);
+        assert_eq!(hit.get_first_concrete_offset(), 2);
+    }
+
+    #[test]
+    fn test_planned_signature_agrees_with_signature() {
+        let bytes = [
+            0x12, 0x34, 0xAA, 0xBB, 0x12, 0xAA, 0xBB, 0xAA, 0xBB, 0xCC, 0xAA,
+        ];
+        for pattern in [
+            "? AA BB",
+            "? ? AA BB ?",
+            "AA ? ?",
+            "? ?",
+            "12 ? AA",
+            "BB ? BB",
+        ] {
+            let signature = Signature::ida(pattern);
+            let planned = PlannedSignature::new(signature.clone());
+
+            for direction in [ScanDirection::Forwards, ScanDirection::Backwards] {
+                assert_eq!(
+                    planned.all(&bytes, direction).collect::<Vec<_>>(),
+                    super::all_in_direction(&signature, &bytes, direction).collect::<Vec<_>>(),
+                    "{pattern} {direction:?}"
+                );
+            }
+        }
+    }
+}
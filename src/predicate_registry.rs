@@ -0,0 +1,56 @@
+//! A process-wide registry of named map predicates.
+//!
+//! Closures passed to [`crate::SearchConstraints::also`] can't be serialized, logged or shared between pipelines.
+//! Predicates registered under a name can be referenced with [`crate::SearchConstraints::with_named_predicate`]
+//! instead, which only stores the name. The predicate is looked up whenever a map is tested,
+//! so it may be registered after the constraints were built or deserialized.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use crate::cached_map::CachedMap;
+
+type NamedPredicate = dyn Fn(&CachedMap) -> bool + Send + Sync;
+
+static REGISTRY: RwLock<Option<HashMap<String, Arc<NamedPredicate>>>> = RwLock::new(None);
+
+/// Registers the predicate under the name, replacing the one registered before
+pub fn register(
+    name: impl Into<String>,
+    predicate: impl Fn(&CachedMap) -> bool + Send + Sync + 'static,
+) {
+    REGISTRY
+        .write()
+        .unwrap_or_else(|error| error.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(name.into(), Arc::new(predicate));
+}
+
+/// Removes the predicate, returns whether one was registered under the name
+pub fn unregister(name: &str) -> bool {
+    REGISTRY
+        .write()
+        .unwrap_or_else(|error| error.into_inner())
+        .as_mut()
+        .is_some_and(|registry| registry.remove(name).is_some())
+}
+
+pub fn is_registered(name: &str) -> bool {
+    get(name).is_some()
+}
+
+pub fn get(name: &str) -> Option<Arc<NamedPredicate>> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(|error| error.into_inner())
+        .as_ref()?
+        .get(name)
+        .cloned()
+}
+
+/// Tests the map with the predicate registered under the name. No map passes a predicate, which isn't registered.
+pub fn test(name: &str, map: &CachedMap) -> bool {
+    get(name).is_some_and(|predicate| predicate(map))
+}
@@ -0,0 +1,60 @@
+//! Finds probable function starts by looking for common prologues.
+//!
+//! The following instructions are considered a function start:
+//! - `endbr64`/`endbr32`, which compilers emit at every indirect branch target with CET enabled
+//! - `push rbp; mov rbp, rsp`, the classic frame setup
+//! - `sub rsp, imm`, but only after padding or a `ret`, since it is also found in the middle of functions
+
+use std::collections::BTreeSet;
+
+use signature_scanner::Signature;
+
+use crate::pattern;
+
+/// `endbr64`/`endbr32`, the only valid indirect branch targets with IBT enabled
+#[cfg(target_pointer_width = "64")]
+pub const END_BRANCH: [u8; 4] = [0xF3, 0x0F, 0x1E, 0xFA];
+#[cfg(target_pointer_width = "32")]
+pub const END_BRANCH: [u8; 4] = [0xF3, 0x0F, 0x1E, 0xFB];
+
+#[cfg(target_pointer_width = "64")]
+const FRAME_SETUPS: &[&str] = &["55 48 89 E5", "55 48 8B EC"];
+#[cfg(target_pointer_width = "32")]
+const FRAME_SETUPS: &[&str] = &["55 89 E5", "55 8B EC"];
+
+#[cfg(target_pointer_width = "64")]
+const STACK_ALLOCATIONS: &[&str] = &["48 83 EC ?", "48 81 EC ? ? ? ?"];
+#[cfg(target_pointer_width = "32")]
+const STACK_ALLOCATIONS: &[&str] = &["83 EC ?", "81 EC ? ? ? ?"];
+
+/// int3, nop and ret are placed in front of functions
+const BOUNDARY_BYTES: &[u8] = &[0xCC, 0x90, 0xC3];
+
+/// Returns the offsets of all probable function starts inside the bytes
+pub fn find_prologues(bytes: &[u8]) -> Vec<usize> {
+    let end_branches = pattern::from_elements(&END_BRANCH.map(Some))
+        .all(bytes)
+        .collect::<BTreeSet<_>>();
+
+    let mut starts = end_branches.clone();
+
+    for frame_setup in FRAME_SETUPS {
+        starts.extend(Signature::ida(frame_setup).all(bytes));
+    }
+
+    for stack_allocation in STACK_ALLOCATIONS {
+        starts.extend(
+            Signature::ida(stack_allocation)
+                .all(bytes)
+                .filter(|offset| *offset == 0 || BOUNDARY_BYTES.contains(&bytes[offset - 1])),
+        );
+    }
+
+    // The prologue following an end branch belongs to the same function
+    starts
+        .into_iter()
+        .filter(|offset| {
+            *offset < 4 || !end_branches.contains(&(offset - 4)) || end_branches.contains(offset)
+        })
+        .collect()
+}
@@ -0,0 +1,35 @@
+//! Restricts relative references to specific instructions by checking the bytes in front of the displacement.
+
+/// The instruction, which contains a relative reference. All of them end with their rel32 displacement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReferenceKind {
+    /// `call rel32` (E8)
+    Call,
+    /// `jmp rel32` (E9) and `jcc rel32` (0F 8x)
+    Jump,
+    /// `lea reg, [rip+disp32]` (8D with a RIP-relative ModRM)
+    Lea,
+}
+
+impl ReferenceKind {
+    /// Returns true if the bytes in front of a displacement belong to this kind of instruction
+    pub fn matches(&self, preceding: &[u8]) -> bool {
+        match (self, preceding) {
+            (ReferenceKind::Call, [.., 0xE8]) => true,
+            (ReferenceKind::Jump, [.., 0xE9]) => true,
+            (ReferenceKind::Jump, [.., 0x0F, condition]) => (0x80..=0x8F).contains(condition),
+            // mod = 00 and rm = 101 selects RIP-relative addressing
+            (ReferenceKind::Lea, [.., 0x8D, modrm]) => modrm & 0xC7 == 0x05,
+            _ => false,
+        }
+    }
+
+    /// The number of bytes in front of the displacement, which are needed to identify the instruction
+    pub fn get_prefix_length(&self) -> usize {
+        match self {
+            ReferenceKind::Call => 1,
+            ReferenceKind::Jump => 2,
+            ReferenceKind::Lea => 2,
+        }
+    }
+}
@@ -0,0 +1,884 @@
+//! Controls other processes using ptrace. System calls are executed inside of them by redirecting the registers
+//! of the stopped main thread to a `syscall` instruction, which already exists in the process,
+//! so no code of the process has to be modified.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    mem::MaybeUninit,
+    os::unix::process::CommandExt,
+    process::{Child, Command},
+    rc::Rc,
+};
+
+use procfs::process::MMPermissions;
+
+use crate::{
+    cached_maps::CachedMaps,
+    unwind::{self, DwarfRegisters},
+};
+
+/// `syscall`
+pub const SYSCALL: [u8; 2] = [0x0F, 0x05];
+
+pub(crate) fn check(result: libc::c_long) -> io::Result<libc::c_long> {
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(result)
+}
+
+pub(crate) fn get_registers(pid: libc::pid_t) -> io::Result<libc::user_regs_struct> {
+    let mut registers = MaybeUninit::<libc::user_regs_struct>::uninit();
+    check(unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETREGS,
+            pid,
+            std::ptr::null_mut::<libc::c_void>(),
+            registers.as_mut_ptr(),
+        )
+    })?;
+    Ok(unsafe { registers.assume_init() })
+}
+
+pub(crate) fn set_registers(
+    pid: libc::pid_t,
+    registers: &libc::user_regs_struct,
+) -> io::Result<()> {
+    check(unsafe {
+        libc::ptrace(
+            libc::PTRACE_SETREGS,
+            pid,
+            std::ptr::null_mut::<libc::c_void>(),
+            registers as *const libc::user_regs_struct,
+        )
+    })?;
+    Ok(())
+}
+
+/// The general purpose registers of a stopped thread
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Registers {
+    pub rip: u64,
+    pub rsp: u64,
+    pub rbp: u64,
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub eflags: u64,
+    pub fs_base: u64,
+    pub gs_base: u64,
+}
+
+impl From<libc::user_regs_struct> for Registers {
+    fn from(registers: libc::user_regs_struct) -> Self {
+        Self {
+            rip: registers.rip,
+            rsp: registers.rsp,
+            rbp: registers.rbp,
+            rax: registers.rax,
+            rbx: registers.rbx,
+            rcx: registers.rcx,
+            rdx: registers.rdx,
+            rsi: registers.rsi,
+            rdi: registers.rdi,
+            r8: registers.r8,
+            r9: registers.r9,
+            r10: registers.r10,
+            r11: registers.r11,
+            r12: registers.r12,
+            r13: registers.r13,
+            r14: registers.r14,
+            r15: registers.r15,
+            eflags: registers.eflags,
+            fs_base: registers.fs_base,
+            gs_base: registers.gs_base,
+        }
+    }
+}
+
+impl Registers {
+    /// The registers in DWARF numbering, as used by call frame information, see [`DwarfRegisters`]
+    pub fn to_dwarf(&self) -> DwarfRegisters {
+        [
+            self.rax, self.rdx, self.rcx, self.rbx, self.rsi, self.rdi, self.rbp, self.rsp,
+            self.r8, self.r9, self.r10, self.r11, self.r12, self.r13, self.r14, self.r15, self.rip,
+        ]
+        .map(Some)
+    }
+}
+
+/// Executes the system call at the address of a `syscall` instruction in the seized thread and returns rax.
+/// Signals, which arrive meanwhile, are remembered and delivered when the thread is detached.
+fn execute(
+    seized: &mut SeizedThreads,
+    tid: libc::pid_t,
+    instruction: usize,
+    number: u64,
+    arguments: [u64; 6],
+) -> io::Result<u64> {
+    let original = get_registers(tid)?;
+
+    let mut registers = original;
+    registers.rip = instruction as u64;
+    registers.rax = number;
+    // Prevents the kernel from restarting a system call, which the process was interrupted in
+    registers.orig_rax = u64::MAX;
+    registers.rdi = arguments[0];
+    registers.rsi = arguments[1];
+    registers.rdx = arguments[2];
+    registers.r10 = arguments[3];
+    registers.r8 = arguments[4];
+    registers.r9 = arguments[5];
+    set_registers(tid, &registers)?;
+
+    let result = step(seized, tid).and_then(|registers| {
+        // Only a trap behind the instruction means, that the system call was executed
+        if registers.rip != (instruction + SYSCALL.len()) as u64 {
+            return Err(io::Error::other(format!(
+                "the system call wasn't executed, the thread stopped at {:#x}",
+                registers.rip
+            )));
+        }
+        Ok(registers.rax)
+    });
+
+    set_registers(tid, &original)?;
+
+    result
+}
+
+/// Single-steps the seized thread and returns its registers after the step.
+/// Other stops, which interrupt the step, are remembered and the step is retried.
+fn step(seized: &mut SeizedThreads, tid: libc::pid_t) -> io::Result<libc::user_regs_struct> {
+    loop {
+        request(libc::PTRACE_SINGLESTEP, tid, 0, 0)?;
+        match seized.wait_for_stop(tid)? {
+            Event::Stopped {
+                signal: libc::SIGTRAP,
+                event: 0,
+            } => return get_registers(tid),
+            Event::Stopped { .. } => continue,
+            Event::Exited => return Err(io::Error::other("the thread exited during the step")),
+        }
+    }
+}
+
+pub(crate) fn request(
+    request: libc::c_uint,
+    tid: libc::pid_t,
+    address: usize,
+    data: usize,
+) -> io::Result<libc::c_long> {
+    check(unsafe {
+        libc::ptrace(
+            request,
+            tid,
+            address as *mut libc::c_void,
+            data as *mut libc::c_void,
+        )
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Event {
+    /// The thread stopped with the signal and the ptrace event
+    Stopped {
+        signal: i32,
+        event: i32,
+    },
+    Exited,
+}
+
+pub(crate) fn wait(
+    tid: libc::pid_t,
+    options: libc::c_int,
+) -> io::Result<Option<(libc::pid_t, Event)>> {
+    let mut status = 0;
+    let tid = unsafe { libc::waitpid(tid, &mut status, libc::__WALL | options) };
+    if tid == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    if tid == 0 {
+        return Ok(None);
+    }
+
+    let event = if libc::WIFSTOPPED(status) {
+        Event::Stopped {
+            signal: libc::WSTOPSIG(status),
+            event: status >> 16,
+        }
+    } else {
+        Event::Exited
+    };
+    Ok(Some((tid, event)))
+}
+
+/// Every thread of a process, stopped using PTRACE_SEIZE. Unlike PTRACE_ATTACH it doesn't send a SIGSTOP, which
+/// could still be pending after detaching and stop the whole process.
+#[derive(Debug)]
+pub(crate) struct SeizedThreads {
+    pub(crate) threads: HashSet<libc::pid_t>,
+    /// Signals, which stopped a thread and have to be delivered, when it continues
+    pub(crate) signals: HashMap<libc::pid_t, i32>,
+}
+
+impl SeizedThreads {
+    pub(crate) fn new() -> Self {
+        Self {
+            threads: HashSet::new(),
+            signals: HashMap::new(),
+        }
+    }
+
+    /// Seizes and stops every thread of the process. Threads created in the meantime are seized by the next pass.
+    pub(crate) fn seize(pid: i32, options: libc::c_int) -> io::Result<Self> {
+        let mut seized = Self::new();
+
+        let mut seen = HashSet::new();
+        loop {
+            let tids = fs::read_dir(format!("/proc/{pid}/task"))?
+                .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+                .filter(|tid| seen.insert(*tid))
+                .collect::<Vec<libc::pid_t>>();
+            if tids.is_empty() {
+                break;
+            }
+
+            for tid in tids {
+                if let Err(error) = seized.seize_thread(tid, options) {
+                    seized.detach();
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(seized)
+    }
+
+    /// Seizes and stops a single thread. Threads, which exited in the meantime, are skipped.
+    /// If the thread can't be stopped, it is detached again.
+    pub(crate) fn seize_thread(
+        &mut self,
+        tid: libc::pid_t,
+        options: libc::c_int,
+    ) -> io::Result<()> {
+        match request(libc::PTRACE_SEIZE, tid, 0, options as usize) {
+            Ok(_) => {}
+            Err(error) if error.raw_os_error() == Some(libc::ESRCH) => return Ok(()),
+            Err(error) => return Err(error),
+        }
+        self.threads.insert(tid);
+
+        let stopped = request(libc::PTRACE_INTERRUPT, tid, 0, 0)
+            .and_then(|_| self.wait_for_stop(tid).map(|_| ()));
+        match stopped {
+            Ok(()) => Ok(()),
+            // The thread exited before it could be stopped, it isn't traced anymore
+            Err(error) if matches!(error.raw_os_error(), Some(libc::ESRCH | libc::ECHILD)) => {
+                self.threads.remove(&tid);
+                self.signals.remove(&tid);
+                Ok(())
+            }
+            Err(error) => {
+                let _ = request(libc::PTRACE_DETACH, tid, 0, 0);
+                self.threads.remove(&tid);
+                self.signals.remove(&tid);
+                Err(error)
+            }
+        }
+    }
+
+    /// Waits until the thread stops and returns why. Signals, which stopped it, are remembered,
+    /// so they are delivered when it continues. Traps are left to the caller.
+    pub(crate) fn wait_for_stop(&mut self, tid: libc::pid_t) -> io::Result<Event> {
+        let Some((_, event)) = wait(tid, 0)? else {
+            return Err(io::Error::other("the thread didn't stop"));
+        };
+        match event {
+            Event::Stopped { signal, event: 0 } if signal != libc::SIGTRAP => {
+                self.signals.insert(tid, signal);
+            }
+            Event::Exited => {
+                self.threads.remove(&tid);
+            }
+            _ => {}
+        }
+        Ok(event)
+    }
+
+    pub(crate) fn resume(&mut self, tid: libc::pid_t) -> io::Result<()> {
+        let signal = self.signals.remove(&tid).unwrap_or(0);
+        request(libc::PTRACE_CONT, tid, 0, signal as usize).map(|_| ())
+    }
+
+    /// Detaches from every thread, which has to be stopped, and delivers the remembered signals
+    pub(crate) fn detach(&self) {
+        for tid in &self.threads {
+            let signal = self.signals.get(tid).copied().unwrap_or(0);
+            let _ = request(libc::PTRACE_DETACH, *tid, 0, signal as usize);
+        }
+    }
+}
+
+/// The registers of a thread, see [`thread_registers`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ThreadRegisters {
+    tid: i32,
+    registers: Registers,
+}
+
+impl ThreadRegisters {
+    pub fn get_tid(&self) -> i32 {
+        self.tid
+    }
+    pub fn get_registers(&self) -> &Registers {
+        &self.registers
+    }
+    pub fn get_instruction_pointer(&self) -> usize {
+        self.registers.rip as usize
+    }
+    pub fn get_stack_pointer(&self) -> usize {
+        self.registers.rsp as usize
+    }
+}
+
+/// Reads the registers of every thread of the process, ordered by thread id.
+/// All threads are stopped at the same time, so the registers are a consistent snapshot of the process.
+/// Threads, which exit while they are read, e.g. because another thread called `exit`, are left out.
+pub fn thread_registers(pid: i32) -> io::Result<Vec<ThreadRegisters>> {
+    let seized = SeizedThreads::seize(pid, 0)?;
+    let threads = seized_registers(&seized);
+    seized.detach();
+
+    let mut threads = threads?;
+    threads.sort_unstable_by_key(ThreadRegisters::get_tid);
+    Ok(threads)
+}
+
+/// Reads the registers of the seized threads, skipping those which exited
+fn seized_registers(seized: &SeizedThreads) -> io::Result<Vec<ThreadRegisters>> {
+    seized
+        .threads
+        .iter()
+        .filter_map(|tid| match get_registers(*tid) {
+            Ok(registers) => Some(Ok(ThreadRegisters {
+                tid: *tid,
+                registers: registers.into(),
+            })),
+            Err(error) if error.raw_os_error() == Some(libc::ESRCH) => None,
+            Err(error) => Some(Err(error)),
+        })
+        .collect()
+}
+
+/// Stops the process and unwinds the stack of the thread, see [`unwind::unwind`].
+/// Returns the pc of every frame, innermost first.
+pub fn backtrace(
+    pid: i32,
+    tid: i32,
+    maps: &Rc<CachedMaps>,
+    max_frames: usize,
+) -> io::Result<Vec<usize>> {
+    let seized = SeizedThreads::seize(pid, 0)?;
+    let frames = if seized.threads.contains(&tid) {
+        get_registers(tid).map(|registers| {
+            let registers = Registers::from(registers);
+            unwind::unwind(
+                maps,
+                registers.to_dwarf(),
+                |address| {
+                    read_vm(pid, address, 8)
+                        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+                },
+                max_frames,
+            )
+        })
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "the thread doesn't belong to the process",
+        ))
+    };
+    seized.detach();
+
+    frames
+}
+
+/// Seizes the main thread of the process, runs the closure while it is stopped and detaches again, even if the
+/// closure failed. Unlike PTRACE_ATTACH no SIGSTOP is sent, which could stop the process after detaching.
+fn attached<T>(
+    pid: i32,
+    f: impl FnOnce(&mut SeizedThreads, libc::pid_t) -> io::Result<T>,
+) -> io::Result<T> {
+    let mut seized = SeizedThreads::new();
+    let result = seized.seize_thread(pid, 0).and_then(|_| {
+        if !seized.threads.contains(&pid) {
+            return Err(io::Error::other("the process exited"));
+        }
+        f(&mut seized, pid)
+    });
+    seized.detach();
+
+    result
+}
+
+fn protection(permissions: MMPermissions) -> u64 {
+    let mut protection = libc::PROT_NONE;
+    if permissions.contains(MMPermissions::READ) {
+        protection |= libc::PROT_READ;
+    }
+    if permissions.contains(MMPermissions::WRITE) {
+        protection |= libc::PROT_WRITE;
+    }
+    if permissions.contains(MMPermissions::EXECUTE) {
+        protection |= libc::PROT_EXEC;
+    }
+    protection as u64
+}
+
+/// Maps size bytes of anonymous private memory into the process, using the `syscall` instruction at the address.
+/// Returns the address of the new memory.
+pub fn mmap(
+    pid: i32,
+    instruction: usize,
+    size: usize,
+    permissions: MMPermissions,
+) -> io::Result<usize> {
+    let address = attached(pid, |seized, pid| {
+        execute(
+            seized,
+            pid,
+            instruction,
+            libc::SYS_mmap as u64,
+            [
+                0,
+                size as u64,
+                protection(permissions),
+                (libc::MAP_PRIVATE | libc::MAP_ANONYMOUS) as u64,
+                u64::MAX,
+                0,
+            ],
+        )
+    })?;
+
+    // Errors are returned as negative errno values
+    let error = -(address as i64);
+    if (1..4096).contains(&error) {
+        return Err(io::Error::from_raw_os_error(error as i32));
+    }
+
+    Ok(address as usize)
+}
+
+/// A child process, which is stopped right after it executed its program. Dropping it without resuming lets it continue as well.
+#[derive(Debug)]
+pub struct Suspended {
+    child: Option<Child>,
+}
+
+impl Suspended {
+    /// Spawns the command traced, so it stops before the first instruction of the new program runs
+    pub fn spawn(command: &mut Command) -> io::Result<Self> {
+        // SAFETY: ptrace is async-signal-safe and doesn't allocate
+        unsafe {
+            command.pre_exec(|| {
+                check(libc::ptrace(
+                    libc::PTRACE_TRACEME,
+                    0,
+                    std::ptr::null_mut::<libc::c_void>(),
+                    std::ptr::null_mut::<libc::c_void>(),
+                ))
+                .map(|_| ())
+            });
+        }
+
+        let mut child = command.spawn()?;
+        // The successful execve stops the child with a SIGTRAP
+        let stopped = match wait(child.id() as libc::pid_t, 0) {
+            Ok(Some((
+                _,
+                Event::Stopped {
+                    signal: libc::SIGTRAP,
+                    event: 0,
+                },
+            ))) => Ok(()),
+            Ok(Some((_, Event::Stopped { signal, .. }))) => Err(io::Error::other(format!(
+                "the process was stopped by signal {signal} before executing its program"
+            ))),
+            Ok(_) => Err(io::Error::other(
+                "the process exited before executing its program",
+            )),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = stopped {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(error);
+        }
+
+        Ok(Self { child: Some(child) })
+    }
+
+    pub fn get_pid(&self) -> i32 {
+        self.child.as_ref().unwrap().id() as i32
+    }
+
+    /// Stops tracing the child and lets it run
+    pub fn resume(mut self) -> io::Result<Child> {
+        detach(self.get_pid())?;
+        Ok(self.child.take().unwrap())
+    }
+}
+
+impl Drop for Suspended {
+    fn drop(&mut self) {
+        if let Some(child) = &self.child {
+            let _ = detach(child.id() as libc::pid_t);
+        }
+    }
+}
+
+pub(crate) fn detach(pid: libc::pid_t) -> io::Result<()> {
+    check(unsafe {
+        libc::ptrace(
+            libc::PTRACE_DETACH,
+            pid,
+            std::ptr::null_mut::<libc::c_void>(),
+            std::ptr::null_mut::<libc::c_void>(),
+        )
+    })?;
+    Ok(())
+}
+
+/// Ranges larger than this aren't read with PTRACE_PEEKDATA, which needs a system call for every word
+pub const MAX_PEEK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Reads the ranges of the process without its mem file, first using process_vm_readv.
+/// Ranges, which can't be read that way, are read word by word with PTRACE_PEEKDATA while the process is attached,
+/// unless they are larger than [`MAX_PEEK_SIZE`]. Returns the bytes of every range, which could be read completely.
+pub fn read_ranges(pid: i32, ranges: &[(usize, usize)]) -> Vec<Option<Vec<u8>>> {
+    let mut results = ranges
+        .iter()
+        .map(|(address, length)| read_vm(pid, *address, *length))
+        .collect::<Vec<_>>();
+
+    let peeked = |(result, (_, length)): (&Option<Vec<u8>>, &(usize, usize))| {
+        result.is_none() && *length <= MAX_PEEK_SIZE
+    };
+    if !results.iter().zip(ranges).any(peeked) {
+        return results;
+    }
+
+    let _ = attached(pid, |_, pid| {
+        for (result, (address, length)) in results.iter_mut().zip(ranges) {
+            if result.is_none() && *length <= MAX_PEEK_SIZE {
+                *result = peek(pid, *address, *length);
+            }
+        }
+        Ok(())
+    });
+
+    results
+}
+
+pub(crate) fn read_vm(pid: i32, address: usize, length: usize) -> Option<Vec<u8>> {
+    let mut bytes = vec![0u8; length];
+    let local = libc::iovec {
+        iov_base: bytes.as_mut_ptr().cast(),
+        iov_len: length,
+    };
+    let remote = libc::iovec {
+        iov_base: address as *mut libc::c_void,
+        iov_len: length,
+    };
+
+    let read = unsafe { libc::process_vm_readv(pid, &local, 1, &remote, 1, 0) };
+    (read >= 0 && read as usize == length).then_some(bytes)
+}
+
+pub(crate) fn peek(pid: libc::pid_t, address: usize, length: usize) -> Option<Vec<u8>> {
+    const WORD: usize = size_of::<libc::c_long>();
+    let mut bytes = Vec::with_capacity(length.next_multiple_of(WORD));
+
+    for word_address in (address..address + length).step_by(WORD) {
+        // PEEKDATA returns the word, so errors can only be told apart by errno
+        let word = unsafe {
+            *libc::__errno_location() = 0;
+            libc::ptrace(
+                libc::PTRACE_PEEKDATA,
+                pid,
+                word_address as *mut libc::c_void,
+                std::ptr::null_mut::<libc::c_void>(),
+            )
+        };
+        if word == -1 && io::Error::last_os_error().raw_os_error() != Some(0) {
+            return None;
+        }
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+
+    bytes.truncate(length);
+    Some(bytes)
+}
+
+/// Bytes below the stack pointer, that leaf functions may use without adjusting it
+const RED_ZONE: u64 = 128;
+/// Bytes of the stack of the process, which are handed out as scratch memory to callers
+const SCRATCH_SIZE: usize = 256;
+
+pub(crate) fn poke(pid: libc::pid_t, address: usize, bytes: &[u8]) -> io::Result<()> {
+    const WORD: usize = size_of::<libc::c_long>();
+
+    for (index, chunk) in bytes.chunks(WORD).enumerate() {
+        let word_address = address + index * WORD;
+        // Partial words keep the bytes behind them
+        let mut word = match chunk.len() {
+            WORD => [0; WORD],
+            _ => peek(pid, word_address, WORD)
+                .ok_or_else(io::Error::last_os_error)?
+                .try_into()
+                .unwrap(),
+        };
+        word[..chunk.len()].copy_from_slice(chunk);
+        check(unsafe {
+            libc::ptrace(
+                libc::PTRACE_POKEDATA,
+                pid,
+                word_address as *mut libc::c_void,
+                libc::c_long::from_ne_bytes(word) as *mut libc::c_void,
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Signals, which the function caused itself. They end the call instead of being delivered, as they would kill the process.
+const FAULTS: [i32; 7] = [
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGTRAP,
+    libc::SIGABRT,
+    libc::SIGSYS,
+];
+
+/// Calls functions inside of a stopped process, see [`with_caller`]
+#[derive(Debug)]
+pub struct RemoteCaller<'a> {
+    seized: &'a mut SeizedThreads,
+    pid: libc::pid_t,
+    original: libc::user_regs_struct,
+    scratch: usize,
+}
+
+impl RemoteCaller<'_> {
+    /// Calls the function with up to six integer arguments following the System V calling convention and returns rax.
+    /// The function returns to address 0, the resulting segmentation fault is caught and suppressed.
+    /// Signals, which arrive during the call, are delivered to the process, faults of the function fail the call.
+    pub fn call(&mut self, function: usize, arguments: &[u64]) -> io::Result<u64> {
+        if arguments.len() > 6 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "at most six arguments can be passed in registers",
+            ));
+        }
+
+        // The stack of the function starts below the scratch memory
+        let stack = self.scratch as u64 & !0xf;
+        let mut registers = self.original;
+        registers.rip = function as u64;
+        registers.rsp = stack - 8;
+        registers.rax = 0;
+        registers.orig_rax = u64::MAX;
+        let slots = [
+            &mut registers.rdi,
+            &mut registers.rsi,
+            &mut registers.rdx,
+            &mut registers.rcx,
+            &mut registers.r8,
+            &mut registers.r9,
+        ];
+        for (slot, argument) in slots.into_iter().zip(arguments) {
+            *slot = *argument;
+        }
+
+        poke(self.pid, registers.rsp as usize, &0u64.to_ne_bytes())?;
+        set_registers(self.pid, &registers)?;
+
+        // A signal, which stopped the thread before the call, is delivered when it is detached instead of during the call
+        let pending = self.seized.signals.remove(&self.pid);
+        let result = self.run_until_return();
+        if let Some(signal) = pending {
+            self.seized.signals.entry(self.pid).or_insert(signal);
+        }
+        set_registers(self.pid, &self.original)?;
+
+        result
+    }
+
+    fn run_until_return(&mut self) -> io::Result<u64> {
+        loop {
+            // Continuing delivers the signals, which stopped the thread during the call
+            self.seized.resume(self.pid)?;
+            match self.seized.wait_for_stop(self.pid)? {
+                Event::Stopped { signal, event: 0 } if FAULTS.contains(&signal) => {
+                    // The registers are restored, so the fault mustn't be delivered
+                    self.seized.signals.remove(&self.pid);
+
+                    let registers = get_registers(self.pid)?;
+                    if signal == libc::SIGSEGV && registers.rip == 0 {
+                        return Ok(registers.rax);
+                    }
+                    return Err(io::Error::other(format!(
+                        "the function crashed at {:#x} with signal {signal}",
+                        registers.rip
+                    )));
+                }
+                Event::Stopped { .. } => continue,
+                Event::Exited => {
+                    return Err(io::Error::other("the process terminated during the call"))
+                }
+            }
+        }
+    }
+
+    /// The address of memory, which arguments can be written to and results read from.
+    /// It is placed on the stack of the process below the red zone, it holds [`Self::get_scratch_size`] bytes.
+    pub fn get_scratch(&self) -> usize {
+        self.scratch
+    }
+
+    pub fn get_scratch_size(&self) -> usize {
+        SCRATCH_SIZE
+    }
+
+    pub fn read(&self, address: usize, length: usize) -> io::Result<Vec<u8>> {
+        read_vm(self.pid, address, length)
+            .or_else(|| peek(self.pid, address, length))
+            .ok_or_else(|| io::Error::other(format!("{address:#x} can't be read")))
+    }
+
+    pub fn read_u64(&self, address: usize) -> io::Result<u64> {
+        Ok(u64::from_ne_bytes(
+            self.read(address, 8)?.try_into().unwrap(),
+        ))
+    }
+
+    /// Reads a null-terminated string of at most max_length bytes
+    pub fn read_string(&self, address: usize, max_length: usize) -> io::Result<String> {
+        let mut bytes = Vec::new();
+        while bytes.len() < max_length {
+            // Reads stay inside of the page, the next one may not be mapped
+            let length = (4096 - (address + bytes.len()) % 4096).min(max_length - bytes.len());
+            let chunk = self.read(address + bytes.len(), length)?;
+            if let Some(end) = chunk.iter().position(|&byte| byte == 0) {
+                bytes.extend_from_slice(&chunk[..end]);
+                break;
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    pub fn write(&self, address: usize, bytes: &[u8]) -> io::Result<()> {
+        poke(self.pid, address, bytes)
+    }
+}
+
+/// Attaches to the process and runs the closure, which can call functions inside of it.
+/// The calls run on the main thread, so they may deadlock if it holds a lock, that the functions need.
+/// The registers of the thread are restored afterwards.
+pub fn with_caller<T>(
+    pid: i32,
+    f: impl FnOnce(&mut RemoteCaller<'_>) -> io::Result<T>,
+) -> io::Result<T> {
+    attached(pid, |seized, pid| {
+        let original = get_registers(pid)?;
+        let mut caller = RemoteCaller {
+            seized,
+            pid,
+            original,
+            scratch: ((original.rsp - RED_ZONE) as usize - SCRATCH_SIZE) & !0xf,
+        };
+
+        f(&mut caller)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Forks a child, which shares the address space layout of the test, so functions can be called by their address
+    fn fork_child() -> libc::pid_t {
+        match unsafe { libc::fork() } {
+            0 => loop {
+                unsafe { libc::pause() };
+            },
+            -1 => panic!("{}", io::Error::last_os_error()),
+            pid => pid,
+        }
+    }
+
+    fn kill(pid: libc::pid_t) {
+        unsafe {
+            libc::kill(pid, libc::SIGKILL);
+            libc::waitpid(pid, std::ptr::null_mut(), 0);
+        }
+    }
+
+    #[test]
+    fn test_remote_call() {
+        let pid = fork_child();
+
+        let result = with_caller(pid, |caller| {
+            let own_pid = caller.call(libc::getpid as *const () as usize, &[])?;
+            // The signal is delivered to the child during the call, which still returns
+            let raised = caller.call(libc::raise as *const () as usize, &[libc::SIGCHLD as u64])?;
+            let crash = caller.call(0x10, &[]);
+
+            Ok((own_pid, raised, crash))
+        });
+        let alive = unsafe { libc::kill(pid, 0) } == 0;
+        let running = fs::read_to_string(format!("/proc/{pid}/stat"))
+            .is_ok_and(|stat| !stat.contains(") t "));
+        kill(pid);
+
+        let (own_pid, raised, crash) = result.unwrap();
+        assert_eq!(own_pid, pid as u64);
+        assert_eq!(raised, 0);
+        assert!(crash.is_err());
+        assert!(alive && running);
+    }
+
+    #[test]
+    fn test_registers_of_exited_thread() {
+        let pid = fork_child();
+        let mut seized = SeizedThreads::new();
+        seized.seize_thread(pid, 0).unwrap();
+        assert_eq!(seized_registers(&seized).unwrap().len(), 1);
+
+        // The thread is still seized, but its registers can't be read anymore
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let registers = seized_registers(&seized);
+        kill(pid);
+
+        assert!(registers.unwrap().is_empty());
+    }
+}
@@ -1,21 +1,42 @@
-use std::rc::Rc;
+use std::{
+    borrow::Cow,
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    rc::Rc,
+};
 
 use byteorder::ByteOrder;
-use procfs::process::MMapPath;
+use procfs_core::process::{MMPermissions, MMapPath};
 use signature_scanner::Signature;
 
+use crate::address_range::AddressRange;
+use crate::cached_map::CachedMap;
 use crate::cached_maps::CachedMaps;
 use crate::cached_maps::FindAddress;
+use crate::error::BcrlError;
 
-use crate::search_constraints::SearchConstraints;
+use crate::endianness::Endianness;
+use crate::hit_context::HitContext;
+use crate::pattern::{self, ExactPattern};
+use crate::prologue::END_BRANCH;
+use crate::reference_kind::ReferenceKind;
+use crate::search_constraints::{ScanDirection, SearchConstraints};
+
+#[cfg(feature = "disassembly")]
+use crate::disassembly;
 
 use x86_xref::*;
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct SafePointer {
     maps: Rc<CachedMaps>,
     address: usize,
     invalid: bool,
+    /// The memory of the process, if reads should bypass the snapshot
+    live: Option<Rc<File>>,
+    /// The sum of the scores the pointer was given, see [`crate::session::Session::score`]
+    score: u64,
 }
 
 impl Eq for SafePointer {}
@@ -38,9 +59,31 @@ impl SafePointer {
             maps,
             address,
             invalid: false,
+            live: None,
+            score: 0,
+        }
+    }
+
+    /// Creates a pointer into the same snapshot, which reads live if this one does
+    fn derive(&self, address: usize) -> SafePointer {
+        SafePointer {
+            live: self.live.clone(),
+            ..SafePointer::new(self.maps.clone(), address)
         }
     }
 
+    /// Makes reads fetch the bytes from the memory of the process at the time of the read instead of from the snapshot.
+    /// The snapshot still decides which addresses are valid.
+    pub fn live(&mut self, memory: Rc<File>) -> &mut Self {
+        self.live = Some(memory);
+
+        self
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.live.is_some()
+    }
+
     pub fn add(&mut self, operand: usize) -> &mut Self {
         self.address += operand;
 
@@ -62,12 +105,18 @@ impl SafePointer {
     }
 
     pub fn dereference<Endian: ByteOrder>(&mut self) -> &mut Self {
-        if let Some(bytes) = self.read(std::mem::size_of::<usize>()) {
-            if cfg!(target_pointer_width = "64") {
-                self.address = Endian::read_u64(bytes) as usize;
-            } else {
-                self.address = Endian::read_u32(bytes) as usize;
-            }
+        self.dereference_width::<Endian>(std::mem::size_of::<usize>())
+    }
+
+    /// Dereferences a pointer of the width (in bytes) of the target, which may differ from the one of this machine
+    /// Widths outside of 1 to 8 bytes can't be read and invalidate the pointer.
+    pub fn dereference_width<Endian: ByteOrder>(&mut self, width: usize) -> &mut Self {
+        if !(1..=8).contains(&width) {
+            return self.invalidate();
+        }
+
+        if let Some(bytes) = self.read_contiguous(width) {
+            self.address = Endian::read_uint(&bytes, width) as usize;
 
             if !self.is_valid(1) {
                 return self.invalidate();
@@ -82,8 +131,8 @@ impl SafePointer {
     #[cfg(target_pointer_width = "64")]
     pub fn relative_to_absolute<Endian: ByteOrder>(&mut self) -> &mut Self {
         let i32_size = std::mem::size_of::<i32>();
-        if let Some(offset_bytes) = self.read(i32_size) {
-            let offset = Endian::read_i32(offset_bytes);
+        if let Some(offset_bytes) = self.read_contiguous(i32_size) {
+            let offset = Endian::read_i32(&offset_bytes);
 
             self.address += i32_size;
 
@@ -121,26 +170,29 @@ impl SafePointer {
         signature: &Signature,
         constraints: &SearchConstraints,
     ) -> &mut Self {
-        let map = self.maps.find_map(self.address);
-        if map.is_none() {
+        let Some(map) = self.maps.find_map(self.address) else {
             return self.invalidate();
-        }
-        let map = map.unwrap();
+        };
 
         if !constraints.allows_map(map) {
             return self.invalidate();
         }
 
-        let range = constraints.clamp_address_range((map.get_from_address(), self.address));
+        let ranges = constraints.scanned_ranges((map.get_from_address(), self.address));
 
-        if let Some(hit) = signature.prev(
-            &map.get_bytes()[range.0 - map.get_from_address()..range.1 - map.get_from_address()],
+        match pattern::prev_in_ranges(
+            signature,
+            map.get_from_address(),
+            map.get_bytes(),
+            &ranges,
+            |address| constraints.allows_hit(map, address),
         ) {
-            self.address -= hit;
-            return self;
+            Some(address) => {
+                self.address = address;
+                self
+            }
+            None => self.invalidate(),
         }
-
-        self.invalidate()
     }
 
     pub fn next_occurrence(
@@ -148,26 +200,41 @@ impl SafePointer {
         signature: &Signature,
         constraints: &SearchConstraints,
     ) -> &mut Self {
-        let map = self.maps.find_map(self.address);
-        if map.is_none() {
+        let Some(map) = self.maps.find_map(self.address) else {
             return self.invalidate();
-        }
-        let map = map.unwrap();
+        };
 
         if !constraints.allows_map(map) {
             return self.invalidate();
         }
 
-        let range = constraints.clamp_address_range((self.address, map.get_to_address()));
+        let ranges = constraints.scanned_ranges((self.address, map.get_to_address()));
 
-        if let Some(hit) = signature.next(
-            &map.get_bytes()[range.0 - map.get_from_address()..range.1 - map.get_from_address()],
+        match pattern::next_in_ranges(
+            signature,
+            map.get_from_address(),
+            map.get_bytes(),
+            &ranges,
+            |address| constraints.allows_hit(map, address),
         ) {
-            self.address += hit;
-            return self;
+            Some(address) => {
+                self.address = address;
+                self
+            }
+            None => self.invalidate(),
         }
+    }
 
-        self.invalidate()
+    /// Finds the next occurrence, or the previous one if the constraints scan backwards
+    pub fn occurrence(
+        &mut self,
+        signature: &Signature,
+        constraints: &SearchConstraints,
+    ) -> &mut Self {
+        match constraints.get_direction() {
+            ScanDirection::Forwards => self.next_occurrence(signature, constraints),
+            ScanDirection::Backwards => self.prev_occurrence(signature, constraints),
+        }
     }
 
     pub fn next_instruction<Isa: lde::Isa>(&mut self) -> &mut Self {
@@ -177,7 +244,9 @@ impl SafePointer {
         }
         let map = map.unwrap();
 
-        let bytes = &map.get_bytes()[self.address - map.get_from_address()..map.get_size()];
+        let Some(bytes) = map.get_bytes().get(self.address - map.get_from_address()..) else {
+            return self.invalidate();
+        };
 
         let len = Isa::ld(bytes);
 
@@ -190,35 +259,111 @@ impl SafePointer {
         self
     }
 
+    /// Decodes instructions starting at the pointer and returns the smallest number of bytes, which is at least min_bytes
+    /// and ends on an instruction boundary. This is how many bytes have to be relocated when placing a hook of min_bytes.
+    /// Returns None if an instruction can't be decoded or the mapping ends first.
+    pub fn instruction_span<Isa: lde::Isa>(&self, min_bytes: usize) -> Option<usize> {
+        let bytes = self.read_remaining()?;
+        let mut span = 0;
+
+        while span < min_bytes {
+            let length = Isa::ld(bytes.get(span..)?) as usize;
+            if length == 0 || span + length > bytes.len() {
+                return None;
+            }
+            span += length;
+        }
+
+        Some(span)
+    }
+
+    /// Finds the previous occurrence of the signature, which starts at most max_bytes in front of the pointer
+    pub fn prev_occurrence_within(&mut self, signature: &Signature, max_bytes: usize) -> &mut Self {
+        let Some(map) = self.maps.find_map(self.address) else {
+            return self.invalidate();
+        };
+
+        let range = AddressRange::new(self.address.saturating_sub(max_bytes), self.address);
+
+        match pattern::prev_in_ranges(
+            signature,
+            map.get_from_address(),
+            map.get_bytes(),
+            &[range],
+            |_| true,
+        ) {
+            Some(address) => {
+                self.address = address;
+                self
+            }
+            None => self.invalidate(),
+        }
+    }
+
+    /// Finds the next occurrence of the signature, which starts at most max_bytes behind the pointer
+    pub fn next_occurrence_within(&mut self, signature: &Signature, max_bytes: usize) -> &mut Self {
+        let Some(map) = self.maps.find_map(self.address) else {
+            return self.invalidate();
+        };
+
+        let range = AddressRange::new(
+            self.address,
+            self.address
+                .saturating_add(max_bytes)
+                .saturating_add(signature.get_elements().len()),
+        );
+
+        match pattern::next_in_ranges(
+            signature,
+            map.get_from_address(),
+            map.get_bytes(),
+            &[range],
+            |_| true,
+        ) {
+            Some(address) => {
+                self.address = address;
+                self
+            }
+            None => self.invalidate(),
+        }
+    }
+
     #[cfg(target_pointer_width = "64")]
     pub fn find_all_references<'a, Endian: ByteOrder>(
         &'a self,
         instruction_length: usize,
         constraints: &'a SearchConstraints,
     ) -> impl Iterator<Item = SafePointer> + 'a {
-        self.maps
-            .iter()
-            .filter(|map| constraints.allows_map(map))
+        constraints
+            .in_scan_order(
+                self.maps
+                    .iter()
+                    .filter(|map| constraints.allows_map(map))
+                    .collect(),
+            )
+            .into_iter()
             .flat_map(move |map| {
-                let (from, to) =
-                    constraints.clamp_address_range((map.get_from_address(), map.get_to_address()));
-
-                let bytes =
-                    &map.get_bytes()[from - map.get_from_address()..to - map.get_from_address()];
-
+                constraints
+                    .regions(map)
+                    .into_iter()
+                    .map(move |(from, bytes)| (map, from, bytes))
+            })
+            .flat_map(move |(map, from, bytes)| {
                 let searcher = RelativeAndAbsoluteFinder::<Endian>::new(
-                    map.get_from_address(),
+                    from,
                     instruction_length,
                     self.address,
                 );
 
-                searcher
-                    .all(bytes)
-                    .map(|offset| {
-                        SafePointer::new(self.maps.clone(), offset + map.get_from_address())
-                    })
-                    .collect::<Vec<_>>()
+                constraints.collect_hits(
+                    searcher
+                        .all(bytes)
+                        .map(|offset| offset + from)
+                        .filter(|address| constraints.allows_hit(map, *address))
+                        .map(|address| self.derive(address)),
+                )
             })
+            .take(constraints.hit_limit())
     }
 
     #[cfg(target_pointer_width = "64")]
@@ -227,96 +372,600 @@ impl SafePointer {
         instruction_length: usize,
         constraints: &'a SearchConstraints,
     ) -> impl Iterator<Item = SafePointer> + 'a {
-        self.maps
-            .iter()
-            .filter(|map| constraints.allows_map(map))
-            .flat_map(move |map| {
-                let (from, to) =
-                    constraints.clamp_address_range((map.get_from_address(), map.get_to_address()));
-
-                let bytes =
-                    &map.get_bytes()[from - map.get_from_address()..to - map.get_from_address()];
+        self.find_relative_references_matching::<Endian>(instruction_length, constraints, |_| true)
+    }
 
-                let searcher = RelativeFinder::<Endian>::new(
-                    map.get_from_address(),
-                    instruction_length,
-                    self.address,
-                );
+    /// Finds relative references from instructions of the given kinds
+    #[cfg(target_pointer_width = "64")]
+    pub fn find_relative_references_of_kind<'a, Endian: ByteOrder>(
+        &'a self,
+        kinds: &'a [ReferenceKind],
+        constraints: &'a SearchConstraints,
+    ) -> impl Iterator<Item = SafePointer> + 'a {
+        // All supported instructions end with their displacement
+        self.find_relative_references_matching::<Endian>(
+            std::mem::size_of::<i32>(),
+            constraints,
+            move |reference| {
+                kinds
+                    .iter()
+                    .any(|kind| reference.is_reference_of_kind(*kind))
+            },
+        )
+    }
 
-                searcher
-                    .all(bytes)
-                    .map(|offset| {
-                        SafePointer::new(self.maps.clone(), offset + map.get_from_address())
-                    })
-                    .collect::<Vec<_>>()
+    /// The predicate is checked before the hits are counted towards the hit limit
+    #[cfg(target_pointer_width = "64")]
+    fn find_relative_references_matching<'a, Endian: ByteOrder>(
+        &'a self,
+        instruction_length: usize,
+        constraints: &'a SearchConstraints,
+        predicate: impl Fn(&SafePointer) -> bool + 'a,
+    ) -> impl Iterator<Item = SafePointer> + 'a {
+        constraints
+            .in_scan_order(
+                self.maps
+                    .iter()
+                    .filter(|map| constraints.allows_map(map))
+                    .collect(),
+            )
+            .into_iter()
+            .flat_map(move |map| {
+                constraints
+                    .regions(map)
+                    .into_iter()
+                    .map(move |(from, bytes)| (map, from, bytes))
+            })
+            .flat_map(move |(map, from, bytes)| {
+                let searcher =
+                    RelativeFinder::<Endian>::new(from, instruction_length, self.address);
+
+                constraints.collect_hits(
+                    searcher
+                        .all(bytes)
+                        .map(|offset| offset + from)
+                        .filter(|address| constraints.allows_hit(map, *address))
+                        .map(|address| self.derive(address))
+                        .filter(&predicate),
+                )
             })
+            .take(constraints.hit_limit())
+    }
+
+    /// Returns true if the pointer points at the displacement of an instruction of the given kind
+    pub fn is_reference_of_kind(&self, kind: ReferenceKind) -> bool {
+        let length = kind.get_prefix_length();
+        if self.address < length {
+            return false;
+        }
+
+        let mut start = self.clone();
+        start.sub(length);
+        start
+            .read(length)
+            .is_ok_and(|preceding| kind.matches(&preceding))
     }
 
     pub fn find_absolute_references<'a, Endian: ByteOrder>(
         &'a self,
         constraints: &'a SearchConstraints,
     ) -> impl Iterator<Item = SafePointer> + 'a {
-        self.maps
-            .iter()
-            .filter(|map| constraints.allows_map(map))
+        constraints
+            .in_scan_order(
+                self.maps
+                    .iter()
+                    .filter(|map| constraints.allows_map(map))
+                    .collect(),
+            )
+            .into_iter()
             .flat_map(move |map| {
-                let (from, to) =
-                    constraints.clamp_address_range((map.get_from_address(), map.get_to_address()));
-
-                let bytes =
-                    &map.get_bytes()[from - map.get_from_address()..to - map.get_from_address()];
-
+                constraints
+                    .regions(map)
+                    .into_iter()
+                    .map(move |(from, bytes)| (map, from, bytes))
+            })
+            .flat_map(move |(map, from, bytes)| {
                 let searcher = AbsoluteFinder::<Endian>::new(self.address);
 
-                searcher
-                    .all(bytes)
-                    .map(|offset| {
-                        SafePointer::new(self.maps.clone(), offset + map.get_from_address())
-                    })
-                    .collect::<Vec<_>>()
+                constraints.collect_hits(
+                    searcher
+                        .all(bytes)
+                        .map(|offset| offset + from)
+                        .filter(|address| constraints.allows_hit(map, *address))
+                        .map(|address| self.derive(address)),
+                )
             })
+            .take(constraints.hit_limit())
+    }
+
+    /// Finds values equal to the pointer, which are stored in writable maps allowed by the constraints.
+    /// Only values aligned to their width count, like the fields of structs and globals holding the pointer.
+    /// Widths outside of 1 to 8 bytes can't be searched, nothing is found for them.
+    pub fn find_data_references<'a>(
+        &'a self,
+        width: usize,
+        endianness: Endianness,
+        constraints: &'a SearchConstraints,
+    ) -> impl Iterator<Item = SafePointer> + 'a {
+        let valid_width = (1..=8).contains(&width);
+        let value = (self.address as u64).to_le_bytes();
+        let mut value = value[..width.min(value.len())].to_vec();
+        if endianness == Endianness::Big {
+            value.reverse();
+        }
+        let needle = ExactPattern::new(&value);
+
+        constraints
+            .in_scan_order(
+                self.maps
+                    .iter()
+                    .filter(|_| valid_width)
+                    .filter(|map| map.get_permissions().contains(MMPermissions::WRITE))
+                    .filter(|map| constraints.allows_map(map))
+                    .collect(),
+            )
+            .into_iter()
+            .flat_map(move |map| {
+                constraints
+                    .regions(map)
+                    .into_iter()
+                    .map(move |(from, bytes)| (map, from, bytes))
+            })
+            .flat_map(move |(map, from, bytes)| {
+                constraints.collect_hits(
+                    needle
+                        .all(bytes, constraints.get_direction())
+                        .map(|offset| offset + from)
+                        .filter(|address| address % width == 0)
+                        .filter(|address| constraints.allows_hit(map, *address))
+                        .map(|address| self.derive(address)),
+                )
+            })
+            .take(constraints.hit_limit())
     }
 
     pub fn does_match(&self, signature: &Signature) -> bool {
-        let bytes = self.read(signature.get_elements().len());
+        self.read_contiguous(signature.get_elements().len())
+            .is_some_and(|bytes| signature.matches(&bytes))
+    }
+
+    /// Returns true if the pointer points at an end branch instruction, see [`crate::prologue::END_BRANCH`]
+    pub fn is_end_branch(&self) -> bool {
+        self.read(END_BRANCH.len())
+            .is_ok_and(|bytes| bytes[..] == END_BRANCH[..])
+    }
 
-        bytes.is_some() && signature.matches(bytes.unwrap())
+    pub(crate) fn get_maps(&self) -> &Rc<CachedMaps> {
+        &self.maps
     }
 
     pub fn get_address(&self) -> usize {
         self.address
     }
 
+    pub fn get_score(&self) -> u64 {
+        self.score
+    }
+
+    /// Adds to the score of the pointer, it stays with the pointer through the following steps
+    pub fn add_score(&mut self, score: u32) -> &mut Self {
+        self.score = self.score.saturating_add(score.into());
+
+        self
+    }
+
     pub fn is_invalidated(&self) -> bool {
         self.invalid
     }
 
     pub fn is_valid(&self, length: usize) -> bool {
+        self.region(length).is_ok()
+    }
+
+    /// The map, which contains the length bytes starting at the pointer
+    fn region(&self, length: usize) -> Result<&CachedMap, BcrlError> {
         if self.invalid {
-            return false;
+            return Err(BcrlError::Invalidated {
+                address: self.address,
+            });
         }
-        let region = self.maps.find_map(self.address);
-        if region.is_none() {
-            return false;
+        let region = self
+            .maps
+            .find_map(self.address)
+            .ok_or(BcrlError::Unmapped {
+                address: self.address,
+            })?;
+        if !region.is_accessible() {
+            return Err(BcrlError::NoAccess {
+                address: self.address,
+            });
+        }
+        if region.get_to_address() - self.address < length {
+            return Err(BcrlError::Unreadable {
+                address: region.get_to_address(),
+            });
+        }
+        // Live pointers read the process, the bytes of the snapshot don't matter to them
+        if self.live.is_none() && !region.is_readable() {
+            return Err(BcrlError::Unreadable {
+                address: self.address,
+            });
         }
-        let region = region.unwrap();
 
-        region.get_to_address() - self.address >= length
+        Ok(region)
     }
 
-    pub fn read(&self, length: usize) -> Option<&[u8]> {
-        if !self.is_valid(length) {
-            return None;
+    /// Reads from the snapshot, or from the process if the pointer is live.
+    /// The bytes have to be inside of the map of the pointer, maps without access permissions can't be read.
+    pub fn read(&self, length: usize) -> Result<Cow<'_, [u8]>, BcrlError> {
+        let region = self.region(length)?;
+
+        if let Some(memory) = &self.live {
+            return read_live(memory, self.address, length).map(Cow::Owned);
         }
 
-        let region = self.maps.find_map(self.address)?;
         let offset = self.address - region.get_from_address();
 
-        Some(&region.get_bytes()[offset..offset + length])
+        Ok(Cow::Borrowed(&region.get_bytes()[offset..offset + length]))
+    }
+
+    /// Like [`Self::read`], but continues reading in the maps directly behind the mapping of the pointer
+    fn read_contiguous(&self, length: usize) -> Option<Cow<'_, [u8]>> {
+        if self.invalid {
+            return None;
+        }
+
+        let bytes = self.maps.read_contiguous(self.address, length)?;
+        match &self.live {
+            Some(memory) => read_live(memory, self.address, length).ok().map(Cow::Owned),
+            None => Some(bytes),
+        }
+    }
+
+    /// Copies bytes starting at the pointer into the buffer and returns how many were copied.
+    /// Reads stop at the end of the mapping, so fewer bytes than the buffer holds are copied near its end.
+    /// Unless the pointer is live, the bytes come from the snapshot the pointer was created with, refreshing the factory doesn't change them.
+    pub fn read_into(&self, buffer: &mut [u8]) -> Result<usize, BcrlError> {
+        let region = self.region(0)?;
+        let length = buffer.len().min(region.get_to_address() - self.address);
+        match &self.live {
+            Some(memory) => {
+                buffer[..length].copy_from_slice(&read_live(memory, self.address, length)?)
+            }
+            None => {
+                let offset = self.address - region.get_from_address();
+                buffer[..length].copy_from_slice(&region.get_bytes()[offset..offset + length])
+            }
+        }
+
+        Ok(length)
+    }
+
+    /// Reads up to before bytes in front of and after bytes starting at the pointer, without leaving its mapping.
+    /// Like [`Self::read`], the bytes come from the process if the pointer is live.
+    pub fn context(&self, before: usize, after: usize) -> Option<HitContext> {
+        let region = self.region(0).ok()?;
+        let start = self
+            .address
+            .saturating_sub(before)
+            .max(region.get_from_address());
+        let end = self
+            .address
+            .saturating_add(after)
+            .min(region.get_to_address());
+
+        let bytes = match &self.live {
+            Some(memory) => read_live(memory, start, end - start).ok()?,
+            None => region.get_bytes()
+                [start - region.get_from_address()..end - region.get_from_address()]
+                .to_vec(),
+        };
+
+        Some(HitContext::new(self.address, start, bytes))
+    }
+
+    /// Returns the bytes from the pointer to the end of its mapping, see [`Self::read`]
+    pub fn read_remaining(&self) -> Option<Cow<'_, [u8]>> {
+        let region = self.region(0).ok()?;
+
+        self.read(region.get_to_address() - self.address).ok()
+    }
+
+    /// Decodes up to count instructions at the pointer
+    #[cfg(feature = "disassembly")]
+    pub fn disassemble(&self, count: usize) -> Vec<iced_x86::Instruction> {
+        self.read_remaining()
+            .map(|bytes| disassembly::decode(&bytes, self.address, count))
+            .unwrap_or_default()
+    }
+
+    /// Walks forward instruction by instruction until one matches the predicate, starting at the instruction
+    /// following the one at the pointer. Invalidates the pointer if none of the next max_instructions instructions match.
+    #[cfg(feature = "disassembly")]
+    pub fn next_instruction_matching(
+        &mut self,
+        max_instructions: usize,
+        predicate: impl FnMut(&iced_x86::Instruction) -> bool,
+    ) -> &mut Self {
+        let instruction = self
+            .disassemble(max_instructions + 1)
+            .into_iter()
+            .skip(1)
+            .find(predicate);
+
+        match instruction {
+            Some(instruction) => {
+                self.address = instruction.ip() as usize;
+                self
+            }
+            None => self.invalidate(),
+        }
     }
 
     pub fn get_module_name(&self) -> Option<&MMapPath> {
         let region = self.maps.find_map(self.address)?;
         Some(region.get_name())
     }
+
+    /// The name of the map containing the pointer, the offset from the start of its module and its permissions.
+    /// Maps of the same file form a module, any other map is a module on its own.
+    fn location(&self) -> Option<(String, usize, MMPermissions)> {
+        let region = self.maps.find_map(self.address)?;
+        let (name, base) = match region.get_name() {
+            MMapPath::Path(path) => (
+                path.file_name()
+                    .unwrap_or(path.as_os_str())
+                    .to_string_lossy()
+                    .into_owned(),
+                self.maps
+                    .iter()
+                    .find(|map| map.get_name() == region.get_name())
+                    .map_or(region.get_from_address(), |map| map.get_from_address()),
+            ),
+            MMapPath::Heap => ("[heap]".to_string(), region.get_from_address()),
+            MMapPath::Stack => ("[stack]".to_string(), region.get_from_address()),
+            MMapPath::TStack(thread) => (format!("[stack:{thread}]"), region.get_from_address()),
+            MMapPath::Vdso => ("[vdso]".to_string(), region.get_from_address()),
+            MMapPath::Vvar => ("[vvar]".to_string(), region.get_from_address()),
+            MMapPath::Vsyscall => ("[vsyscall]".to_string(), region.get_from_address()),
+            MMapPath::Other(name) => (format!("[{name}]"), region.get_from_address()),
+            _ => ("[anon]".to_string(), region.get_from_address()),
+        };
+
+        Some((name, self.address - base, region.get_permissions()))
+    }
+}
+
+/// Formats as e.g. `0x7f3a12345678 (libc.so.6+0x45678 r-xp)`, followed by whether it is invalidated or live
+impl fmt::Display for SafePointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.address)?;
+        match self.location() {
+            Some((name, offset, permissions)) => {
+                write!(f, " ({name}+{offset:#x} {})", permissions.as_str())?
+            }
+            None => write!(f, " (unmapped)")?,
+        }
+        if self.invalid {
+            write!(f, " invalidated")?;
+        }
+        if self.is_live() {
+            write!(f, " live")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Leaves out the snapshot, which would print every map with all its bytes
+impl fmt::Debug for SafePointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let location = self.location();
+
+        f.debug_struct("SafePointer")
+            .field("address", &format_args!("{:#x}", self.address))
+            .field(
+                "location",
+                &location
+                    .as_ref()
+                    .map(|(name, offset, _)| format!("{name}+{offset:#x}")),
+            )
+            .field(
+                "permissions",
+                &location
+                    .as_ref()
+                    .map(|(_, _, permissions)| permissions.as_str()),
+            )
+            .field("invalid", &self.invalid)
+            .field("live", &self.is_live())
+            .field("score", &self.score)
+            .finish()
+    }
+}
+
+/// Reads from the mem file of the process. Once the process exited, its address space is gone and reads return nothing,
+/// while unmapped or protected addresses fail with an error.
+fn read_live(memory: &File, address: usize, length: usize) -> Result<Vec<u8>, BcrlError> {
+    let mut memory = memory;
+    let mut bytes = vec![0; length];
+
+    memory
+        .seek(SeekFrom::Start(address as u64))
+        .and_then(|_| memory.read_exact(&mut bytes))
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::UnexpectedEof => BcrlError::ProcessExited,
+            _ => BcrlError::Unreadable { address },
+        })?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, rc::Rc};
+
+    use procfs_core::process::{MMPermissions, MMapPath};
+    use proptest::prelude::*;
+    use signature_scanner::Signature;
+
+    use crate::{cached_map::CachedMap, endianness::Endianness, pattern, SearchConstraints};
+
+    use super::SafePointer;
+
+    const BASE: usize = 0x1000;
+
+    fn map(bytes: &[u8]) -> Rc<BTreeSet<CachedMap>> {
+        Rc::new(BTreeSet::from([CachedMap::new(
+            BASE,
+            BASE + bytes.len(),
+            MMPermissions::READ,
+            MMapPath::Anonymous,
+            bytes.into(),
+        )]))
+    }
+
+    /// Every match, which lies completely inside of the window and one of the allowed ranges
+    fn matches(
+        elements: &[Option<u8>],
+        bytes: &[u8],
+        window: (usize, usize),
+        constraints: &SearchConstraints,
+    ) -> Vec<usize> {
+        (0..=bytes.len().saturating_sub(elements.len()))
+            .filter(|offset| offset + elements.len() <= bytes.len())
+            .filter(|offset| {
+                elements
+                    .iter()
+                    .zip(&bytes[*offset..])
+                    .all(|(element, byte)| element.is_none_or(|element| element == *byte))
+            })
+            .map(|offset| BASE + offset)
+            .filter(|address| window.0 <= *address && address + elements.len() <= window.1)
+            .filter(|address| {
+                constraints
+                    .clamp_address_ranges(window)
+                    .iter()
+                    .any(|range| {
+                        range.get_from() <= *address && address + elements.len() <= range.get_to()
+                    })
+            })
+            .collect()
+    }
+
+    fn constraints(from: usize, to: usize, ranges: &[(usize, usize)]) -> SearchConstraints {
+        let constraints = SearchConstraints::everything()
+            .from(BASE + from)
+            .to(BASE + to);
+
+        if ranges.is_empty() {
+            constraints
+        } else {
+            let ranges = ranges
+                .iter()
+                .map(|(from, to)| (BASE + from, BASE + to))
+                .collect::<Vec<_>>();
+            constraints.in_ranges(&ranges)
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_next_occurrence(
+            bytes in prop::collection::vec(0u8..3, 1..64),
+            elements in prop::collection::vec(prop::option::of(0u8..3), 1..4),
+            start in 0usize..64,
+            (from, to) in (0usize..80, 0usize..80),
+            ranges in prop::collection::vec((0usize..80, 0usize..80), 0..4),
+        ) {
+            let start = start.min(bytes.len() - 1);
+            let constraints = constraints(from, to, &ranges);
+            let expected = matches(&elements, &bytes, (BASE + start, BASE + bytes.len()), &constraints)
+                .first()
+                .copied();
+
+            let mut pointer = SafePointer::new(map(&bytes), BASE + start);
+            pointer.next_occurrence(&pattern::from_elements(&elements), &constraints);
+
+            prop_assert_eq!((!pointer.is_invalidated()).then_some(pointer.get_address()), expected);
+        }
+
+        #[test]
+        fn test_prev_occurrence(
+            bytes in prop::collection::vec(0u8..3, 1..64),
+            elements in prop::collection::vec(prop::option::of(0u8..3), 1..4),
+            start in 0usize..64,
+            (from, to) in (0usize..80, 0usize..80),
+            ranges in prop::collection::vec((0usize..80, 0usize..80), 0..4),
+        ) {
+            let start = start.min(bytes.len() - 1);
+            let constraints = constraints(from, to, &ranges);
+            let expected = matches(&elements, &bytes, (BASE, BASE + start), &constraints)
+                .last()
+                .copied();
+
+            let mut pointer = SafePointer::new(map(&bytes), BASE + start);
+            pointer.prev_occurrence(&pattern::from_elements(&elements), &constraints);
+
+            prop_assert_eq!((!pointer.is_invalidated()).then_some(pointer.get_address()), expected);
+        }
+    }
+
+    #[test]
+    fn test_occurrence_outside_of_constraints() {
+        let bytes = [0xAA, 0xBB, 0xCC, 0xAA, 0xBB, 0xCC];
+        let signature = Signature::ida("AA BB");
+
+        // The map is only partially inside the constraints
+        let mut pointer = SafePointer::new(map(&bytes), BASE);
+        pointer.next_occurrence(&signature, &SearchConstraints::everything().from(BASE + 1));
+        assert_eq!(pointer.get_address(), BASE + 3);
+
+        // The hit would cross the end of the constraints
+        let mut pointer = SafePointer::new(map(&bytes), BASE + 1);
+        pointer.next_occurrence(&signature, &SearchConstraints::everything().to(BASE + 4));
+        assert!(pointer.is_invalidated());
+    }
+
+    #[test]
+    fn test_find_data_references() {
+        let mut bytes = [0; 16];
+        bytes[8..].copy_from_slice(&(BASE as u64).to_le_bytes());
+        let maps = Rc::new(BTreeSet::from([CachedMap::new(
+            BASE,
+            BASE + bytes.len(),
+            MMPermissions::READ | MMPermissions::WRITE,
+            MMapPath::Anonymous,
+            bytes.into(),
+        )]));
+        let pointer = SafePointer::new(maps, BASE);
+        let constraints = SearchConstraints::everything();
+        let references = |width| {
+            pointer
+                .find_data_references(width, Endianness::Little, &constraints)
+                .map(|reference| reference.get_address())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(references(8), vec![BASE + 8]);
+        assert_eq!(references(4), vec![BASE + 8]);
+        assert!(references(0).is_empty());
+        assert!(references(16).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "disassembly")]
+    fn test_next_instruction_matching() {
+        // nop; nop; ret
+        let maps = map(&[0x90, 0x90, 0xC3]);
+        let is_nop =
+            |instruction: &iced_x86::Instruction| instruction.mnemonic() == iced_x86::Mnemonic::Nop;
+
+        let mut pointer = SafePointer::new(maps.clone(), BASE);
+        pointer.next_instruction_matching(2, is_nop);
+        assert_eq!(pointer.get_address(), BASE + 1);
+
+        let mut pointer = SafePointer::new(maps, BASE + 1);
+        pointer.next_instruction_matching(2, is_nop);
+        assert!(pointer.is_invalidated());
+    }
 }
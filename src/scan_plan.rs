@@ -0,0 +1,449 @@
+//! A description of a pipeline, which can be stored, transported and executed later.
+//!
+//! Plans have a line based text representation:
+//!
+//! ```text
+//! signature 48 8D 05 ? ? ? ?
+//! in module=libfoo.so readable executable
+//! forwards 3
+//! relative_to_absolute
+//! find_all_references 4 | executable
+//! ```
+//!
+//! The first line describes the initial scan (`signature <ida pattern>` or `string <text>`), optionally followed by
+//! an `in` line with its constraints. Every following line is a step.
+//! Steps, which need a signature or constraints, separate them from their arguments with a `|`.
+//! Constraints consist of `module=<name>`, `from=<address>`, `to=<address>` and the permissions `readable`,
+//! `writable` and `executable`, which can be negated with a `!`.
+
+use std::{fmt, str::FromStr};
+
+use signature_scanner::Signature;
+
+use crate::{
+    factory::BcrlFactory,
+    search_constraints::SearchConstraints,
+    session::{is_in_module, Session},
+};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PlanConstraints {
+    pub module: Option<String>,
+    pub from: Option<usize>,
+    pub to: Option<usize>,
+    pub readable: Option<bool>,
+    pub writable: Option<bool>,
+    pub executable: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Seed {
+    /// An IDA-style signature
+    Signature(String),
+    /// A string without terminator
+    String(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Step {
+    StepForwards(usize),
+    StepBackwards(usize),
+    Dereference,
+    RelativeToAbsolute,
+    NextInstruction,
+    PrevOccurrence(String, PlanConstraints),
+    NextOccurrence(String, PlanConstraints),
+    #[cfg(target_pointer_width = "64")]
+    FindAllReferences(usize, PlanConstraints),
+    #[cfg(target_pointer_width = "64")]
+    FindRelativeReferences(usize, PlanConstraints),
+    FindAbsoluteReferences(PlanConstraints),
+    SignatureFilter(String),
+    FilterModule(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ScanPlan {
+    seed: Seed,
+    constraints: PlanConstraints,
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+
+    /// The line (starting at 1) that couldn't be parsed
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl PlanConstraints {
+    pub fn to_search_constraints(&self) -> SearchConstraints {
+        let mut constraints = SearchConstraints::everything();
+
+        if let Some(module) = &self.module {
+            constraints = constraints.with_name(module.clone());
+        }
+        if let Some(from) = self.from {
+            constraints = constraints.from(from);
+        }
+        if let Some(to) = self.to {
+            constraints = constraints.to(to);
+        }
+        constraints = match self.readable {
+            Some(true) => constraints.thats_readable(),
+            Some(false) => constraints.thats_not_readable(),
+            None => constraints,
+        };
+        constraints = match self.writable {
+            Some(true) => constraints.thats_writable(),
+            Some(false) => constraints.thats_not_writable(),
+            None => constraints,
+        };
+        constraints = match self.executable {
+            Some(true) => constraints.thats_executable(),
+            Some(false) => constraints.thats_not_executable(),
+            None => constraints,
+        };
+
+        constraints
+    }
+
+    fn parse(line: usize, text: &str) -> Result<Self, ParseError> {
+        let mut constraints = PlanConstraints::default();
+
+        for token in text.split_whitespace() {
+            let (negated, name) = match token.strip_prefix('!') {
+                Some(name) => (true, name),
+                None => (false, token),
+            };
+
+            match name.split_once('=') {
+                Some(("module", module)) if !negated => {
+                    constraints.module = Some(module.to_string())
+                }
+                Some(("from", address)) if !negated => {
+                    constraints.from = Some(parse_number(line, address)?)
+                }
+                Some(("to", address)) if !negated => {
+                    constraints.to = Some(parse_number(line, address)?)
+                }
+                None if name == "readable" => constraints.readable = Some(!negated),
+                None if name == "writable" => constraints.writable = Some(!negated),
+                None if name == "executable" => constraints.executable = Some(!negated),
+                _ => {
+                    return Err(ParseError::new(
+                        line,
+                        format!("unknown constraint '{token}'"),
+                    ))
+                }
+            }
+        }
+
+        Ok(constraints)
+    }
+}
+
+impl fmt::Display for PlanConstraints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut tokens = Vec::new();
+
+        if let Some(module) = &self.module {
+            tokens.push(format!("module={module}"));
+        }
+        if let Some(from) = self.from {
+            tokens.push(format!("from={from:#x}"));
+        }
+        if let Some(to) = self.to {
+            tokens.push(format!("to={to:#x}"));
+        }
+        for (name, value) in [
+            ("readable", self.readable),
+            ("writable", self.writable),
+            ("executable", self.executable),
+        ] {
+            match value {
+                Some(true) => tokens.push(name.to_string()),
+                Some(false) => tokens.push(format!("!{name}")),
+                None => {}
+            }
+        }
+
+        write!(f, "{}", tokens.join(" "))
+    }
+}
+
+fn parse_number(line: usize, text: &str) -> Result<usize, ParseError> {
+    let result = match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+
+    result.map_err(|_| ParseError::new(line, format!("invalid number '{text}'")))
+}
+
+/// Splits "arguments | constraints" into both parts
+fn split_constraints(line: usize, text: &str) -> Result<(&str, PlanConstraints), ParseError> {
+    match text.split_once('|') {
+        Some((arguments, constraints)) => {
+            Ok((arguments.trim(), PlanConstraints::parse(line, constraints)?))
+        }
+        None => Ok((text.trim(), PlanConstraints::default())),
+    }
+}
+
+impl Step {
+    pub fn apply<'a>(&self, session: Session<'a>) -> Session<'a> {
+        match self {
+            Step::StepForwards(operand) => session.step_forwards(*operand),
+            Step::StepBackwards(operand) => session.step_backwards(*operand),
+            Step::Dereference => session.dereference_configured(),
+            Step::RelativeToAbsolute => session.relative_to_absolute_configured(),
+            Step::NextInstruction => session.next_instruction_configured(),
+            Step::PrevOccurrence(signature, constraints) => session.prev_occurrence(
+                Signature::ida(signature),
+                constraints.to_search_constraints(),
+            ),
+            Step::NextOccurrence(signature, constraints) => session.next_occurrence(
+                Signature::ida(signature),
+                constraints.to_search_constraints(),
+            ),
+            #[cfg(target_pointer_width = "64")]
+            Step::FindAllReferences(instruction_length, constraints) => session
+                .find_all_references_configured(
+                    *instruction_length,
+                    constraints.to_search_constraints(),
+                ),
+            #[cfg(target_pointer_width = "64")]
+            Step::FindRelativeReferences(instruction_length, constraints) => session
+                .find_relative_references_configured(
+                    *instruction_length,
+                    constraints.to_search_constraints(),
+                ),
+            Step::FindAbsoluteReferences(constraints) => {
+                session.find_absolute_references_configured(constraints.to_search_constraints())
+            }
+            Step::SignatureFilter(signature) => session.signature_filter(Signature::ida(signature)),
+            Step::FilterModule(module) => {
+                let module = module.clone();
+                session.filter(move |ptr| is_in_module(ptr, &module))
+            }
+        }
+    }
+
+    fn parse(line: usize, text: &str) -> Result<Self, ParseError> {
+        let (name, arguments) = text.split_once(' ').unwrap_or((text, ""));
+        let arguments = arguments.trim();
+
+        let step = match name {
+            "forwards" => Step::StepForwards(parse_number(line, arguments)?),
+            "backwards" => Step::StepBackwards(parse_number(line, arguments)?),
+            "dereference" => Step::Dereference,
+            "relative_to_absolute" => Step::RelativeToAbsolute,
+            "next_instruction" => Step::NextInstruction,
+            "prev_occurrence" => {
+                let (signature, constraints) = split_constraints(line, arguments)?;
+                Step::PrevOccurrence(signature.to_string(), constraints)
+            }
+            "next_occurrence" => {
+                let (signature, constraints) = split_constraints(line, arguments)?;
+                Step::NextOccurrence(signature.to_string(), constraints)
+            }
+            #[cfg(target_pointer_width = "64")]
+            "find_all_references" => {
+                let (length, constraints) = split_constraints(line, arguments)?;
+                Step::FindAllReferences(parse_number(line, length)?, constraints)
+            }
+            #[cfg(target_pointer_width = "64")]
+            "find_relative_references" => {
+                let (length, constraints) = split_constraints(line, arguments)?;
+                Step::FindRelativeReferences(parse_number(line, length)?, constraints)
+            }
+            "find_absolute_references" => {
+                let (_, constraints) = split_constraints(line, arguments)?;
+                Step::FindAbsoluteReferences(constraints)
+            }
+            "signature_filter" => Step::SignatureFilter(arguments.to_string()),
+            "filter_module" => Step::FilterModule(arguments.to_string()),
+            _ => return Err(ParseError::new(line, format!("unknown step '{name}'"))),
+        };
+
+        Ok(step)
+    }
+}
+
+fn with_constraints(arguments: &str, constraints: &PlanConstraints) -> String {
+    if *constraints == PlanConstraints::default() {
+        arguments.to_string()
+    } else {
+        format!("{arguments} | {constraints}")
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::StepForwards(operand) => write!(f, "forwards {operand}"),
+            Step::StepBackwards(operand) => write!(f, "backwards {operand}"),
+            Step::Dereference => write!(f, "dereference"),
+            Step::RelativeToAbsolute => write!(f, "relative_to_absolute"),
+            Step::NextInstruction => write!(f, "next_instruction"),
+            Step::PrevOccurrence(signature, constraints) => {
+                write!(
+                    f,
+                    "prev_occurrence {}",
+                    with_constraints(signature, constraints)
+                )
+            }
+            Step::NextOccurrence(signature, constraints) => {
+                write!(
+                    f,
+                    "next_occurrence {}",
+                    with_constraints(signature, constraints)
+                )
+            }
+            #[cfg(target_pointer_width = "64")]
+            Step::FindAllReferences(length, constraints) => write!(
+                f,
+                "find_all_references {}",
+                with_constraints(&length.to_string(), constraints)
+            ),
+            #[cfg(target_pointer_width = "64")]
+            Step::FindRelativeReferences(length, constraints) => write!(
+                f,
+                "find_relative_references {}",
+                with_constraints(&length.to_string(), constraints)
+            ),
+            Step::FindAbsoluteReferences(constraints) => {
+                write!(
+                    f,
+                    "find_absolute_references {}",
+                    with_constraints("", constraints)
+                )
+            }
+            Step::SignatureFilter(signature) => write!(f, "signature_filter {signature}"),
+            Step::FilterModule(module) => write!(f, "filter_module {module}"),
+        }
+    }
+}
+
+impl ScanPlan {
+    pub fn new(seed: Seed, constraints: PlanConstraints) -> Self {
+        Self {
+            seed,
+            constraints,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Appends a step to the plan
+    pub fn then(mut self, step: Step) -> Self {
+        self.steps.push(step);
+
+        self
+    }
+
+    pub fn get_seed(&self) -> &Seed {
+        &self.seed
+    }
+    pub fn get_constraints(&self) -> &PlanConstraints {
+        &self.constraints
+    }
+    pub fn get_steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// Creates a Session for the plan using the factory
+    pub fn execute<'a>(&self, factory: &'a BcrlFactory) -> Session<'a> {
+        let signature = match &self.seed {
+            Seed::Signature(signature) => Signature::ida(signature),
+            Seed::String(string) => Signature::string(string, false),
+        };
+
+        let session = factory.signature(signature, self.constraints.to_search_constraints());
+
+        self.steps
+            .iter()
+            .fold(session, |session, step| step.apply(session))
+    }
+}
+
+impl fmt::Display for ScanPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.seed {
+            Seed::Signature(signature) => writeln!(f, "signature {signature}")?,
+            Seed::String(string) => writeln!(f, "string {string}")?,
+        }
+        if self.constraints != PlanConstraints::default() {
+            writeln!(f, "in {}", self.constraints)?;
+        }
+        for step in &self.steps {
+            writeln!(f, "{step}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for ScanPlan {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .peekable();
+
+        let (line, seed) = lines
+            .next()
+            .ok_or_else(|| ParseError::new(1, "missing signature"))?;
+        let seed = match seed.split_once(' ') {
+            Some(("signature", signature)) => Seed::Signature(signature.trim().to_string()),
+            Some(("string", string)) => Seed::String(string.to_string()),
+            _ => return Err(ParseError::new(line, "expected 'signature' or 'string'")),
+        };
+
+        let mut constraints = PlanConstraints::default();
+        if let Some((line, text)) = lines.peek() {
+            if let Some(text) = text.strip_prefix("in ") {
+                constraints = PlanConstraints::parse(*line, text)?;
+                lines.next();
+            }
+        }
+
+        let steps = lines
+            .map(|(line, text)| Step::parse(line, text))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            seed,
+            constraints,
+            steps,
+        })
+    }
+}
@@ -1,29 +1,172 @@
-use std::rc::Rc;
+use std::{collections::HashSet, fmt, path::PathBuf, rc::Rc};
 
-use procfs::process::{MMPermissions, MMapPath};
+use procfs_core::process::{MMPermissions, MMapPath};
 
-use crate::cached_map::CachedMap;
+use crate::{
+    address_range::AddressRange,
+    cached_map::CachedMap,
+    coverage::CoverageRecorder,
+    jit::JitKind,
+    module::ModuleId,
+    predicate_registry,
+    shared_memory::{SharedMemoryKind, SharedSegment},
+};
 
 type MapPredicate = dyn Fn(&CachedMap) -> bool;
+type HitPredicate = dyn Fn(&CachedMap, usize) -> bool;
+
+/// The order, in which maps and their bytes are scanned
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum ScanDirection {
+    /// From low to high addresses
+    #[default]
+    Forwards,
+    /// From high to low addresses
+    Backwards,
+}
+
+/// A predicate added by one of the builder methods. They are recorded, so the constraints can be serialized
+/// and rebuilt, unlike predicates passed to [`SearchConstraints::also`], which are opaque closures.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum BuiltinPredicate {
+    Name(String),
+    Path(PathBuf),
+    Module(ModuleId),
+    FileBacked,
+    AlignedTo(usize),
+    SharedMemory,
+    SharedMemoryOfKind(SharedMemoryKind),
+    JitCode,
+    JitCodeOfKind(JitKind),
+    ExcludingSegments(Vec<SharedSegment>),
+    /// A predicate of the [`predicate_registry`]
+    Named(String),
+    Resident,
+    NotSwapped,
+    Locked,
+    HugePageEligible,
+}
 
 #[derive(Clone)]
 pub struct SearchConstraints {
-    address_range: (usize, usize),
+    address_range: AddressRange,
+    ranges: Option<Vec<AddressRange>>,
+    max_hits: Option<usize>,
+    direction: ScanDirection,
+    ordered: bool,
     predicates: Vec<Rc<MapPredicate>>,
+    hit_predicates: Vec<Rc<HitPredicate>>,
+    builtin_predicates: Vec<BuiltinPredicate>,
+    /// The number of predicates, which were passed as closures
+    opaque_predicates: usize,
     readable: Option<bool>,
     writable: Option<bool>,
     executable: Option<bool>,
+    tls: Option<bool>,
+    accessible: bool,
+    coverage: Option<CoverageRecorder>,
 }
 
 impl SearchConstraints {
-    pub fn get_address_range(&self) -> (usize, usize) {
+    pub fn get_address_range(&self) -> AddressRange {
         self.address_range
     }
-    pub fn clamp_address_range(&self, address_range: (usize, usize)) -> (usize, usize) {
-        let from = address_range.0.max(self.get_address_range().0);
-        let to = address_range.1.min(self.get_address_range().1);
-
-        (from, to)
+    /// Intersects the address range with the constrained one, the result is empty if they don't overlap
+    pub fn clamp_address_range(&self, address_range: impl Into<AddressRange>) -> AddressRange {
+        self.address_range.intersect(&address_range.into())
+    }
+    pub fn get_ranges(&self) -> Option<&[AddressRange]> {
+        self.ranges.as_deref()
+    }
+    /// Splits the address range into the non-empty parts, that are allowed by the address range and the whitelisted ranges
+    pub fn clamp_address_ranges(
+        &self,
+        address_range: impl Into<AddressRange>,
+    ) -> Vec<AddressRange> {
+        let clamped = self.clamp_address_range(address_range);
+
+        match &self.ranges {
+            Some(ranges) => ranges
+                .iter()
+                .map(|range| range.intersect(&clamped))
+                .filter(|range| !range.is_empty())
+                .collect(),
+            None if !clamped.is_empty() => vec![clamped],
+            None => Vec::new(),
+        }
+    }
+    /// Returns the start addresses and bytes of the parts of the map, which should be scanned, in scan direction.
+    /// They are recorded by the coverage recorder, see [`Self::recording_coverage`]. Unreadable maps have none.
+    pub fn regions<'a>(&self, map: &'a CachedMap) -> Vec<(usize, &'a [u8])> {
+        let regions = self
+            .scanned_ranges(map.get_range())
+            .into_iter()
+            .filter_map(|range| {
+                Some((
+                    range.get_from(),
+                    map.get_bytes()
+                        .get(range.offsets_from(map.get_from_address()))?,
+                ))
+            })
+            .collect();
+
+        self.in_scan_order(regions)
+    }
+    /// Clamps the address range like [`Self::clamp_address_ranges`] for a scan and records the result as examined
+    pub(crate) fn scanned_ranges(
+        &self,
+        address_range: impl Into<AddressRange>,
+    ) -> Vec<AddressRange> {
+        let ranges = self.clamp_address_ranges(address_range);
+        if let Some(coverage) = &self.coverage {
+            ranges.iter().for_each(|range| coverage.record(*range));
+        }
+        ranges
+    }
+    pub fn get_coverage(&self) -> Option<&CoverageRecorder> {
+        self.coverage.as_ref()
+    }
+    pub fn get_direction(&self) -> ScanDirection {
+        self.direction
+    }
+    /// Reverses items, which are sorted by address, when scanning backwards
+    pub(crate) fn in_scan_order<T>(&self, mut items: Vec<T>) -> Vec<T> {
+        if self.direction == ScanDirection::Backwards {
+            items.reverse();
+        }
+        items
+    }
+    /// Collects hits, which are sorted by address, in scan direction up to the hit limit.
+    /// Scanning backwards collects all hits of the iterator before reversing them.
+    pub(crate) fn collect_hits<T>(&self, hits: impl Iterator<Item = T>) -> Vec<T> {
+        match self.direction {
+            ScanDirection::Forwards => hits.take(self.hit_limit()).collect(),
+            ScanDirection::Backwards => {
+                let mut hits = self.in_scan_order(hits.collect());
+                hits.truncate(self.hit_limit());
+                hits
+            }
+        }
+    }
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+    pub fn get_max_hits(&self) -> Option<usize> {
+        self.max_hits
+    }
+    /// The number of hits after which scans stop
+    pub(crate) fn hit_limit(&self) -> usize {
+        self.max_hits.unwrap_or(usize::MAX)
     }
     pub fn get_readable(&self) -> Option<bool> {
         self.readable
@@ -34,45 +177,166 @@ impl SearchConstraints {
     pub fn get_executable(&self) -> Option<bool> {
         self.executable
     }
+    pub fn get_tls(&self) -> Option<bool> {
+        self.tls
+    }
+    /// Whether maps without access permissions are skipped, which is the default
+    pub fn get_accessible(&self) -> bool {
+        self.accessible
+    }
+    pub fn get_builtin_predicates(&self) -> &[BuiltinPredicate] {
+        &self.builtin_predicates
+    }
+    /// Whether closures were passed as predicates, which prevents serialization
+    pub fn has_opaque_predicates(&self) -> bool {
+        self.opaque_predicates > 0
+    }
 
     pub fn everything() -> Self {
         SearchConstraints {
-            address_range: (usize::min_value(), usize::max_value()),
+            address_range: AddressRange::everything(),
+            ranges: None,
+            max_hits: None,
+            direction: ScanDirection::Forwards,
+            ordered: false,
             predicates: Vec::new(),
+            hit_predicates: Vec::new(),
+            builtin_predicates: Vec::new(),
+            opaque_predicates: 0,
             readable: None,
             writable: None,
             executable: None,
+            tls: None,
+            accessible: true,
+            coverage: None,
         }
     }
 
-    pub fn with_name(mut self, name: String) -> Self {
-        self.predicates
-            .push(Rc::new(move |map| match &map.get_name() {
-                MMapPath::Other(path) => path
-                    .split('/')
-                    .last()
-                    .map(|other_name| other_name == name)
-                    .unwrap_or(false),
-                MMapPath::Path(path) => path
-                    .file_name()
-                    .and_then(|other_name| other_name.to_str().map(|other_name| other_name == name))
-                    .unwrap_or(false),
-                _ => false,
-            }));
+    /// Readable, executable and file-backed maps, which is where code of loaded modules lives
+    pub fn code() -> Self {
+        Self::everything()
+            .thats_readable()
+            .thats_executable()
+            .thats_file_backed()
+    }
+
+    /// Readable and writable maps
+    pub fn data() -> Self {
+        Self::everything().thats_readable().thats_writable()
+    }
+
+    /// Readable, but neither writable nor executable file-backed maps, like `.rodata`
+    pub fn rodata() -> Self {
+        Self::everything()
+            .thats_readable()
+            .thats_not_writable()
+            .thats_not_executable()
+            .thats_file_backed()
+    }
 
+    /// Records the ranges, that scans using these constraints examine, see [`crate::coverage`]
+    pub fn recording_coverage(mut self, recorder: &CoverageRecorder) -> Self {
+        self.coverage = Some(recorder.clone());
         self
     }
 
+    pub fn with_name(self, name: String) -> Self {
+        self.builtin(BuiltinPredicate::Name(name))
+    }
+
+    /// Only allows maps of the file at the full path, unlike [`Self::with_name`] which only compares the file name
+    pub fn with_path(self, path: impl Into<PathBuf>) -> Self {
+        self.builtin(BuiltinPredicate::Path(path.into()))
+    }
+
+    /// Only allows maps of the module instance, see [`ModuleId`]
+    pub fn in_module(self, module: &ModuleId) -> Self {
+        self.builtin(BuiltinPredicate::Module(module.clone()))
+    }
+
     pub fn from(mut self, value: usize) -> Self {
-        self.address_range.0 = value;
-        self.address_range.1 = self.address_range.1.max(self.address_range.0);
+        self.address_range = AddressRange::new(value, self.address_range.get_to().max(value));
 
         self
     }
 
+    /// The end is exclusive
     pub fn to(mut self, value: usize) -> Self {
-        self.address_range.1 = value;
-        self.address_range.0 = self.address_range.0.min(self.address_range.1);
+        self.address_range = AddressRange::new(self.address_range.get_from().min(value), value);
+
+        self
+    }
+
+    /// Only scans inside of the given ranges, the ends are exclusive. Replaces previously given ranges.
+    /// Maps are allowed if they overlap with at least one of the ranges.
+    pub fn in_ranges(mut self, ranges: &[(usize, usize)]) -> Self {
+        let mut ranges = ranges
+            .iter()
+            .map(|range| AddressRange::from(*range))
+            .filter(|range| !range.is_empty())
+            .collect::<Vec<_>>();
+        ranges.sort_unstable_by_key(|range| (range.get_from(), range.get_to()));
+
+        // Overlapping ranges are merged, so no address is scanned twice
+        let mut merged: Vec<AddressRange> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if last.overlaps(&range) => {
+                    *last = AddressRange::new(last.get_from(), last.get_to().max(range.get_to()))
+                }
+                _ => merged.push(range),
+            }
+        }
+        self.ranges = Some(merged);
+
+        self
+    }
+
+    /// Stops scanning once n hits are found, these are the first n hits in scan order, see [`Self::backwards`].
+    pub fn max_hits(mut self, n: usize) -> Self {
+        self.max_hits = Some(n);
+
+        self
+    }
+
+    /// Scans from high to low addresses, so hits are reported in descending order.
+    /// Together with [`Self::max_hits`] this finds the last hits. Signatures are searched backwards and stop at the
+    /// limit, reference and value scans still find every hit of a region before reversing them.
+    pub fn backwards(mut self) -> Self {
+        self.direction = ScanDirection::Backwards;
+        self.ordered = false;
+
+        self
+    }
+
+    pub fn forwards(mut self) -> Self {
+        self.direction = ScanDirection::Forwards;
+
+        self
+    }
+
+    /// Guarantees, that the resulting pool is in ascending address order without duplicates,
+    /// regardless of the scan type or how a step combines the results of multiple pointers.
+    /// This resolves the whole pool at once and implies scanning forwards.
+    pub fn ordered(mut self) -> Self {
+        self.direction = ScanDirection::Forwards;
+        self.ordered = true;
+
+        self
+    }
+
+    /// Skips maps without any access permissions, like guard pages, whose bytes aren't part of the snapshot.
+    /// This is on by default.
+    pub fn thats_accessible(mut self) -> Self {
+        self.accessible = true;
+
+        self
+    }
+
+    /// Also allows maps without any access permissions. They have no bytes in the snapshot,
+    /// so pointers into them can't be read, reads fail with [`BcrlError::NoAccess`](crate::error::BcrlError::NoAccess).
+    pub fn including_inaccessible(mut self) -> Self {
+        self.accessible = false;
 
         self
     }
@@ -113,14 +377,184 @@ impl SearchConstraints {
         self
     }
 
+    pub fn thats_tls(mut self) -> Self {
+        self.tls = Some(true);
+
+        self
+    }
+
+    pub fn thats_not_tls(mut self) -> Self {
+        self.tls = Some(false);
+
+        self
+    }
+
+    /// Only allows maps, which are backed by a file
+    pub fn thats_file_backed(self) -> Self {
+        self.builtin(BuiltinPredicate::FileBacked)
+    }
+
+    /// Only allows hits, whose address is a multiple of the alignment
+    pub fn aligned_to(self, alignment: usize) -> Self {
+        self.builtin(BuiltinPredicate::AlignedTo(alignment))
+    }
+
+    /// Only allows memfd, POSIX and SysV shared memory maps
+    pub fn thats_shared_memory(self) -> Self {
+        self.builtin(BuiltinPredicate::SharedMemory)
+    }
+
+    /// Only allows shared memory maps of the kind
+    pub fn thats_shared_memory_of_kind(self, kind: SharedMemoryKind) -> Self {
+        self.builtin(BuiltinPredicate::SharedMemoryOfKind(kind))
+    }
+
+    /// Only allows the code caches of JIT compilers, see [`JitKind`]
+    pub fn thats_jit_code(self) -> Self {
+        self.builtin(BuiltinPredicate::JitCode)
+    }
+
+    /// Only allows JIT code caches of the kind
+    pub fn thats_jit_code_of_kind(self, kind: JitKind) -> Self {
+        self.builtin(BuiltinPredicate::JitCodeOfKind(kind))
+    }
+
+    /// Skips maps of shared segments, which were already scanned in another process, see [`crate::BcrlFactory::shared_segments`]
+    pub fn excluding_segments(self, segments: impl IntoIterator<Item = SharedSegment>) -> Self {
+        self.builtin(BuiltinPredicate::ExcludingSegments(
+            segments.into_iter().collect(),
+        ))
+    }
+
+    /// Only allows maps, of which at least one page is in physical memory.
+    /// Like the other predicates on the [`crate::map_usage::MapUsage`], this rejects maps without one.
+    pub fn thats_resident(self) -> Self {
+        self.builtin(BuiltinPredicate::Resident)
+    }
+
+    /// Only allows maps, of which no page is swapped out, since reading those is slow
+    pub fn thats_not_swapped(self) -> Self {
+        self.builtin(BuiltinPredicate::NotSwapped)
+    }
+
+    /// Only allows maps, which are locked into memory
+    pub fn thats_locked(self) -> Self {
+        self.builtin(BuiltinPredicate::Locked)
+    }
+
+    /// Only allows maps, which can be backed by transparent huge pages
+    pub fn thats_huge_page_eligible(self) -> Self {
+        self.builtin(BuiltinPredicate::HugePageEligible)
+    }
+
+    /// Only allows maps, which pass the predicate registered under the name, see [`predicate_registry`]
+    pub fn with_named_predicate(self, name: impl Into<String>) -> Self {
+        self.builtin(BuiltinPredicate::Named(name.into()))
+    }
+
+    /// Adds the predicate and records it
+    fn builtin(mut self, predicate: BuiltinPredicate) -> Self {
+        self.builtin_predicates.push(predicate.clone());
+
+        match predicate {
+            BuiltinPredicate::Name(name) => {
+                self.predicates
+                    .push(Rc::new(move |map| match &map.get_name() {
+                        MMapPath::Other(path) => path
+                            .split('/')
+                            .next_back()
+                            .map(|other_name| other_name == name)
+                            .unwrap_or(false),
+                        MMapPath::Path(path) => path
+                            .file_name()
+                            .and_then(|other_name| {
+                                other_name.to_str().map(|other_name| other_name == name)
+                            })
+                            .unwrap_or(false),
+                        _ => false,
+                    }))
+            }
+            BuiltinPredicate::Path(path) => self.predicates.push(Rc::new(
+                move |map| matches!(map.get_name(), MMapPath::Path(other) if *other == path),
+            )),
+            BuiltinPredicate::Module(module) => self
+                .predicates
+                .push(Rc::new(move |map| module.contains_map(map))),
+            BuiltinPredicate::FileBacked => self
+                .predicates
+                .push(Rc::new(|map| matches!(map.get_name(), MMapPath::Path(_)))),
+            BuiltinPredicate::AlignedTo(alignment) => self
+                .hit_predicates
+                .push(Rc::new(move |_, address| address % alignment.max(1) == 0)),
+            BuiltinPredicate::SharedMemory => self
+                .predicates
+                .push(Rc::new(|map| SharedMemoryKind::of(map).is_some())),
+            BuiltinPredicate::SharedMemoryOfKind(kind) => self
+                .predicates
+                .push(Rc::new(move |map| SharedMemoryKind::of(map) == Some(kind))),
+            BuiltinPredicate::JitCode => self
+                .predicates
+                .push(Rc::new(|map| JitKind::of(map).is_some())),
+            BuiltinPredicate::JitCodeOfKind(kind) => self
+                .predicates
+                .push(Rc::new(move |map| JitKind::of(map) == Some(kind))),
+            BuiltinPredicate::Named(name) => self
+                .predicates
+                .push(Rc::new(move |map| predicate_registry::test(&name, map))),
+            BuiltinPredicate::ExcludingSegments(segments) => {
+                let segments = segments.into_iter().collect::<HashSet<_>>();
+                self.predicates.push(Rc::new(move |map| {
+                    SharedSegment::of(map).is_none_or(|segment| !segments.contains(&segment))
+                }))
+            }
+            BuiltinPredicate::Resident => self.predicates.push(Rc::new(|map| {
+                map.get_usage().is_some_and(|usage| usage.is_resident())
+            })),
+            BuiltinPredicate::NotSwapped => self.predicates.push(Rc::new(|map| {
+                map.get_usage().is_some_and(|usage| !usage.is_swapped())
+            })),
+            BuiltinPredicate::Locked => self.predicates.push(Rc::new(|map| {
+                map.get_usage().is_some_and(|usage| usage.is_locked())
+            })),
+            BuiltinPredicate::HugePageEligible => self.predicates.push(Rc::new(|map| {
+                map.get_usage()
+                    .is_some_and(|usage| usage.is_huge_page_eligible())
+            })),
+        }
+
+        self
+    }
+
+    /// Only allows maps, for which the predicate returns true. Constraints with closures can't be serialized.
     pub fn also(mut self, predicate: impl Fn(&CachedMap) -> bool + 'static) -> Self {
         self.predicates.push(Rc::new(predicate));
+        self.opaque_predicates += 1;
 
         self
     }
 
+    /// Only allows hits, for which the predicate returns true.
+    /// It receives the map containing the hit and the address of the hit, so the surrounding bytes can be read from the map.
+    pub fn also_hit(mut self, predicate: impl Fn(&CachedMap, usize) -> bool + 'static) -> Self {
+        self.hit_predicates.push(Rc::new(predicate));
+        self.opaque_predicates += 1;
+
+        self
+    }
+
+    /// Checks the hit predicates, the hit has to be inside of the map
+    pub fn allows_hit(&self, map: &CachedMap, address: usize) -> bool {
+        self.hit_predicates
+            .iter()
+            .all(|predicate| (*predicate)(map, address))
+    }
+
     pub fn allows_address(&self, address: usize) -> bool {
-        self.address_range.0 >= address || self.address_range.1 <= address
+        self.address_range.contains(address)
+            && self
+                .ranges
+                .as_ref()
+                .is_none_or(|ranges| ranges.iter().any(|range| range.contains(address)))
     }
 
     pub fn allows_map(&self, map: &CachedMap) -> bool {
@@ -130,9 +564,12 @@ impl SearchConstraints {
             }
         }
 
-        if self.address_range.0 > map.get_from_address()
-            || self.address_range.1 < map.get_to_address()
-        {
+        // Maps, which are only partially inside, are allowed and get clamped when scanning
+        if self.clamp_address_ranges(map.get_range()).is_empty() {
+            return false;
+        }
+
+        if self.accessible && !map.is_accessible() {
             return false;
         }
 
@@ -154,6 +591,12 @@ impl SearchConstraints {
             }
         }
 
+        if let Some(tls) = self.tls {
+            if tls != map.is_tls() {
+                return false;
+            }
+        }
+
         true
     }
 
@@ -166,3 +609,126 @@ impl SearchConstraints {
         true
     }
 }
+
+impl SearchConstraints {
+    /// Describes the constraints to tell scans apart, see [`crate::checkpoint`]. Closures are only counted,
+    /// so constraints with them can't be told apart.
+    /// The coverage recorder doesn't change the hits, so it isn't part of it.
+    pub(crate) fn describe(&self) -> String {
+        let mut constraints = self.clone();
+        constraints.coverage = None;
+
+        format!("{constraints:?}")
+    }
+}
+
+/// Lists the recorded predicates, closures are only counted
+impl fmt::Debug for SearchConstraints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SearchConstraints")
+            .field("address_range", &self.address_range)
+            .field("ranges", &self.ranges)
+            .field("max_hits", &self.max_hits)
+            .field("direction", &self.direction)
+            .field("ordered", &self.ordered)
+            .field("predicates", &self.builtin_predicates)
+            .field("opaque_predicates", &self.opaque_predicates)
+            .field("readable", &self.readable)
+            .field("writable", &self.writable)
+            .field("executable", &self.executable)
+            .field("tls", &self.tls)
+            .field("accessible", &self.accessible)
+            .field("coverage", &self.coverage.is_some())
+            .finish()
+    }
+}
+
+/// The serialized form of [`SearchConstraints`], missing fields are taken from [`SearchConstraints::everything`]
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+struct SerializedConstraints {
+    address_range: AddressRange,
+    ranges: Option<Vec<AddressRange>>,
+    max_hits: Option<usize>,
+    direction: ScanDirection,
+    ordered: bool,
+    predicates: Vec<BuiltinPredicate>,
+    readable: Option<bool>,
+    writable: Option<bool>,
+    executable: Option<bool>,
+    tls: Option<bool>,
+    accessible: bool,
+}
+
+#[cfg(feature = "serde")]
+impl Default for SerializedConstraints {
+    fn default() -> Self {
+        Self {
+            address_range: AddressRange::everything(),
+            ranges: None,
+            max_hits: None,
+            direction: ScanDirection::Forwards,
+            ordered: false,
+            predicates: Vec::new(),
+            readable: None,
+            writable: None,
+            executable: None,
+            tls: None,
+            accessible: true,
+        }
+    }
+}
+
+/// Fails if closures were passed as predicates, see [`SearchConstraints::has_opaque_predicates`]
+#[cfg(feature = "serde")]
+impl serde::Serialize for SearchConstraints {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.has_opaque_predicates() {
+            return Err(serde::ser::Error::custom(
+                "constraints with closure predicates can't be serialized",
+            ));
+        }
+
+        SerializedConstraints {
+            address_range: self.address_range,
+            ranges: self.ranges.clone(),
+            max_hits: self.max_hits,
+            direction: self.direction,
+            ordered: self.ordered,
+            predicates: self.builtin_predicates.clone(),
+            readable: self.readable,
+            writable: self.writable,
+            executable: self.executable,
+            tls: self.tls,
+            accessible: self.accessible,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SearchConstraints {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = SerializedConstraints::deserialize(deserializer)?;
+
+        let constraints = SearchConstraints {
+            address_range: serialized.address_range,
+            ranges: serialized.ranges,
+            max_hits: serialized.max_hits,
+            direction: serialized.direction,
+            ordered: serialized.ordered,
+            readable: serialized.readable,
+            writable: serialized.writable,
+            executable: serialized.executable,
+            tls: serialized.tls,
+            accessible: serialized.accessible,
+            ..SearchConstraints::everything()
+        };
+
+        Ok(serialized
+            .predicates
+            .into_iter()
+            .fold(constraints, SearchConstraints::builtin))
+    }
+}
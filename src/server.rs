@@ -0,0 +1,319 @@
+//! A small server, which executes scan plans on behalf of other processes.
+//!
+//! Reading the memory of another process usually requires `CAP_SYS_PTRACE`. Instead of granting it to every tool,
+//! a privileged helper can run a [`ScanServer`] and unprivileged clients submit [`ScanPlan`]s over a unix socket.
+//!
+//! Every message is a frame consisting of its length as a little-endian u32 followed by UTF-8 text.
+//! A request contains `pid <pid>` on its first line followed by the plan.
+//! A response starts with `ok` followed by one hexadecimal address per line, or is `error <message>`.
+//!
+//! Every client is handled on its own thread. By default a client may only scan processes, which are owned by its
+//! user, the user is taken from the credentials of the socket (`SO_PEERCRED`). Root may scan every process.
+
+use std::{
+    io::{self, Read, Write},
+    mem,
+    os::{
+        fd::AsRawFd,
+        unix::net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use procfs::process::Process;
+
+use crate::{
+    factory::BcrlFactory,
+    scan_plan::{ScanPlan, Seed, Step},
+};
+
+/// Frames larger than this are rejected to avoid allocating arbitrary amounts of memory for malicious clients
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+/// Requests larger than this are rejected by the server, plans are small
+pub const MAX_REQUEST_SIZE: usize = 64 * 1024;
+/// Signatures and strings of a plan, which are longer than this, are rejected by the server
+pub const MAX_PATTERN_LENGTH: usize = 4096;
+/// Clients, which don't send or receive anything for this long, are disconnected
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn write_frame(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frame is too large",
+        ));
+    }
+
+    writer.write_u32::<LittleEndian>(payload.len() as u32)?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    read_frame_limited(reader, MAX_FRAME_SIZE)
+}
+
+/// Reads a frame, which may be at most max_size bytes large
+pub fn read_frame_limited(reader: &mut impl Read, max_size: usize) -> io::Result<Vec<u8>> {
+    let length = reader.read_u32::<LittleEndian>()? as usize;
+    if length > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame is too large",
+        ));
+    }
+
+    let mut payload = vec![0; length];
+    reader.read_exact(&mut payload)?;
+
+    Ok(payload)
+}
+
+fn parse_request(request: &str) -> Result<(i32, ScanPlan), String> {
+    let (header, plan) = request.split_once('\n').unwrap_or((request, ""));
+
+    let pid = header
+        .strip_prefix("pid ")
+        .and_then(|pid| pid.trim().parse().ok())
+        .ok_or_else(|| "expected 'pid <pid>' on the first line".to_string())?;
+    let plan = ScanPlan::from_str(plan).map_err(|error| error.to_string())?;
+
+    Ok((pid, plan))
+}
+
+/// The signatures and strings of the plan
+fn patterns(plan: &ScanPlan) -> impl Iterator<Item = &str> {
+    let seed = match plan.get_seed() {
+        Seed::Signature(signature) => signature,
+        Seed::String(string) => string,
+    };
+
+    std::iter::once(seed.as_str()).chain(plan.get_steps().iter().filter_map(|step| match step {
+        Step::PrevOccurrence(signature, _)
+        | Step::NextOccurrence(signature, _)
+        | Step::SignatureFilter(signature) => Some(signature.as_str()),
+        _ => None,
+    }))
+}
+
+/// The uid of the process on the other end of the socket
+fn peer_uid(stream: &UnixStream) -> io::Result<u32> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut length = mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    // SAFETY: the buffer is a ucred and the length is its size
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut credentials as *mut libc::ucred).cast(),
+            &mut length,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(credentials.uid)
+}
+
+/// The default access check, root may scan every process and other users only their own ones
+fn owns_process(uid: u32, pid: i32) -> bool {
+    uid == 0
+        || Process::new(pid)
+            .and_then(|process| process.uid())
+            .is_ok_and(|owner| owner == uid)
+}
+
+/// Formats the response, results which don't fit into a frame are reported as an error
+fn format_response(result: Result<Vec<usize>, String>) -> String {
+    match result {
+        Ok(addresses) => {
+            let mut response = "ok".to_string();
+            for address in addresses {
+                response.push_str(&format!("\n{address:x}"));
+            }
+            if response.len() > MAX_FRAME_SIZE {
+                return format_response(Err("result is too large".to_string()));
+            }
+            response
+        }
+        Err(message) => format!("error {message}"),
+    }
+}
+
+fn parse_response(response: &str) -> io::Result<Result<Vec<usize>, String>> {
+    if let Some(message) = response.strip_prefix("error ") {
+        return Ok(Err(message.to_string()));
+    }
+
+    let mut lines = response.lines();
+    if lines.next() != Some("ok") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "malformed response",
+        ));
+    }
+
+    lines
+        .map(|line| usize::from_str_radix(line, 16))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Ok)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+type PidFilter = dyn Fn(i32) -> bool + Send + Sync;
+type PeerFilter = dyn Fn(u32, i32) -> bool + Send + Sync;
+
+pub struct ScanServer {
+    listener: UnixListener,
+    pid_filter: Option<Box<PidFilter>>,
+    peer_filter: Box<PeerFilter>,
+    timeout: Option<Duration>,
+}
+
+impl ScanServer {
+    /// Binds the server to a unix socket. Access to the server is controlled through the permissions of the socket file
+    /// and the uid of the clients, see [`Self::with_peer_filter`].
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            listener: UnixListener::bind(path)?,
+            pid_filter: None,
+            peer_filter: Box::new(owns_process),
+            timeout: Some(DEFAULT_TIMEOUT),
+        })
+    }
+
+    /// Only allows scanning processes, which are accepted by the filter
+    pub fn with_pid_filter(mut self, filter: impl Fn(i32) -> bool + Send + Sync + 'static) -> Self {
+        self.pid_filter = Some(Box::new(filter));
+
+        self
+    }
+
+    /// Decides whether a client, given by its uid, may scan a process, given by its pid.
+    /// Replaces the default, which only allows root and the owner of the process.
+    pub fn with_peer_filter(
+        mut self,
+        filter: impl Fn(u32, i32) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.peer_filter = Box::new(filter);
+
+        self
+    }
+
+    /// The read and write timeout of client connections, None waits forever
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+
+        self
+    }
+
+    fn execute(&self, uid: u32, request: &str) -> Result<Vec<usize>, String> {
+        let (pid, plan) = parse_request(request)?;
+
+        if patterns(&plan).any(|pattern| pattern.len() > MAX_PATTERN_LENGTH) {
+            return Err(format!(
+                "patterns may be at most {MAX_PATTERN_LENGTH} characters long"
+            ));
+        }
+        if let Some(filter) = &self.pid_filter {
+            if !filter(pid) {
+                return Err(format!("scanning {pid} is not allowed"));
+            }
+        }
+        if !(self.peer_filter)(uid, pid) {
+            return Err(format!("user {uid} may not scan {pid}"));
+        }
+
+        let process = Process::new(pid).map_err(|error| error.to_string())?;
+        let factory = BcrlFactory::from_process(&process).map_err(|error| error.to_string())?;
+
+        Ok(plan.execute(&factory).get_pool().collect())
+    }
+
+    /// Answers requests of a single client until it disconnects or times out
+    pub fn handle(&self, stream: &mut UnixStream) -> io::Result<()> {
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+        let uid = peer_uid(stream)?;
+
+        loop {
+            let request = match read_frame_limited(stream, MAX_REQUEST_SIZE) {
+                Ok(request) => request,
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(error) => return Err(error),
+            };
+
+            let result = match String::from_utf8(request) {
+                Ok(request) => self.execute(uid, &request),
+                Err(_) => Err("request is not valid UTF-8".to_string()),
+            };
+
+            write_frame(stream, format_response(result).as_bytes())?;
+        }
+    }
+
+    /// Accepts clients and handles each of them on its own thread. Errors of single clients, including failed accepts,
+    /// don't stop the server.
+    pub fn serve(&self) -> io::Result<()> {
+        thread::scope(|scope| {
+            for stream in self.listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_error) => {
+                        #[cfg(feature = "log")]
+                        log::warn!("failed to accept a client: {_error}");
+                        // Running out of file descriptors fails every accept until a client disconnects
+                        thread::sleep(Duration::from_millis(10));
+                        continue;
+                    }
+                };
+                scope.spawn(move || self.handle(&mut stream));
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Submits a plan to a server. The outer result contains communication errors,
+/// the inner one contains errors, which the server reported.
+pub fn submit(
+    stream: &mut UnixStream,
+    pid: i32,
+    plan: &ScanPlan,
+) -> io::Result<Result<Vec<usize>, String>> {
+    write_frame(stream, format!("pid {pid}\n{plan}").as_bytes())?;
+
+    let response = read_frame(stream)?;
+    let response = String::from_utf8(response)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+    parse_response(&response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_response, parse_response, MAX_FRAME_SIZE};
+
+    #[test]
+    fn test_oversized_response() {
+        let addresses = vec![usize::MAX; MAX_FRAME_SIZE / 8];
+
+        assert_eq!(format_response(Ok(vec![0x10, 0x20])), "ok\n10\n20");
+        assert_eq!(
+            parse_response(&format_response(Ok(addresses))).unwrap(),
+            Err("result is too large".to_string())
+        );
+    }
+}
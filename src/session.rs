@@ -1,64 +1,499 @@
-use procfs::process::MMapPath;
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    rc::Rc,
+};
+#[cfg(feature = "linux")]
+use std::{io, thread, time::Duration};
+
+#[cfg(feature = "linux")]
+use procfs::{process::Process, ProcError};
+use procfs_core::process::MMapPath;
 use signature_scanner::Signature;
 
-use byteorder::ByteOrder;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+#[cfg(all(feature = "hooks", target_arch = "x86_64"))]
+use crate::hooks::HookSet;
+use crate::{
+    address_class::{self, AddressClass},
+    endianness::Endianness,
+    hit_context::HitContext,
+    module::{Module, ModuleId},
+    pattern,
+    safe_pointer::SafePointer,
+    search_constraints::SearchConstraints,
+    target::{Isa, TargetSpec},
+    trace::{Checkpoint, Trace},
+};
+#[cfg(feature = "linux")]
+use crate::{
+    error::BcrlError,
+    factory::BcrlFactory,
+    patch_set::{self, PatchSet},
+};
+
+/// The default bound for walking over instructions
+#[cfg(feature = "disassembly")]
+pub const MAX_INSTRUCTIONS: usize = 1024;
 
-use crate::{safe_pointer::SafePointer, search_constraints::SearchConstraints};
+/// Calls a step generic over the byte order with the endianness of the session's (or factory's) target
+macro_rules! with_endianness {
+    ($session:ident.$step:ident($($argument:expr),*)) => {
+        match $session.target.get_endianness() {
+            $crate::endianness::Endianness::Little => {
+                $session.$step::<::byteorder::LittleEndian>($($argument),*)
+            }
+            $crate::endianness::Endianness::Big => {
+                $session.$step::<::byteorder::BigEndian>($($argument),*)
+            }
+        }
+    };
+}
+pub(crate) use with_endianness;
 
 pub struct Session<'a> {
     pub(crate) pool: Box<dyn Iterator<Item = SafePointer> + 'a>,
+    target: TargetSpec,
+    /// The root directory of the process, see [`crate::container`]
+    root: Option<PathBuf>,
+    #[cfg(feature = "log")]
+    log_invalidations: bool,
+}
+
+/// The pool of a split session, which is shared between its parts
+struct SharedPool<'a> {
+    upstream: RefCell<Option<Box<dyn Iterator<Item = SafePointer> + 'a>>>,
+    resolved: OnceCell<Vec<SafePointer>>,
+}
+
+impl SharedPool<'_> {
+    fn resolve(&self) -> &[SafePointer] {
+        self.resolved.get_or_init(|| {
+            self.upstream
+                .borrow_mut()
+                .take()
+                .map(|pool| pool.collect())
+                .unwrap_or_default()
+        })
+    }
+}
+
+#[cfg(feature = "log")]
+fn log_invalidation(step: &str, address: usize, ptr: &SafePointer) {
+    use crate::cached_maps::FindAddress;
+
+    let reason = if ptr.get_maps().find_map(ptr.get_address()).is_none() {
+        format!("{:#x} is not mapped", ptr.get_address())
+    } else {
+        "no result was found".to_string()
+    };
+
+    log::warn!("{step} invalidated the pointer at {address:#x}: {reason}");
+}
+
+/// Whether the file name of the module of the pointer is the module name
+pub(crate) fn is_in_module(ptr: &SafePointer, module_name: &str) -> bool {
+    ptr.get_module_name()
+        .map(|module| match module {
+            MMapPath::Path(path) => path
+                .file_name()
+                .map(|file_name| file_name == module_name)
+                .unwrap_or(false),
+            MMapPath::Other(name) => name
+                .split('/')
+                .next_back()
+                .map(|file_name| file_name == module_name)
+                .unwrap_or(false),
+            _ => false,
+        })
+        .unwrap_or(false)
 }
 
 impl<'a> Session<'a> {
+    pub(crate) fn new(pool: impl Iterator<Item = SafePointer> + 'a) -> Self {
+        Self {
+            pool: Box::new(pool),
+            target: TargetSpec::native(),
+            root: None,
+            #[cfg(feature = "log")]
+            log_invalidations: false,
+        }
+    }
+
+    /// Makes the pointers read from the memory of the process at the time of the read instead of from the snapshot,
+    /// so steps like dereference see the current values. Pointers created by later steps, like references, read live as well.
+    #[cfg(feature = "linux")]
+    pub fn live(mut self, process: &Process) -> Result<Self, ProcError> {
+        let memory = Rc::new(process.mem()?);
+        self.pool = Box::new(self.pool.map(move |mut pointer| {
+            pointer.live(memory.clone());
+            pointer
+        }));
+
+        Ok(self)
+    }
+
+    /// Invalidates the pointers, whose map was unmapped or changed its permissions since the snapshot was taken.
+    /// Regions like JIT code caches come and go while the process runs, so this should run right before the pointers
+    /// are used, e.g. before patching them.
+    #[cfg(feature = "linux")]
+    pub fn still_mapped(self, process: &Process) -> Result<Self, ProcError> {
+        use crate::cached_maps::FindAddress;
+
+        let mut current = process
+            .maps()?
+            .into_iter()
+            .map(|map| (map.address.0 as usize, map.address.1 as usize, map.perms))
+            .collect::<Vec<_>>();
+        current.sort_unstable_by_key(|(from, _, _)| *from);
+
+        Ok(self.mutate_step("still_mapped", move |ptr| {
+            let address = ptr.get_address();
+            let permissions = ptr
+                .get_maps()
+                .find_map(address)
+                .map(|map| map.get_permissions());
+            let index = current.partition_point(|(from, _, _)| *from <= address);
+            let mapped = index > 0 && {
+                let (_, to, perms) = current[index - 1];
+                address < to && Some(perms) == permissions
+            };
+
+            if !mapped {
+                ptr.invalidate();
+            }
+        }))
+    }
+
+    /// Runs the pipeline and, as long as its pool is empty, refreshes the factory and runs it again,
+    /// waiting for the delay before each of at most retries refreshes.
+    /// This helps when attaching while the target starts, before the library of interest was loaded.
+    /// The pool is resolved right away, the session of the last run is returned, even if it is still empty.
+    #[cfg(feature = "linux")]
+    pub fn retry_with_refresh<F>(
+        factory: &'a BcrlFactory,
+        retries: usize,
+        delay: Duration,
+        mut pipeline: F,
+    ) -> Result<Self, BcrlError>
+    where
+        F: FnMut(&'a BcrlFactory) -> Self,
+    {
+        let mut attempt = 0;
+        loop {
+            let mut session = pipeline(factory);
+            let pool = session.pool.collect::<Vec<_>>();
+            let found = pool.iter().any(|pointer| !pointer.is_invalidated());
+            session.pool = Box::new(pool.into_iter());
+
+            if found || attempt == retries {
+                return Ok(session);
+            }
+
+            attempt += 1;
+            thread::sleep(delay);
+            factory.refresh()?;
+        }
+    }
+
+    /// Overwrites length bytes at every valid pointer with nops, see [`Self::patch_with_backup`]
+    #[cfg(feature = "linux")]
+    pub fn nop(self, process: &Process, length: usize) -> io::Result<PatchSet> {
+        self.patch_with_backup(process, &vec![patch_set::NOP; length])
+    }
+
+    /// Writes the bytes to every valid pointer and returns the original bytes, so the patches can be restored.
+    /// If a write fails, the already written patches are restored before returning the error.
+    #[cfg(feature = "linux")]
+    pub fn patch_with_backup(self, process: &Process, bytes: &[u8]) -> io::Result<PatchSet> {
+        let mut patches = PatchSet::new(process)?;
+
+        for pointer in self.pool.filter(|pointer| !pointer.is_invalidated()) {
+            if let Err(error) = patches.patch(pointer.get_address(), bytes) {
+                patches.restore()?;
+                return Err(error);
+            }
+        }
+
+        Ok(patches)
+    }
+
+    /// Hooks every valid pointer with the detour and returns the trampolines, see [`HookSet::install`].
+    /// Pointers to the same address are hooked once, the trampolines are in the order of the first of them.
+    /// If an installation fails, the hooks installed by this call are removed before returning the error.
+    #[cfg(all(feature = "hooks", target_arch = "x86_64"))]
+    pub fn hook(self, hooks: &mut HookSet, detour: usize) -> io::Result<Vec<usize>> {
+        let mut trampolines = Vec::new();
+        let mut targets = Vec::new();
+        let mut seen = HashSet::new();
+
+        for pointer in self
+            .pool
+            .filter(|pointer| !pointer.is_invalidated())
+            .filter(|pointer| seen.insert(pointer.get_address()))
+        {
+            match hooks.install_at(&pointer, detour) {
+                Ok(hook) => {
+                    trampolines.push(hook.get_trampoline());
+                    targets.push(hook.get_target());
+                }
+                Err(error) => {
+                    for target in targets.into_iter().rev() {
+                        hooks.remove(target)?;
+                    }
+                    return Err(error);
+                }
+            }
+        }
+
+        Ok(trampolines)
+    }
+
+    /// Logs a warning with the address, step and reason, whenever a following step invalidates a pointer
+    #[cfg(feature = "log")]
+    pub fn log_invalidations(mut self, enabled: bool) -> Self {
+        self.log_invalidations = enabled;
+
+        self
+    }
+
+    /// Sets the byte order, that the non-generic steps like [`Self::dereference_configured`] read integers in.
+    /// Sessions created by a factory use the endianness of the factory.
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.target = self.target.with_endianness(endianness);
+
+        self
+    }
+
+    pub fn get_endianness(&self) -> Endianness {
+        self.target.get_endianness()
+    }
+
+    /// Sets the architecture, that the non-generic steps assume. Sessions created by a factory use the target of the factory.
+    pub fn with_target(mut self, target: TargetSpec) -> Self {
+        self.target = target;
+
+        self
+    }
+
+    pub fn get_target(&self) -> TargetSpec {
+        self.target
+    }
+
+    pub(crate) fn with_root(mut self, root: Option<PathBuf>) -> Self {
+        self.root = root;
+
+        self
+    }
+
     /// Steps forward through the process memory map.
     pub fn step_forwards(self, operand: usize) -> Self {
-        self.mutate(move |ptr| {
+        self.mutate_step("step_forwards", move |ptr| {
             ptr.add(operand);
         })
     }
 
     /// Steps backwards through the process memory map.
     pub fn step_backwards(self, operand: usize) -> Self {
-        self.mutate(move |ptr| {
+        self.mutate_step("step_backwards", move |ptr| {
             ptr.sub(operand);
         })
     }
 
+    /// Steps from the start of a match of the signature to its first byte, which isn't a wildcard
+    pub fn skip_leading_wildcards(self, signature: &Signature) -> Self {
+        self.step_forwards(pattern::first_concrete_offset(signature.get_elements()).unwrap_or(0))
+    }
+
     /// Dereferences each pointer in the pool.
     pub fn dereference<Endian: ByteOrder>(self) -> Self {
-        self.mutate(move |ptr| {
+        self.mutate_step("dereference", move |ptr| {
             ptr.dereference::<Endian>();
         })
     }
 
     /// Dereferences relative addresses.
     pub fn relative_to_absolute<Endian: ByteOrder>(self) -> Self {
-        self.mutate(move |ptr| {
+        self.mutate_step("relative_to_absolute", move |ptr| {
             ptr.relative_to_absolute::<Endian>();
         })
     }
 
+    fn dereference_width<Endian: ByteOrder>(self, width: usize) -> Self {
+        self.mutate_step("dereference", move |ptr| {
+            ptr.dereference_width::<Endian>(width);
+        })
+    }
+
+    /// Dereferences each pointer in the pool using the pointer width and endianness of the session's target.
+    pub fn dereference_configured(self) -> Self {
+        let width = self.target.get_pointer_width();
+        with_endianness!(self.dereference_width(width))
+    }
+
+    /// Dereferences relative addresses using the endianness of the session.
+    pub fn relative_to_absolute_configured(self) -> Self {
+        with_endianness!(self.relative_to_absolute())
+    }
+
     /// Finds the previous occurrence of a signature. Note, that this won't jump to the next mapping.
     pub fn prev_occurrence(self, signature: Signature, constraints: SearchConstraints) -> Self {
-        self.mutate(move |ptr| {
+        self.mutate_step("prev_occurrence", move |ptr| {
             ptr.prev_occurrence(&signature, &constraints);
         })
     }
 
     /// Finds the next occurrence of a signature. Note, that this won't jump to the next mapping.
     pub fn next_occurrence(self, signature: Signature, constraints: SearchConstraints) -> Self {
-        self.mutate(move |ptr| {
+        self.mutate_step("next_occurrence", move |ptr| {
             ptr.next_occurrence(&signature, &constraints);
         })
     }
 
+    /// Finds the next occurrence of a signature, or the previous one if the constraints scan backwards.
+    /// Note, that this won't jump to the next mapping.
+    pub fn occurrence(self, signature: Signature, constraints: SearchConstraints) -> Self {
+        self.mutate_step("occurrence", move |ptr| {
+            ptr.occurrence(&signature, &constraints);
+        })
+    }
+
+    /// Finds the previous occurrence of a signature, which starts at most max_bytes in front of the pointer.
+    pub fn prev_occurrence_within(self, signature: Signature, max_bytes: usize) -> Self {
+        self.mutate_step("prev_occurrence_within", move |ptr| {
+            ptr.prev_occurrence_within(&signature, max_bytes);
+        })
+    }
+
+    /// Finds the next occurrence of a signature, which starts at most max_bytes behind the pointer.
+    pub fn next_occurrence_within(self, signature: Signature, max_bytes: usize) -> Self {
+        self.mutate_step("next_occurrence_within", move |ptr| {
+            ptr.next_occurrence_within(&signature, max_bytes);
+        })
+    }
+
     /// Jumps over the current instruction to the next one.
     pub fn next_instruction<Isa: lde::Isa>(self) -> Self {
-        self.mutate(move |ptr| {
+        self.mutate_step("next_instruction", move |ptr| {
             ptr.next_instruction::<Isa>();
         })
     }
 
+    /// Jumps over the current instruction using the ISA of the session's target.
+    /// Pointers are invalidated, if the instructions of the ISA can't be decoded.
+    pub fn next_instruction_configured(self) -> Self {
+        match self.target.get_isa() {
+            Isa::X86 => self.next_instruction::<lde::X86>(),
+            Isa::X86_64 => self.next_instruction::<lde::X64>(),
+            Isa::Other(_) => self.mutate_step("next_instruction", |ptr| {
+                ptr.invalidate();
+            }),
+        }
+    }
+
+    /// Walks forward from the following instruction until one matches the predicate,
+    /// at most [`MAX_INSTRUCTIONS`] instructions are decoded.
+    #[cfg(feature = "disassembly")]
+    pub fn next_instruction_matching<F>(self, predicate: F) -> Self
+    where
+        F: FnMut(&iced_x86::Instruction) -> bool + 'a,
+    {
+        self.next_instruction_matching_within(MAX_INSTRUCTIONS, predicate)
+    }
+
+    /// Walks forward from the following instruction until one matches the predicate,
+    /// at most max_instructions instructions are decoded.
+    #[cfg(feature = "disassembly")]
+    pub fn next_instruction_matching_within<F>(
+        self,
+        max_instructions: usize,
+        mut predicate: F,
+    ) -> Self
+    where
+        F: FnMut(&iced_x86::Instruction) -> bool + 'a,
+    {
+        self.mutate_step("next_instruction_matching_within", move |ptr| {
+            ptr.next_instruction_matching(max_instructions, &mut predicate);
+        })
+    }
+
+    /// Prints the next instructions at every pointer, useful to check where a pipeline landed.
+    /// The output goes to stderr, or to the debug log if the `log` feature is enabled.
+    #[cfg(feature = "disassembly")]
+    pub fn disassemble(self, instructions: usize) -> Self {
+        self.disassemble_with(instructions, |ptr, decoded| {
+            let listing = format!(
+                "{:#x}:\n{}",
+                ptr.get_address(),
+                crate::disassembly::format_listing(decoded)
+            );
+
+            #[cfg(feature = "log")]
+            log::debug!("{listing}");
+            #[cfg(not(feature = "log"))]
+            eprint!("{listing}");
+        })
+    }
+
+    /// Prints a hexdump of the bytes at every pointer, useful to check where a pipeline landed.
+    /// The output goes to stderr, or to the debug log if the `log` feature is enabled.
+    pub fn dump(self, bytes_per_ptr: usize) -> Self {
+        self.dump_with(bytes_per_ptr, |context| {
+            #[cfg(feature = "log")]
+            log::debug!("{context}");
+            #[cfg(not(feature = "log"))]
+            eprint!("{context}");
+        })
+    }
+
+    /// Prints the first limit pointers with their module, offset and permissions and how many pointers were left out.
+    /// The pool is resolved once it is first needed. The output goes to stderr, or to the debug log if the `log` feature is enabled.
+    pub fn debug_dump(self, limit: usize) -> Self {
+        self.materialize(move |pool| {
+            let mut dump = format!("{} pointers\n", pool.len());
+            for pointer in pool.iter().take(limit) {
+                dump += &format!("  {pointer}\n");
+            }
+            if pool.len() > limit {
+                dump += &format!("  ... {} more\n", pool.len() - limit);
+            }
+
+            #[cfg(feature = "log")]
+            log::debug!("{dump}");
+            #[cfg(not(feature = "log"))]
+            eprint!("{dump}");
+
+            pool
+        })
+    }
+
+    /// Passes the bytes at every pointer to a custom function, see [`Self::dump`].
+    pub fn dump_with<F>(self, bytes_per_ptr: usize, mut f: F) -> Self
+    where
+        F: FnMut(&HitContext) + 'a,
+    {
+        self.filter(move |ptr| {
+            if let Some(context) = ptr.context(0, bytes_per_ptr) {
+                f(&context);
+            }
+            true
+        })
+    }
+
+    /// Passes the next instructions at every pointer to a custom function.
+    #[cfg(feature = "disassembly")]
+    pub fn disassemble_with<F>(self, instructions: usize, mut f: F) -> Self
+    where
+        F: FnMut(&SafePointer, &[iced_x86::Instruction]) + 'a,
+    {
+        self.filter(move |ptr| {
+            f(ptr, &ptr.disassemble(instructions));
+            true
+        })
+    }
+
     /// Finds all references to the pointer.
     #[cfg(target_pointer_width = "64")]
     pub fn find_all_references<Endian: ByteOrder>(
@@ -66,6 +501,7 @@ impl<'a> Session<'a> {
         instruction_length: usize,
         constraints: SearchConstraints,
     ) -> Self {
+        let ordered = constraints.is_ordered();
         self.pool = Box::new(
             self.pool
                 .flat_map(move |ptr| {
@@ -75,7 +511,7 @@ impl<'a> Session<'a> {
                 .filter(|ptr| !ptr.is_invalidated()),
         );
 
-        self
+        self.in_address_order_if(ordered)
     }
 
     /// Finds all relative references to the pointer
@@ -85,6 +521,7 @@ impl<'a> Session<'a> {
         instruction_length: usize,
         constraints: SearchConstraints,
     ) -> Self {
+        let ordered = constraints.is_ordered();
         self.pool = Box::new(
             self.pool
                 .flat_map(move |ptr| {
@@ -94,7 +531,65 @@ impl<'a> Session<'a> {
                 .filter(|ptr| !ptr.is_invalidated()),
         );
 
-        self
+        self.in_address_order_if(ordered)
+    }
+
+    /// Finds relative references to the pointer, that are made by the given kinds of instructions.
+    #[cfg(target_pointer_width = "64")]
+    pub fn find_relative_references_of_kind<Endian: ByteOrder>(
+        mut self,
+        kinds: &[crate::reference_kind::ReferenceKind],
+        constraints: SearchConstraints,
+    ) -> Self {
+        let ordered = constraints.is_ordered();
+        let kinds = kinds.to_vec();
+        self.pool = Box::new(
+            self.pool
+                .flat_map(move |ptr| {
+                    ptr.find_relative_references_of_kind::<Endian>(&kinds, &constraints)
+                        .collect::<Vec<_>>()
+                })
+                .filter(|ptr| !ptr.is_invalidated()),
+        );
+
+        self.in_address_order_if(ordered)
+    }
+
+    /// Finds all `call rel32` instructions in executable memory, that call the pointer.
+    /// The pool contains the addresses of the call instructions.
+    #[cfg(target_pointer_width = "64")]
+    pub fn find_callers<Endian: ByteOrder>(self, constraints: SearchConstraints) -> Self {
+        self.find_relative_references_of_kind::<Endian>(
+            &[crate::reference_kind::ReferenceKind::Call],
+            constraints.thats_executable(),
+        )
+        .step_backwards(1)
+    }
+
+    /// Finds all references to the pointer using the endianness of the session.
+    #[cfg(target_pointer_width = "64")]
+    pub fn find_all_references_configured(
+        self,
+        instruction_length: usize,
+        constraints: SearchConstraints,
+    ) -> Self {
+        with_endianness!(self.find_all_references(instruction_length, constraints))
+    }
+
+    /// Finds all relative references to the pointer using the endianness of the session.
+    #[cfg(target_pointer_width = "64")]
+    pub fn find_relative_references_configured(
+        self,
+        instruction_length: usize,
+        constraints: SearchConstraints,
+    ) -> Self {
+        with_endianness!(self.find_relative_references(instruction_length, constraints))
+    }
+
+    /// Finds the callers of the pointer using the endianness of the session.
+    #[cfg(target_pointer_width = "64")]
+    pub fn find_callers_configured(self, constraints: SearchConstraints) -> Self {
+        with_endianness!(self.find_callers(constraints))
     }
 
     /// Finds all absolute references to the pointer.
@@ -102,6 +597,7 @@ impl<'a> Session<'a> {
         mut self,
         constraints: SearchConstraints,
     ) -> Self {
+        let ordered = constraints.is_ordered();
         self.pool = Box::new(
             self.pool
                 .flat_map(move |ptr| {
@@ -111,6 +607,69 @@ impl<'a> Session<'a> {
                 .filter(|ptr| !ptr.is_invalidated()),
         );
 
+        self.in_address_order_if(ordered)
+    }
+
+    /// Finds all absolute references to the pointer using the endianness of the session.
+    pub fn find_absolute_references_configured(self, constraints: SearchConstraints) -> Self {
+        with_endianness!(self.find_absolute_references(constraints))
+    }
+
+    /// Finds the places in writable memory, which store one of the pointers, e.g. the globals or heap objects owning an object.
+    /// Unlike [`Self::find_absolute_references`], which is meant for code, only values aligned to the pointer width count.
+    /// The width and endianness are taken from the target of the session.
+    pub fn find_data_references(mut self, constraints: SearchConstraints) -> Self {
+        let ordered = constraints.is_ordered();
+        let (width, endianness) = (
+            self.target.get_pointer_width(),
+            self.target.get_endianness(),
+        );
+        self.pool = Box::new(
+            self.pool
+                .flat_map(move |ptr| {
+                    ptr.find_data_references(width, endianness, &constraints)
+                        .collect::<Vec<_>>()
+                })
+                .filter(|ptr| !ptr.is_invalidated()),
+        );
+
+        self.in_address_order_if(ordered)
+    }
+
+    /// Filters the pool to only contain pointers to `endbr64`/`endbr32`, which are the only valid indirect branch targets with IBT.
+    pub fn end_branch_filter(mut self) -> Self {
+        self.pool = Box::new(self.pool.filter(|ptr| ptr.is_end_branch()));
+
+        self
+    }
+
+    /// Filters the pool to only contain pointers, whose stored pointer targets `endbr64`/`endbr32`.
+    /// The pointers themselves stay in the pool, this is meant to prune absolute pointer scans.
+    pub fn end_branch_target_filter<Endian: ByteOrder>(mut self) -> Self {
+        self.pool = Box::new(self.pool.filter(|ptr| {
+            let mut target = ptr.clone();
+            target.dereference::<Endian>();
+            !target.is_invalidated() && target.is_end_branch()
+        }));
+
+        self
+    }
+
+    /// Filters the pool like [`Self::end_branch_target_filter`] using the pointer width and endianness of the session's target.
+    pub fn end_branch_target_filter_configured(mut self) -> Self {
+        let (width, endianness) = (
+            self.target.get_pointer_width(),
+            self.target.get_endianness(),
+        );
+        self.pool = Box::new(self.pool.filter(move |ptr| {
+            let mut target = ptr.clone();
+            match endianness {
+                Endianness::Little => target.dereference_width::<LittleEndian>(width),
+                Endianness::Big => target.dereference_width::<BigEndian>(width),
+            };
+            !target.is_invalidated() && target.is_end_branch()
+        }));
+
         self
     }
 
@@ -123,26 +682,187 @@ impl<'a> Session<'a> {
 
     /// Filters the pool to only contain pointers that currently match the signature.
     pub fn filter_module(mut self, module_name: &'a str) -> Self {
+        self.pool = Box::new(self.pool.filter(move |ptr| is_in_module(ptr, module_name)));
+
+        self
+    }
+
+    /// Filters the pool to only contain pointers inside the file at the full path.
+    /// Unlike [`Self::filter_module`], files with the same name in other directories are filtered out.
+    pub fn filter_module_path(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
         self.pool = Box::new(self.pool.filter(move |ptr| {
-            ptr.get_module_name()
-                .map(|module| match module {
-                    MMapPath::Path(path) => path
-                        .file_name()
-                        .map(|file_name| file_name == module_name)
-                        .unwrap_or(false),
-                    MMapPath::Other(name) => name
-                        .split('/')
-                        .last()
-                        .map(|file_name| file_name == module_name)
-                        .unwrap_or(false),
-                    _ => false,
-                })
-                .unwrap_or(false)
+            matches!(ptr.get_module_name(), Some(MMapPath::Path(other)) if *other == path)
+        }));
+
+        self
+    }
+
+    /// Filters the pool to only contain pointers inside the module instance, see [`ModuleId`]
+    pub fn filter_module_id(mut self, module: &ModuleId) -> Self {
+        use crate::cached_maps::FindAddress;
+
+        let module = module.clone();
+        self.pool = Box::new(self.pool.filter(move |ptr| {
+            ptr.get_maps()
+                .find_map(ptr.get_address())
+                .is_some_and(|map| module.contains_map(map))
+        }));
+
+        self
+    }
+
+    /// Filters the pool to only contain pointers at most max_distance bytes away from the anchor, in either direction.
+    pub fn filter_within(mut self, anchor: usize, max_distance: usize) -> Self {
+        self.pool = Box::new(
+            self.pool
+                .filter(move |ptr| ptr.get_address().abs_diff(anchor) <= max_distance),
+        );
+
+        self
+    }
+
+    /// Filters the pool to only contain pointers inside the same map of the snapshot as the anchor.
+    /// If the anchor is unmapped, the pool becomes empty.
+    pub fn filter_same_map_as(mut self, anchor: usize) -> Self {
+        use crate::cached_maps::FindAddress;
+
+        self.pool = Box::new(self.pool.filter(move |ptr| {
+            ptr.get_maps()
+                .find_map(anchor)
+                .is_some_and(|map| map.contains(ptr.get_address()))
+        }));
+
+        self
+    }
+
+    /// Keeps only the first pointer of every mapping name, e.g. to collapse copies of a string literal.
+    pub fn unique_per_module(mut self) -> Self {
+        let mut seen = HashSet::new();
+        self.pool = Box::new(self.pool.filter(move |ptr| match ptr.get_module_name() {
+            Some(name) => seen.insert(name.clone()),
+            None => true,
         }));
 
         self
     }
 
+    /// For every module, which has pointers inside of the section (e.g. `.rodata`), drops its other pointers.
+    /// Pointers of modules without hits in the section are kept. Requires the whole pool to be resolved.
+    pub fn prefer_section(self, section: impl Into<String>) -> Self {
+        let section = section.into();
+
+        self.materialize(move |pool| {
+            let mut modules = HashMap::new();
+            let in_section = pool
+                .iter()
+                .map(|ptr| {
+                    let Some(name) = ptr.get_module_name() else {
+                        return false;
+                    };
+                    modules
+                        .entry(name.clone())
+                        .or_insert_with(|| Module::new(ptr.get_maps().clone(), name.clone()))
+                        .as_ref()
+                        .and_then(|module| module.section_of(ptr.get_address()))
+                        .is_some_and(|header| header.get_name() == section)
+                })
+                .collect::<Vec<_>>();
+
+            let preferring = pool
+                .iter()
+                .zip(&in_section)
+                .filter(|(_, in_section)| **in_section)
+                .filter_map(|(ptr, _)| ptr.get_module_name().cloned())
+                .collect::<HashSet<_>>();
+
+            pool.into_iter()
+                .zip(in_section)
+                .filter(|(ptr, in_section)| {
+                    *in_section
+                        || ptr
+                            .get_module_name()
+                            .is_none_or(|name| !preferring.contains(name))
+                })
+                .map(|(ptr, _)| ptr)
+                .collect()
+        })
+    }
+
+    /// Splits the session into multiple sessions, which continue from the same pool.
+    /// The pool is resolved only once, when the first of them is iterated.
+    pub fn split(self, count: usize) -> Vec<Self> {
+        #[cfg(feature = "log")]
+        let log_invalidations = self.log_invalidations;
+        let target = self.target;
+        let root = self.root;
+        let shared = Rc::new(SharedPool {
+            upstream: RefCell::new(Some(self.pool)),
+            resolved: OnceCell::new(),
+        });
+
+        (0..count)
+            .map(|_| {
+                let shared = shared.clone();
+                let session =
+                    Session::new(std::iter::once(()).flat_map(move |_| shared.resolve().to_vec()))
+                        .with_target(target)
+                        .with_root(root.clone());
+                #[cfg(feature = "log")]
+                let session = session.log_invalidations(log_invalidations);
+
+                session
+            })
+            .collect()
+    }
+
+    /// Records the addresses of the pool at this point of the pipeline into the trace.
+    /// The pool is resolved once it is first needed, the pointers pass through unchanged.
+    pub fn checkpoint(self, label: impl Into<String>, trace: &Trace) -> Self {
+        let label = label.into();
+        let trace = trace.clone();
+
+        self.materialize(move |pool| {
+            trace.record(Checkpoint::new(
+                label,
+                pool.iter().map(SafePointer::get_address).collect(),
+            ));
+            pool
+        })
+    }
+
+    /// Sorts the pool by ascending address and removes duplicates. The pool is resolved once it is first needed.
+    pub fn in_address_order(self) -> Self {
+        self.materialize(|mut pool| {
+            pool.sort_unstable_by_key(SafePointer::get_address);
+            pool.dedup();
+            pool
+        })
+    }
+
+    /// Applies [`Self::in_address_order`], if the constraints of a scan asked for ordered results
+    pub(crate) fn in_address_order_if(self, ordered: bool) -> Self {
+        if ordered {
+            self.in_address_order()
+        } else {
+            self
+        }
+    }
+
+    /// Resolves the whole pool once it is first needed and replaces it with the result of the function.
+    fn materialize<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(Vec<SafePointer>) -> Vec<SafePointer> + 'a,
+    {
+        let mut f = Some(f);
+        self.pool = Box::new(
+            std::iter::once(self.pool)
+                .flat_map(move |pool| f.take().map(|f| f(pool.collect())).unwrap_or_default()),
+        );
+
+        self
+    }
+
     /// Filters the pool using a custom filter function.
     pub fn filter<F>(mut self, mut f: F) -> Self
     where
@@ -153,15 +873,66 @@ impl<'a> Session<'a> {
         self
     }
 
+    /// Scores every pointer for picking the best candidate later on, see [`Self::best`].
+    /// Scores of multiple calls add up, so each heuristic can be a step of its own, e.g. one preferring
+    /// hits in the main module and one preferring hits in large functions.
+    pub fn score<F>(self, mut f: F) -> Self
+    where
+        F: FnMut(&SafePointer) -> u32 + 'a,
+    {
+        self.mutate_step("score", move |ptr| {
+            let score = f(ptr);
+            ptr.add_score(score);
+        })
+    }
+
+    /// Keeps only the pointer with the highest score, the first one wins ties. Requires the whole pool to be resolved.
+    /// Invalidated pointers are never the best one.
+    pub fn best(self) -> Self {
+        self.materialize(|pool| {
+            pool.into_iter()
+                .filter(|ptr| !ptr.is_invalidated())
+                .reduce(|best, ptr| {
+                    if ptr.get_score() > best.get_score() {
+                        ptr
+                    } else {
+                        best
+                    }
+                })
+                .into_iter()
+                .collect()
+        })
+    }
+
     /// Mutates the pool using a custom mutator function.
-    pub fn mutate<F>(mut self, mut f: F) -> Self
+    pub fn mutate<F>(self, f: F) -> Self
+    where
+        F: FnMut(&mut SafePointer) + 'a,
+    {
+        self.mutate_step("mutate", f)
+    }
+
+    /// Mutates the pool, the step name is used to report invalidations
+    #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+    fn mutate_step<F>(mut self, step: &'static str, mut f: F) -> Self
     where
         F: FnMut(&mut SafePointer) + 'a,
     {
+        #[cfg(feature = "log")]
+        let log_invalidations = self.log_invalidations;
+
         self.pool = Box::new(
             self.pool
                 .map(move |mut ptr| {
+                    #[cfg(feature = "log")]
+                    let address = ptr.get_address();
+
                     f(&mut ptr);
+
+                    #[cfg(feature = "log")]
+                    if log_invalidations && ptr.is_invalidated() {
+                        log_invalidation(step, address, &ptr);
+                    }
                     ptr
                 })
                 .filter(|ptr| !ptr.is_invalidated()),
@@ -175,7 +946,7 @@ impl<'a> Session<'a> {
     where
         F: FnMut(&mut SafePointer) + 'a,
     {
-        self.mutate(move |ptr| {
+        self.mutate_step("repeat_n", move |ptr| {
             for _ in 0..iterations {
                 f(ptr);
             }
@@ -187,7 +958,24 @@ impl<'a> Session<'a> {
     where
         F: FnMut(&mut SafePointer) -> bool + 'a,
     {
-        self.mutate(move |ptr| while f(ptr) {})
+        self.mutate_step("repeat_while", move |ptr| while f(ptr) {})
+    }
+
+    /// Repeats the mutation until the pointer matches the signature, the pointer is checked after every iteration.
+    /// Pointers, which don't match after max_iterations iterations, are invalidated.
+    pub fn repeat_until<F>(self, signature: Signature, max_iterations: usize, mut f: F) -> Self
+    where
+        F: FnMut(&mut SafePointer) + 'a,
+    {
+        self.mutate_step("repeat_until", move |ptr| {
+            for _ in 0..max_iterations {
+                f(ptr);
+                if ptr.is_invalidated() || ptr.does_match(&signature) {
+                    return;
+                }
+            }
+            ptr.invalidate();
+        })
     }
 
     /// Maps the pool using a custom mapper function.
@@ -228,4 +1016,100 @@ impl<'a> Session<'a> {
     pub fn get_pool(self) -> impl Iterator<Item = usize> + 'a {
         self.pool.map(|ptr| ptr.get_address())
     }
+
+    /// Tells what every valid pointer points into, see [`crate::BcrlFactory::classify`]
+    pub fn classify(self) -> Vec<AddressClass> {
+        let root = self.root;
+        self.pool
+            .filter(|ptr| !ptr.is_invalidated())
+            .map(|ptr| address_class::classify(ptr.get_maps(), root.as_deref(), ptr.get_address()))
+            .collect()
+    }
+
+    /// Groups the valid pointers by the name of their mapping, e.g. the path of a module
+    pub fn group_by_module(self) -> HashMap<MMapPath, Vec<usize>> {
+        let mut groups = HashMap::<MMapPath, Vec<usize>>::new();
+        for ptr in self.pool.filter(|ptr| !ptr.is_invalidated()) {
+            if let Some(name) = ptr.get_module_name() {
+                groups
+                    .entry(name.clone())
+                    .or_default()
+                    .push(ptr.get_address());
+            }
+        }
+
+        groups
+    }
+
+    /// Groups the valid pointers by the start of the function containing them. The boundaries of functions are
+    /// taken from the exported symbols of their module, pointers outside of exported functions are left out.
+    /// [`crate::BcrlFactory::nearest_symbol`] names the functions.
+    pub fn group_by_function(self) -> BTreeMap<usize, Vec<usize>> {
+        let root = self.root;
+        let mut functions = HashMap::new();
+        let mut groups = BTreeMap::<usize, Vec<usize>>::new();
+
+        for ptr in self.pool.filter(|ptr| !ptr.is_invalidated()) {
+            let Some(name) = ptr.get_module_name() else {
+                continue;
+            };
+            // The address ranges of the exported symbols, sorted by their start
+            let ranges = functions.entry(name.clone()).or_insert_with(|| {
+                let Some(module) = Module::new(ptr.get_maps().clone(), name.clone()) else {
+                    return Vec::new();
+                };
+                let module = module.with_root(root.clone());
+                let mut ranges = module
+                    .exported_symbols()
+                    .iter()
+                    .filter(|symbol| symbol.get_size() > 0)
+                    .filter_map(|symbol| {
+                        let start = module.to_runtime_address(symbol.get_value())?;
+                        Some((start, start + symbol.get_size() as usize))
+                    })
+                    .collect::<Vec<_>>();
+                ranges.sort_unstable();
+                ranges
+            });
+
+            let address = ptr.get_address();
+            let index = ranges.partition_point(|(start, _)| *start <= address);
+            if let Some((start, _)) = ranges[..index].iter().rev().find(|(_, end)| address < *end) {
+                groups.entry(*start).or_default().push(address);
+            }
+        }
+
+        groups
+    }
+
+    /// Pairs the pointers of both pools, whose addresses are at most max_distance bytes apart, in either direction.
+    /// E.g. a pool of `mov` instructions loading a constant joined with a pool of calls to a function.
+    /// The pairs are ordered by the address of this pool's pointer, then by the other one.
+    pub fn join_near(self, other: Session<'_>, max_distance: usize) -> Vec<(usize, usize)> {
+        let mut others = other.get_pool().collect::<Vec<_>>();
+        others.sort_unstable();
+        others.dedup();
+        let mut addresses = self.get_pool().collect::<Vec<_>>();
+        addresses.sort_unstable();
+        addresses.dedup();
+
+        addresses
+            .into_iter()
+            .flat_map(|address| {
+                let start =
+                    others.partition_point(|other| *other < address.saturating_sub(max_distance));
+                others[start..]
+                    .iter()
+                    .take_while(move |other| **other <= address.saturating_add(max_distance))
+                    .map(move |other| (address, *other))
+            })
+            .collect()
+    }
+
+    /// Returns every pointer together with up to before bytes in front of and after bytes starting at it
+    pub fn with_context(self, before: usize, after: usize) -> Vec<HitContext> {
+        self.pool
+            .filter_map(|ptr| ptr.context(before, after))
+            .collect()
+    }
 }
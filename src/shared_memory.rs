@@ -0,0 +1,65 @@
+//! Identifies shared memory mappings, memfds, POSIX shared memory in `/dev/shm` and SysV segments,
+//! so the same segment can be recognized in every process it is mapped into.
+
+use procfs_core::process::{MMPermissions, MMapPath};
+
+use crate::cached_map::CachedMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SharedMemoryKind {
+    /// An anonymous file created with memfd_create
+    Memfd,
+    /// A POSIX shared memory object in `/dev/shm`
+    Posix,
+    /// A SysV shared memory segment
+    SysV,
+}
+
+impl SharedMemoryKind {
+    pub fn of(map: &CachedMap) -> Option<Self> {
+        match map.get_name() {
+            MMapPath::Path(path) if path.starts_with("/dev/shm") => Some(SharedMemoryKind::Posix),
+            MMapPath::Path(path) if path.to_str()?.starts_with("/memfd:") => {
+                Some(SharedMemoryKind::Memfd)
+            }
+            MMapPath::Vsys(_) => Some(SharedMemoryKind::SysV),
+            _ => None,
+        }
+    }
+}
+
+/// Identifies a shared memory object across processes by the device and inode of its backing file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SharedSegment {
+    device: (i32, i32),
+    inode: u64,
+}
+
+impl SharedSegment {
+    pub fn new(device: (i32, i32), inode: u64) -> Self {
+        Self { device, inode }
+    }
+
+    /// Returns the segment of maps, which share a memfd, POSIX or SysV shared memory object with other processes
+    pub fn of(map: &CachedMap) -> Option<Self> {
+        if !map.get_permissions().contains(MMPermissions::SHARED) || map.get_inode() == 0 {
+            return None;
+        }
+        SharedMemoryKind::of(map)?;
+
+        Some(Self::new(map.get_device(), map.get_inode()))
+    }
+
+    pub fn get_device(&self) -> (i32, i32) {
+        self.device
+    }
+    pub fn get_inode(&self) -> u64 {
+        self.inode
+    }
+}
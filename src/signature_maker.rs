@@ -0,0 +1,212 @@
+//! Generates signatures for code in the snapshot.
+//!
+//! Bytes that are likely to change between builds or loads are replaced by wildcards:
+//! RIP-relative and absolute displacements, the targets of relative branches and every operand,
+//! which the dynamic linker relocates, e.g. absolute addresses in the immediates of non-PIC code.
+
+use iced_x86::{Decoder, DecoderOptions, Instruction, OpKind};
+
+use crate::{
+    cached_maps::FindAddress, factory::BcrlFactory, pattern, search_constraints::SearchConstraints,
+};
+use signature_scanner::Signature;
+
+pub struct SignatureMaker<'a> {
+    factory: &'a BcrlFactory,
+    bitness: u32,
+    max_length: usize,
+}
+
+fn is_branch(kind: OpKind) -> bool {
+    matches!(
+        kind,
+        OpKind::NearBranch16
+            | OpKind::NearBranch32
+            | OpKind::NearBranch64
+            | OpKind::FarBranch16
+            | OpKind::FarBranch32
+    )
+}
+
+/// Whether a relocation patches the operand at the offset of the instruction
+fn is_relocated(
+    instruction: &Instruction,
+    offset: usize,
+    size: usize,
+    relocations: &[usize],
+) -> bool {
+    let start = instruction.ip() as usize + offset;
+    let index = relocations.partition_point(|address| *address < start);
+    relocations
+        .get(index)
+        .is_some_and(|address| *address < start + size)
+}
+
+fn wildcard_instruction(
+    decoder: &Decoder,
+    instruction: &Instruction,
+    relocations: &[usize],
+    elements: &mut [Option<u8>],
+) {
+    let offsets = decoder.get_constant_offsets(instruction);
+
+    let address_displacement = instruction.is_ip_rel_memory_operand()
+        || (instruction.memory_base() == iced_x86::Register::None
+            && offsets.displacement_size() >= 4)
+        || is_relocated(
+            instruction,
+            offsets.displacement_offset(),
+            offsets.displacement_size(),
+            relocations,
+        );
+    if offsets.has_displacement() && address_displacement {
+        let start = offsets.displacement_offset();
+        elements[start..start + offsets.displacement_size()].fill(None);
+    }
+
+    let branch = (0..instruction.op_count()).any(|operand| is_branch(instruction.op_kind(operand)));
+    let relocated = is_relocated(
+        instruction,
+        offsets.immediate_offset(),
+        offsets.immediate_size(),
+        relocations,
+    );
+    if offsets.has_immediate() && ((branch && offsets.immediate_size() >= 4) || relocated) {
+        let start = offsets.immediate_offset();
+        elements[start..start + offsets.immediate_size()].fill(None);
+    }
+}
+
+impl<'a> SignatureMaker<'a> {
+    pub fn new(factory: &'a BcrlFactory) -> Self {
+        Self {
+            factory,
+            bitness: 64,
+            max_length: 64,
+        }
+    }
+
+    /// Sets the bitness of the code (16, 32 or 64)
+    pub fn with_bitness(mut self, bitness: u32) -> Self {
+        self.bitness = bitness;
+
+        self
+    }
+
+    /// Sets the maximum length of signatures created by [`SignatureMaker::make_unique`]
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+
+        self
+    }
+
+    /// Decodes whole instructions covering at least length bytes at the address.
+    /// Returns the elements and the end offsets of every instruction.
+    fn decode(&self, address: usize, length: usize) -> Option<(Vec<Option<u8>>, Vec<usize>)> {
+        let maps = self.factory.get_cache();
+        let map = maps.find_map(address)?;
+        let bytes = map.get_bytes().get(address - map.get_from_address()..)?;
+        let relocations = self
+            .factory
+            .module(map.get_name())
+            .map(|module| module.relocated_addresses())
+            .unwrap_or_default();
+
+        let mut decoder =
+            Decoder::with_ip(self.bitness, bytes, address as u64, DecoderOptions::NONE);
+        let mut elements = Vec::new();
+        let mut boundaries = Vec::new();
+
+        while elements.len() < length {
+            if !decoder.can_decode() {
+                return None;
+            }
+
+            let position = decoder.position();
+            let instruction = decoder.decode();
+            if instruction.is_invalid() {
+                return None;
+            }
+
+            let mut instruction_elements = bytes[position..position + instruction.len()]
+                .iter()
+                .map(|byte| Some(*byte))
+                .collect::<Vec<_>>();
+            wildcard_instruction(
+                &decoder,
+                &instruction,
+                &relocations,
+                &mut instruction_elements,
+            );
+
+            elements.extend(instruction_elements);
+            boundaries.push(elements.len());
+        }
+
+        Some((elements, boundaries))
+    }
+
+    /// Creates the elements for the whole instructions covering at least length bytes at the address
+    pub fn elements(&self, address: usize, length: usize) -> Option<Vec<Option<u8>>> {
+        self.decode(address, length).map(|(elements, _)| elements)
+    }
+
+    /// Creates a signature for the whole instructions covering at least length bytes at the address
+    pub fn make(&self, address: usize, length: usize) -> Option<Signature> {
+        self.elements(address, length)
+            .map(|elements| pattern::from_elements(&elements))
+    }
+
+    /// Creates the shortest signature (made out of whole instructions) that only matches the address
+    pub fn make_unique(&self, address: usize, constraints: SearchConstraints) -> Option<Signature> {
+        let (elements, boundaries) = self.decode(address, self.max_length)?;
+
+        boundaries
+            .into_iter()
+            .map(|length| &elements[..length])
+            .filter(|elements| elements.iter().any(|element| element.is_some()))
+            .map(pattern::from_elements)
+            .find(|signature| {
+                let mut hits = self
+                    .factory
+                    .signature(signature.clone(), constraints.clone())
+                    .get_pool();
+
+                hits.next() == Some(address) && hits.next().is_none()
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iced_x86::{Decoder, DecoderOptions};
+
+    use super::wildcard_instruction;
+
+    fn wildcarded(bytes: &[u8], relocations: &[usize]) -> Vec<Option<u8>> {
+        let mut decoder = Decoder::with_ip(64, bytes, 0x1000, DecoderOptions::NONE);
+        let instruction = decoder.decode();
+        let mut elements = bytes.iter().map(|byte| Some(*byte)).collect::<Vec<_>>();
+        wildcard_instruction(&decoder, &instruction, relocations, &mut elements);
+
+        elements
+    }
+
+    #[test]
+    fn test_wildcard_relocated_immediate() {
+        // mov eax, 0x401000
+        let mov = [0xB8, 0x00, 0x10, 0x40, 0x00];
+        assert_eq!(wildcarded(&mov, &[]), mov.map(Some));
+        assert_eq!(
+            wildcarded(&mov, &[0x1001]),
+            vec![Some(0xB8), None, None, None, None]
+        );
+
+        // lea rax, [rip + 0x10]
+        let lea = [0x48, 0x8D, 0x05, 0x10, 0x00, 0x00, 0x00];
+        assert_eq!(
+            wildcarded(&lea, &[]),
+            vec![Some(0x48), Some(0x8D), Some(0x05), None, None, None, None]
+        );
+    }
+}
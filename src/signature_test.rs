@@ -0,0 +1,23 @@
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureTest {
+    hits: Vec<usize>,
+}
+
+impl SignatureTest {
+    pub fn new(hits: Vec<usize>) -> Self {
+        Self { hits }
+    }
+
+    pub fn get_hits(&self) -> &[usize] {
+        &self.hits
+    }
+    pub fn get_count(&self) -> usize {
+        self.hits.len()
+    }
+    pub fn is_unique(&self) -> bool {
+        self.hits.len() == 1
+    }
+    pub fn is_missing(&self) -> bool {
+        self.hits.is_empty()
+    }
+}
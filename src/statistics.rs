@@ -0,0 +1,227 @@
+//! Breaks down the hits and scan time of a scan per module, to find the mappings which eat up the scan budget,
+//! and measures the throughput of repeated scans.
+
+use std::{fmt, time::Duration};
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+use std::time::Instant;
+
+use procfs_core::process::MMapPath;
+
+/// Measures the time since it was started. Instant panics on wasm32 targets without an operating system,
+/// there no time is measured and the elapsed time is always zero.
+pub(crate) struct Stopwatch {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    start: Instant,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        return self.start.elapsed();
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        return Duration::ZERO;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleStatistics {
+    module: MMapPath,
+    maps: usize,
+    bytes: usize,
+    hits: usize,
+    duration: Duration,
+}
+
+impl ModuleStatistics {
+    /// The mapping name, anonymous maps and other special mappings are grouped by their kind
+    pub fn get_module(&self) -> &MMapPath {
+        &self.module
+    }
+    /// The number of maps with this name, which were scanned
+    pub fn get_maps(&self) -> usize {
+        self.maps
+    }
+    /// The number of bytes, which were scanned
+    pub fn get_bytes(&self) -> usize {
+        self.bytes
+    }
+    pub fn get_hits(&self) -> usize {
+        self.hits
+    }
+    pub fn get_duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScanStatistics {
+    modules: Vec<ModuleStatistics>,
+}
+
+impl ScanStatistics {
+    pub(crate) fn record(
+        &mut self,
+        module: &MMapPath,
+        bytes: usize,
+        hits: usize,
+        duration: Duration,
+    ) {
+        match self
+            .modules
+            .iter_mut()
+            .find(|statistics| statistics.module == *module)
+        {
+            Some(statistics) => {
+                statistics.maps += 1;
+                statistics.bytes += bytes;
+                statistics.hits += hits;
+                statistics.duration += duration;
+            }
+            None => self.modules.push(ModuleStatistics {
+                module: module.clone(),
+                maps: 1,
+                bytes,
+                hits,
+                duration,
+            }),
+        }
+    }
+
+    /// The modules in the order they were scanned
+    pub fn get_modules(&self) -> &[ModuleStatistics] {
+        &self.modules
+    }
+    pub fn get_module(&self, module: &MMapPath) -> Option<&ModuleStatistics> {
+        self.modules
+            .iter()
+            .find(|statistics| statistics.module == *module)
+    }
+    pub fn get_total_hits(&self) -> usize {
+        self.modules.iter().map(ModuleStatistics::get_hits).sum()
+    }
+    pub fn get_total_bytes(&self) -> usize {
+        self.modules.iter().map(ModuleStatistics::get_bytes).sum()
+    }
+    pub fn get_total_duration(&self) -> Duration {
+        self.modules
+            .iter()
+            .map(ModuleStatistics::get_duration)
+            .sum()
+    }
+
+    /// The modules sorted by their scan time, the most expensive one first
+    pub fn by_duration(&self) -> Vec<&ModuleStatistics> {
+        let mut modules = self.modules.iter().collect::<Vec<_>>();
+        modules.sort_by_key(|statistics| std::cmp::Reverse(statistics.duration));
+        modules
+    }
+}
+
+impl fmt::Display for ScanStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for statistics in self.by_duration() {
+            let name = match &statistics.module {
+                MMapPath::Path(path) => path.display().to_string(),
+                other => format!("{other:?}"),
+            };
+            writeln!(
+                f,
+                "{name}: {} hits in {} bytes of {} maps, {:?}",
+                statistics.hits, statistics.bytes, statistics.maps, statistics.duration
+            )?;
+        }
+
+        writeln!(
+            f,
+            "total: {} hits in {} bytes, {:?}",
+            self.get_total_hits(),
+            self.get_total_bytes(),
+            self.get_total_duration()
+        )
+    }
+}
+
+/// The result of scanning repeatedly with the same signature and constraints
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Benchmark {
+    bytes: usize,
+    hits: usize,
+    durations: Vec<Duration>,
+}
+
+impl Benchmark {
+    pub(crate) fn new(bytes: usize, hits: usize, durations: Vec<Duration>) -> Self {
+        Self {
+            bytes,
+            hits,
+            durations,
+        }
+    }
+
+    pub fn get_iterations(&self) -> usize {
+        self.durations.len()
+    }
+    /// The number of bytes, which were scanned per iteration
+    pub fn get_bytes(&self) -> usize {
+        self.bytes
+    }
+    /// The number of hits per iteration
+    pub fn get_hits(&self) -> usize {
+        self.hits
+    }
+    pub fn get_durations(&self) -> &[Duration] {
+        &self.durations
+    }
+    pub fn get_fastest(&self) -> Duration {
+        self.durations.iter().min().copied().unwrap_or_default()
+    }
+    pub fn get_mean(&self) -> Duration {
+        match self.durations.len() {
+            0 => Duration::ZERO,
+            iterations => self.durations.iter().sum::<Duration>() / iterations as u32,
+        }
+    }
+
+    /// The bytes scanned per second in GB/s, based on the mean duration
+    pub fn get_throughput(&self) -> f64 {
+        throughput(self.bytes, self.get_mean())
+    }
+    /// The bytes scanned per second in GB/s, based on the fastest iteration
+    pub fn get_peak_throughput(&self) -> f64 {
+        throughput(self.bytes, self.get_fastest())
+    }
+}
+
+fn throughput(bytes: usize, duration: Duration) -> f64 {
+    if duration.is_zero() {
+        return 0.0;
+    }
+    bytes as f64 / duration.as_secs_f64() / 1e9
+}
+
+impl fmt::Display for Benchmark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} hits in {} bytes, {} iterations",
+            self.hits,
+            self.bytes,
+            self.get_iterations()
+        )?;
+        writeln!(
+            f,
+            "mean: {:?} ({:.3} GB/s), fastest: {:?} ({:.3} GB/s)",
+            self.get_mean(),
+            self.get_throughput(),
+            self.get_fastest(),
+            self.get_peak_throughput()
+        )
+    }
+}
@@ -0,0 +1,184 @@
+//! Signatures built from the layout of a `#[repr(C)]` struct, for scanning data structures like headers or configuration blocks.
+//!
+//! Fields with a known value become concrete bytes, every other field and the padding between them are wildcards.
+//! With the `macros` feature, `#[derive(ScanPattern)]` builds the pattern from the struct definition:
+//!
+//! ```
+//! # #[cfg(feature = "macros")] {
+//! use bcrl_rs::{struct_pattern::ScanPattern, Endianness};
+//!
+//! #[derive(bcrl_rs::ScanPattern)]
+//! #[repr(C)]
+//! struct Header {
+//!     #[scan(value = 0x4244_4346)]
+//!     magic: u32,
+//!     size: u32,
+//!     #[scan(value = 2)]
+//!     version: u16,
+//! }
+//!
+//! let pattern = Header::scan_pattern(Endianness::Little);
+//! assert_eq!(pattern.get_size(), 12);
+//! assert_eq!(&pattern.get_elements()[..4], &[Some(0x46), Some(0x43), Some(0x44), Some(0x42)]);
+//! assert_eq!(pattern.get_elements()[8..10], [Some(2), Some(0)]);
+//! assert_eq!(pattern.field_offset("version"), Some(8));
+//! # }
+//! ```
+//!
+//! The pattern is scanned for with [`crate::BcrlFactory::struct_pattern`]. Structs, whose layout isn't fixed by
+//! `#[repr(C)]`, are rejected, this includes a bare `#[repr(packed)]`:
+//!
+//! ```compile_fail
+//! #[derive(bcrl_rs::ScanPattern)]
+//! #[repr(packed)]
+//! struct Header {
+//!     magic: u32,
+//!     version: u16,
+//! }
+//! ```
+
+use signature_scanner::Signature;
+
+use crate::{endianness::Endianness, pattern};
+
+/// The value of a field, as it is laid out in memory
+pub trait PatternBytes {
+    fn pattern_bytes(&self, endianness: Endianness) -> Vec<u8>;
+}
+
+macro_rules! impl_pattern_bytes {
+    ($($ty:ty),*) => {
+        $(
+            impl PatternBytes for $ty {
+                fn pattern_bytes(&self, endianness: Endianness) -> Vec<u8> {
+                    match endianness {
+                        Endianness::Little => self.to_le_bytes().to_vec(),
+                        Endianness::Big => self.to_be_bytes().to_vec(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_pattern_bytes!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+impl PatternBytes for bool {
+    fn pattern_bytes(&self, _endianness: Endianness) -> Vec<u8> {
+        vec![u8::from(*self)]
+    }
+}
+
+impl<T: PatternBytes, const N: usize> PatternBytes for [T; N] {
+    fn pattern_bytes(&self, endianness: Endianness) -> Vec<u8> {
+        self.iter()
+            .flat_map(|element| element.pattern_bytes(endianness))
+            .collect()
+    }
+}
+
+/// The signature of a struct together with the offsets of its fields
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructPattern {
+    elements: Vec<Option<u8>>,
+    fields: Vec<(String, usize)>,
+}
+
+impl StructPattern {
+    /// Creates a pattern of wildcards for a struct of the size
+    pub fn new(size: usize) -> Self {
+        Self {
+            elements: vec![None; size],
+            fields: Vec::new(),
+        }
+    }
+
+    /// Requires the bytes at the offset. Panics if they don't fit into the struct.
+    pub fn with_constant(mut self, offset: usize, bytes: &[u8]) -> Self {
+        for (element, byte) in self.elements[offset..offset + bytes.len()]
+            .iter_mut()
+            .zip(bytes)
+        {
+            *element = Some(*byte);
+        }
+
+        self
+    }
+
+    /// Records the offset of a field
+    pub fn with_field(mut self, name: impl Into<String>, offset: usize) -> Self {
+        self.fields.push((name.into(), offset));
+
+        self
+    }
+
+    pub fn get_elements(&self) -> &[Option<u8>] {
+        &self.elements
+    }
+    /// The fields in declaration order
+    pub fn get_fields(&self) -> &[(String, usize)] {
+        &self.fields
+    }
+    pub fn get_size(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn field_offset(&self, name: &str) -> Option<usize> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, offset)| *offset)
+    }
+
+    pub fn signature(&self) -> Signature {
+        pattern::from_elements(&self.elements)
+    }
+}
+
+/// A struct, which can be scanned for, see [`crate::BcrlFactory::struct_pattern`].
+/// Usually derived, see the [module documentation](self).
+pub trait ScanPattern {
+    fn scan_pattern(endianness: Endianness) -> StructPattern;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_struct_pattern() {
+        let pattern = StructPattern::new(8)
+            .with_field("magic", 0)
+            .with_constant(0, &0x1234u16.pattern_bytes(Endianness::Big))
+            .with_field("size", 4);
+
+        assert_eq!(
+            pattern.get_elements(),
+            &[Some(0x12), Some(0x34), None, None, None, None, None, None]
+        );
+        assert_eq!(pattern.field_offset("size"), Some(4));
+        assert_eq!(pattern.field_offset("missing"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "macros")]
+    fn derives_packed_struct_pattern() {
+        #[derive(bcrl_rs::ScanPattern)]
+        #[repr(C, packed)]
+        #[allow(dead_code)]
+        struct Packed {
+            #[scan(value = 0xAA)]
+            tag: u8,
+            value: u32,
+            #[scan(value = 0x1234)]
+            kind: u16,
+        }
+
+        let pattern = Packed::scan_pattern(Endianness::Big);
+        assert_eq!(
+            pattern.get_elements(),
+            &[Some(0xAA), None, None, None, None, Some(0x12), Some(0x34)]
+        );
+        assert_eq!(pattern.field_offset("kind"), Some(5));
+    }
+}
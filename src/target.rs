@@ -0,0 +1,135 @@
+//! Describes the architecture of the target, so snapshots of foreign architectures and core dumps can be analyzed.
+//! The factory detects it from the ELF header of the main module, the non-generic steps of sessions consult it.
+
+use crate::{
+    elf::{ElfClass, ElfEndianness, ElfHeader},
+    endianness::Endianness,
+};
+
+const EM_386: u16 = 3;
+const EM_X86_64: u16 = 62;
+/// Pointers are read into a u64, so wider ones can't be represented
+const MAX_POINTER_WIDTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Isa {
+    X86,
+    X86_64,
+    /// An architecture, that BCRL can't decode instructions of. Contains the machine of the ELF header.
+    Other(u16),
+}
+
+impl Isa {
+    /// The ISA of the machine, that BCRL is running on
+    pub const fn native() -> Self {
+        if cfg!(target_arch = "x86_64") {
+            Isa::X86_64
+        } else if cfg!(target_arch = "x86") {
+            Isa::X86
+        } else {
+            Isa::Other(0)
+        }
+    }
+
+    /// Maps the machine of an ELF header (e_machine) to the ISA
+    pub fn from_machine(machine: u16) -> Self {
+        match machine {
+            EM_386 => Isa::X86,
+            EM_X86_64 => Isa::X86_64,
+            machine => Isa::Other(machine),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TargetSpec {
+    pointer_width: usize,
+    endianness: Endianness,
+    isa: Isa,
+}
+
+impl TargetSpec {
+    /// The pointer width is given in bytes, None is returned if it isn't between 1 and 8
+    pub const fn new(pointer_width: usize, endianness: Endianness, isa: Isa) -> Option<Self> {
+        if pointer_width == 0 || pointer_width > MAX_POINTER_WIDTH {
+            return None;
+        }
+
+        Some(Self {
+            pointer_width,
+            endianness,
+            isa,
+        })
+    }
+
+    /// The architecture of the machine, that BCRL is running on
+    pub const fn native() -> Self {
+        Self {
+            pointer_width: core::mem::size_of::<usize>(),
+            endianness: Endianness::native(),
+            isa: Isa::native(),
+        }
+    }
+
+    pub fn from_elf_header(header: &ElfHeader) -> Self {
+        Self {
+            pointer_width: match header.get_class() {
+                ElfClass::Elf32 => 4,
+                ElfClass::Elf64 => 8,
+            },
+            endianness: match header.get_endianness() {
+                ElfEndianness::Little => Endianness::Little,
+                ElfEndianness::Big => Endianness::Big,
+            },
+            isa: Isa::from_machine(header.get_machine()),
+        }
+    }
+
+    pub fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+
+        self
+    }
+
+    /// The size of a pointer in bytes
+    pub fn get_pointer_width(&self) -> usize {
+        self.pointer_width
+    }
+    pub fn get_endianness(&self) -> Endianness {
+        self.endianness
+    }
+    pub fn get_isa(&self) -> Isa {
+        self.isa
+    }
+
+    /// Whether pointers of the target have the width of the ones of this machine,
+    /// parsers of in-memory structures (malloc chunks, TCBs) read pointers as usize
+    pub fn has_native_pointer_width(&self) -> bool {
+        self.pointer_width == core::mem::size_of::<usize>()
+    }
+}
+
+impl Default for TargetSpec {
+    fn default() -> Self {
+        Self::native()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{endianness::Endianness, target::Isa};
+
+    use super::TargetSpec;
+
+    #[test]
+    fn test_pointer_width() {
+        assert!(TargetSpec::new(0, Endianness::Little, Isa::X86_64).is_none());
+        assert!(TargetSpec::new(9, Endianness::Little, Isa::X86_64).is_none());
+        assert_eq!(
+            TargetSpec::new(4, Endianness::Big, Isa::Other(8))
+                .unwrap()
+                .get_pointer_width(),
+            4
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Identifies thread-local storage by looking for thread control blocks.
+//!
+//! On x86 the thread pointer points at the TCB (glibc's `struct pthread`), which begins with `tcbhead_t`:
+//! a pointer to itself, the dynamic thread vector and another pointer to itself.
+//! The static TLS blocks of all modules are placed directly below it, so the mapping containing the TCB
+//! also contains the thread's TLS.
+
+use alloc::vec::Vec;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use procfs_core::process::{MMPermissions, MMapPath};
+
+use crate::{
+    cached_map::CachedMap,
+    cached_maps::{CachedMaps, FindAddress},
+    endianness::Endianness,
+};
+
+const POINTER_SIZE: usize = core::mem::size_of::<usize>();
+const TCB_ALIGNMENT: usize = 16;
+/// glibc places the TCB of a thread at the top of its stack, it is only searched there
+const STACK_TOP_WINDOW: usize = 64 * 1024;
+/// The dynamic loader allocates the TLS of the main thread in a small map of its own
+const SMALL_MAP_SIZE: usize = 1024 * 1024;
+
+fn read_usize<Endian: ByteOrder>(bytes: &[u8]) -> usize {
+    if cfg!(target_pointer_width = "64") {
+        Endian::read_u64(bytes) as usize
+    } else {
+        Endian::read_u32(bytes) as usize
+    }
+}
+
+/// Returns the offsets of all thread control blocks inside the bytes, which are located at base.
+/// This only checks the self pointers, use [`thread_control_blocks`] to also validate the DTV.
+pub fn find_thread_control_blocks<Endian: ByteOrder>(bytes: &[u8], base: usize) -> Vec<usize> {
+    let misalignment = base % TCB_ALIGNMENT;
+    let start = if misalignment == 0 {
+        0
+    } else {
+        TCB_ALIGNMENT - misalignment
+    };
+
+    (start..bytes.len().saturating_sub(3 * POINTER_SIZE - 1))
+        .step_by(TCB_ALIGNMENT)
+        .filter(|offset| {
+            let address = base + offset;
+            let tcb = read_usize::<Endian>(&bytes[*offset..]);
+            let dtv = read_usize::<Endian>(&bytes[offset + POINTER_SIZE..]);
+            let this = read_usize::<Endian>(&bytes[offset + 2 * POINTER_SIZE..]);
+
+            tcb == address && this == address && dtv != 0 && dtv != address
+        })
+        .collect()
+}
+
+/// Returns the addresses of all thread control blocks in the snapshot
+pub fn thread_control_blocks<Endian: ByteOrder>(maps: &CachedMaps) -> Vec<usize> {
+    maps.iter()
+        .filter(|map| map.is_tls())
+        .flat_map(|map| {
+            find_thread_control_blocks::<Endian>(map.get_bytes(), map.get_from_address())
+                .into_iter()
+                .map(|offset| map.get_from_address() + offset)
+                .filter(|tcb| {
+                    let dtv = &map.get_bytes()[tcb - map.get_from_address() + POINTER_SIZE..];
+                    maps.find_map(read_usize::<Endian>(dtv)).is_some()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// The part of the map, that may contain a TCB. Only anonymous readable and writable maps are candidates:
+/// thread stacks, which directly follow their guard page, are searched at their top, small maps completely.
+fn candidate_window(map: &CachedMap, follows_guard: bool) -> Option<usize> {
+    if *map.get_name() != MMapPath::Anonymous
+        || !map
+            .get_permissions()
+            .contains(MMPermissions::READ | MMPermissions::WRITE)
+    {
+        return None;
+    }
+
+    if follows_guard {
+        Some(map.get_size().min(STACK_TOP_WINDOW))
+    } else {
+        (map.get_size() <= SMALL_MAP_SIZE).then_some(map.get_size())
+    }
+}
+
+fn mark<Endian: ByteOrder>(maps: CachedMaps) -> CachedMaps {
+    let mut guard_end = None;
+    let mut marked = CachedMaps::new();
+
+    for map in maps {
+        let tls = candidate_window(&map, guard_end == Some(map.get_from_address())).is_some_and(
+            |window| {
+                // Only the window is read from lazy maps, so they stay unloaded
+                let start = map.get_size() - window;
+                let mut bytes = vec![0; window];
+                map.read_at(start, &mut bytes)
+                    && !find_thread_control_blocks::<Endian>(&bytes, map.get_from_address() + start)
+                        .is_empty()
+            },
+        );
+        guard_end = (!map.is_accessible()).then_some(map.get_to_address());
+        marked.insert(map.with_tls(tls));
+    }
+
+    marked
+}
+
+/// Marks the maps, which contain a thread control block, as containing thread-local storage.
+/// Previous marks are replaced. Only the candidate windows of lazy maps are read, they aren't loaded.
+pub fn mark_thread_local_storage(maps: CachedMaps, endianness: Endianness) -> CachedMaps {
+    match endianness {
+        Endianness::Little => mark::<LittleEndian>(maps),
+        Endianness::Big => mark::<BigEndian>(maps),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use byteorder::{ByteOrder, LittleEndian};
+    use procfs_core::process::{MMPermissions, MMapPath};
+
+    use crate::{
+        cached_map::{CachedMap, MemorySource},
+        cached_maps::CachedMaps,
+        endianness::Endianness,
+    };
+
+    use super::mark_thread_local_storage;
+
+    /// The memory of a map, which is read lazily
+    struct Memory {
+        from: usize,
+        bytes: Vec<u8>,
+    }
+
+    impl MemorySource for Memory {
+        fn read(&self, address: usize, buffer: &mut [u8]) -> bool {
+            let start = address - self.from;
+            match self.bytes.get(start..start + buffer.len()) {
+                Some(bytes) => {
+                    buffer.copy_from_slice(bytes);
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    fn tcb_bytes(from: usize, size: usize, offset: usize) -> Vec<u8> {
+        let mut bytes = vec![0; size];
+        let tcb = (from + offset) as u64;
+        LittleEndian::write_u64(&mut bytes[offset..], tcb);
+        LittleEndian::write_u64(&mut bytes[offset + 8..], 0x1234);
+        LittleEndian::write_u64(&mut bytes[offset + 16..], tcb);
+        bytes
+    }
+
+    fn with_tcb(from: usize, size: usize, offset: usize) -> CachedMap {
+        CachedMap::new(
+            from,
+            from + size,
+            MMPermissions::READ | MMPermissions::WRITE,
+            MMapPath::Anonymous,
+            tcb_bytes(from, size, offset).into(),
+        )
+    }
+
+    #[test]
+    fn test_mark_thread_local_storage() {
+        let stack = 0x10_0000_0000;
+        let mut maps = CachedMaps::new();
+        // A thread stack with its TCB at the top
+        maps.insert(CachedMap::no_access(
+            stack - 0x1000,
+            stack,
+            MMPermissions::NONE,
+            MMapPath::Anonymous,
+        ));
+        maps.insert(with_tcb(stack, 0x20_0000, 0x20_0000 - 0x800));
+        // A large map without a guard page is no candidate
+        maps.insert(with_tcb(0x20_0000_0000, 0x20_0000, 0x10));
+        maps.insert(with_tcb(0x30_0000_0000, 0x3000, 0x740));
+
+        let marked = mark_thread_local_storage(maps.clone(), Endianness::Little);
+        let tls = marked.iter().map(CachedMap::is_tls).collect::<Vec<_>>();
+        assert_eq!(tls, vec![false, true, false, true]);
+
+        let mut bottom = CachedMaps::new();
+        bottom.insert(maps.first().unwrap().clone());
+        bottom.insert(with_tcb(stack, 0x20_0000, 0x10));
+        let marked = mark_thread_local_storage(bottom, Endianness::Little);
+        assert!(!marked.last().unwrap().is_tls());
+
+        let marked = mark_thread_local_storage(maps, Endianness::Big);
+        assert!(marked.iter().all(|map| !map.is_tls()));
+    }
+
+    #[test]
+    fn test_mark_lazy_maps() {
+        let from = 0x30_0000_0000;
+        let memory = Memory {
+            from,
+            bytes: tcb_bytes(from, 0x3000, 0x740),
+        };
+        let mut maps = CachedMaps::new();
+        maps.insert(CachedMap::lazy(
+            from,
+            from + 0x3000,
+            MMPermissions::READ | MMPermissions::WRITE,
+            MMapPath::Anonymous,
+            Arc::new(memory),
+        ));
+
+        let marked = mark_thread_local_storage(maps, Endianness::Little);
+        let map = marked.first().unwrap();
+        assert!(map.is_tls());
+        assert!(!map.is_loaded());
+    }
+}
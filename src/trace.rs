@@ -0,0 +1,59 @@
+//! Records intermediate pools of a pipeline, see [`crate::session::Session::checkpoint`].
+//!
+//! A trace is a shared handle, clones record into the same list. This allows comparing the pools of
+//! different library versions or signature variants step by step.
+
+use std::{cell::RefCell, rc::Rc};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    label: String,
+    addresses: Vec<usize>,
+}
+
+impl Checkpoint {
+    pub fn new(label: String, addresses: Vec<usize>) -> Self {
+        Self { label, addresses }
+    }
+
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+    /// The addresses of the pool, in the order in which they were produced
+    pub fn get_addresses(&self) -> &[usize] {
+        &self.addresses
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    checkpoints: Rc<RefCell<Vec<Checkpoint>>>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, checkpoint: Checkpoint) {
+        self.checkpoints.borrow_mut().push(checkpoint);
+    }
+
+    /// All checkpoints in the order in which they were reached
+    pub fn get_checkpoints(&self) -> Vec<Checkpoint> {
+        self.checkpoints.borrow().clone()
+    }
+
+    /// The first checkpoint with the label
+    pub fn get_checkpoint(&self, label: &str) -> Option<Checkpoint> {
+        self.checkpoints
+            .borrow()
+            .iter()
+            .find(|checkpoint| checkpoint.get_label() == label)
+            .cloned()
+    }
+
+    pub fn clear(&self) {
+        self.checkpoints.borrow_mut().clear();
+    }
+}
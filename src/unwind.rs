@@ -0,0 +1,686 @@
+//! Unwinds the stack of an x86-64 thread, see [`crate::BcrlFactory::backtrace`].
+//!
+//! The call frame information in `.eh_frame` describes for every instruction of a function, where the caller's
+//! registers and the return address are saved. It is found using the binary search table in `.eh_frame_hdr`, which
+//! the `PT_GNU_EH_FRAME` program header points to, so only the snapshot is needed and not the files of the modules.
+//! Frames without call frame information, e.g. of JIT code, are unwound by following the chain of saved frame
+//! pointers instead, which only works if the code keeps one.
+//!
+//! DWARF expressions are not evaluated, rules using them leave the register unknown.
+
+use std::{collections::HashMap, rc::Rc};
+
+use procfs_core::process::MMapPath;
+
+use crate::{
+    cached_maps::{CachedMaps, FindAddress},
+    elf,
+    module::{self, Module, ModuleId},
+    nearest::Anchor,
+};
+
+/// The registers, that call frame information refers to, in DWARF numbering: the 16 general purpose registers,
+/// followed by the return address column, which holds the instruction pointer
+pub const DWARF_REGISTERS: usize = 17;
+
+const RBP: usize = 6;
+const RSP: usize = 7;
+const RETURN_ADDRESS: usize = 16;
+
+/// The values of the registers of a frame in DWARF numbering, None if they are unknown
+pub type DwarfRegisters = [Option<u64>; DWARF_REGISTERS];
+
+/// A frame of the stack of a thread
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pc: usize,
+    module: Option<(MMapPath, usize)>,
+    symbol: Option<Anchor>,
+}
+
+impl Frame {
+    pub fn new(pc: usize, module: Option<(MMapPath, usize)>, symbol: Option<Anchor>) -> Self {
+        Self { pc, module, symbol }
+    }
+
+    /// The instruction pointer of the innermost frame and the return address of all others
+    pub fn get_pc(&self) -> usize {
+        self.pc
+    }
+    /// The module containing the frame and the offset of the frame into it
+    pub fn get_module(&self) -> Option<&(MMapPath, usize)> {
+        self.module.as_ref()
+    }
+    /// The exported symbol, which the frame is executing in
+    pub fn get_symbol(&self) -> Option<&Anchor> {
+        self.symbol.as_ref()
+    }
+}
+
+const DW_EH_PE_OMIT: u8 = 0xFF;
+const DW_EH_PE_PCREL: u8 = 0x10;
+const DW_EH_PE_DATAREL: u8 = 0x30;
+/// `DW_EH_PE_datarel | DW_EH_PE_sdata4`, the only encoding linkers use for the search table
+const DW_EH_PE_DATAREL_SDATA4: u8 = 0x3B;
+
+/// Reads the little-endian DWARF encodings from memory of the snapshot
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    /// The address of the first byte
+    base: usize,
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    /// Starts at the address and reads until the end of the map containing it
+    fn at(maps: &'a CachedMaps, address: usize) -> Option<Self> {
+        let map = maps.find_map(address).filter(|map| map.is_accessible())?;
+        let start = address - map.get_from_address();
+
+        Some(Self {
+            bytes: map.get_bytes().get(start..)?,
+            base: address,
+            offset: 0,
+        })
+    }
+
+    fn address(&self) -> usize {
+        self.base + self.offset
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.offset >= self.bytes.len()
+    }
+
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.checked_add(length)?)?;
+        self.offset += length;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn uleb128(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+    }
+
+    fn sleb128(&mut self) -> Option<i64> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7F) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Some(value);
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<&'a [u8]> {
+        let length = self
+            .bytes
+            .get(self.offset..)?
+            .iter()
+            .position(|byte| *byte == 0)?;
+        let string = self.take(length)?;
+        self.offset += 1;
+        Some(string)
+    }
+
+    /// Reads a pointer in the `DW_EH_PE_*` encoding, data relative pointers are relative to data_base
+    fn pointer(&mut self, encoding: u8, data_base: usize) -> Option<u64> {
+        if encoding == DW_EH_PE_OMIT {
+            return None;
+        }
+        let position = self.address() as u64;
+
+        let value = match encoding & 0x0F {
+            0x00 | 0x04 | 0x0C => self.u64()?,
+            0x01 => self.uleb128()?,
+            0x02 => self.u16()? as u64,
+            0x03 => self.u32()? as u64,
+            0x09 => self.sleb128()? as u64,
+            0x0A => self.u16()? as i16 as u64,
+            0x0B => self.u32()? as i32 as u64,
+            _ => return None,
+        };
+        let base = match encoding & 0x70 {
+            0x00 => 0,
+            DW_EH_PE_PCREL => position,
+            DW_EH_PE_DATAREL => data_base as u64,
+            _ => return None,
+        };
+        // Indirect pointers would have to be dereferenced, they are only used for personality routines
+        if encoding & 0x80 != 0 {
+            return None;
+        }
+
+        Some(base.wrapping_add(value))
+    }
+}
+
+/// Reads the header of a CIE or FDE and returns a cursor behind its id, which ends with the entry,
+/// together with the address of the id and the id
+fn entry(maps: &CachedMaps, address: usize) -> Option<(Cursor<'_>, usize, u32)> {
+    let mut cursor = Cursor::at(maps, address)?;
+    let length = match cursor.u32()? {
+        0 => return None,
+        0xFFFF_FFFF => cursor.u64()?,
+        length => length as u64,
+    };
+    let end = cursor.offset.checked_add(usize::try_from(length).ok()?)?;
+    cursor.bytes = cursor.bytes.get(..end)?;
+
+    let id_address = cursor.address();
+    let id = cursor.u32()?;
+    Some((cursor, id_address, id))
+}
+
+/// The common information entry, that FDEs refer to
+struct Cie<'a> {
+    code_alignment: u64,
+    data_alignment: i64,
+    return_register: u64,
+    fde_encoding: u8,
+    has_augmentation_data: bool,
+    instructions: &'a [u8],
+}
+
+impl<'a> Cie<'a> {
+    fn parse(maps: &'a CachedMaps, address: usize) -> Option<Self> {
+        let (mut cursor, _, id) = entry(maps, address)?;
+        if id != 0 {
+            return None;
+        }
+
+        let version = cursor.u8()?;
+        let augmentation = cursor.string()?;
+        if augmentation.starts_with(b"eh") {
+            return None;
+        }
+        let code_alignment = cursor.uleb128()?;
+        let data_alignment = cursor.sleb128()?;
+        let return_register = match version {
+            1 => cursor.u8()? as u64,
+            _ => cursor.uleb128()?,
+        };
+
+        let mut fde_encoding = 0;
+        let has_augmentation_data = augmentation.first() == Some(&b'z');
+        if has_augmentation_data {
+            let length = usize::try_from(cursor.uleb128()?).ok()?;
+            let end = cursor.offset.checked_add(length)?;
+            for character in &augmentation[1..] {
+                match character {
+                    b'L' => {
+                        cursor.u8()?;
+                    }
+                    b'P' => {
+                        let encoding = cursor.u8()?;
+                        cursor.pointer(encoding & 0x7F, 0)?;
+                    }
+                    b'R' => fde_encoding = cursor.u8()?,
+                    b'S' | b'B' => {}
+                    _ => break,
+                }
+            }
+            cursor.offset = end;
+        }
+
+        Some(Self {
+            code_alignment,
+            data_alignment,
+            return_register,
+            fde_encoding,
+            has_augmentation_data,
+            instructions: cursor.bytes.get(cursor.offset..)?,
+        })
+    }
+}
+
+/// Where the value, that a register had in the caller, can be found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Rule {
+    Undefined,
+    SameValue,
+    /// Saved at the CFA plus the offset
+    Offset(i64),
+    /// Is the CFA plus the offset
+    ValOffset(i64),
+    /// Saved in another register
+    Register(usize),
+}
+
+/// The rules of an instruction, the canonical frame address is a register plus an offset
+#[derive(Clone, Debug)]
+struct Row {
+    cfa: Option<(usize, i64)>,
+    rules: [Rule; DWARF_REGISTERS],
+}
+
+impl Row {
+    fn set(&mut self, register: u64, rule: Rule) {
+        // Vector registers are never needed to unwind
+        if let Some(slot) = self.rules.get_mut(register as usize) {
+            *slot = rule;
+        }
+    }
+}
+
+/// Executes the call frame instructions until the row, which applies to the pc, is built.
+/// Rows, that `DW_CFA_restore` goes back to, are taken from the initial row.
+fn execute(
+    cie: &Cie,
+    instructions: &[u8],
+    initial: Option<&Row>,
+    row: &mut Row,
+    mut location: u64,
+    pc: u64,
+) -> Option<()> {
+    let mut cursor = Cursor {
+        bytes: instructions,
+        base: 0,
+        offset: 0,
+    };
+    let mut remembered = Vec::new();
+    let offset = |factor: i64| factor.wrapping_mul(cie.data_alignment);
+    let restore = |row: &mut Row, register: u64| {
+        let rule = initial
+            .and_then(|initial| initial.rules.get(register as usize))
+            .copied()
+            .unwrap_or(Rule::SameValue);
+        row.set(register, rule);
+    };
+
+    while !cursor.is_at_end() {
+        let opcode = cursor.u8()?;
+        let advance = match (opcode >> 6, opcode) {
+            (1, _) => (opcode & 0x3F) as u64,
+            (2, _) => {
+                let factor = cursor.uleb128()? as i64;
+                row.set((opcode & 0x3F) as u64, Rule::Offset(offset(factor)));
+                0
+            }
+            (3, _) => {
+                restore(row, (opcode & 0x3F) as u64);
+                0
+            }
+            (_, 0x00) => 0,
+            (_, 0x01) => {
+                let new_location = cursor.pointer(cie.fde_encoding, 0)?;
+                if new_location > pc {
+                    return Some(());
+                }
+                location = new_location;
+                0
+            }
+            (_, 0x02) => cursor.u8()? as u64,
+            (_, 0x03) => cursor.u16()? as u64,
+            (_, 0x04) => cursor.u32()? as u64,
+            (_, 0x05) => {
+                let register = cursor.uleb128()?;
+                let factor = cursor.uleb128()? as i64;
+                row.set(register, Rule::Offset(offset(factor)));
+                0
+            }
+            (_, 0x06) => {
+                restore(row, cursor.uleb128()?);
+                0
+            }
+            (_, 0x07) => {
+                row.set(cursor.uleb128()?, Rule::Undefined);
+                0
+            }
+            (_, 0x08) => {
+                row.set(cursor.uleb128()?, Rule::SameValue);
+                0
+            }
+            (_, 0x09) => {
+                let register = cursor.uleb128()?;
+                let other = cursor.uleb128()? as usize;
+                row.set(register, Rule::Register(other));
+                0
+            }
+            (_, 0x0A) => {
+                remembered.push(row.clone());
+                0
+            }
+            (_, 0x0B) => {
+                *row = remembered.pop()?;
+                0
+            }
+            (_, 0x0C) => {
+                let register = cursor.uleb128()? as usize;
+                row.cfa = Some((register, cursor.uleb128()? as i64));
+                0
+            }
+            (_, 0x0D) => {
+                let register = cursor.uleb128()? as usize;
+                row.cfa = Some((register, row.cfa.map_or(0, |(_, offset)| offset)));
+                0
+            }
+            (_, 0x0E) => {
+                let offset = cursor.uleb128()? as i64;
+                row.cfa = row.cfa.map(|(register, _)| (register, offset));
+                0
+            }
+            (_, 0x0F) => {
+                let length = cursor.uleb128()? as usize;
+                cursor.take(length)?;
+                row.cfa = None;
+                0
+            }
+            (_, 0x10 | 0x16) => {
+                let register = cursor.uleb128()?;
+                let length = cursor.uleb128()? as usize;
+                cursor.take(length)?;
+                row.set(register, Rule::Undefined);
+                0
+            }
+            (_, 0x11) => {
+                let register = cursor.uleb128()?;
+                let factor = cursor.sleb128()?;
+                row.set(register, Rule::Offset(offset(factor)));
+                0
+            }
+            (_, 0x12) => {
+                let register = cursor.uleb128()? as usize;
+                row.cfa = Some((register, offset(cursor.sleb128()?)));
+                0
+            }
+            (_, 0x13) => {
+                let cfa_offset = offset(cursor.sleb128()?);
+                row.cfa = row.cfa.map(|(register, _)| (register, cfa_offset));
+                0
+            }
+            (_, 0x14) => {
+                let register = cursor.uleb128()?;
+                let factor = cursor.uleb128()? as i64;
+                row.set(register, Rule::ValOffset(offset(factor)));
+                0
+            }
+            (_, 0x15) => {
+                let register = cursor.uleb128()?;
+                let factor = cursor.sleb128()?;
+                row.set(register, Rule::ValOffset(offset(factor)));
+                0
+            }
+            // DW_CFA_GNU_args_size
+            (_, 0x2E) => {
+                cursor.uleb128()?;
+                0
+            }
+            // DW_CFA_GNU_negative_offset_extended
+            (_, 0x2F) => {
+                let register = cursor.uleb128()?;
+                let factor = cursor.uleb128()? as i64;
+                row.set(register, Rule::Offset(-offset(factor)));
+                0
+            }
+            _ => return None,
+        };
+
+        location = location.wrapping_add(advance * cie.code_alignment);
+        if location > pc {
+            break;
+        }
+    }
+
+    Some(())
+}
+
+/// Finds the FDE covering the pc using the search table of `.eh_frame_hdr` and builds the row for the pc
+fn row_for(maps: &CachedMaps, header: usize, pc: u64) -> Option<Row> {
+    let mut cursor = Cursor::at(maps, header)?;
+    if cursor.u8()? != 1 {
+        return None;
+    }
+    let eh_frame_encoding = cursor.u8()?;
+    let count_encoding = cursor.u8()?;
+    let table_encoding = cursor.u8()?;
+    cursor.pointer(eh_frame_encoding, header)?;
+    let count = cursor.pointer(count_encoding, header)? as usize;
+    if table_encoding != DW_EH_PE_DATAREL_SDATA4 {
+        return None;
+    }
+
+    let table = cursor.take(count.checked_mul(8)?)?;
+    let entry_at = |index: usize| {
+        let field = |offset: usize| {
+            let bytes = table[index * 8 + offset..][..4].try_into().unwrap();
+            (header as u64).wrapping_add(i32::from_le_bytes(bytes) as u64)
+        };
+        (field(0), field(4))
+    };
+    // The table is sorted by the start addresses of the functions
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let middle = (low + high) / 2;
+        if entry_at(middle).0 <= pc {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    let fde = entry_at(low.checked_sub(1)?).1 as usize;
+
+    let (mut cursor, id_address, id) = entry(maps, fde)?;
+    let cie = Cie::parse(maps, id_address.checked_sub(id as usize)?)?;
+    let start = cursor.pointer(cie.fde_encoding, header)?;
+    let range = cursor.pointer(cie.fde_encoding & 0x0F, header)?;
+    if !(start..start.wrapping_add(range)).contains(&pc) {
+        return None;
+    }
+    if cie.has_augmentation_data {
+        let length = cursor.uleb128()? as usize;
+        cursor.take(length)?;
+    }
+
+    let mut row = Row {
+        cfa: None,
+        rules: [Rule::SameValue; DWARF_REGISTERS],
+    };
+    execute(&cie, cie.instructions, None, &mut row, start, u64::MAX)?;
+    let initial = row.clone();
+    execute(
+        &cie,
+        cursor.bytes.get(cursor.offset..)?,
+        Some(&initial),
+        &mut row,
+        start,
+        pc,
+    )?;
+
+    // The return address column of x86-64 is always the instruction pointer
+    (cie.return_register == RETURN_ADDRESS as u64).then_some(row)
+}
+
+/// Walks the stack from the registers of the innermost frame and returns the pc of every frame.
+/// Saved registers are read using read_word, which has to read the live stack, since the snapshot of the stack is
+/// older than the registers. Unwinding stops at the outermost frame, at max_frames or when the stack pointer stops
+/// growing, which means the stack is corrupt or the unwinding went wrong.
+pub fn unwind(
+    maps: &Rc<CachedMaps>,
+    registers: DwarfRegisters,
+    mut read_word: impl FnMut(usize) -> Option<u64>,
+    max_frames: usize,
+) -> Vec<usize> {
+    // Keyed by the instance, a module can be loaded more than once, e.g. into another namespace with dlmopen
+    let ids = module::module_ids(maps);
+    let mut headers = HashMap::<ModuleId, Option<usize>>::new();
+    let mut header_for = |pc: u64| {
+        let map = maps.find_map(pc as usize)?;
+        let id = ids.iter().find(|id| id.contains_map(map))?;
+        *headers.entry(id.clone()).or_insert_with(|| {
+            let module = Module::from_id(maps.clone(), id)?;
+            let header = module
+                .program_headers()
+                .iter()
+                .find(|header| header.get_type() == elf::PT_GNU_EH_FRAME)?;
+            module.to_runtime_address(header.get_virtual_address())
+        })
+    };
+
+    let mut registers = registers;
+    let mut frames = Vec::new();
+    while frames.len() < max_frames {
+        let Some(pc) = registers[RETURN_ADDRESS].filter(|pc| *pc != 0) else {
+            break;
+        };
+        // Return addresses point behind the call, which may be the start of the next function for calls, that don't return
+        let lookup = if frames.is_empty() { pc } else { pc - 1 };
+        frames.push(pc as usize);
+
+        let row = header_for(lookup).and_then(|header| row_for(maps, header, lookup));
+        let caller = match row {
+            Some(Row {
+                cfa: Some((cfa_register, cfa_offset)),
+                rules,
+            }) => {
+                let Some(cfa) = registers
+                    .get(cfa_register)
+                    .copied()
+                    .flatten()
+                    .map(|value| value.wrapping_add(cfa_offset as u64))
+                else {
+                    break;
+                };
+                if rules[RETURN_ADDRESS] == Rule::Undefined {
+                    break;
+                }
+
+                let mut caller = [None; DWARF_REGISTERS];
+                for (register, rule) in rules.iter().enumerate() {
+                    caller[register] = match rule {
+                        Rule::Undefined => None,
+                        Rule::SameValue => registers[register],
+                        Rule::Offset(offset) => {
+                            read_word(cfa.wrapping_add(*offset as u64) as usize)
+                        }
+                        Rule::ValOffset(offset) => Some(cfa.wrapping_add(*offset as u64)),
+                        Rule::Register(other) => registers.get(*other).copied().flatten(),
+                    };
+                }
+                caller[RSP] = Some(cfa);
+                caller
+            }
+            _ => {
+                let Some(frame_pointer) = registers[RBP].filter(|rbp| *rbp != 0) else {
+                    break;
+                };
+                let mut caller = registers;
+                caller[RETURN_ADDRESS] = read_word(frame_pointer.wrapping_add(8) as usize);
+                caller[RBP] = read_word(frame_pointer as usize);
+                caller[RSP] = Some(frame_pointer.wrapping_add(16));
+                caller
+            }
+        };
+
+        if caller[RSP] <= registers[RSP] {
+            break;
+        }
+        registers = caller;
+    }
+
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_execute() {
+        // The CIE and FDE, that GCC emits for a function with a frame pointer
+        let cie = Cie {
+            code_alignment: 1,
+            data_alignment: -8,
+            return_register: RETURN_ADDRESS as u64,
+            fde_encoding: 0x1B,
+            has_augmentation_data: true,
+            // def_cfa rsp+8, offset rip at cfa-8
+            instructions: &[0x0C, 0x07, 0x08, 0x90, 0x01],
+        };
+        // advance 1, def_cfa_offset 16, offset rbp at cfa-16, advance 3, def_cfa_register rbp
+        let instructions = [0x41, 0x0E, 0x10, 0x86, 0x02, 0x43, 0x0D, 0x06];
+
+        let row_at = |pc: u64| {
+            let mut row = Row {
+                cfa: None,
+                rules: [Rule::SameValue; DWARF_REGISTERS],
+            };
+            execute(&cie, cie.instructions, None, &mut row, 0x1000, u64::MAX).unwrap();
+            let initial = row.clone();
+            execute(&cie, &instructions, Some(&initial), &mut row, 0x1000, pc).unwrap();
+            row
+        };
+
+        let row = row_at(0x1000);
+        assert_eq!(row.cfa, Some((RSP, 8)));
+        assert_eq!(row.rules[RETURN_ADDRESS], Rule::Offset(-8));
+        assert_eq!(row.rules[RBP], Rule::SameValue);
+
+        let row = row_at(0x1001);
+        assert_eq!(row.cfa, Some((RSP, 16)));
+        assert_eq!(row.rules[RBP], Rule::Offset(-16));
+
+        let row = row_at(0x1004);
+        assert_eq!(row.cfa, Some((RBP, 16)));
+    }
+
+    #[test]
+    fn test_cie_with_oversized_augmentation() {
+        use std::collections::BTreeSet;
+
+        use procfs_core::process::MMPermissions;
+
+        use crate::cached_map::CachedMap;
+
+        // length, id, version, augmentation, code and data alignment, return register
+        let mut cie = vec![0, 0, 0, 0, 0, 0, 0, 0, 1, b'z', b'R', 0, 0x01, 0x78, 0x10];
+        // The length of the augmentation data is the largest uleb128, which doesn't fit behind the cursor
+        cie.extend([0xFF; 9]);
+        cie.extend([0x01, 0x1B]);
+        let length = (cie.len() - 4) as u32;
+        cie[..4].copy_from_slice(&length.to_le_bytes());
+        let maps = BTreeSet::from([CachedMap::new(
+            0x1000,
+            0x1000 + cie.len(),
+            MMPermissions::READ,
+            MMapPath::Anonymous,
+            cie.into(),
+        )]);
+
+        assert!(Cie::parse(&maps, 0x1000).is_none());
+    }
+}
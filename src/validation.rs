@@ -0,0 +1,97 @@
+use std::fmt;
+
+use procfs_core::process::MMapPath;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationStatus {
+    /// The plan resolved to exactly one address. Module and offset are known if the address is inside a file-backed mapping.
+    Unique {
+        address: usize,
+        module: Option<MMapPath>,
+        offset: Option<usize>,
+    },
+    /// The plan resolved to more than one address
+    Ambiguous(usize),
+    /// The plan didn't resolve to any address
+    Missing,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationEntry {
+    name: String,
+    status: ValidationStatus,
+}
+
+impl ValidationEntry {
+    pub fn new(name: String, status: ValidationStatus) -> Self {
+        Self { name, status }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn get_status(&self) -> &ValidationStatus {
+        &self.status
+    }
+    pub fn is_unique(&self) -> bool {
+        matches!(self.status, ValidationStatus::Unique { .. })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    entries: Vec<ValidationEntry>,
+}
+
+impl ValidationReport {
+    pub fn new(entries: Vec<ValidationEntry>) -> Self {
+        Self { entries }
+    }
+
+    pub fn get_entries(&self) -> &[ValidationEntry] {
+        &self.entries
+    }
+    pub fn get_entry(&self, name: &str) -> Option<&ValidationEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Returns true if every entry resolved to exactly one address
+    pub fn is_valid(&self) -> bool {
+        self.entries.iter().all(ValidationEntry::is_unique)
+    }
+
+    /// Returns all entries that didn't resolve to exactly one address
+    pub fn failures(&self) -> impl Iterator<Item = &ValidationEntry> {
+        self.entries.iter().filter(|entry| !entry.is_unique())
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            match &entry.status {
+                ValidationStatus::Unique {
+                    address,
+                    module: Some(MMapPath::Path(path)),
+                    offset: Some(offset),
+                } => writeln!(
+                    f,
+                    "{}: unique at {address:#x} ({}+{offset:#x})",
+                    entry.name,
+                    path.file_name()
+                        .map(|name| name.to_string_lossy())
+                        .unwrap_or_else(|| path.to_string_lossy()),
+                )?,
+                ValidationStatus::Unique { address, .. } => {
+                    writeln!(f, "{}: unique at {address:#x}", entry.name)?
+                }
+                ValidationStatus::Ambiguous(count) => {
+                    writeln!(f, "{}: ambiguous ({count} hits)", entry.name)?
+                }
+                ValidationStatus::Missing => writeln!(f, "{}: missing", entry.name)?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use std::{thread, time::Duration};
+
+use procfs::{process::Process, ProcError};
+
+use crate::factory::BcrlFactory;
+
+type Pipeline<'a, T> = dyn FnMut(&BcrlFactory) -> T + 'a;
+type ChangeCallback<'a, T> = dyn FnMut(Option<&T>, &T) + 'a;
+
+/// Periodically takes a new snapshot of a process and re-executes a pipeline against it.
+/// The callback is invoked whenever the result of the pipeline differs from the previous one.
+///
+/// The result can be anything comparable, e.g. the resolved addresses or values read from them.
+pub struct Watcher<'a, T: PartialEq> {
+    pid: i32,
+    interval: Duration,
+    pipeline: Box<Pipeline<'a, T>>,
+    callbacks: Vec<Box<ChangeCallback<'a, T>>>,
+    last: Option<T>,
+}
+
+impl<'a, T: PartialEq> Watcher<'a, T> {
+    pub fn new(pid: i32, interval: Duration, pipeline: impl FnMut(&BcrlFactory) -> T + 'a) -> Self {
+        Self {
+            pid,
+            interval,
+            pipeline: Box::new(pipeline),
+            callbacks: Vec::new(),
+            last: None,
+        }
+    }
+
+    /// Registers a callback, which receives the previous (None on the first poll) and the new result.
+    pub fn on_change(mut self, callback: impl FnMut(Option<&T>, &T) + 'a) -> Self {
+        self.callbacks.push(Box::new(callback));
+
+        self
+    }
+
+    pub fn get_interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// The result of the latest poll
+    pub fn get_last(&self) -> Option<&T> {
+        self.last.as_ref()
+    }
+
+    /// Takes a new snapshot and executes the pipeline once. Returns whether the result changed.
+    pub fn poll(&mut self) -> Result<bool, ProcError> {
+        let process = Process::new(self.pid)?;
+        let factory = BcrlFactory::from_process(&process)?;
+
+        let result = (self.pipeline)(&factory);
+
+        if self.last.as_ref() == Some(&result) {
+            return Ok(false);
+        }
+
+        for callback in &mut self.callbacks {
+            callback(self.last.as_ref(), &result);
+        }
+        self.last = Some(result);
+
+        Ok(true)
+    }
+
+    /// Polls in the configured interval as long as the condition holds or an error occurs.
+    pub fn run_while(&mut self, mut condition: impl FnMut() -> bool) -> Result<(), ProcError> {
+        while condition() {
+            self.poll()?;
+            thread::sleep(self.interval);
+        }
+
+        Ok(())
+    }
+}